@@ -0,0 +1,31 @@
+use std::net::Ipv4Addr;
+
+use common::types::{DataType, NetworkAlert, NetworkAlertPayload, PayloadType};
+use pnet::util::MacAddr;
+use tracing::warn;
+
+use crate::interfaces::ws::WsReporter;
+
+
+/// A host an alert names, with its address when known
+pub type Host = (MacAddr, Option<Ipv4Addr>);
+
+/// Reports something seen on the network about `source`, and the host it affects if a single
+/// one does. Sent from its own task, so the capture loop never waits on the server
+pub fn raise(reporter: &WsReporter, source: Host, target: Option<Host>, alert: NetworkAlert) {
+    let priority = alert.priority();
+    warn!("🚨 {} {} ({:?}): {:?}", alert.kind(), source.0, source.1, alert);
+
+    let payload = NetworkAlertPayload {
+        source_mac: source.0.octets(),
+        source_ip: source.1.map(|ip| ip.to_string()),
+        target_mac: target.map(|(mac, _)| mac.octets()),
+        target_ip: target.and_then(|(_, ip)| ip).map(|ip| ip.to_string()),
+        alert,
+    };
+
+    let reporter = reporter.clone();
+    tokio::spawn(async move {
+        reporter.send(DataType::NetworkAlert, priority, source.0, PayloadType::NetworkAlert(payload)).await;
+    });
+}
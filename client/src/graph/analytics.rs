@@ -0,0 +1,348 @@
+use std::{cmp::Ordering, collections::{BinaryHeap, HashMap, HashSet, VecDeque}, net::Ipv4Addr, sync::Arc, time::{Duration, SystemTime}};
+
+use common::types::{NetworkAlert, TopologyFindingKind};
+use lazy_static::lazy_static;
+use petgraph::{stable_graph::{NodeIndex, StableDiGraph}, visit::{EdgeRef, IntoEdgeReferences}, Direction};
+use pnet::util::MacAddr;
+use tokio::{sync::Mutex, time::sleep};
+use tracing::info;
+
+use crate::{graph::{alerts::{self, Host}, types::{Link, NetworkGraph, NetworkNode, NodeType}}, interfaces::ws::WsReporter};
+
+const ANALYSIS_INTERVAL: Duration = Duration::from_secs(300);
+// Robust z-score (median and MAD) above which a host's peer count is an outlier
+const OUTLIER_SCORE: f64 = 3.5;
+// However quiet the rest of the network, fewer peers than this are never an outlier
+const MIN_OUTLIER_PEERS: usize = 10;
+// Hosts known for less than this have no habits yet for a new peer to break
+const NEW_EDGE_GRACE: Duration = Duration::from_secs(3600);
+// Share of the shortest paths between the other hosts crossing a host, above which it is a pivot
+const PIVOT_CENTRALITY: f64 = 0.3;
+// With fewer hosts every one of them is central
+const MIN_PIVOT_HOSTS: usize = 5;
+// Where an attacker wants to get: remote access, directory, file sharing, databases, Modbus
+const CRITICAL_TCP_PORTS: [u16; 12] = [22, 88, 389, 445, 502, 1433, 1521, 3306, 3389, 5432, 5985, 27017];
+// Paths reported from each anomalous host, nearest critical hosts first
+const MAX_PATHS: usize = 3;
+const MAX_RELATED: usize = 20;
+
+
+/// Kind of finding, host and target
+type Flag = (TopologyFindingKind, MacAddr, Option<MacAddr>);
+
+lazy_static! {
+    /// Findings raised in the last analysis, reported again only once they stopped holding
+    static ref FLAGGED: Mutex<HashSet<Flag>> = Mutex::new(HashSet::new());
+}
+
+/// Something the graph shows about a host
+#[derive(Debug)]
+pub struct Finding {
+    pub finding: TopologyFindingKind,
+    pub host: MacAddr,
+    /// Critical host an attack path ends at
+    pub target: Option<MacAddr>,
+    pub score: f64,
+    /// Peers behind the finding, or the hosts along the path
+    pub related: Vec<MacAddr>,
+}
+
+/// Real hosts: not the decoys, nor broadcast and multicast destinations
+fn is_host(node: &NetworkNode) -> bool {
+    node.node_type != NodeType::Virtual && node.mac_address.0 & 0x01 == 0
+}
+
+/// Runs every analysis on the graph; new edges are those first seen after `since`
+pub fn analyze(graph: &NetworkGraph, since: SystemTime, gateway: Option<MacAddr>) -> Vec<Finding> {
+    let g = graph.petgraph();
+    let hosts: HashSet<NodeIndex> = g.node_indices().filter(|&index| is_host(&g[index])).collect();
+    // The gateway talks to every host and sits on every path between them, it would always stand out
+    let ordinary: HashSet<NodeIndex> = hosts.iter().copied()
+        .filter(|&index| Some(g[index].mac_address) != gateway)
+        .collect();
+
+    let mut findings = fan_outliers(g, &ordinary, Direction::Outgoing);
+    findings.extend(fan_outliers(g, &ordinary, Direction::Incoming));
+    findings.extend(new_edges(g, &hosts, since));
+    findings.extend(pivots(g, &ordinary));
+    findings.extend(attack_paths(g, &hosts));
+    findings
+}
+
+/// Distinct hosts each host sends to, or receives from
+fn peers(g: &StableDiGraph<NetworkNode, Link>, hosts: &HashSet<NodeIndex>, index: NodeIndex, direction: Direction) -> Vec<NodeIndex> {
+    g.neighbors_directed(index, direction).filter(|peer| *peer != index && hosts.contains(peer)).collect()
+}
+
+fn median(sorted: &[f64]) -> f64 {
+    let middle = sorted.len() / 2;
+    if sorted.len().is_multiple_of(2) { (sorted[middle - 1] + sorted[middle]) / 2.0 } else { sorted[middle] }
+}
+
+/// Hosts talking to, or reached by, far more peers than the rest: scanners, worms, or a
+/// server nobody should be that interested in
+fn fan_outliers(g: &StableDiGraph<NetworkNode, Link>, hosts: &HashSet<NodeIndex>, direction: Direction) -> Vec<Finding> {
+    let degrees: Vec<(NodeIndex, Vec<NodeIndex>)> = hosts.iter().map(|&index| (index, peers(g, hosts, index, direction))).collect();
+    if degrees.len() < 3 {
+        return Vec::new();
+    }
+    let mut counts: Vec<f64> = degrees.iter().map(|(_, peers)| peers.len() as f64).collect();
+    counts.sort_by(f64::total_cmp);
+    let center = median(&counts);
+    let mut deviations: Vec<f64> = counts.iter().map(|count| (count - center).abs()).collect();
+    deviations.sort_by(f64::total_cmp);
+    // When most hosts have the same count the MAD is 0, one peer keeps the score finite
+    let mad = median(&deviations).max(1.0);
+
+    let finding = if direction == Direction::Outgoing { TopologyFindingKind::FanOut } else { TopologyFindingKind::FanIn };
+    degrees.into_iter()
+        .filter(|(_, peers)| peers.len() >= MIN_OUTLIER_PEERS)
+        .filter_map(|(index, peers)| {
+            let score = 0.6745 * (peers.len() as f64 - center) / mad;
+            (score > OUTLIER_SCORE).then(|| Finding {
+                finding,
+                host: g[index].mac_address,
+                target: None,
+                score,
+                related: peers.iter().take(MAX_RELATED).map(|&peer| g[peer].mac_address).collect(),
+            })
+        })
+        .collect()
+}
+
+/// Hosts that started talking to peers they never did before, both known long enough to
+/// have habits. Grouped by sender, since a host sweeping the network opens many at once
+fn new_edges(g: &StableDiGraph<NetworkNode, Link>, hosts: &HashSet<NodeIndex>, since: SystemTime) -> Vec<Finding> {
+    let mut by_sender: HashMap<NodeIndex, Vec<NodeIndex>> = HashMap::new();
+    for edge in g.edge_references() {
+        let (source, target, link) = (edge.source(), edge.target(), edge.weight());
+        if source == target || !hosts.contains(&source) || !hosts.contains(&target) || link.first_seen < since {
+            continue;
+        }
+        let known_before = |index: NodeIndex| g[index].first_seen + NEW_EDGE_GRACE <= link.first_seen;
+        if known_before(source) && known_before(target) {
+            by_sender.entry(source).or_default().push(target);
+        }
+    }
+    by_sender.into_iter()
+        .map(|(source, targets)| Finding {
+            finding: TopologyFindingKind::NewEdge,
+            host: g[source].mac_address,
+            target: None,
+            score: targets.len() as f64,
+            related: targets.iter().take(MAX_RELATED).map(|&target| g[target].mac_address).collect(),
+        })
+        .collect()
+}
+
+/// Betweenness centrality of the hosts, ignoring direction, normalized between 0 and 1 (Brandes)
+fn betweenness(adjacency: &HashMap<NodeIndex, Vec<NodeIndex>>) -> HashMap<NodeIndex, f64> {
+    let mut centrality: HashMap<NodeIndex, f64> = adjacency.keys().map(|&index| (index, 0.0)).collect();
+    for &source in adjacency.keys() {
+        let mut stack = Vec::new();
+        let mut predecessors: HashMap<NodeIndex, Vec<NodeIndex>> = HashMap::new();
+        let mut paths: HashMap<NodeIndex, f64> = HashMap::from([(source, 1.0)]);
+        let mut distance: HashMap<NodeIndex, usize> = HashMap::from([(source, 0)]);
+        let mut queue = VecDeque::from([source]);
+        while let Some(current) = queue.pop_front() {
+            stack.push(current);
+            for &next in &adjacency[&current] {
+                if !distance.contains_key(&next) {
+                    distance.insert(next, distance[&current] + 1);
+                    queue.push_back(next);
+                }
+                if distance[&next] == distance[&current] + 1 {
+                    *paths.entry(next).or_default() += paths[&current];
+                    predecessors.entry(next).or_default().push(current);
+                }
+            }
+        }
+        let mut dependency: HashMap<NodeIndex, f64> = HashMap::new();
+        while let Some(current) = stack.pop() {
+            for &previous in predecessors.get(&current).into_iter().flatten() {
+                let share = paths[&previous] / paths[&current] * (1.0 + dependency.get(&current).copied().unwrap_or_default());
+                *dependency.entry(previous).or_default() += share;
+            }
+            if current != source {
+                *centrality.entry(current).or_default() += dependency.get(&current).copied().unwrap_or_default();
+            }
+        }
+    }
+    // Every pair was walked from both ends
+    let n = adjacency.len() as f64;
+    let pairs = (n - 1.0) * (n - 2.0);
+    centrality.values_mut().for_each(|value| *value /= pairs);
+    centrality
+}
+
+/// Hosts most of the paths between the others go through: where an attacker would pivot from
+fn pivots(g: &StableDiGraph<NetworkNode, Link>, hosts: &HashSet<NodeIndex>) -> Vec<Finding> {
+    if hosts.len() < MIN_PIVOT_HOSTS {
+        return Vec::new();
+    }
+    let adjacency: HashMap<NodeIndex, Vec<NodeIndex>> = hosts.iter()
+        .map(|&index| {
+            let neighbours: HashSet<NodeIndex> = g.neighbors_undirected(index).filter(|peer| *peer != index && hosts.contains(peer)).collect();
+            (index, neighbours.into_iter().collect())
+        })
+        .collect();
+    betweenness(&adjacency).into_iter()
+        .filter(|(_, centrality)| *centrality >= PIVOT_CENTRALITY)
+        .map(|(index, centrality)| Finding {
+            finding: TopologyFindingKind::Pivot,
+            host: g[index].mac_address,
+            target: None,
+            score: centrality,
+            related: adjacency[&index].iter().take(MAX_RELATED).map(|&peer| g[peer].mac_address).collect(),
+        })
+        .collect()
+}
+
+/// Cost of following a link: the more traffic already flows over it, the less an attacker
+/// using it stands out
+fn cost(link: &Link) -> f64 {
+    1.0 / (1.0 + (link.packets as f64).ln_1p())
+}
+
+/// Host waiting to be walked from, cheapest first
+#[derive(PartialEq)]
+struct Frontier(f64, NodeIndex);
+
+impl Eq for Frontier {}
+
+impl Ord for Frontier {
+    fn cmp(&self, other: &Self) -> Ordering {
+        other.0.total_cmp(&self.0).then_with(|| self.1.cmp(&other.1))
+    }
+}
+
+impl PartialOrd for Frontier {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// Cheapest cost from `source` to every host it reaches over links between hosts, with the
+/// hop before it on the way (Dijkstra)
+fn cheapest_paths(g: &StableDiGraph<NetworkNode, Link>, hosts: &HashSet<NodeIndex>, source: NodeIndex) -> HashMap<NodeIndex, (f64, NodeIndex)> {
+    let mut best: HashMap<NodeIndex, (f64, NodeIndex)> = HashMap::from([(source, (0.0, source))]);
+    let mut frontier = BinaryHeap::from([Frontier(0.0, source)]);
+    while let Some(Frontier(reached, current)) = frontier.pop() {
+        if reached > best[&current].0 {
+            continue;
+        }
+        for edge in g.edges(current) {
+            let next = edge.target();
+            if next == current || !hosts.contains(&next) {
+                continue;
+            }
+            let through = reached + cost(edge.weight());
+            if best.get(&next).is_none_or(|(known, _)| through < *known) {
+                best.insert(next, (through, current));
+                frontier.push(Frontier(through, next));
+            }
+        }
+    }
+    best
+}
+
+/// Cheapest chains of hosts talking to one another from each host the model flagged as
+/// anomalous to the nearest hosts offering critical services
+fn attack_paths(g: &StableDiGraph<NetworkNode, Link>, hosts: &HashSet<NodeIndex>) -> Vec<Finding> {
+    let critical: HashSet<NodeIndex> = hosts.iter().copied()
+        .filter(|&index| CRITICAL_TCP_PORTS.iter().any(|port| g[index].tcp_services.contains(port)))
+        .collect();
+    if critical.is_empty() {
+        return Vec::new();
+    }
+
+    let mut findings = Vec::new();
+    for &source in hosts.iter().filter(|&&index| !g[index].anomalies.is_empty()) {
+        let paths = cheapest_paths(g, hosts, source);
+        let mut reachable: Vec<(NodeIndex, f64)> = paths.iter()
+            .filter(|(target, _)| **target != source && critical.contains(target))
+            .map(|(&target, &(cost, _))| (target, cost))
+            .collect();
+        reachable.sort_by(|a, b| a.1.total_cmp(&b.1).then_with(|| g[a.0].mac_address.octets().cmp(&g[b.0].mac_address.octets())));
+
+        for (target, _) in reachable.into_iter().take(MAX_PATHS) {
+            let mut path = vec![target];
+            let mut hop = target;
+            while hop != source {
+                hop = paths[&hop].1;
+                path.push(hop);
+            }
+            path.reverse();
+            findings.push(Finding {
+                finding: TopologyFindingKind::AttackPath,
+                host: g[source].mac_address,
+                target: Some(g[target].mac_address),
+                score: (path.len() - 1) as f64,
+                related: path.into_iter().map(|index| g[index].mac_address).collect(),
+            });
+        }
+    }
+    findings
+}
+
+
+/// The host's address if it has one, its MAC otherwise
+fn describe(graph: &NetworkGraph, mac: MacAddr) -> String {
+    match graph.node(mac).and_then(|node| node.ipv4_address) {
+        Some(ip) => ip.to_string(),
+        None => mac.to_string(),
+    }
+}
+
+fn located(graph: &NetworkGraph, mac: MacAddr) -> Host {
+    (mac, graph.node(mac).and_then(|node| node.ipv4_address))
+}
+
+/// Analyzes a copy of the graph every few minutes, so capture doesn't wait on it, and reports
+/// what it shows. Findings that keep holding are reported once, when they first do
+pub async fn analyze_periodically(graph: Arc<Mutex<NetworkGraph>>, reporter: WsReporter, gateway: Option<Ipv4Addr>) {
+    let mut since = SystemTime::now();
+    // Resolved to a MAC through the inventory, whose addresses follow ARP, and kept: a stale
+    // address on another node must not make the gateway an ordinary host again
+    let mut gateway_mac = None;
+    loop {
+        sleep(ANALYSIS_INTERVAL).await;
+        let now = SystemTime::now();
+        let reports: Vec<(Flag, Host, Option<Host>, NetworkAlert)> = {
+            let g = graph.lock().await.clone();
+            if let Some(node) = gateway.and_then(|ip| g.find_by_ip(ip)) && node.node_type != NodeType::Virtual && gateway_mac != Some(node.mac_address) {
+                info!("🕸️ Gateway {} all'indirizzo {:?}, escluso dall'analisi", node.mac_address, gateway);
+                gateway_mac = Some(node.mac_address);
+            }
+            let findings = analyze(&g, since, gateway_mac);
+            info!("🕸️ Analisi del grafo: {} nodi, {} collegamenti, {} risultati", g.nodes().count(), g.links().count(), findings.len());
+            findings.into_iter()
+                .map(|finding| {
+                    // Paths are raised against the critical host they reach
+                    let alert = NetworkAlert::TopologyFinding {
+                        finding: finding.finding,
+                        score: finding.score,
+                        related: finding.related.iter().map(|&mac| describe(&g, mac)).collect(),
+                    };
+                    let target = finding.target.map(|target| located(&g, target));
+                    ((finding.finding, finding.host, finding.target), located(&g, finding.host), target, alert)
+                })
+                .collect()
+        };
+        since = now;
+
+        let mut flagged = FLAGGED.lock().await;
+        let mut current = HashSet::new();
+        for (flag, source, target, alert) in reports {
+            // New edges are new by definition
+            if flag.0 != TopologyFindingKind::NewEdge {
+                current.insert(flag);
+                if flagged.contains(&flag) {
+                    continue;
+                }
+            }
+            alerts::raise(&reporter, source, target, alert);
+        }
+        *flagged = current;
+    }
+}
@@ -0,0 +1,363 @@
+use std::{collections::HashMap, env, fs, io, net::Ipv4Addr, str::FromStr, sync::Arc, time::{Duration, Instant, SystemTime, UNIX_EPOCH}};
+
+use common::types::{AssetChangeKind, NetworkAlert};
+use lazy_static::lazy_static;
+use pnet::{packet::{arp::ArpPacket, ethernet::{EtherTypes, EthernetPacket}, ip::IpNextHeaderProtocols, ipv4::Ipv4Packet, tcp::{TcpFlags, TcpPacket}, Packet}, util::MacAddr};
+use serde::{Deserialize, Serialize};
+use tokio::{sync::Mutex, task::spawn_blocking, time::sleep};
+use tracing::{error, info, warn};
+
+use crate::{graph::{alerts, types::{NetworkGraph, NodeType}, utils::initial_ttl}, interfaces::ws::WsReporter, state::write_json_atomic};
+
+/// Seconds during which the hosts seen on a first start are learned without alerts
+const LEARNING_VARIABLE: &str = "HONEYPOT_LEARNING_PERIOD";
+const DEFAULT_LEARNING: Duration = Duration::from_secs(3600);
+/// Days of absence after which a returning host is reported
+const REAPPEAR_VARIABLE: &str = "HONEYPOT_REAPPEAR_DAYS";
+const DEFAULT_REAPPEAR_DAYS: u64 = 7;
+const KNOWN_FILE: &str = "state/known_devices.json";
+const SAVE_INTERVAL: Duration = Duration::from_secs(60);
+// An address flapping between two MACs, as ARP spoofing makes it, is reported once in this
+const REBIND_REPORT_INTERVAL: Duration = Duration::from_secs(600);
+// New hosts reported one by one per window, across the network; the rest go in one summary
+const NEW_DEVICE_WINDOW: Duration = Duration::from_secs(60);
+const MAX_NEW_DEVICE_ALERTS: u32 = 20;
+const DAY: u64 = 24 * 60 * 60;
+
+
+/// What was learned about a host, kept across restarts
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+struct KnownDevice {
+    ipv4: Option<Ipv4Addr>,
+    /// Unix seconds
+    last_seen: u64,
+    /// Initial TTL of the SYNs it sends from its own address
+    initial_ttl: Option<u8>,
+}
+
+/// On-disk format of the known hosts, by MAC
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct KnownFile {
+    devices: HashMap<String, KnownDevice>,
+}
+
+struct Tracker {
+    devices: HashMap<MacAddr, KnownDevice>,
+    /// End of the learning period, `None` once the hosts are known
+    learning_until: Option<Instant>,
+    reappear_after: u64,
+    dirty: bool,
+    rebinds_reported: HashMap<Ipv4Addr, Instant>,
+    new_devices: NewDeviceLimit,
+}
+
+/// Rate limit of the new host alerts
+struct NewDeviceLimit {
+    window_start: Instant,
+    reported: u32,
+    suppressed: u32,
+    /// Last host left out, named by the summary
+    last_suppressed: Option<MacAddr>,
+}
+
+impl NewDeviceLimit {
+    fn new() -> Self {
+        NewDeviceLimit { window_start: Instant::now(), reported: 0, suppressed: 0, last_suppressed: None }
+    }
+
+    /// Whether a new host can be reported on its own; if not it is counted for the summary
+    fn admit(&mut self, mac: MacAddr) -> bool {
+        if self.reported < MAX_NEW_DEVICE_ALERTS {
+            self.reported += 1;
+            return true;
+        }
+        self.suppressed += 1;
+        self.last_suppressed = Some(mac);
+        false
+    }
+
+    /// Starts a new window once the current one is over, with the last host and the count it left out
+    fn close(&mut self) -> Option<(MacAddr, u32)> {
+        if self.window_start.elapsed() < NEW_DEVICE_WINDOW {
+            return None;
+        }
+        self.window_start = Instant::now();
+        self.reported = 0;
+        let suppressed = std::mem::take(&mut self.suppressed);
+        self.last_suppressed.take().map(|mac| (mac, suppressed))
+    }
+}
+
+lazy_static! {
+    static ref TRACKER: Mutex<Tracker> = Mutex::new(Tracker::new());
+}
+
+/// Observed change about a host, before it is reported
+struct Change {
+    change: AssetChangeKind,
+    previous: Option<String>,
+    current: Option<String>,
+}
+
+impl Change {
+    fn new(change: AssetChangeKind, previous: Option<String>, current: Option<String>) -> Self {
+        Change { change, previous, current }
+    }
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |elapsed| elapsed.as_secs())
+}
+
+impl Tracker {
+    /// Hosts known from a previous run are the baseline, without one the network is learned first
+    fn new() -> Self {
+        let devices = load();
+        let learning_until = if devices.is_empty() {
+            let learning = env::var(LEARNING_VARIABLE).ok()
+                .and_then(|value| value.trim().parse().ok())
+                .map_or(DEFAULT_LEARNING, Duration::from_secs);
+            info!("📚 Apprendimento degli host della rete per {:?}, senza avvisi", learning);
+            Some(Instant::now() + learning)
+        } else {
+            info!("📚 {} host noti caricati da {}", devices.len(), KNOWN_FILE);
+            None
+        };
+        let reappear_days = env::var(REAPPEAR_VARIABLE).ok()
+            .and_then(|value| value.trim().parse().ok())
+            .unwrap_or(DEFAULT_REAPPEAR_DAYS);
+        Tracker { devices, learning_until, reappear_after: reappear_days * DAY, dirty: false, rebinds_reported: HashMap::new(), new_devices: NewDeviceLimit::new() }
+    }
+
+    fn learning(&mut self) -> bool {
+        match self.learning_until {
+            Some(until) if Instant::now() < until => true,
+            Some(_) => {
+                self.learning_until = None;
+                self.dirty = true;
+                info!("📚 Apprendimento concluso con {} host noti, da ora i cambiamenti vengono segnalati", self.devices.len());
+                false
+            }
+            None => false,
+        }
+    }
+
+    /// Records a frame from `mac`, what it says of the host's address and stack, and what changed
+    fn observe(&mut self, mac: MacAddr, binding: Option<Ipv4Addr>, syn: Option<(Ipv4Addr, u8)>) -> Vec<Change> {
+        let now = unix_now();
+        let mut changes = Vec::new();
+        let device = match self.devices.get_mut(&mac) {
+            Some(device) => {
+                if self.reappear_after > 0 && now.saturating_sub(device.last_seen) >= self.reappear_after {
+                    changes.push(Change::new(AssetChangeKind::Reappeared, Some(format!("{} days", (now - device.last_seen) / DAY)), None));
+                }
+                device
+            }
+            None => {
+                // Locally administered: a random MAC, as phones use per network
+                let change = if mac.0 & 0x02 != 0 { AssetChangeKind::RandomizedMac } else { AssetChangeKind::NewDevice };
+                changes.push(Change::new(change, None, binding.map(|ip| ip.to_string())));
+                self.devices.entry(mac).or_default()
+            }
+        };
+        // Last seen only moves by the minute, so the file isn't rewritten for every frame
+        if now.saturating_sub(device.last_seen) >= SAVE_INTERVAL.as_secs() {
+            device.last_seen = now;
+            self.dirty = true;
+        }
+
+        if let Some((source, ttl)) = syn && device.ipv4 == Some(source) {
+            let initial = initial_ttl(ttl);
+            match device.initial_ttl {
+                Some(known) if known != initial => {
+                    changes.push(Change::new(AssetChangeKind::StackChanged, Some(format!("ttl {}", known)), Some(format!("ttl {}", initial))));
+                    device.initial_ttl = Some(initial);
+                    self.dirty = true;
+                }
+                Some(_) => {}
+                None => {
+                    device.initial_ttl = Some(initial);
+                    self.dirty = true;
+                }
+            }
+        }
+
+        if let Some(ip) = binding && device.ipv4 != Some(ip) {
+            if let Some(previous) = device.ipv4 {
+                changes.push(Change::new(AssetChangeKind::IpChanged, Some(previous.to_string()), Some(ip.to_string())));
+            }
+            device.ipv4 = Some(ip);
+            // A different stack may answer from the new address
+            device.initial_ttl = None;
+            self.dirty = true;
+
+            let holders: Vec<MacAddr> = self.devices.iter()
+                .filter(|(other, device)| **other != mac && device.ipv4 == Some(ip))
+                .map(|(other, _)| *other)
+                .collect();
+            for holder in holders {
+                if let Some(device) = self.devices.get_mut(&holder) {
+                    device.ipv4 = None;
+                }
+                let reported = self.rebinds_reported.get(&ip).is_some_and(|at| at.elapsed() < REBIND_REPORT_INTERVAL);
+                if !reported {
+                    self.rebinds_reported.insert(ip, Instant::now());
+                    changes.push(Change::new(AssetChangeKind::MacChanged, Some(holder.to_string()), Some(mac.to_string())));
+                }
+            }
+        }
+        changes
+    }
+
+    /// What changed since the last save; nothing while learning, so a restart meanwhile
+    /// learns again from the start
+    fn take_unsaved(&mut self) -> Option<KnownFile> {
+        if !self.dirty || self.learning_until.is_some() {
+            return None;
+        }
+        self.dirty = false;
+        Some(KnownFile { devices: self.devices.iter().map(|(mac, device)| (mac.to_string(), device.clone())).collect() })
+    }
+}
+
+
+/// Address a frame binds to its source MAC: only ARP says it for sure, IPv4 sources of
+/// frames from a router are other networks' hosts
+pub fn binding(ethernet_packet: &EthernetPacket) -> Option<Ipv4Addr> {
+    if ethernet_packet.get_ethertype() != EtherTypes::Arp {
+        return None;
+    }
+    let arp_packet = ArpPacket::new(ethernet_packet.payload())?;
+    let ip = arp_packet.get_sender_proto_addr();
+    // Probes carry no address, proxies answer for others
+    (arp_packet.get_sender_hw_addr() == ethernet_packet.get_source() && !ip.is_unspecified()).then_some(ip)
+}
+
+/// Source and TTL of a SYN or SYN-ACK
+fn syn(ethernet_packet: &EthernetPacket) -> Option<(Ipv4Addr, u8)> {
+    if ethernet_packet.get_ethertype() != EtherTypes::Ipv4 {
+        return None;
+    }
+    let ipv4_packet = Ipv4Packet::new(ethernet_packet.payload())?;
+    if ipv4_packet.get_next_level_protocol() != IpNextHeaderProtocols::Tcp {
+        return None;
+    }
+    let tcp_packet = TcpPacket::new(ipv4_packet.payload())?;
+    (tcp_packet.get_flags() & TcpFlags::SYN != 0).then(|| (ipv4_packet.get_source(), ipv4_packet.get_ttl()))
+}
+
+/// Reports hosts joining the network or coming back after days, addresses moving to another
+/// MAC or a host changing address, random MACs, and a known MAC sent by a different stack as
+/// when it is spoofed. Hosts seen during the learning period of a first start are the baseline
+pub async fn handle_asset_changes(ethernet_packet: &EthernetPacket<'_>, graph: &Arc<Mutex<NetworkGraph>>, reporter: &WsReporter, local_mac: MacAddr) {
+    let mac = ethernet_packet.get_source();
+    if mac.0 & 0x01 != 0 || mac == local_mac {
+        return;
+    }
+    let (vendor, hostname) = {
+        let g = graph.lock().await;
+        let Some(node) = g.node(mac) else {
+            return;
+        };
+        if node.node_type != NodeType::Physical {
+            return;
+        }
+        (node.vendor.clone(), node.hostname.clone())
+    };
+
+    let (changes, flood) = {
+        let mut tracker = TRACKER.lock().await;
+        let learning = tracker.learning();
+        let mut changes = tracker.observe(mac, binding(ethernet_packet), syn(ethernet_packet));
+        if learning {
+            return;
+        }
+        let flood = tracker.new_devices.close();
+        changes.retain(|change| {
+            !matches!(change.change, AssetChangeKind::NewDevice | AssetChangeKind::RandomizedMac) || tracker.new_devices.admit(mac)
+        });
+        (changes, flood)
+    };
+
+    if let Some((last, suppressed)) = flood {
+        warn!("🆕 {} nuovi host non segnalati singolarmente nell'ultimo minuto", suppressed);
+        alerts::raise(reporter, (last, None), None, NetworkAlert::AssetChange {
+            change: AssetChangeKind::NewDeviceFlood,
+            previous: None,
+            current: Some(format!("{} new hosts", suppressed)),
+            vendor: None,
+            hostname: None,
+        });
+    }
+
+    for change in changes {
+        warn!("🆕 {} ({:?}): {:?} {:?} -> {:?}", mac, vendor, change.change, change.previous, change.current);
+        alerts::raise(reporter, (mac, binding(ethernet_packet)), None, NetworkAlert::AssetChange {
+            change: change.change,
+            previous: change.previous,
+            current: change.current,
+            vendor: vendor.clone(),
+            hostname: hostname.clone(),
+        });
+    }
+}
+
+
+fn load() -> HashMap<MacAddr, KnownDevice> {
+    let text = match fs::read_to_string(KNOWN_FILE) {
+        Ok(text) => text,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return HashMap::new(),
+        Err(e) => {
+            error!("❌ Impossibile leggere gli host noti {}: {}", KNOWN_FILE, e);
+            return HashMap::new();
+        }
+    };
+    match serde_json::from_str::<KnownFile>(&text) {
+        Ok(file) => file.devices.into_iter()
+            .filter_map(|(mac, device)| Some((MacAddr::from_str(&mac).ok()?, device)))
+            .collect(),
+        Err(e) => {
+            error!("❌ Host noti non validi in {} ({}), li apprendo di nuovo", KNOWN_FILE, e);
+            HashMap::new()
+        }
+    }
+}
+
+/// Writes the known hosts every minute if they changed, off the capture path
+pub async fn save_periodically() {
+    loop {
+        sleep(SAVE_INTERVAL).await;
+        let Some(file) = TRACKER.lock().await.take_unsaved() else {
+            continue;
+        };
+        match spawn_blocking(move || write_json_atomic(KNOWN_FILE, &file)).await {
+            Ok(Ok(())) => {}
+            Ok(Err(e)) => error!("❌ Impossibile salvare gli host noti {}: {}", KNOWN_FILE, e),
+            Err(e) => error!("❌ Salvataggio degli host noti interrotto: {}", e),
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn new_devices_past_the_limit_are_summed_up() {
+        let mut limit = NewDeviceLimit::new();
+        let mac = |n: u32| MacAddr::new(0x02, 0, 0, 0, (n >> 8) as u8, n as u8);
+        assert!((0..MAX_NEW_DEVICE_ALERTS).all(|n| limit.admit(mac(n))));
+        assert!(!limit.admit(mac(1000)));
+        assert!(!limit.admit(mac(1001)));
+        assert_eq!(limit.close(), None);
+
+        limit.window_start -= NEW_DEVICE_WINDOW;
+        assert_eq!(limit.close(), Some((mac(1001), 2)));
+        assert!(limit.admit(mac(1002)));
+
+        // A window without excess ends without a summary
+        limit.window_start -= NEW_DEVICE_WINDOW;
+        assert_eq!(limit.close(), None);
+    }
+}
@@ -0,0 +1,440 @@
+use std::{collections::{HashMap, HashSet, VecDeque}, fs, io, net::Ipv4Addr, sync::Arc, time::{Duration, Instant}};
+
+use common::types::{NetworkAlert, RogueDhcpReason};
+use lazy_static::lazy_static;
+use pnet::{packet::{ethernet::EthernetPacket, ip::IpNextHeaderProtocols, ipv4::Ipv4Packet, udp::UdpPacket, Packet}, util::MacAddr};
+use serde::{Deserialize, Serialize};
+use tokio::sync::Mutex;
+use tracing::{error, info, warn};
+
+use crate::{graph::{alerts, types::NetworkGraph}, interfaces::ws::WsReporter, state::write_json_atomic};
+
+const SERVER_PORT: u16 = 67;
+const CLIENT_PORT: u16 = 68;
+const BASELINE_FILE: &str = "state/dhcp_servers.json";
+
+// Servers answering this long after the first one is heard are taken as the network's own
+const LEARNING_PERIOD: Duration = Duration::from_secs(600);
+// Distinct clients discovering within the window that make a starvation attack
+const STARVATION_WINDOW: Duration = Duration::from_secs(10);
+const STARVATION_CLIENTS: usize = 30;
+// Discovers kept at most, so a flood costs no more than this to count
+const MAX_TRACKED_DISCOVERS: usize = 1024;
+// The same finding is reported again after this
+const REPORT_INTERVAL: Duration = Duration::from_secs(3600);
+const STARVATION_REPORT_INTERVAL: Duration = Duration::from_secs(600);
+
+// RFC 2131 §2 fixed fields, then the magic cookie (RFC 2132 §2)
+const OPTIONS_OFFSET: usize = 240;
+const MAGIC_COOKIE: [u8; 4] = [99, 130, 83, 99];
+const BOOTREQUEST: u8 = 1;
+const BOOTREPLY: u8 = 2;
+
+// RFC 2132 options
+const OPTION_PAD: u8 = 0;
+const OPTION_ROUTER: u8 = 3;
+const OPTION_DNS: u8 = 6;
+const OPTION_HOSTNAME: u8 = 12;
+const OPTION_MESSAGE_TYPE: u8 = 53;
+const OPTION_SERVER_ID: u8 = 54;
+const OPTION_PARAMETER_LIST: u8 = 55;
+const OPTION_VENDOR_CLASS: u8 = 60;
+const OPTION_END: u8 = 255;
+
+const DISCOVER: u8 = 1;
+const OFFER: u8 = 2;
+const REQUEST: u8 = 3;
+const ACK: u8 = 5;
+const INFORM: u8 = 8;
+
+
+/// What a DHCP message says, as far as the observer cares
+struct DhcpMessage {
+    op: u8,
+    message_type: u8,
+    client: MacAddr,
+    offered: Ipv4Addr,
+    server: Option<Ipv4Addr>,
+    routers: Vec<Ipv4Addr>,
+    dns: Vec<Ipv4Addr>,
+    hostname: Option<String>,
+    parameters: Option<Vec<u8>>,
+    vendor: Option<String>,
+}
+
+/// A server the network uses and the options it hands out
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct KnownServer {
+    server: Ipv4Addr,
+    routers: Vec<Ipv4Addr>,
+    dns: Vec<Ipv4Addr>,
+}
+
+/// On-disk format of the learned servers
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct Baseline {
+    servers: Vec<KnownServer>,
+}
+
+struct Observer {
+    baseline: Baseline,
+    /// End of the learning period, `None` until a server is first heard
+    learning_until: Option<Instant>,
+    learned: bool,
+    /// Recent discovers: when, client hardware address, Ethernet source
+    discovers: VecDeque<(Instant, MacAddr, MacAddr)>,
+    reported: HashMap<(Ipv4Addr, Vec<RogueDhcpReason>), Instant>,
+    starvation_reported: Option<Instant>,
+}
+
+lazy_static! {
+    static ref OBSERVER: Mutex<Observer> = Mutex::new(Observer::new());
+}
+
+impl Observer {
+    /// Servers learned by a previous run are trusted from the start
+    fn new() -> Self {
+        let baseline = load();
+        let learned = !baseline.servers.is_empty();
+        if learned {
+            info!("📚 {} server DHCP noti caricati da {}", baseline.servers.len(), BASELINE_FILE);
+        }
+        Observer { baseline, learning_until: None, learned, discovers: VecDeque::new(), reported: HashMap::new(), starvation_reported: None }
+    }
+
+    fn learning(&mut self) -> bool {
+        if self.learned {
+            return false;
+        }
+        let until = *self.learning_until.get_or_insert_with(|| Instant::now() + LEARNING_PERIOD);
+        if Instant::now() < until {
+            return true;
+        }
+        self.learned = true;
+        info!("📚 Apprendimento dei server DHCP concluso: {:?}", self.baseline.servers.iter().map(|known| known.server).collect::<Vec<_>>());
+        false
+    }
+
+    fn learn(&mut self, server: Ipv4Addr, message: &DhcpMessage) {
+        let mut changed = false;
+        let index = match self.baseline.servers.iter().position(|known| known.server == server) {
+            Some(index) => index,
+            None => {
+                info!("📚 Server DHCP {} appreso", server);
+                self.baseline.servers.push(KnownServer { server, routers: Vec::new(), dns: Vec::new() });
+                changed = true;
+                self.baseline.servers.len() - 1
+            }
+        };
+        let known = &mut self.baseline.servers[index];
+        for (learned, seen) in [(&mut known.routers, &message.routers), (&mut known.dns, &message.dns)] {
+            for address in seen {
+                if !learned.contains(address) {
+                    learned.push(*address);
+                    changed = true;
+                }
+            }
+        }
+        if changed {
+            save(&self.baseline);
+        }
+    }
+
+    /// Why an offer from `server` wasn't one of the network's; empty if it was
+    fn suspicious(&self, server: Ipv4Addr, message: &DhcpMessage) -> Vec<RogueDhcpReason> {
+        let Some(known) = self.baseline.servers.iter().find(|known| known.server == server) else {
+            return vec![RogueDhcpReason::UnknownServer];
+        };
+        let mut reasons = Vec::new();
+        if message.routers.iter().any(|router| !known.routers.contains(router)) {
+            reasons.push(RogueDhcpReason::UnexpectedRouter);
+        }
+        if message.dns.iter().any(|dns| !known.dns.contains(dns)) {
+            reasons.push(RogueDhcpReason::UnexpectedDns);
+        }
+        reasons
+    }
+
+    fn first_report(&mut self, server: Ipv4Addr, reasons: &[RogueDhcpReason]) -> bool {
+        self.reported.retain(|_, at| at.elapsed() < REPORT_INTERVAL);
+        self.reported.insert((server, reasons.to_vec()), Instant::now()).is_none()
+    }
+
+    /// Discovers in the window, distinct clients and the Ethernet source sending most of them
+    fn discovered(&mut self, client: MacAddr, source: MacAddr) -> (usize, usize, usize, MacAddr) {
+        let now = Instant::now();
+        self.discovers.push_back((now, client, source));
+        while self.discovers.len() > MAX_TRACKED_DISCOVERS || self.discovers.front().is_some_and(|(at, _, _)| now.duration_since(*at) > STARVATION_WINDOW) {
+            self.discovers.pop_front();
+        }
+        let clients: HashSet<MacAddr> = self.discovers.iter().map(|(_, client, _)| *client).collect();
+        let mut sources: HashMap<MacAddr, usize> = HashMap::new();
+        for (_, _, source) in &self.discovers {
+            *sources.entry(*source).or_insert(0) += 1;
+        }
+        let busiest = sources.iter().max_by_key(|(_, count)| **count).map_or(source, |(mac, _)| *mac);
+        (self.discovers.len(), clients.len(), sources.len(), busiest)
+    }
+}
+
+
+/// Learns the network's DHCP servers and watches for offers from others or with other
+/// gateways and DNS servers, for starvation floods, and notes on the graph what clients
+/// say about themselves: hostname, parameter request list and vendor class
+pub async fn handle_dhcp_packet(ethernet_packet: &EthernetPacket<'_>, graph: &Arc<Mutex<NetworkGraph>>, reporter: &WsReporter) {
+    let Some(ipv4_packet) = Ipv4Packet::new(ethernet_packet.payload()) else {
+        return;
+    };
+    if ipv4_packet.get_next_level_protocol() != IpNextHeaderProtocols::Udp {
+        return;
+    }
+    let Some(udp_packet) = UdpPacket::new(ipv4_packet.payload()) else {
+        return;
+    };
+    let ports = (udp_packet.get_source(), udp_packet.get_destination());
+    if ports != (CLIENT_PORT, SERVER_PORT) && ports != (SERVER_PORT, CLIENT_PORT) && ports != (SERVER_PORT, SERVER_PORT) {
+        return;
+    }
+    let Some(message) = parse(udp_packet.payload()) else {
+        return;
+    };
+
+    match (message.op, message.message_type) {
+        (BOOTREQUEST, DISCOVER | REQUEST | INFORM) => {
+            describe_client(graph, &message).await;
+            if message.message_type == DISCOVER {
+                check_starvation(ethernet_packet.get_source(), &message, reporter).await;
+            }
+        }
+        (BOOTREPLY, OFFER | ACK) => {
+            let server = message.server.unwrap_or(ipv4_packet.get_source());
+            check_server(ethernet_packet.get_source(), server, &message, reporter).await;
+        }
+        _ => {}
+    }
+}
+
+async fn describe_client(graph: &Arc<Mutex<NetworkGraph>>, message: &DhcpMessage) {
+    let mut graph = graph.lock().await;
+    let Some(node) = graph.get_node_by_mac(message.client) else {
+        return;
+    };
+    if message.hostname.is_some() && node.hostname != message.hostname {
+        info!("🏷️ {} si presenta via DHCP come {:?}", message.client, message.hostname);
+        node.hostname = message.hostname.clone();
+    }
+    if let Some(parameters) = &message.parameters {
+        node.dhcp_fingerprint = Some(parameters.iter().map(u8::to_string).collect::<Vec<_>>().join(","));
+    }
+    if message.vendor.is_some() {
+        node.dhcp_vendor = message.vendor.clone();
+    }
+}
+
+async fn check_server(source_mac: MacAddr, server: Ipv4Addr, message: &DhcpMessage, reporter: &WsReporter) {
+    let mut observer = OBSERVER.lock().await;
+    if observer.learning() {
+        observer.learn(server, message);
+        return;
+    }
+    let reasons = observer.suspicious(server, message);
+    if reasons.is_empty() || !observer.first_report(server, &reasons) {
+        return;
+    }
+    drop(observer);
+
+    warn!("🚨 Server DHCP sospetto {} ({}): {:?}, router {:?}, DNS {:?}", server, source_mac, reasons, message.routers, message.dns);
+    let offered = Some(message.offered).filter(|offered| !offered.is_unspecified());
+    alerts::raise(reporter, (source_mac, Some(server)), Some((message.client, offered)), NetworkAlert::RogueDhcp {
+        server: server.to_string(),
+        offered: message.offered.to_string(),
+        routers: message.routers.iter().map(Ipv4Addr::to_string).collect(),
+        dns: message.dns.iter().map(Ipv4Addr::to_string).collect(),
+        reasons,
+    });
+}
+
+async fn check_starvation(source_mac: MacAddr, message: &DhcpMessage, reporter: &WsReporter) {
+    let mut observer = OBSERVER.lock().await;
+    let (discovers, clients, sources, busiest) = observer.discovered(message.client, source_mac);
+    if clients < STARVATION_CLIENTS || observer.starvation_reported.is_some_and(|at| at.elapsed() < STARVATION_REPORT_INTERVAL) {
+        return;
+    }
+    observer.starvation_reported = Some(Instant::now());
+    drop(observer);
+
+    warn!("🚨 Possibile esaurimento DHCP: {} discover da {} client in {:?}", discovers, clients, STARVATION_WINDOW);
+    alerts::raise(reporter, (busiest, None), None, NetworkAlert::DhcpStarvation { discovers, clients, sources });
+}
+
+
+fn parse(data: &[u8]) -> Option<DhcpMessage> {
+    if data.len() < OPTIONS_OFFSET || data[236..240] != MAGIC_COOKIE {
+        return None;
+    }
+    let address = |at: usize| Ipv4Addr::new(data[at], data[at + 1], data[at + 2], data[at + 3]);
+    let mut message = DhcpMessage {
+        op: data[0],
+        message_type: 0,
+        client: MacAddr::new(data[28], data[29], data[30], data[31], data[32], data[33]),
+        offered: address(16),
+        server: None,
+        routers: Vec::new(),
+        dns: Vec::new(),
+        hostname: None,
+        parameters: None,
+        vendor: None,
+    };
+
+    let mut offset = OPTIONS_OFFSET;
+    while let Some(&code) = data.get(offset) {
+        match code {
+            OPTION_END => break,
+            OPTION_PAD => {
+                offset += 1;
+                continue;
+            }
+            _ => {}
+        }
+        let len = *data.get(offset + 1)? as usize;
+        let value = data.get(offset + 2..offset + 2 + len)?;
+        let addresses = || value.chunks_exact(4).map(|o| Ipv4Addr::new(o[0], o[1], o[2], o[3])).collect::<Vec<_>>();
+        let text = || Some(String::from_utf8_lossy(value).trim_end_matches('\0').to_string()).filter(|text| !text.is_empty());
+        match code {
+            OPTION_MESSAGE_TYPE => message.message_type = *value.first()?,
+            OPTION_SERVER_ID => message.server = addresses().first().copied(),
+            OPTION_ROUTER => message.routers = addresses(),
+            OPTION_DNS => message.dns = addresses(),
+            OPTION_HOSTNAME => message.hostname = text(),
+            OPTION_PARAMETER_LIST => message.parameters = Some(value.to_vec()),
+            OPTION_VENDOR_CLASS => message.vendor = text(),
+            _ => {}
+        }
+        offset += 2 + len;
+    }
+    Some(message)
+}
+
+fn load() -> Baseline {
+    match fs::read_to_string(BASELINE_FILE) {
+        Ok(text) => serde_json::from_str(&text).unwrap_or_else(|e| {
+            error!("❌ Server DHCP noti non validi in {} ({}), li apprendo di nuovo", BASELINE_FILE, e);
+            Baseline::default()
+        }),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Baseline::default(),
+        Err(e) => {
+            error!("❌ Impossibile leggere i server DHCP noti {}: {}", BASELINE_FILE, e);
+            Baseline::default()
+        }
+    }
+}
+
+fn save(baseline: &Baseline) {
+    if let Err(e) = write_json_atomic(BASELINE_FILE, baseline) {
+        error!("❌ Impossibile salvare i server DHCP noti {}: {}", BASELINE_FILE, e);
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SERVER: Ipv4Addr = Ipv4Addr::new(192, 168, 1, 1);
+
+    /// BOOTP header with the magic cookie, followed by `options`
+    fn message(op: u8, options: &[u8]) -> Vec<u8> {
+        let mut data = vec![0u8; OPTIONS_OFFSET];
+        data[0] = op;
+        data[16..20].copy_from_slice(&[192, 168, 1, 23]);
+        data[28..34].copy_from_slice(&[0x00, 0x1b, 0x63, 0x84, 0x45, 0xe6]);
+        data[236..240].copy_from_slice(&MAGIC_COOKIE);
+        data.extend_from_slice(options);
+        data
+    }
+
+    fn offer() -> Vec<u8> {
+        message(BOOTREPLY, &[
+            OPTION_MESSAGE_TYPE, 1, OFFER,
+            OPTION_SERVER_ID, 4, 192, 168, 1, 1,
+            OPTION_PAD, OPTION_PAD,
+            OPTION_ROUTER, 4, 192, 168, 1, 1,
+            OPTION_DNS, 8, 1, 1, 1, 1, 8, 8, 8, 8,
+            51, 4, 0, 1, 81, 128,
+            OPTION_END,
+            // Padding after the end option is never read
+            OPTION_DNS, 4, 6, 6, 6, 6,
+        ])
+    }
+
+    fn observer(servers: Vec<KnownServer>) -> Observer {
+        Observer { baseline: Baseline { servers }, learning_until: None, learned: true, discovers: VecDeque::new(), reported: HashMap::new(), starvation_reported: None }
+    }
+
+    #[test]
+    fn offer_options() {
+        let offer = parse(&offer()).unwrap();
+        assert_eq!((offer.op, offer.message_type), (BOOTREPLY, OFFER));
+        assert_eq!(offer.client, MacAddr::new(0x00, 0x1b, 0x63, 0x84, 0x45, 0xe6));
+        assert_eq!(offer.offered, Ipv4Addr::new(192, 168, 1, 23));
+        assert_eq!(offer.server, Some(SERVER));
+        assert_eq!(offer.routers, [SERVER]);
+        assert_eq!(offer.dns, [Ipv4Addr::new(1, 1, 1, 1), Ipv4Addr::new(8, 8, 8, 8)]);
+    }
+
+    #[test]
+    fn client_options() {
+        let mut options = vec![OPTION_MESSAGE_TYPE, 1, REQUEST, OPTION_HOSTNAME, 9];
+        options.extend_from_slice(b"DESKTOP\0\0");
+        options.extend_from_slice(&[OPTION_PARAMETER_LIST, 4, 1, 3, 6, 15, OPTION_VENDOR_CLASS, 8]);
+        options.extend_from_slice(b"MSFT 5.0");
+        // No end option: the options just stop with the packet
+        let request = parse(&message(BOOTREQUEST, &options)).unwrap();
+        assert_eq!(request.message_type, REQUEST);
+        assert_eq!(request.hostname.as_deref(), Some("DESKTOP"));
+        assert_eq!(request.parameters.as_deref(), Some(&[1, 3, 6, 15][..]));
+        assert_eq!(request.vendor.as_deref(), Some("MSFT 5.0"));
+
+        assert_eq!(parse(&message(BOOTREQUEST, &[OPTION_HOSTNAME, 1, 0])).unwrap().hostname, None);
+        // A message type without its value makes the message unusable
+        assert!(parse(&message(BOOTREQUEST, &[OPTION_MESSAGE_TYPE, 0])).is_none());
+    }
+
+    #[test]
+    fn truncated_messages_do_not_panic() {
+        let offer = offer();
+        for cut in 0..OPTIONS_OFFSET {
+            assert!(parse(&offer[..cut]).is_none());
+        }
+        for cut in OPTIONS_OFFSET..offer.len() {
+            let _ = parse(&offer[..cut]);
+        }
+        // An option running past the end of the packet, and a server id shorter than an address
+        assert!(parse(&message(BOOTREPLY, &[OPTION_DNS, 8, 1, 1, 1, 1])).is_none());
+        assert_eq!(parse(&message(BOOTREPLY, &[OPTION_SERVER_ID, 2, 10, 0])).unwrap().server, None);
+
+        let mut wrong_cookie = offer.clone();
+        wrong_cookie[239] = 0;
+        assert!(parse(&wrong_cookie).is_none());
+    }
+
+    #[test]
+    fn offers_are_checked_against_the_baseline() {
+        let observer = observer(vec![KnownServer { server: SERVER, routers: vec![SERVER], dns: vec![Ipv4Addr::new(1, 1, 1, 1)] }]);
+        let offer = parse(&offer()).unwrap();
+
+        assert_eq!(observer.suspicious(SERVER, &offer), [RogueDhcpReason::UnexpectedDns]);
+        assert_eq!(observer.suspicious(Ipv4Addr::new(192, 168, 1, 66), &offer), [RogueDhcpReason::UnknownServer]);
+    }
+
+    #[test]
+    fn discovers_are_counted_per_client_and_source() {
+        let mut observer = observer(Vec::new());
+        let attacker = MacAddr::new(0x02, 0, 0, 0, 0, 0x66);
+        for i in 0..40u8 {
+            observer.discovered(MacAddr::new(0x02, 0x42, 0, 0, 0, i), attacker);
+        }
+        let laptop = MacAddr::new(0x3c, 0x22, 0xfb, 0, 0, 1);
+        assert_eq!(observer.discovered(laptop, laptop), (41, 41, 2, attacker));
+    }
+}
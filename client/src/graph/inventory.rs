@@ -0,0 +1,287 @@
+use std::{collections::HashMap, net::Ipv4Addr, sync::Arc, time::{Duration, SystemTime, UNIX_EPOCH}};
+
+use common::types::{Asset, AssetInventoryPayload, DataType, PayloadType, PriorityLevel};
+use lazy_static::lazy_static;
+use pnet::{packet::{ethernet::{EtherTypes, EthernetPacket}, ip::IpNextHeaderProtocols, ipv4::{Ipv4Flags, Ipv4Packet}, tcp::{TcpFlags, TcpOptionNumbers, TcpPacket}, udp::UdpPacket, Packet}, util::MacAddr};
+use serde::Deserialize;
+use tokio::{sync::Mutex, time::sleep};
+use tracing::{error, info};
+
+use crate::{graph::{changes, types::{NetworkGraph, NodeType}, utils::initial_ttl}, honeypot::{persona::{personalities, OptionKind}, services::udp::{mdns::MDNS_PORT, message::{self, TYPE_A}, nbns::{self, NBNS_PORT}}}, interfaces::ws::WsReporter};
+
+// Built into the binary, so vendors resolve whatever directory the client runs from
+const OUI_DATABASE: &str = include_str!("oui.json");
+const REPORT_INTERVAL: Duration = Duration::from_secs(300);
+// Replies from a well-known port to an ephemeral one come from a UDP service
+const EPHEMERAL_PORTS: u16 = 1024;
+
+
+lazy_static! {
+    /// Vendors by MAC prefix (`XX:XX:XX`)
+    static ref VENDORS: HashMap<String, String> = load();
+}
+
+/// Format of the OUI database
+#[derive(Debug, Deserialize)]
+struct DatabaseFile {
+    vendors: HashMap<String, String>,
+}
+
+fn load() -> HashMap<String, String> {
+    match serde_json::from_str::<DatabaseFile>(OUI_DATABASE) {
+        Ok(file) => file.vendors,
+        Err(e) => {
+            error!("❌ Database OUI non valido: {}", e);
+            HashMap::new()
+        }
+    }
+}
+
+/// Manufacturer of a MAC address; group addresses have none
+pub fn vendor_of(mac: MacAddr) -> Option<String> {
+    if mac.0 & 0x01 != 0 {
+        return None;
+    }
+    VENDORS.get(&format!("{:02X}:{:02X}:{:02X}", mac.0, mac.1, mac.2)).cloned()
+}
+
+
+/// Updates the inventory with what a frame tells about its endpoints: traffic, when they
+/// were last seen, their address from ARP, the services they answer on, their OS from their
+/// SYNs and the names they claim
+pub fn observe(graph: &mut NetworkGraph, ethernet_packet: &EthernetPacket) {
+    let now = SystemTime::now();
+    let bytes = ethernet_packet.packet().len() as u64;
+    if let Some(node) = graph.get_node_by_mac(ethernet_packet.get_source()) {
+        node.last_seen = now;
+        node.traffic.packets_sent += 1;
+        node.traffic.bytes_sent += bytes;
+    }
+    if let Some(node) = graph.get_node_by_mac(ethernet_packet.get_destination()) {
+        node.traffic.packets_received += 1;
+        node.traffic.bytes_received += bytes;
+    }
+    if let Some(ip) = changes::binding(ethernet_packet) {
+        rebind(graph, ethernet_packet.get_source(), ip);
+    }
+
+    if ethernet_packet.get_ethertype() != EtherTypes::Ipv4 {
+        return;
+    }
+    let Some(ipv4_packet) = Ipv4Packet::new(ethernet_packet.payload()) else {
+        return;
+    };
+    let Some(node) = graph.get_node_by_mac(ethernet_packet.get_source()) else {
+        return;
+    };
+    match ipv4_packet.get_next_level_protocol() {
+        IpNextHeaderProtocols::Tcp => {
+            let Some(tcp_packet) = TcpPacket::new(ipv4_packet.payload()) else {
+                return;
+            };
+            let flags = tcp_packet.get_flags();
+            if flags & TcpFlags::SYN == 0 {
+                return;
+            }
+            if flags & TcpFlags::ACK != 0 {
+                node.tcp_services.insert(tcp_packet.get_source());
+            }
+            let (signature, guess) = fingerprint(&ipv4_packet, &tcp_packet);
+            if node.os_fingerprint.as_ref() != Some(&signature) {
+                info!("🔎 {} firma TCP {} ({:?})", node.mac_address, signature, guess);
+                node.os_fingerprint = Some(signature);
+                node.os_guess = guess;
+            }
+        }
+        IpNextHeaderProtocols::Udp => {
+            let Some(udp_packet) = UdpPacket::new(ipv4_packet.payload()) else {
+                return;
+            };
+            let (source, destination) = (udp_packet.get_source(), udp_packet.get_destination());
+            if source < EPHEMERAL_PORTS && destination >= EPHEMERAL_PORTS {
+                node.udp_services.insert(source);
+            }
+            if node.hostname.is_none() && let Some(name) = claimed_name(&ipv4_packet, &udp_packet) {
+                info!("🏷️ {} si presenta come {}", node.mac_address, name);
+                node.hostname = Some(name);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Moves `ip` to the host that announced it over ARP: the address a node was first seen with
+/// goes stale when DHCP hands it out again. The decoys' addresses are the allocator's to manage
+fn rebind(graph: &mut NetworkGraph, mac: MacAddr, ip: Ipv4Addr) {
+    let Some(node) = graph.get_node_by_mac(mac) else {
+        return;
+    };
+    if node.node_type == NodeType::Virtual || node.ipv4_address == Some(ip) {
+        return;
+    }
+    info!("🔁 {} ora ha l'indirizzo {} (era {:?})", mac, ip, node.ipv4_address);
+    node.ipv4_address = Some(ip);
+
+    let holders: Vec<MacAddr> = graph.nodes()
+        .filter(|other| other.mac_address != mac && other.node_type != NodeType::Virtual && other.ipv4_address == Some(ip))
+        .map(|other| other.mac_address)
+        .collect();
+    for holder in holders {
+        if let Some(node) = graph.get_node_by_mac(holder) {
+            node.ipv4_address = None;
+        }
+    }
+}
+
+/// Name a host gives for its own address in an mDNS or NetBIOS answer
+fn claimed_name(ipv4_packet: &Ipv4Packet, udp_packet: &UdpPacket) -> Option<String> {
+    let own = Some(ipv4_packet.get_source());
+    match udp_packet.get_source() {
+        MDNS_PORT => message::parse_answers(udp_packet.payload())?.into_iter()
+            .find(|answer| answer.rtype == TYPE_A && answer.address == own)
+            .map(|answer| answer.name.strip_suffix(".local").unwrap_or(&answer.name).to_string()),
+        NBNS_PORT => nbns::parse_answer(udp_packet.payload())
+            .filter(|(_, address)| *address == own)
+            .map(|(name, _)| name),
+        _ => None,
+    }
+}
+
+/// p0f-style signature of a SYN or SYN-ACK (`ittl:mss:window,scale:layout:quirks`) and the
+/// personality whose stack sends the same one, if any
+fn fingerprint(ipv4_packet: &Ipv4Packet, tcp_packet: &TcpPacket) -> (String, Option<String>) {
+    let ttl = ipv4_packet.get_ttl();
+    let initial_ttl = initial_ttl(ttl);
+    let dont_fragment = ipv4_packet.get_flags() & Ipv4Flags::DontFragment != 0;
+
+    let (mut mss, mut wscale) = (None, None);
+    let mut layout = Vec::new();
+    let mut kinds = Vec::new();
+    for option in tcp_packet.get_options_iter() {
+        let data = option.payload();
+        let (name, kind) = match option.get_number() {
+            TcpOptionNumbers::EOL => ("eol", None),
+            TcpOptionNumbers::NOP => ("nop", Some(OptionKind::Nop)),
+            TcpOptionNumbers::MSS => {
+                mss = (data.len() == 2).then(|| u16::from_be_bytes([data[0], data[1]]));
+                ("mss", Some(OptionKind::Mss))
+            }
+            TcpOptionNumbers::WSCALE => {
+                wscale = data.first().copied();
+                ("ws", Some(OptionKind::Wscale))
+            }
+            TcpOptionNumbers::SACK_PERMITTED => ("sok", Some(OptionKind::Sack)),
+            TcpOptionNumbers::TIMESTAMPS => ("ts", Some(OptionKind::Timestamp)),
+            _ => ("?", None),
+        };
+        layout.push(name);
+        kinds.extend(kind);
+    }
+
+    let window = tcp_packet.get_window();
+    let text = |value: Option<String>| value.unwrap_or_else(|| "*".to_string());
+    let signature = format!("{}:{}:{},{}:{}:{}", initial_ttl, text(mss.map(|mss| mss.to_string())), window,
+        text(wscale.map(|wscale| wscale.to_string())), layout.join(","), if dont_fragment { "df" } else { "" });
+
+    let guess = personalities().iter()
+        .filter(|p| p.ttl == initial_ttl && p.dont_fragment == dont_fragment && p.tcp.options == kinds)
+        .max_by_key(|p| 2 * usize::from(p.tcp.window == window) + usize::from(p.tcp.wscale == wscale))
+        .map(|p| p.name.clone());
+    (signature, guess)
+}
+
+
+fn unix_seconds(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH).map_or(0, |elapsed| elapsed.as_secs())
+}
+
+/// The hosts of the inventory: physical ones and the client's own device, not the decoys
+/// nor broadcast and multicast destinations
+fn assets(graph: &NetworkGraph) -> Vec<Asset> {
+    graph.nodes()
+        .filter(|node| node.node_type != NodeType::Virtual && node.mac_address.0 & 0x01 == 0)
+        .map(|node| Asset {
+            mac_address: node.mac_address.octets(),
+            ipv4_address: node.ipv4_address.map(|ip| ip.to_string()),
+            ipv6_addresses: node.ipv6_addresses.iter().map(|ip| ip.to_string()).collect(),
+            device: node.node_type == NodeType::Device,
+            vendor: node.vendor.clone(),
+            hostname: node.hostname.clone(),
+            os_fingerprint: node.os_fingerprint.clone(),
+            os_guess: node.os_guess.clone(),
+            dhcp_fingerprint: node.dhcp_fingerprint.clone(),
+            dhcp_vendor: node.dhcp_vendor.clone(),
+            tcp_services: node.tcp_services.iter().copied().collect(),
+            udp_services: node.udp_services.iter().copied().collect(),
+            first_seen: unix_seconds(node.first_seen),
+            last_seen: unix_seconds(node.last_seen),
+            packets_sent: node.traffic.packets_sent,
+            bytes_sent: node.traffic.bytes_sent,
+            packets_received: node.traffic.packets_received,
+            bytes_received: node.traffic.bytes_received,
+        })
+        .collect()
+}
+
+/// Sends the inventory to the server every few minutes
+pub async fn report_periodically(graph: Arc<Mutex<NetworkGraph>>, reporter: WsReporter, local_mac: MacAddr) {
+    loop {
+        sleep(REPORT_INTERVAL).await;
+        let assets = assets(&*graph.lock().await);
+        if assets.is_empty() {
+            continue;
+        }
+        info!("📦 Invio inventario di {} host al server", assets.len());
+        reporter.send(DataType::AssetInventory, PriorityLevel::Low, local_mac, PayloadType::AssetInventory(AssetInventoryPayload { assets })).await;
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use pnet::packet::{arp::{ArpHardwareTypes, ArpOperations, MutableArpPacket}, ethernet::MutableEthernetPacket, MutablePacket};
+
+    use super::*;
+    use crate::graph::types::NetworkNode;
+
+    /// ARP reply from `mac` saying it has `ip`
+    fn arp_reply(mac: MacAddr, ip: Ipv4Addr) -> Vec<u8> {
+        let mut frame = vec![0u8; 42];
+        let mut ethernet = MutableEthernetPacket::new(&mut frame).unwrap();
+        ethernet.set_source(mac);
+        ethernet.set_destination(MacAddr::broadcast());
+        ethernet.set_ethertype(EtherTypes::Arp);
+        let mut arp = MutableArpPacket::new(ethernet.payload_mut()).unwrap();
+        arp.set_hardware_type(ArpHardwareTypes::Ethernet);
+        arp.set_protocol_type(EtherTypes::Ipv4);
+        arp.set_hw_addr_len(6);
+        arp.set_proto_addr_len(4);
+        arp.set_operation(ArpOperations::Reply);
+        arp.set_sender_hw_addr(mac);
+        arp.set_sender_proto_addr(ip);
+        frame
+    }
+
+    #[test]
+    fn arp_moves_addresses_between_hosts() {
+        let (laptop, printer, decoy) = (MacAddr::new(0, 0x11, 0, 0, 0, 1), MacAddr::new(0, 0x11, 0, 0, 0, 2), MacAddr::new(0, 0x11, 0, 0, 0, 3));
+        let mut graph = NetworkGraph::default();
+        graph.add_node(NetworkNode::new(laptop, Some(Ipv4Addr::new(10, 0, 0, 9)), NodeType::Physical));
+        graph.add_node(NetworkNode::new(printer, Some(Ipv4Addr::new(10, 0, 0, 7)), NodeType::Physical));
+        graph.add_node(NetworkNode::new(decoy, Some(Ipv4Addr::new(10, 0, 0, 20)), NodeType::Virtual));
+
+        // The printer's lease went to the laptop
+        let frame = arp_reply(laptop, Ipv4Addr::new(10, 0, 0, 7));
+        observe(&mut graph, &EthernetPacket::new(&frame).unwrap());
+        assert_eq!(graph.node(laptop).unwrap().ipv4_address, Some(Ipv4Addr::new(10, 0, 0, 7)));
+        assert_eq!(graph.node(printer).unwrap().ipv4_address, None);
+
+        // Decoys keep the address the allocator gave them
+        let frame = arp_reply(decoy, Ipv4Addr::new(10, 0, 0, 21));
+        observe(&mut graph, &EthernetPacket::new(&frame).unwrap());
+        assert_eq!(graph.node(decoy).unwrap().ipv4_address, Some(Ipv4Addr::new(10, 0, 0, 20)));
+
+        let assets = assets(&graph);
+        assert_eq!(assets.iter().find(|asset| asset.mac_address == laptop.octets()).unwrap().ipv4_address.as_deref(), Some("10.0.0.7"));
+    }
+}
@@ -1,2 +1,8 @@
+pub mod alerts;
+pub mod analytics;
+pub mod changes;
+pub mod dhcp;
+pub mod inventory;
+pub mod poisoning;
 pub mod types;
 pub mod utils;
\ No newline at end of file
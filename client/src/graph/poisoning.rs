@@ -0,0 +1,220 @@
+use std::{collections::HashMap, env, net::{Ipv4Addr, SocketAddrV4}, sync::Arc, time::{Duration, Instant}};
+
+use common::types::{NameProtocol, NetworkAlert};
+use lazy_static::lazy_static;
+use pnet::{datalink::DataLinkSender, packet::{ethernet::EthernetPacket, ip::IpNextHeaderProtocols, ipv4::Ipv4Packet, udp::UdpPacket, Packet}, util::MacAddr};
+use rand::{seq::IndexedRandom, Rng};
+use tokio::{sync::Mutex, time::sleep};
+use tracing::{info, warn};
+
+use crate::{graph::{alerts, types::NetworkGraph}, honeypot::{discovery::{decoys, multicast_mac, Decoy, LLMNR_GROUP, MDNS_GROUP, MDNS_TTL}, services::{udp::{llmnr::LLMNR_PORT, mdns::MDNS_PORT, message::{self, TYPE_A}, nbns::{self, NBNS_PORT}}}}, interfaces::{sender::send_udp_datagram, ws::WsReporter}};
+
+/// Seconds between two rounds of bait queries, 0 disables them
+const INTERVAL_VARIABLE: &str = "HONEYPOT_BAIT_INTERVAL";
+const DEFAULT_INTERVAL: Duration = Duration::from_secs(300);
+const JITTER: Duration = Duration::from_secs(60);
+// How long an answer to a bait is attributed to it
+const BAIT_LIFETIME: Duration = Duration::from_secs(600);
+// The same responder answering the same name over the same protocol is reported again after this
+const REPORT_INTERVAL: Duration = Duration::from_secs(3600);
+// Windows sends its LLMNR queries link-local only
+const LLMNR_TTL: u8 = 1;
+// Workstation suffix, what a client resolving a server name asks for
+const NBNS_SUFFIX: u8 = 0x20;
+const WPAD: &str = "wpad";
+
+// Names a user could mistype or a stale shortcut could point to, numbered so no host owns them
+const BAIT_PREFIXES: [&str; 10] = ["FILESRV", "FILESERVER", "PRINTSRV", "BACKUP", "NAS", "SHARE", "INTRANET", "SCANNER", "ARCHIVE", "SQLSRV"];
+
+
+lazy_static! {
+    static ref BAITS: Mutex<HashMap<(&'static str, String), Bait>> = Mutex::new(HashMap::new());
+    static ref REPORTED: Mutex<HashMap<(MacAddr, &'static str, String), Instant>> = Mutex::new(HashMap::new());
+}
+
+/// Name a decoy queried that nobody on the link owns
+struct Bait {
+    querier: MacAddr,
+    ip: Ipv4Addr,
+    expires: Instant,
+}
+
+#[derive(Clone, Copy)]
+enum Protocol {
+    Llmnr,
+    Nbns,
+    Mdns,
+}
+
+impl Protocol {
+    fn name(self) -> &'static str {
+        match self {
+            Protocol::Llmnr => "llmnr",
+            Protocol::Nbns => "nbns",
+            Protocol::Mdns => "mdns",
+        }
+    }
+
+    fn alerted(self) -> NameProtocol {
+        match self {
+            Protocol::Llmnr => NameProtocol::Llmnr,
+            Protocol::Nbns => NameProtocol::Nbns,
+            Protocol::Mdns => NameProtocol::Mdns,
+        }
+    }
+
+    fn port(self) -> u16 {
+        match self {
+            Protocol::Llmnr => LLMNR_PORT,
+            Protocol::Nbns => NBNS_PORT,
+            Protocol::Mdns => MDNS_PORT,
+        }
+    }
+}
+
+fn interval() -> Duration {
+    env::var(INTERVAL_VARIABLE).ok()
+        .and_then(|value| value.trim().parse().ok())
+        .map_or(DEFAULT_INTERVAL, Duration::from_secs)
+}
+
+fn bait_name() -> String {
+    let mut rng = rand::rng();
+    let prefix = BAIT_PREFIXES.choose(&mut rng).unwrap_or(&"FILESRV");
+    format!("{}-{:02}{}", prefix, rng.random_range(0..100), rng.random_range(b'A'..=b'Z') as char)
+}
+
+
+/// Has the decoys resolve names nobody owns, over LLMNR, NetBIOS and mDNS, and ask for `wpad`
+/// as Windows does: on a clean link no one answers, so whoever does is poisoning name resolution
+pub async fn query_baits_periodically(graph: Arc<Mutex<NetworkGraph>>, tx: Arc<Mutex<Box<dyn DataLinkSender + Send>>>, broadcast: Ipv4Addr) {
+    let interval = interval();
+    if interval.is_zero() {
+        info!("🎣 Query esca disattivate da {}", INTERVAL_VARIABLE);
+        return;
+    }
+    loop {
+        let jitter = rand::rng().random_range(Duration::ZERO..=JITTER);
+        sleep(interval + jitter).await;
+
+        let decoys = decoys(&graph).await;
+        if decoys.is_empty() {
+            continue;
+        }
+        BAITS.lock().await.retain(|_, bait| Instant::now() < bait.expires);
+
+        let bait = bait_name();
+        for protocol in [Protocol::Llmnr, Protocol::Nbns, Protocol::Mdns] {
+            let querier = querier(&decoys, protocol);
+            let key = (protocol.name(), bait.to_ascii_lowercase());
+            BAITS.lock().await.insert(key, Bait { querier: querier.mac, ip: querier.ip, expires: Instant::now() + BAIT_LIFETIME });
+            send_query(&tx, querier, protocol, &bait, broadcast).await;
+        }
+        for protocol in [Protocol::Llmnr, Protocol::Nbns] {
+            send_query(&tx, querier(&decoys, protocol), protocol, WPAD, broadcast).await;
+        }
+        info!("🎣 Query esca per {} e {} inviate", bait, WPAD);
+    }
+}
+
+/// A decoy whose OS speaks the protocol, any decoy if none does
+fn querier(decoys: &[Decoy], protocol: Protocol) -> &Decoy {
+    let speaking: Vec<&Decoy> = decoys.iter().filter(|decoy| decoy.profile.udp_services.get(protocol.port()).is_some()).collect();
+    let mut rng = rand::rng();
+    speaking.choose(&mut rng).copied().unwrap_or_else(|| &decoys[rng.random_range(0..decoys.len())])
+}
+
+// Sent from the protocol's own port, so answers reach the decoy's responder instead of a closed port
+async fn send_query(tx: &Arc<Mutex<Box<dyn DataLinkSender + Send>>>, decoy: &Decoy, protocol: Protocol, name: &str, broadcast: Ipv4Addr) {
+    let id = match protocol {
+        Protocol::Mdns => 0,
+        _ => rand::random(),
+    };
+    let mut fields = decoy.profile.os.ipv4_fields();
+    let (payload, destination_mac, destination) = match protocol {
+        Protocol::Llmnr => {
+            fields.ttl = LLMNR_TTL;
+            (message::query(id, 0, name, TYPE_A), multicast_mac(LLMNR_GROUP), LLMNR_GROUP)
+        }
+        Protocol::Nbns => (nbns::query(id, name, NBNS_SUFFIX), MacAddr::broadcast(), broadcast),
+        Protocol::Mdns => {
+            fields.ttl = MDNS_TTL;
+            (message::query(id, 0, &format!("{}.local", name.to_ascii_lowercase()), TYPE_A), multicast_mac(MDNS_GROUP), MDNS_GROUP)
+        }
+    };
+    let (local, destination) = (SocketAddrV4::new(decoy.ip, protocol.port()), SocketAddrV4::new(destination, protocol.port()));
+    send_udp_datagram(tx.clone(), decoy.mac, local, destination_mac, destination, fields, &payload).await;
+}
+
+
+/// Name service responses claiming a bait name or `wpad`. Answers to a decoy's bait are raised
+/// against the decoy that asked, `wpad` answers to real hosts against the host they poison
+pub async fn handle_poisoning_packet(ethernet_packet: &EthernetPacket<'_>, graph: &Arc<Mutex<NetworkGraph>>, reporter: &WsReporter) {
+    let Some(ipv4_packet) = Ipv4Packet::new(ethernet_packet.payload()) else {
+        return;
+    };
+    if ipv4_packet.get_next_level_protocol() != IpNextHeaderProtocols::Udp {
+        return;
+    }
+    let Some(udp_packet) = UdpPacket::new(ipv4_packet.payload()) else {
+        return;
+    };
+    let protocol = match udp_packet.get_source() {
+        LLMNR_PORT => Protocol::Llmnr,
+        NBNS_PORT => Protocol::Nbns,
+        MDNS_PORT => Protocol::Mdns,
+        _ => return,
+    };
+    let answers: Vec<(String, Option<Ipv4Addr>)> = match protocol {
+        Protocol::Nbns => nbns::parse_answer(udp_packet.payload()).into_iter().collect(),
+        _ => message::parse_answers(udp_packet.payload()).unwrap_or_default().into_iter()
+            .filter(|answer| answer.rtype == TYPE_A)
+            .map(|answer| (answer.name, answer.address))
+            .collect(),
+    };
+    if answers.is_empty() {
+        return;
+    }
+
+    let source_mac = ethernet_packet.get_source();
+    let decoys = decoys(graph).await;
+    // The decoys' own responders
+    if decoys.iter().any(|decoy| decoy.mac == source_mac) {
+        return;
+    }
+    let source = SocketAddrV4::new(ipv4_packet.get_source(), udp_packet.get_source());
+    let destination = SocketAddrV4::new(ipv4_packet.get_destination(), udp_packet.get_destination());
+
+    for (name, address) in answers {
+        let name = name.to_ascii_lowercase();
+        let name = name.strip_suffix(".local").unwrap_or(&name).to_string();
+        let target = match BAITS.lock().await.get(&(protocol.name(), name.clone())) {
+            Some(bait) if Instant::now() < bait.expires => Some((bait.querier, bait.ip, true)),
+            _ if name == WPAD => Some(match decoys.iter().find(|decoy| decoy.mac == ethernet_packet.get_destination()) {
+                Some(decoy) => (decoy.mac, decoy.ip, true),
+                None => (ethernet_packet.get_destination(), *destination.ip(), false),
+            }),
+            _ => None,
+        };
+        let Some((target_mac, target_ip, bait)) = target else {
+            continue;
+        };
+        if !first_report(source_mac, protocol, &name).await {
+            continue;
+        }
+
+        warn!("🚨 {} ha risposto via {} per {} ({:?}) a {}: possibile avvelenamento dei nomi", source, protocol.name(), name, address, target_ip);
+        alerts::raise(reporter, (source_mac, Some(*source.ip())), Some((target_mac, Some(target_ip))), NetworkAlert::NamePoisoning {
+            protocol: protocol.alerted(),
+            name,
+            answer: address.map(|address| address.to_string()),
+            bait,
+        });
+    }
+}
+
+async fn first_report(responder: MacAddr, protocol: Protocol, name: &str) -> bool {
+    let mut reported = REPORTED.lock().await;
+    reported.retain(|_, at| at.elapsed() < REPORT_INTERVAL);
+    reported.insert((responder, protocol.name(), name.to_string()), Instant::now()).is_none()
+}
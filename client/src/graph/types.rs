@@ -1,7 +1,8 @@
-use pnet::{packet::ethernet::EthernetPacket, util::MacAddr};
-use std::{collections::{HashMap, HashSet}, net::Ipv4Addr, time::SystemTime};
+use petgraph::{stable_graph::{NodeIndex, StableDiGraph}, visit::{EdgeRef, IntoEdgeReferences}};
+use pnet::{packet::{ethernet::{EtherTypes, EthernetPacket}, ip::IpNextHeaderProtocols, ipv4::Ipv4Packet, tcp::TcpPacket, udp::UdpPacket, Packet}, util::MacAddr};
+use std::{collections::{BTreeSet, HashMap}, net::{Ipv4Addr, Ipv6Addr}, sync::Arc, time::SystemTime};
 
-use crate::{ai::anomaly::anomalies::{Anomaly, AnomalyClassification}, graph::utils::{generate_virtual_ip, generate_virtual_ipv6, generate_virtual_mac, get_src_and_dest_ip, get_src_and_dest_protocol}};
+use crate::{ai::anomaly::anomalies::{Anomaly, AnomalyClassification}, honeypot::{ndp::link_local_address, profile::HoneypotProfile}, graph::{inventory::vendor_of, utils::{get_src_and_dest_ip, get_src_and_dest_protocol}}};
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum NodeType {
@@ -14,33 +15,154 @@ pub enum NodeType {
 pub struct NetworkNode {
     pub mac_address: MacAddr,
     pub ipv4_address: Option<Ipv4Addr>,
-    pub ipv6_address: Option<String>,
+    /// Link-local address first, then any SLAAC addresses
+    pub ipv6_addresses: Vec<Ipv6Addr>,
     pub node_type: NodeType,
     pub anomalies: Vec<Anomaly>,
+    pub honeypot: Option<Arc<HoneypotProfile>>,
+    /// Manufacturer of the MAC's OUI
+    pub vendor: Option<String>,
+    /// Name the host gives itself over DHCP, mDNS or NetBIOS
+    pub hostname: Option<String>,
+    /// DHCP parameter request list, comma-separated as fingerprint databases key it
+    pub dhcp_fingerprint: Option<String>,
+    /// DHCP vendor class identifier
+    pub dhcp_vendor: Option<String>,
+    /// p0f-style signature of the host's TCP SYNs and the personality it matches
+    pub os_fingerprint: Option<String>,
+    pub os_guess: Option<String>,
+    /// Ports the host was seen answering on
+    pub tcp_services: BTreeSet<u16>,
+    pub udp_services: BTreeSet<u16>,
+    pub first_seen: SystemTime,
+    pub last_seen: SystemTime,
+    pub traffic: Traffic,
 }
 
-#[derive(Debug, Default)]
+/// Frames and bytes a node sent and received
+#[derive(Debug, Clone, Default)]
+pub struct Traffic {
+    pub packets_sent: u64,
+    pub bytes_sent: u64,
+    pub packets_received: u64,
+    pub bytes_received: u64,
+}
+
+// Beyond this many destination ports a link is a scan, and keeping them all only costs memory
+const MAX_LINK_PORTS: usize = 256;
+
+/// Traffic a node sent to another
+#[derive(Debug, Clone)]
+pub struct Link {
+    pub packets: u64,
+    pub bytes: u64,
+    /// `arp`, `tcp`, `udp`, `icmp`, `ipv6`...
+    pub protocols: BTreeSet<&'static str>,
+    /// TCP and UDP destination ports, up to `MAX_LINK_PORTS`
+    pub ports: BTreeSet<u16>,
+    pub first_seen: SystemTime,
+    pub last_seen: SystemTime,
+}
+
+/// Hosts by MAC, linked in the direction their traffic flows
+#[derive(Debug, Clone, Default)]
 pub struct NetworkGraph {
-    pub nodes: HashMap<MacAddr, NetworkNode>,
-    pub edges: HashMap<MacAddr, HashSet<MacAddr>>,
+    graph: StableDiGraph<NetworkNode, Link>,
+    indices: HashMap<MacAddr, NodeIndex>,
 }
 
 impl NetworkGraph {
     pub fn add_node(&mut self, node: NetworkNode) {
-        self.nodes.entry(node.mac_address).or_insert(node);
+        if !self.indices.contains_key(&node.mac_address) {
+            let mac = node.mac_address;
+            self.indices.insert(mac, self.graph.add_node(node));
+        }
+    }
+
+    /// Accounts a frame from `src_mac` to `dst_mac` on their link, created on the first one
+    pub fn add_connection(&mut self, src_mac: MacAddr, dst_mac: MacAddr, ethernet_packet: &EthernetPacket) {
+        let (Some(&src), Some(&dst)) = (self.indices.get(&src_mac), self.indices.get(&dst_mac)) else {
+            return;
+        };
+        let now = SystemTime::now();
+        let edge = match self.graph.find_edge(src, dst) {
+            Some(edge) => edge,
+            None => self.graph.add_edge(src, dst, Link {
+                packets: 0,
+                bytes: 0,
+                protocols: BTreeSet::new(),
+                ports: BTreeSet::new(),
+                first_seen: now,
+                last_seen: now,
+            }),
+        };
+        let link = &mut self.graph[edge];
+        link.packets += 1;
+        link.bytes += ethernet_packet.packet().len() as u64;
+        link.last_seen = now;
+        let (protocol, port) = protocol_and_port(ethernet_packet);
+        link.protocols.insert(protocol);
+        if let Some(port) = port && link.ports.len() < MAX_LINK_PORTS {
+            link.ports.insert(port);
+        }
+    }
+
+    pub fn nodes(&self) -> impl Iterator<Item = &NetworkNode> {
+        self.graph.node_weights()
+    }
+
+    pub fn node(&self, mac: MacAddr) -> Option<&NetworkNode> {
+        self.indices.get(&mac).map(|&index| &self.graph[index])
+    }
+
+    /// Every link as (sender, receiver, traffic)
+    pub fn links(&self) -> impl Iterator<Item = (MacAddr, MacAddr, &Link)> {
+        self.graph.edge_references()
+            .map(|edge| (self.graph[edge.source()].mac_address, self.graph[edge.target()].mac_address, edge.weight()))
     }
 
-    pub fn add_connection(&mut self, src_mac: MacAddr, dst_mac: MacAddr) {
-        self.edges.entry(src_mac).or_default().insert(dst_mac);
-        self.edges.entry(dst_mac).or_default().insert(src_mac);
+    /// The petgraph graph the analytics run on
+    pub fn petgraph(&self) -> &StableDiGraph<NetworkNode, Link> {
+        &self.graph
     }
 
     pub fn find_by_ip(&self, ip: Ipv4Addr) -> Option<&NetworkNode> {
-        self.nodes.values().find(|n| n.ipv4_address == Some(ip))
+        self.nodes().find(|n| n.ipv4_address == Some(ip))
+    }
+
+    /// MAC of the virtual node owning `ip`
+    pub fn find_virtual_by_ip(&self, ip: Ipv4Addr) -> Option<MacAddr> {
+        self.nodes()
+            .find(|n| n.node_type == NodeType::Virtual && n.ipv4_address == Some(ip))
+            .map(|n| n.mac_address)
+    }
+
+    /// Addresses currently held by virtual nodes
+    pub fn virtual_addresses(&self) -> Vec<(MacAddr, Ipv4Addr)> {
+        self.nodes()
+            .filter(|n| n.node_type == NodeType::Virtual)
+            .filter_map(|n| n.ipv4_address.map(|ip| (n.mac_address, ip)))
+            .collect()
+    }
+
+    /// MAC of the virtual node owning the IPv6 address `ip`
+    pub fn find_virtual_by_ipv6(&self, ip: Ipv6Addr) -> Option<MacAddr> {
+        self.nodes()
+            .find(|n| n.node_type == NodeType::Virtual && n.ipv6_addresses.contains(&ip))
+            .map(|n| n.mac_address)
+    }
+
+    /// MACs of every virtual node
+    pub fn virtual_macs(&self) -> Vec<MacAddr> {
+        self.nodes()
+            .filter(|n| n.node_type == NodeType::Virtual)
+            .map(|n| n.mac_address)
+            .collect()
     }
 
     pub fn get_node_by_mac(&mut self, mac: MacAddr) -> Option<&mut NetworkNode> {
-        self.nodes.get_mut(&mac)
+        let index = *self.indices.get(&mac)?;
+        self.graph.node_weight_mut(index)
     }
 
     pub async fn add_nodes_and_connections<'a>(
@@ -57,49 +179,40 @@ impl NetworkGraph {
         let dst_mac = ethernet_packet.get_destination();
 
         if self.get_node_by_mac(src_mac).is_none(){
-            self.add_node(NetworkNode {
-                mac_address: src_mac,
-                ipv4_address: src_ip,
-                ipv6_address: None,
-                node_type: if src_mac == local_mac { NodeType::Device } else { NodeType::Physical },
-                anomalies: Vec::new(),
-            });
+            self.add_node(NetworkNode::new(src_mac, src_ip, if src_mac == local_mac { NodeType::Device } else { NodeType::Physical }));
         }
 
         if self.get_node_by_mac(dst_mac).is_none() {
-            self.add_node(NetworkNode {
-                mac_address: dst_mac,
-                ipv4_address: dst_ip,
-                ipv6_address: None,
-                node_type: if dst_mac == local_mac { NodeType::Device } else { NodeType::Physical },
-                anomalies: Vec::new(),
-            });
+            self.add_node(NetworkNode::new(dst_mac, dst_ip, if dst_mac == local_mac { NodeType::Device } else { NodeType::Physical }));
         }
 
-        self.add_connection(src_mac, dst_mac);
+        self.add_connection(src_mac, dst_mac, ethernet_packet);
 
-        return (self.nodes.get(&src_mac).expect("Failed to get source node"), self.nodes.get(&dst_mac).expect("Failed to get destination node"));
+        return (self.node(src_mac).expect("Failed to get source node"), self.node(dst_mac).expect("Failed to get destination node"));
     }
 
 
-    pub fn add_virtual_node(&mut self) -> MacAddr {
-        let assigned_ip = generate_virtual_ip(self);
-        let assigned_ipv6 = generate_virtual_ipv6();
-        let assigned_mac = generate_virtual_mac();
-
+    pub fn add_virtual_node(&mut self, assigned_mac: MacAddr, assigned_ip: Ipv4Addr, profile: HoneypotProfile) -> MacAddr {
         let node = NetworkNode {
-            mac_address: assigned_mac,
-            ipv4_address: Some(assigned_ip),
-            ipv6_address: Some(assigned_ipv6),
-            node_type: NodeType::Virtual,
-            anomalies: Vec::new(),
+            ipv6_addresses: vec![link_local_address(assigned_mac)],
+            honeypot: Some(Arc::new(profile)),
+            ..NetworkNode::new(assigned_mac, Some(assigned_ip), NodeType::Virtual)
         };
 
-        self.nodes.insert(assigned_mac, node);
+        match self.get_node_by_mac(assigned_mac) {
+            Some(existing) => *existing = node,
+            None => self.add_node(node),
+        }
 
         assigned_mac
     }
 
+    /// Removes a node and every connection it had
+    pub fn remove_node(&mut self, mac: MacAddr) -> Option<NetworkNode> {
+        let index = self.indices.remove(&mac)?;
+        self.graph.remove_node(index)
+    }
+
     pub fn add_anomaly(&mut self, ethernet_packet: &EthernetPacket, classification: AnomalyClassification) -> usize {
         let (src_ip, dst_ip) = get_src_and_dest_ip(ethernet_packet)
             .map(|(s, d)| (Some(s), Some(d)))
@@ -114,7 +227,7 @@ impl NetworkGraph {
             timestamp: SystemTime::now(),
             classification
         };
-        let node = self.nodes.get_mut(&ethernet_packet.get_source()).unwrap();
+        let node = self.get_node_by_mac(ethernet_packet.get_source()).unwrap();
         node.anomalies.push(anomaly.clone());
 
         node.anomalies.len()
@@ -122,15 +235,58 @@ impl NetworkGraph {
 
     pub fn print_virtual_nodes(&self) {
         println!("\n📌 **Nodi VIRTUALI nel grafo:**");
-        for node in self.nodes.values() {
-            if node.node_type == NodeType::Virtual {
-                println!("⭕️ Nodo Virtuale: MAC={} | IP={:?}", node.mac_address, node.ipv4_address);
+        for node in self.nodes() {
+            if let Some(profile) = &node.honeypot {
+                println!("⭕️ Nodo Virtuale: MAC={} | IP={:?} | IPv6={:?} | OS={} | Porte={:?} | UDP={:?}", node.mac_address, node.ipv4_address, node.ipv6_addresses, profile.os.name, profile.services.ports(), profile.udp_services.ports());
             }
         }
     }
 }
 
+/// Protocol a frame carries and, for TCP and UDP, its destination port
+fn protocol_and_port(ethernet_packet: &EthernetPacket) -> (&'static str, Option<u16>) {
+    match ethernet_packet.get_ethertype() {
+        EtherTypes::Arp => ("arp", None),
+        EtherTypes::Ipv6 => ("ipv6", None),
+        EtherTypes::Ipv4 => {
+            let Some(ipv4_packet) = Ipv4Packet::new(ethernet_packet.payload()) else {
+                return ("ipv4", None);
+            };
+            match ipv4_packet.get_next_level_protocol() {
+                IpNextHeaderProtocols::Tcp => ("tcp", TcpPacket::new(ipv4_packet.payload()).map(|tcp| tcp.get_destination())),
+                IpNextHeaderProtocols::Udp => ("udp", UdpPacket::new(ipv4_packet.payload()).map(|udp| udp.get_destination())),
+                IpNextHeaderProtocols::Icmp => ("icmp", None),
+                _ => ("ipv4", None),
+            }
+        }
+        _ => ("other", None),
+    }
+}
+
 impl NetworkNode {
+    /// Node first seen now, with nothing learned about it beyond its vendor
+    pub fn new(mac_address: MacAddr, ipv4_address: Option<Ipv4Addr>, node_type: NodeType) -> Self {
+        let now = SystemTime::now();
+        NetworkNode {
+            mac_address,
+            ipv4_address,
+            ipv6_addresses: Vec::new(),
+            node_type,
+            anomalies: Vec::new(),
+            honeypot: None,
+            vendor: vendor_of(mac_address),
+            hostname: None,
+            dhcp_fingerprint: None,
+            dhcp_vendor: None,
+            os_fingerprint: None,
+            os_guess: None,
+            tcp_services: BTreeSet::new(),
+            udp_services: BTreeSet::new(),
+            first_seen: now,
+            last_seen: now,
+            traffic: Traffic::default(),
+        }
+    }
 
     pub fn get_anomaly_count(&self) -> usize {
         self.anomalies.len()
@@ -3,7 +3,15 @@ use std::{net::Ipv4Addr, str::FromStr};
 use pnet::{datalink::NetworkInterface, packet::{arp::ArpPacket, ethernet::{EtherTypes, EthernetPacket}, ipv4::Ipv4Packet, Packet}, util::MacAddr};
 use pnet::datalink;
 use rand::Rng;
-use crate::graph::types::NetworkGraph;
+
+// Initial TTLs stacks start from
+const INITIAL_TTLS: [u8; 4] = [32, 64, 128, 255];
+
+
+/// TTL the sender's stack started from, the observed TTL rounded up to a common initial one
+pub(crate) fn initial_ttl(ttl: u8) -> u8 {
+    INITIAL_TTLS.into_iter().find(|initial| ttl <= *initial).unwrap_or(u8::MAX)
+}
 
 pub fn get_src_and_dest_ip(packet: &EthernetPacket) -> Option<(Ipv4Addr, Ipv4Addr)> {
     match packet.get_ethertype() {
@@ -57,37 +65,8 @@ pub fn get_primary_interface() -> Option<NetworkInterface> {
 }
 
 
-pub fn generate_virtual_ip(graph: &NetworkGraph) -> Ipv4Addr {
-    let mut rng = rand::rng();
-    let mut last_octet = rng.random_range(100..115);
-
-    let base_ip = [192, 168, 1];
-
-    loop {
-        let new_ip = Ipv4Addr::new(base_ip[0], base_ip[1], base_ip[2], last_octet);
-
-        if !graph.nodes.values().any(|node| node.ipv4_address == Some(new_ip)) {
-            return new_ip;
-        }
-
-        last_octet += 1;
-        if last_octet > 253 {
-            panic!("No IP address available!");
-        }
-    }
-}
-
-pub fn generate_virtual_ipv6() -> String {
-    let mut rng = rand::rng();
-    let last_segment = rng.random_range(100..130);
-    let base_ip = "fe80::1000:".to_string(); // Link-local address base
-    
-    format!("{}{:x}", base_ip, last_segment) // Concatenate to create a valid IPv6 address
-}
-
-
-
-pub fn generate_virtual_mac() -> MacAddr {
+/// Random MAC of the given vendor prefix (`XX:XX:XX`), or of one of the usual vendors
+pub fn generate_virtual_mac(vendor: Option<&str>) -> MacAddr {
     let mac_prefixes = vec![
         "00:1A:2B", // Cisco
         "34:56:78", // Samsung
@@ -98,7 +77,7 @@ pub fn generate_virtual_mac() -> MacAddr {
     ];
 
     let mut rng = rand::rng();
-    let prefix = mac_prefixes[rng.random_range(0..mac_prefixes.len())];
+    let prefix = vendor.unwrap_or_else(|| mac_prefixes[rng.random_range(0..mac_prefixes.len())]);
     
     let suffix = [
         rng.random_range(0..=255),
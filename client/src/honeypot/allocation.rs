@@ -0,0 +1,443 @@
+use std::{collections::{HashMap, HashSet}, env, fs, net::Ipv4Addr, ops::RangeInclusive, sync::Arc, time::{Duration, Instant}};
+
+use pnet::{datalink::{DataLinkSender, NetworkInterface}, ipnetwork::IpNetwork, packet::{arp::ArpPacket, ethernet::{EtherTypes, EthernetPacket}, ipv4::Ipv4Packet, Packet}, util::MacAddr};
+use rand::Rng;
+use tokio::{sync::Mutex, time::sleep};
+use tracing::{error, info, warn};
+
+use crate::{graph::types::NetworkGraph, honeypot::identity, interfaces::sender::send_arp_request};
+
+/// Addresses the operator reserves (DHCP pools, static hosts): comma-separated IPs, `a-b` ranges or CIDRs
+const EXCLUDE_VARIABLE: &str = "HONEYPOT_EXCLUDE";
+const ROUTE_TABLE: &str = "/proc/net/route";
+
+// RFC 5227 §1.1 timing
+const PROBE_WAIT: Duration = Duration::from_secs(1);
+const PROBE_NUM: u32 = 3;
+const PROBE_MIN: Duration = Duration::from_secs(1);
+const PROBE_MAX: Duration = Duration::from_secs(2);
+const ANNOUNCE_WAIT: Duration = Duration::from_secs(2);
+const ANNOUNCE_NUM: u32 = 2;
+const ANNOUNCE_INTERVAL: Duration = Duration::from_secs(2);
+const MAX_CONFLICTS: u32 = 10;
+const RATE_LIMIT_INTERVAL: Duration = Duration::from_secs(60);
+// Without ARP, a bound decoy yields only to a host that keeps sending from its address this many
+// times over at least `SIGHTING_SPAN`, so a few spoofed frames can't chase it away
+const MIN_SIGHTINGS: u32 = 10;
+const SIGHTING_SPAN: Duration = Duration::from_secs(30);
+const SIGHTING_WINDOW: Duration = Duration::from_secs(300);
+const MAX_SIGHTINGS: usize = 1024;
+
+// Random picks before falling back to a scan of the subnet
+const RANDOM_PICKS: usize = 64;
+// Hosts scanned at most in very large subnets
+const MAX_SCAN: u32 = 1 << 16;
+
+
+/// IPv4 subnet of the capture interface and the addresses in it decoys must not take
+#[derive(Debug, Clone)]
+pub struct Subnet {
+    pub network: Ipv4Addr,
+    pub prefix: u8,
+    pub local: Ipv4Addr,
+    pub gateway: Option<Ipv4Addr>,
+    excluded: Vec<RangeInclusive<u32>>,
+}
+
+impl Subnet {
+    /// First IPv4 network of the interface, with the operator's exclusions from `HONEYPOT_EXCLUDE`
+    pub fn for_interface(interface: &NetworkInterface) -> Option<Self> {
+        let network = interface.ips.iter().find_map(|ip| match ip {
+            IpNetwork::V4(network) => Some(*network),
+            IpNetwork::V6(_) => None,
+        })?;
+
+        let excluded = env::var(EXCLUDE_VARIABLE).unwrap_or_default()
+            .split(',')
+            .map(str::trim)
+            .filter(|entry| !entry.is_empty())
+            .filter_map(|entry| {
+                let range = parse_range(entry);
+                if range.is_none() {
+                    warn!("⚠️ Esclusione {} non valida in {}, ignorata", entry, EXCLUDE_VARIABLE);
+                }
+                range
+            })
+            .collect();
+
+        Some(Subnet {
+            network: network.network(),
+            prefix: network.prefix(),
+            local: network.ip(),
+            gateway: default_gateway(&interface.name),
+            excluded,
+        })
+    }
+
+    fn first_host(&self) -> u32 {
+        u32::from(self.network) + 1
+    }
+
+    /// Usable host addresses, network and broadcast excluded
+    fn host_count(&self) -> u32 {
+        match self.prefix {
+            31.. => 0,
+            prefix => (1u32 << (32 - prefix)) - 2,
+        }
+    }
+
+    /// Directed broadcast address of the subnet
+    pub fn broadcast(&self) -> Ipv4Addr {
+        Ipv4Addr::from(u32::from(self.network) | u32::MAX.checked_shr(self.prefix as u32).unwrap_or(0))
+    }
+
+    /// Whether a decoy may use `ip`; without a known gateway the first host is kept free, where gateways usually sit
+    pub fn is_assignable(&self, ip: Ipv4Addr) -> bool {
+        let value = u32::from(ip);
+        let first = self.first_host();
+        value >= first
+            && value < first + self.host_count()
+            && ip != self.local
+            && self.gateway.map_or(value != first, |gateway| ip != gateway)
+            && !self.excluded.iter().any(|range| range.contains(&value))
+    }
+
+    /// Assignable address not in `taken`, inside the placement's range if any: picked at random,
+    /// or in the middle of the largest unused block when spreading
+    fn pick(&self, taken: &HashSet<Ipv4Addr>, placement: &Placement) -> Option<Ipv4Addr> {
+        let hosts = self.host_count();
+        if hosts == 0 {
+            return None;
+        }
+        let (start, count) = match &placement.within {
+            None => (self.first_host(), hosts),
+            Some(range) => {
+                let start = (*range.start()).max(self.first_host());
+                let end = (*range.end()).min(self.first_host() + hosts - 1);
+                if end < start {
+                    return None;
+                }
+                (start, end - start + 1)
+            }
+        };
+        let usable = |ip: &Ipv4Addr| self.is_assignable(*ip) && !taken.contains(ip);
+        if placement.spread {
+            return widest_gap(start, count.min(MAX_SCAN), usable);
+        }
+
+        let mut rng = rand::rng();
+        let random = (0..RANDOM_PICKS)
+            .map(|_| Ipv4Addr::from(start + rng.random_range(0..count)))
+            .find(usable);
+        if random.is_some() {
+            return random;
+        }
+
+        let offset = rng.random_range(0..count);
+        (0..count.min(MAX_SCAN))
+            .map(|i| Ipv4Addr::from(start + (offset + i) % count))
+            .find(usable)
+    }
+}
+
+/// Middle of the longest run of usable addresses, so decoys land away from hosts and each other
+fn widest_gap(start: u32, count: u32, usable: impl Fn(&Ipv4Addr) -> bool) -> Option<Ipv4Addr> {
+    let mut widest: Option<(u32, u32)> = None;
+    let mut run: Option<(u32, u32)> = None;
+    for value in start..start + count {
+        run = match (usable(&Ipv4Addr::from(value)), run) {
+            (true, Some((first, len))) => Some((first, len + 1)),
+            (true, None) => Some((value, 1)),
+            (false, _) => None,
+        };
+        if let Some((first, len)) = run && widest.is_none_or(|(_, widest_len)| len > widest_len) {
+            widest = Some((first, len));
+        }
+    }
+    widest.map(|(first, len)| Ipv4Addr::from(first + len / 2))
+}
+
+/// Where a decoy may take its address
+#[derive(Debug, Clone, Default)]
+pub struct Placement {
+    pub within: Option<RangeInclusive<u32>>,
+    /// Take the middle of the largest unused block rather than a random address
+    pub spread: bool,
+}
+
+/// `a.b.c.d`, `a.b.c.d-e.f.g.h` or `a.b.c.d/n`
+pub fn parse_range(entry: &str) -> Option<RangeInclusive<u32>> {
+    if let Some((start, end)) = entry.split_once('-') {
+        let (start, end) = (start.trim().parse::<Ipv4Addr>().ok()?, end.trim().parse::<Ipv4Addr>().ok()?);
+        return Some(u32::from(start)..=u32::from(end));
+    }
+    if let Some((address, prefix)) = entry.split_once('/') {
+        let (address, prefix) = (address.parse::<Ipv4Addr>().ok()?, prefix.parse::<u32>().ok()?);
+        let mask = u32::MAX.checked_shl(32 - prefix.min(32)).unwrap_or(0);
+        let start = u32::from(address) & mask;
+        return Some(start..=start | !mask);
+    }
+    let address = u32::from(entry.parse::<Ipv4Addr>().ok()?);
+    Some(address..=address)
+}
+
+/// Default gateway of the interface from the kernel routing table
+fn default_gateway(interface: &str) -> Option<Ipv4Addr> {
+    let table = fs::read_to_string(ROUTE_TABLE).ok()?;
+    table.lines().skip(1).find_map(|line| {
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        if fields.len() < 3 || fields[0] != interface || fields[1] != "00000000" {
+            return None;
+        }
+        // Stored as a little-endian hex word
+        u32::from_str_radix(fields[2], 16).ok().map(|gateway| Ipv4Addr::from(gateway.swap_bytes()))
+    })
+}
+
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ClaimState {
+    /// Probing the address, `conflict` is set if someone else turns out to use it
+    Probing { conflict: bool },
+    /// Address in use by the decoy
+    Bound,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Claim {
+    mac: MacAddr,
+    state: ClaimState,
+}
+
+/// IPv4 traffic from another host with a decoy's address as source
+#[derive(Debug, Clone, Copy)]
+struct Sighting {
+    first: Instant,
+    last: Instant,
+    count: u32,
+}
+
+/// What a frame says about who uses an address
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Evidence {
+    /// ARP from the host itself: it owns or is probing the address
+    Arp,
+    /// IPv4 source address, which anyone can forge
+    Traffic,
+}
+
+/// Hands out decoy addresses in the interface subnet, claiming each one with RFC 5227 conflict
+/// detection, and moves a decoy elsewhere when a real host shows up on its address
+pub struct IpAllocator {
+    subnet: Subnet,
+    tx: Arc<Mutex<Box<dyn DataLinkSender + Send>>>,
+    graph: Arc<Mutex<NetworkGraph>>,
+    claims: Mutex<HashMap<Ipv4Addr, Claim>>,
+    // Placements the fleet configuration gives decoys, kept for reallocations
+    placements: Mutex<HashMap<MacAddr, Placement>>,
+    sightings: Mutex<HashMap<(Ipv4Addr, MacAddr), Sighting>>,
+}
+
+impl IpAllocator {
+    pub fn new(subnet: Subnet, tx: Arc<Mutex<Box<dyn DataLinkSender + Send>>>, graph: Arc<Mutex<NetworkGraph>>) -> Self {
+        info!("🧭 Subnet {}/{} (gateway {:?}) per i nodi virtuali", subnet.network, subnet.prefix, subnet.gateway);
+        IpAllocator { subnet, tx, graph, claims: Mutex::new(HashMap::new()), placements: Mutex::new(HashMap::new()), sightings: Mutex::new(HashMap::new()) }
+    }
+
+    /// Finds a free address for the decoy `mac` as its placement asks: probes candidates until
+    /// one gets no answer, then announces it. `None` if there is no address left
+    pub async fn claim(&self, mac: MacAddr, placement: Placement) -> Option<Ipv4Addr> {
+        self.place(mac, placement).await;
+        self.claim_avoiding(mac, HashSet::new()).await
+    }
+
+    /// Claims `preferred` again if it is still free, as a host renewing its previous lease would;
+    /// otherwise falls back to a new address
+    pub async fn claim_preferring(&self, mac: MacAddr, preferred: Ipv4Addr, placement: Placement) -> Option<Ipv4Addr> {
+        let in_range = placement.within.as_ref().is_none_or(|range| range.contains(&u32::from(preferred)));
+        self.place(mac, placement).await;
+        let available = in_range && self.subnet.is_assignable(preferred) && {
+            let graph = self.graph.lock().await;
+            let claims = self.claims.lock().await;
+            !claims.contains_key(&preferred) && graph.nodes().all(|node| node.ipv4_address != Some(preferred))
+        };
+        if available && self.probe(mac, preferred).await {
+            self.announce(mac, preferred).await;
+            info!("📍 Indirizzo {} riassegnato a {}", preferred, mac);
+            return Some(preferred);
+        }
+        warn!("⚠️ L'indirizzo precedente {} di {} non è disponibile, ne cerco un altro", preferred, mac);
+        self.claim_avoiding(mac, HashSet::from([preferred])).await
+    }
+
+    async fn place(&self, mac: MacAddr, placement: Placement) {
+        self.placements.lock().await.insert(mac, placement);
+    }
+
+    /// Gives up every address of a decoy that is being removed
+    pub async fn release(&self, mac: MacAddr) {
+        self.claims.lock().await.retain(|_, claim| claim.mac != mac);
+        self.placements.lock().await.remove(&mac);
+    }
+
+    async fn claim_avoiding(&self, mac: MacAddr, mut rejected: HashSet<Ipv4Addr>) -> Option<Ipv4Addr> {
+        let mut conflicts = 0;
+        let placement = self.placements.lock().await.get(&mac).cloned().unwrap_or_default();
+
+        loop {
+            let candidate = {
+                let graph = self.graph.lock().await;
+                let claims = self.claims.lock().await;
+                let taken: HashSet<Ipv4Addr> = graph.nodes()
+                    .filter_map(|node| node.ipv4_address)
+                    .chain(claims.keys().copied())
+                    .chain(rejected.iter().copied())
+                    .collect();
+                self.subnet.pick(&taken, &placement)
+            };
+            let Some(candidate) = candidate else {
+                match &placement.within {
+                    Some(range) => error!("❌ Nessun indirizzo libero tra {} e {} per {}", Ipv4Addr::from(*range.start()), Ipv4Addr::from(*range.end()), mac),
+                    None => error!("❌ Nessun indirizzo libero nella subnet {}/{} per {}", self.subnet.network, self.subnet.prefix, mac),
+                }
+                return None;
+            };
+
+            if conflicts >= MAX_CONFLICTS {
+                sleep(RATE_LIMIT_INTERVAL).await;
+            }
+            if self.probe(mac, candidate).await {
+                self.announce(mac, candidate).await;
+                info!("📍 Indirizzo {} assegnato a {}", candidate, mac);
+                return Some(candidate);
+            }
+            warn!("⚠️ Conflitto su {}: già in uso, provo un altro indirizzo per {}", candidate, mac);
+            conflicts += 1;
+            rejected.insert(candidate);
+        }
+    }
+
+    /// Sends the ARP probes and waits for answers; true if nobody else uses `ip`
+    async fn probe(&self, mac: MacAddr, ip: Ipv4Addr) -> bool {
+        self.claims.lock().await.insert(ip, Claim { mac, state: ClaimState::Probing { conflict: false } });
+
+        sleep(random_between(Duration::ZERO, PROBE_WAIT)).await;
+        for i in 0..PROBE_NUM {
+            send_arp_request(self.tx.clone(), mac, Ipv4Addr::UNSPECIFIED, ip).await;
+            sleep(if i + 1 < PROBE_NUM { random_between(PROBE_MIN, PROBE_MAX) } else { ANNOUNCE_WAIT }).await;
+            if self.is_conflicted(ip).await {
+                break;
+            }
+        }
+
+        let mut claims = self.claims.lock().await;
+        match claims.get(&ip) {
+            Some(Claim { state: ClaimState::Probing { conflict: false }, .. }) => {
+                claims.insert(ip, Claim { mac, state: ClaimState::Bound });
+                true
+            }
+            _ => {
+                claims.remove(&ip);
+                false
+            }
+        }
+    }
+
+    async fn is_conflicted(&self, ip: Ipv4Addr) -> bool {
+        matches!(self.claims.lock().await.get(&ip), Some(Claim { state: ClaimState::Probing { conflict: true }, .. }))
+    }
+
+    async fn announce(&self, mac: MacAddr, ip: Ipv4Addr) {
+        for i in 0..ANNOUNCE_NUM {
+            if i > 0 {
+                sleep(ANNOUNCE_INTERVAL).await;
+            }
+            send_arp_request(self.tx.clone(), mac, ip, ip).await;
+        }
+    }
+
+    /// Watches captured traffic for other hosts using a probed or assigned address. A decoy
+    /// gives up its address and moves to a new one when a real host claims it over ARP, or keeps
+    /// sending from it for a while
+    pub async fn observe(self: &Arc<Self>, ethernet_packet: &EthernetPacket<'_>) {
+        let source_mac = ethernet_packet.get_source();
+        let (ip, evidence) = match ethernet_packet.get_ethertype() {
+            EtherTypes::Arp => match ArpPacket::new(ethernet_packet.payload()) {
+                // The RFC 5227 sender pair, from the host that sent the frame
+                Some(arp) if arp.get_sender_hw_addr() != source_mac => return,
+                // Someone else probing for the same address counts as a conflict too (RFC 5227 §2.1.1)
+                Some(arp) if arp.get_sender_proto_addr().is_unspecified() => (arp.get_target_proto_addr(), Evidence::Arp),
+                Some(arp) => (arp.get_sender_proto_addr(), Evidence::Arp),
+                None => return,
+            },
+            EtherTypes::Ipv4 => match Ipv4Packet::new(ethernet_packet.payload()) {
+                Some(ipv4) => (ipv4.get_source(), Evidence::Traffic),
+                None => return,
+            },
+            _ => return,
+        };
+
+        let evicted = {
+            let mut claims = self.claims.lock().await;
+            let Some(claim) = claims.get_mut(&ip).filter(|claim| claim.mac != source_mac) else {
+                return;
+            };
+            match claim.state {
+                ClaimState::Probing { .. } => {
+                    claim.state = ClaimState::Probing { conflict: true };
+                    None
+                }
+                ClaimState::Bound if evidence == Evidence::Traffic && !self.sighted(ip, source_mac).await => None,
+                ClaimState::Bound => {
+                    let mac = claim.mac;
+                    claims.remove(&ip);
+                    Some((ip, mac))
+                }
+            }
+        };
+
+        if let Some((ip, mac)) = evicted {
+            self.sightings.lock().await.retain(|(sighted, _), _| *sighted != ip);
+            warn!("🚚 L'host {} usa l'indirizzo {} del nodo virtuale {}: lo rilascio", source_mac, ip, mac);
+            let allocator = self.clone();
+            tokio::spawn(async move { allocator.reallocate(mac, ip).await });
+        }
+    }
+
+    /// Counts traffic from `mac` with source `ip`; true once it has gone on long enough to be a real host
+    async fn sighted(&self, ip: Ipv4Addr, mac: MacAddr) -> bool {
+        let now = Instant::now();
+        let mut sightings = self.sightings.lock().await;
+        if sightings.len() >= MAX_SIGHTINGS {
+            sightings.retain(|_, sighting| now.duration_since(sighting.first) < SIGHTING_WINDOW);
+        }
+        let sighting = sightings.entry((ip, mac)).or_insert(Sighting { first: now, last: now, count: 0 });
+        if now.duration_since(sighting.first) >= SIGHTING_WINDOW {
+            *sighting = Sighting { first: now, last: now, count: 0 };
+        }
+        sighting.count += 1;
+        sighting.last = now;
+        sighting.count >= MIN_SIGHTINGS && sighting.last.duration_since(sighting.first) >= SIGHTING_SPAN
+    }
+
+    async fn reallocate(&self, mac: MacAddr, lost: Ipv4Addr) {
+        // Decoys removed from the fleet meanwhile don't need a new address
+        match self.graph.lock().await.get_node_by_mac(mac) {
+            Some(node) => node.ipv4_address = None,
+            None => return,
+        }
+        // The real host may not be in the graph yet
+        let Some(ip) = self.claim_avoiding(mac, HashSet::from([lost])).await else {
+            return;
+        };
+        if let Some(node) = self.graph.lock().await.get_node_by_mac(mac) {
+            node.ipv4_address = Some(ip);
+        }
+        info!("🚚 Nodo virtuale {} spostato da {} a {}", mac, lost, ip);
+        identity::save(&self.graph).await;
+    }
+}
+
+fn random_between(min: Duration, max: Duration) -> Duration {
+    rand::rng().random_range(min..=max)
+}
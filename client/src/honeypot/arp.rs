@@ -0,0 +1,90 @@
+use std::{collections::HashMap, sync::Arc, time::{Duration, Instant}};
+
+use lazy_static::lazy_static;
+use pnet::{datalink::DataLinkSender, packet::{arp::{ArpOperations, ArpPacket}, ethernet::EthernetPacket, Packet}, util::MacAddr};
+use rand::Rng;
+use tokio::{sync::Mutex, time::sleep};
+use tracing::warn;
+
+use crate::{graph::types::NetworkGraph, interfaces::sender::{send_arp_reply, send_arp_request}};
+
+// Replies each requester may get per window: enough for a host resolving every decoy at once,
+// not for turning the decoys into an ARP reflector
+const REPLY_WINDOW: Duration = Duration::from_secs(1);
+const MAX_REPLIES_PER_WINDOW: u32 = 16;
+// Requesters tracked before expired windows are swept
+const MAX_TRACKED_REQUESTERS: usize = 4096;
+
+// Gratuitous ARP keeps neighbour caches and switch MAC tables (300 s ageing) pointing at the decoys
+const ANNOUNCE_PERIOD: Duration = Duration::from_secs(120);
+const ANNOUNCE_JITTER: Duration = Duration::from_secs(20);
+
+
+lazy_static! {
+    static ref REQUESTER_LIMITS: Mutex<HashMap<MacAddr, RequesterWindow>> = Mutex::new(HashMap::new());
+}
+
+struct RequesterWindow {
+    window_start: Instant,
+    replies: u32,
+}
+
+/// Answers every ARP request for an address a virtual node owns, broadcast or unicast.
+/// Probes (sender 0.0.0.0) are left unanswered: the host probing is about to take the address,
+/// and the allocator moves the decoy away
+pub async fn handle_arp_packet(
+    ethernet_packet: &EthernetPacket<'_>,
+    graph: &Arc<Mutex<NetworkGraph>>,
+    tx: Arc<Mutex<Box<dyn DataLinkSender + Send>>>,
+) {
+    let Some(arp_packet) = ArpPacket::new(ethernet_packet.payload()) else {
+        return;
+    };
+    if arp_packet.get_operation() != ArpOperations::Request || arp_packet.get_sender_proto_addr().is_unspecified() {
+        return;
+    }
+
+    let target_ip = arp_packet.get_target_proto_addr();
+    let requester_mac = arp_packet.get_sender_hw_addr();
+    let Some(virtual_mac) = graph.lock().await.find_virtual_by_ip(target_ip) else {
+        return;
+    };
+    // Announcements of our own decoys come back through the capture
+    if requester_mac == virtual_mac || !allow_reply(requester_mac).await {
+        return;
+    }
+
+    send_arp_reply(virtual_mac, target_ip, requester_mac, arp_packet.get_sender_proto_addr(), tx).await;
+}
+
+/// Counts one reply to `requester` against its window, shared by ARP and neighbor discovery
+pub async fn allow_reply(requester: MacAddr) -> bool {
+    let mut limits = REQUESTER_LIMITS.lock().await;
+    let now = Instant::now();
+    if limits.len() >= MAX_TRACKED_REQUESTERS {
+        limits.retain(|_, window| now.duration_since(window.window_start) < REPLY_WINDOW);
+    }
+
+    let window = limits.entry(requester).or_insert(RequesterWindow { window_start: now, replies: 0 });
+    if now.duration_since(window.window_start) >= REPLY_WINDOW {
+        *window = RequesterWindow { window_start: now, replies: 0 };
+    }
+    window.replies += 1;
+    if window.replies == MAX_REPLIES_PER_WINDOW + 1 {
+        warn!("🚦 Troppe richieste ARP da {}, risposte sospese", requester);
+    }
+    window.replies <= MAX_REPLIES_PER_WINDOW
+}
+
+/// Re-announces every virtual node's address with gratuitous ARP, forever
+pub async fn announce_periodically(graph: Arc<Mutex<NetworkGraph>>, tx: Arc<Mutex<Box<dyn DataLinkSender + Send>>>) {
+    loop {
+        let jitter = rand::rng().random_range(Duration::ZERO..=ANNOUNCE_JITTER);
+        sleep(ANNOUNCE_PERIOD + jitter).await;
+
+        let owned = graph.lock().await.virtual_addresses();
+        for (mac, ip) in owned {
+            send_arp_request(tx.clone(), mac, ip, ip).await;
+        }
+    }
+}
@@ -0,0 +1,85 @@
+use std::{collections::HashMap, net::IpAddr, time::{Duration, Instant}};
+
+use lazy_static::lazy_static;
+use tokio::sync::Mutex;
+use tracing::warn;
+
+// Per-source budget for connectionless replies (UDP, ICMP): their sources are trivially
+// spoofed, so every reply may be aimed at a victim
+const BUDGET_WINDOW: Duration = Duration::from_secs(10);
+const MAX_REPLIES_PER_WINDOW: u32 = 30;
+const MAX_BYTES_PER_WINDOW: usize = 8 * 1024;
+// Sources tracked before expired budgets are swept
+const MAX_TRACKED_SOURCES: usize = 4096;
+
+
+lazy_static! {
+    static ref REPLY_BUDGETS: Mutex<HashMap<IpAddr, SourceBudget>> = Mutex::new(HashMap::new());
+}
+
+struct SourceBudget {
+    window_start: Instant,
+    replies: u32,
+    bytes: usize,
+    // Whether going over the budget has been reported in this window
+    reported: bool,
+}
+
+impl SourceBudget {
+    fn new() -> Self {
+        SourceBudget { window_start: Instant::now(), replies: 0, bytes: 0, reported: false }
+    }
+}
+
+pub enum Budget {
+    Allowed,
+    /// Over budget; `true` the first time in the window
+    Exceeded(bool),
+}
+
+
+/// Counts one request from `source` against its budget
+pub async fn charge(source: IpAddr) -> Budget {
+    let mut budgets = REPLY_BUDGETS.lock().await;
+    if budgets.len() >= MAX_TRACKED_SOURCES {
+        budgets.retain(|_, budget| budget.window_start.elapsed() < BUDGET_WINDOW);
+    }
+
+    let budget = budgets.entry(source).or_insert_with(SourceBudget::new);
+    if budget.window_start.elapsed() >= BUDGET_WINDOW {
+        *budget = SourceBudget::new();
+    }
+
+    budget.replies += 1;
+    if budget.replies <= MAX_REPLIES_PER_WINDOW && budget.bytes <= MAX_BYTES_PER_WINDOW {
+        return Budget::Allowed;
+    }
+
+    let first = !budget.reported;
+    budget.reported = true;
+    if first {
+        warn!("🛑 Troppe richieste da {}: risposte sospese per {:?}", source, BUDGET_WINDOW);
+    }
+    Budget::Exceeded(first)
+}
+
+/// Takes `bytes` of reply from the budget of `source`, false if it would go over
+pub async fn spend(source: IpAddr, bytes: usize) -> bool {
+    let mut budgets = REPLY_BUDGETS.lock().await;
+    let Some(budget) = budgets.get_mut(&source) else {
+        return false;
+    };
+    if budget.bytes + bytes > MAX_BYTES_PER_WINDOW {
+        return false;
+    }
+    budget.bytes += bytes;
+    true
+}
+
+/// Whether a reply to this source could reach a single real host
+pub fn is_unicast(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => !(ip.is_unspecified() || ip.is_broadcast() || ip.is_multicast() || ip.is_loopback()),
+        IpAddr::V6(ip) => !(ip.is_unspecified() || ip.is_multicast() || ip.is_loopback()),
+    }
+}
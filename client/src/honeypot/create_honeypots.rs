@@ -1,18 +1,166 @@
-use std::sync::Arc;
-use tokio::sync::Mutex;
-use tracing::info;
-use crate::graph::types::NetworkGraph;
+use std::{collections::HashMap, sync::Arc, time::{Duration, Instant}};
+use futures_util::future::join_all;
+use tokio::{sync::Mutex, time::sleep};
+use tracing::{error, info, warn};
+use crate::graph::{types::NetworkGraph, utils::generate_virtual_mac};
+use super::{allocation::IpAllocator, density, fleet::{self, DecoyGroup, FleetConfig}, identity::{self, HoneypotIdentity}, profile::HoneypotProfile};
 
-const NUMBER_OF_HONEYPOTS: u8 = 10;
+// How often the fleet configuration is checked for edits
+const RELOAD_INTERVAL: Duration = Duration::from_secs(5);
+// How often the adaptive fleet is resized on the hosts seen since
+const ADAPT_INTERVAL: Duration = Duration::from_secs(60);
+// Identities of removed decoys kept to bring back when the fleet grows again
+const MAX_SPARE_IDENTITIES: usize = 256;
 
-pub async fn create_honeypots(graph: &Arc<Mutex<NetworkGraph>>){
-    for _ in 0..NUMBER_OF_HONEYPOTS {
-        let mut graph_locked = graph.lock().await;
-        graph_locked.add_virtual_node();
-    }
-    let graph_locked = graph.lock().await;
-    graph_locked.print_virtual_nodes();
+/// Starts the decoys of the fleet configuration, then keeps them in line with it:
+/// edits to the file add, rebuild or remove decoys while the capture goes on, and in
+/// adaptive mode the fleet follows the number of real hosts seen
+pub async fn create_honeypots(graph: Arc<Mutex<NetworkGraph>>, allocator: Arc<IpAllocator>){
+    let path = fleet::config_path();
+    let mut modified = fleet::modified(&path);
+    let mut config = fleet::load(&path).unwrap_or_else(|problems| {
+        report(&path, &problems);
+        warn!("⚠️ Configurazione della flotta non valida, uso quella predefinita");
+        FleetConfig::default()
+    });
 
+    // Decoys from the previous run come back with the same MAC, address and fingerprint
+    let saved = identity::load();
+    if !saved.is_empty() {
+        info!("♻️ Ripristino fino a {} nodi virtuali salvati", saved.len());
+    }
+    let mut hysteresis = density::Hysteresis::default();
+    let fleet = hysteresis.settle(density::expand(&config, &*graph.lock().await), true);
+    let mut sizes = group_sizes(&fleet);
+    // Saved decoys of adaptive groups wait here until the hosts seen call for their group
+    let mut spare = apply(&fleet, saved, &graph, &allocator).await;
     info!("🤖 Virtual Honeypots created");
 
+    let mut adapted = Instant::now();
+    loop {
+        sleep(RELOAD_INTERVAL).await;
+        let current = fleet::modified(&path);
+        let edited = current != modified;
+        if edited {
+            modified = current;
+            match fleet::load(&path) {
+                Ok(loaded) => {
+                    info!("🔁 Configurazione della flotta {} cambiata, aggiorno i nodi virtuali", path);
+                    config = loaded;
+                }
+                Err(problems) => {
+                    report(&path, &problems);
+                    warn!("⚠️ Configurazione della flotta non valida, i nodi virtuali restano invariati");
+                    continue;
+                }
+            }
+        } else if config.adaptive.is_none() || adapted.elapsed() < ADAPT_INTERVAL {
+            continue;
+        }
+
+        adapted = Instant::now();
+        let fleet = hysteresis.settle(density::expand(&config, &*graph.lock().await), edited);
+        let resized = group_sizes(&fleet);
+        if edited || resized != sizes {
+            if !edited {
+                info!("📈 Host reali sulla rete cambiati, ridimensiono la flotta adattiva: {:?}", resized);
+            }
+            spare = apply(&fleet, std::mem::take(&mut spare), &graph, &allocator).await;
+            sizes = resized;
+        }
+    }
+}
+
+fn group_sizes(fleet: &FleetConfig) -> Vec<(String, usize)> {
+    fleet.decoys.iter().map(|group| (group.name.clone(), group.count)).collect()
+}
+
+fn report(path: &str, problems: &[String]) {
+    for problem in problems {
+        error!("❌ {}: {}", path, problem);
+    }
+}
+
+/// Brings the virtual nodes in line with `config`. Running decoys that still fit their group are
+/// kept with their new settings, then saved identities and new decoys fill the missing places.
+/// Returns the identities left out, removed decoys first, to fill places of a later round
+async fn apply(config: &FleetConfig, saved: Vec<HoneypotIdentity>, graph: &Arc<Mutex<NetworkGraph>>, allocator: &Arc<IpAllocator>) -> Vec<HoneypotIdentity> {
+    let mut places: HashMap<&str, usize> = config.decoys.iter().map(|group| (group.name.as_str(), group.count)).collect();
+    let mut removed = Vec::new();
+    {
+        let mut g = graph.lock().await;
+        for running in identity::snapshot(&g) {
+            let group = config.group(&running.group).filter(|group| group.admits(&running));
+            match (group, places.get_mut(running.group.as_str())) {
+                (Some(group), Some(left)) if *left > 0 => {
+                    *left -= 1;
+                    if let Some(node) = g.get_node_by_mac(running.mac) {
+                        node.honeypot = Some(Arc::new(HoneypotProfile::restore(&running, group)));
+                    }
+                }
+                _ => {
+                    g.remove_node(running.mac);
+                    removed.push(running);
+                }
+            }
+        }
+    }
+    for identity in &removed {
+        allocator.release(identity.mac).await;
+        info!("🗑️ Nodo virtuale {} rimosso dalla flotta", identity.mac);
+    }
+
+    // Saved identities take the places their group still has, the others are left over
+    let mut starts = Vec::new();
+    let mut spare = removed;
+    for identity in saved {
+        let group = config.group(&identity.group).filter(|group| group.admits(&identity));
+        match (group, group.and_then(|group| places.get_mut(group.name.as_str()))) {
+            (Some(group), Some(left)) if *left > 0 => {
+                *left -= 1;
+                starts.push(start_decoy(graph.clone(), allocator.clone(), group.clone(), Some(identity)));
+            }
+            _ => spare.push(identity),
+        }
+    }
+    spare.truncate(MAX_SPARE_IDENTITIES);
+    for group in &config.decoys {
+        for _ in 0..places[group.name.as_str()] {
+            starts.push(start_decoy(graph.clone(), allocator.clone(), group.clone(), None));
+        }
+    }
+
+    // Every decoy probes its address at the same time, as independent hosts booting would
+    join_all(starts).await;
+    identity::save(graph).await;
+    graph.lock().await.print_virtual_nodes();
+    spare
+}
+
+/// Claims an address in the group's range for a restored or new decoy and adds it to the graph
+async fn start_decoy(graph: Arc<Mutex<NetworkGraph>>, allocator: Arc<IpAllocator>, group: DecoyGroup, saved: Option<HoneypotIdentity>) {
+    let placement = group.placement();
+    let (mac, ip, profile) = match saved {
+        Some(saved) => {
+            let ip = match saved.ipv4 {
+                Some(previous) => allocator.claim_preferring(saved.mac, previous, placement).await,
+                None => allocator.claim(saved.mac, placement).await,
+            };
+            (saved.mac, ip, HoneypotProfile::restore(&saved, &group))
+        }
+        None => {
+            let mac = generate_virtual_mac(group.vendor_for_new_decoy().as_deref());
+            (mac, allocator.claim(mac, placement).await, HoneypotProfile::new(mac, &group))
+        }
+    };
+
+    match ip {
+        Some(ip) => {
+            graph.lock().await.add_virtual_node(mac, ip, profile);
+        }
+        None => {
+            allocator.release(mac).await;
+            error!("❌ Nodo virtuale {} del gruppo {} non creato: nessun indirizzo disponibile", mac, group.name);
+        }
+    }
 }
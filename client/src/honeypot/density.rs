@@ -0,0 +1,261 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use crate::graph::types::{NetworkGraph, NodeType};
+use super::fleet::{AdaptiveDensity, DecoyGroup, FleetConfig, ADAPTIVE_PREFIX};
+use super::persona;
+use super::services::banner::CATALOGUE;
+
+// Rounds in a row an adaptive group's size must differ from the running one before the fleet follows
+const STABLE_ROUNDS: usize = 3;
+
+
+/// What the hosts of one vendor look like
+#[derive(Default)]
+struct Observed {
+    hosts: usize,
+    /// Hosts by guessed personality
+    os_guesses: BTreeMap<String, usize>,
+    /// Open TCP ports of each host
+    tcp_services: Vec<BTreeSet<u16>>,
+}
+
+/// Real hosts seen on the link by MAC vendor, `None` for locally administered MACs whose
+/// prefix is random. Broadcast and multicast destinations aren't hosts
+fn observed_vendors(graph: &NetworkGraph) -> BTreeMap<Option<String>, Observed> {
+    let mut vendors: BTreeMap<Option<String>, Observed> = BTreeMap::new();
+    for node in graph.nodes() {
+        let mac = node.mac_address;
+        if node.node_type != NodeType::Physical || mac.0 & 0x01 != 0 {
+            continue;
+        }
+        let vendor = (mac.0 & 0x02 == 0).then(|| format!("{:02X}:{:02X}:{:02X}", mac.0, mac.1, mac.2));
+        let observed = vendors.entry(vendor).or_default();
+        observed.hosts += 1;
+        if let Some(os) = &node.os_guess {
+            *observed.os_guesses.entry(os.clone()).or_insert(0) += 1;
+        }
+        if !node.tcp_services.is_empty() {
+            observed.tcp_services.push(node.tcp_services.clone());
+        }
+    }
+    vendors
+}
+
+/// Adaptive decoys for `hosts` physical hosts
+fn target(adaptive: &AdaptiveDensity, hosts: usize) -> usize {
+    ((hosts as f64 * adaptive.ratio).round() as usize).clamp(adaptive.min, adaptive.max)
+}
+
+/// The configured groups plus, in adaptive mode, one group per vendor seen on the network:
+/// vendors with more hosts get more decoys, each spread over the unused addresses and
+/// looking like that vendor's hosts. Until a
+/// host is seen there is nothing to adapt to, and only the configured groups run
+pub fn expand(config: &FleetConfig, graph: &NetworkGraph) -> FleetConfig {
+    let mut fleet = config.clone();
+    let Some(adaptive) = &config.adaptive else {
+        return fleet;
+    };
+
+    let vendors = observed_vendors(graph);
+    let hosts: usize = vendors.values().map(|observed| observed.hosts).sum();
+    if hosts == 0 {
+        return fleet;
+    }
+    let target = target(adaptive, hosts);
+
+    // Largest remainder, so the shares add up to the target
+    let mut shares: Vec<(Option<String>, Observed, usize, usize)> = vendors.into_iter()
+        .map(|(vendor, observed)| {
+            let (share, remainder) = (target * observed.hosts / hosts, target * observed.hosts % hosts);
+            (vendor, observed, share, remainder)
+        })
+        .collect();
+    let mut left = target - shares.iter().map(|(_, _, share, _)| share).sum::<usize>();
+    let mut order: Vec<usize> = (0..shares.len()).collect();
+    order.sort_by_key(|&i| std::cmp::Reverse(shares[i].3));
+    for i in order {
+        if left == 0 {
+            break;
+        }
+        shares[i].2 += 1;
+        left -= 1;
+    }
+
+    fleet.decoys.extend(shares.into_iter()
+        .filter(|(_, _, share, _)| *share > 0)
+        .map(|(vendor, observed, share, _)| adaptive_group(adaptive, vendor, &observed, share)));
+    fleet
+}
+
+/// Decoys of `vendor` imitating its observed hosts: the banner profile whose services most
+/// of them answer, and the personality most of them were guessed as if it fits both
+fn adaptive_group(adaptive: &AdaptiveDensity, vendor: Option<String>, observed: &Observed, count: usize) -> DecoyGroup {
+    let name = match &vendor {
+        Some(vendor) => format!("{}-{}", ADAPTIVE_PREFIX, vendor.replace(':', "")),
+        None => ADAPTIVE_PREFIX.to_string(),
+    };
+
+    let mut banner_profile: Option<(&str, usize)> = None;
+    for profile in &CATALOGUE.profiles {
+        let answering = observed.tcp_services.iter()
+            .filter(|ports| profile.services.iter().any(|service| service.ports.iter().any(|port| ports.contains(port))))
+            .count();
+        if answering > banner_profile.map_or(0, |(_, best)| best) {
+            banner_profile = Some((&profile.name, answering));
+        }
+    }
+    let banner_profile = banner_profile.map(|(name, _)| name.to_string());
+
+    let mut guesses: Vec<(&String, &usize)> = observed.os_guesses.iter().collect();
+    guesses.sort_by_key(|(_, hosts)| std::cmp::Reverse(**hosts));
+    let personality = guesses.into_iter()
+        .filter_map(|(name, _)| persona::personality_named(name))
+        .find(|personality| {
+            let vendor_fits = personality.vendors.is_empty()
+                || vendor.as_ref().is_some_and(|vendor| personality.vendors.iter().any(|v| v.eq_ignore_ascii_case(vendor)));
+            let profile_fits = personality.profiles.is_empty()
+                || banner_profile.as_ref().is_none_or(|profile| personality.profiles.contains(profile));
+            vendor_fits && profile_fits
+        })
+        .map(|personality| personality.name.clone());
+
+    DecoyGroup {
+        name,
+        count,
+        vendor,
+        personality,
+        banner_profile,
+        addresses: adaptive.addresses.clone(),
+        spread: true,
+        ..Default::default()
+    }
+}
+
+
+/// Adaptive group sizes the fleet runs with. A group is resized, added or dropped only once
+/// the hosts seen called for it `STABLE_ROUNDS` rounds in a row, so a host coming and going
+/// doesn't make decoys appear and vanish
+#[derive(Default)]
+pub struct Hysteresis {
+    running: BTreeMap<String, DecoyGroup>,
+    /// Rounds in a row each group's size differed from the running one
+    differing: BTreeMap<String, usize>,
+}
+
+impl Hysteresis {
+    /// `expanded` with each adaptive group at its running size unless the new one held long
+    /// enough. The first adaptive groups and those of an edited configuration apply at once
+    pub fn settle(&mut self, expanded: FleetConfig, immediately: bool) -> FleetConfig {
+        let (adaptive, mut fleet): (Vec<DecoyGroup>, Vec<DecoyGroup>) = expanded.decoys.into_iter()
+            .partition(|group| group.name.starts_with(ADAPTIVE_PREFIX));
+        let mut wanted: BTreeMap<String, DecoyGroup> = adaptive.into_iter().map(|group| (group.name.clone(), group)).collect();
+        if immediately || self.running.is_empty() {
+            self.differing.clear();
+            self.running = wanted;
+        } else {
+            let names: BTreeSet<String> = self.running.keys().chain(wanted.keys()).cloned().collect();
+            for name in names {
+                let running = self.running.get(&name).map_or(0, |group| group.count);
+                let count = wanted.get(&name).map_or(0, |group| group.count);
+                if count == running {
+                    self.differing.remove(&name);
+                    continue;
+                }
+                let rounds = self.differing.entry(name.clone()).or_insert(0);
+                *rounds += 1;
+                if *rounds < STABLE_ROUNDS {
+                    continue;
+                }
+                self.differing.remove(&name);
+                match wanted.remove(&name) {
+                    Some(group) => self.running.insert(name, group),
+                    None => self.running.remove(&name),
+                };
+            }
+        }
+
+        fleet.extend(self.running.values().filter(|group| group.count > 0).cloned());
+        FleetConfig { decoys: fleet, ..expanded }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use pnet::util::MacAddr;
+
+    use crate::graph::types::NetworkNode;
+    use super::*;
+
+    fn fleet(counts: &[(&str, usize)]) -> FleetConfig {
+        let decoys = counts.iter()
+            .map(|(name, count)| DecoyGroup { name: name.to_string(), count: *count, ..Default::default() })
+            .collect();
+        FleetConfig { decoys, adaptive: None }
+    }
+
+    fn sizes(fleet: &FleetConfig) -> Vec<(String, usize)> {
+        fleet.decoys.iter().map(|group| (group.name.clone(), group.count)).collect()
+    }
+
+    #[test]
+    fn adaptive_groups_follow_only_stable_sizes() {
+        let mut hysteresis = Hysteresis::default();
+        let first = hysteresis.settle(fleet(&[("web", 1), ("adaptive-001122", 2)]), false);
+        assert_eq!(sizes(&first), [("web".to_string(), 1), ("adaptive-001122".to_string(), 2)]);
+
+        // A vendor showing up for less than STABLE_ROUNDS rounds changes nothing
+        for _ in 1..STABLE_ROUNDS {
+            let settled = hysteresis.settle(fleet(&[("web", 1), ("adaptive-001122", 3), ("adaptive-AABBCC", 1)]), false);
+            assert_eq!(sizes(&settled), sizes(&first));
+        }
+        let settled = hysteresis.settle(fleet(&[("web", 1), ("adaptive-001122", 2)]), false);
+        assert_eq!(sizes(&settled), sizes(&first));
+
+        for _ in 1..STABLE_ROUNDS {
+            hysteresis.settle(fleet(&[("web", 1), ("adaptive-001122", 3)]), false);
+        }
+        let settled = hysteresis.settle(fleet(&[("web", 1), ("adaptive-001122", 3)]), false);
+        assert_eq!(sizes(&settled), [("web".to_string(), 1), ("adaptive-001122".to_string(), 3)]);
+    }
+
+    fn host(graph: &mut NetworkGraph, mac: MacAddr, os_guess: Option<&str>, ports: &[u16]) {
+        let mut node = NetworkNode::new(mac, None, NodeType::Physical);
+        node.os_guess = os_guess.map(str::to_string);
+        node.tcp_services = ports.iter().copied().collect();
+        graph.add_node(node);
+    }
+
+    #[test]
+    fn adaptive_groups_imitate_the_observed_hosts() {
+        let mut graph = NetworkGraph::default();
+        host(&mut graph, MacAddr(0x3C, 0xD9, 0x2B, 0, 0, 1), None, &[80, 9100]);
+        host(&mut graph, MacAddr(0x3C, 0xD9, 0x2B, 0, 0, 2), None, &[515, 9100]);
+        host(&mut graph, MacAddr(0x60, 0x1D, 0x9D, 0, 0, 1), Some("linux-server"), &[22, 3306]);
+        host(&mut graph, MacAddr(0x60, 0x1D, 0x9D, 0, 0, 2), Some("windows-server"), &[]);
+        host(&mut graph, MacAddr(0x60, 0x1D, 0x9D, 0, 0, 3), Some("linux-server"), &[5432]);
+        let config = FleetConfig {
+            decoys: Vec::new(),
+            adaptive: Some(AdaptiveDensity { ratio: 1.0, min: 0, max: 10, addresses: None }),
+        };
+
+        let fleet = expand(&config, &graph);
+        let group = |name: &str| fleet.decoys.iter().find(|group| group.name == name).unwrap();
+        let printers = group("adaptive-3CD92B");
+        assert_eq!(printers.count, 2);
+        assert_eq!(printers.banner_profile.as_deref(), Some("printer"));
+        assert_eq!(printers.personality, None);
+        let servers = group("adaptive-601D9D");
+        assert_eq!(servers.count, 3);
+        assert_eq!(servers.banner_profile.as_deref(), Some("database-server"));
+        assert_eq!(servers.personality.as_deref(), Some("linux-server"));
+    }
+
+    #[test]
+    fn edits_apply_at_once() {
+        let mut hysteresis = Hysteresis::default();
+        hysteresis.settle(fleet(&[("adaptive-001122", 2)]), false);
+        let settled = hysteresis.settle(fleet(&[("adaptive-001122", 5)]), true);
+        assert_eq!(sizes(&settled), [("adaptive-001122".to_string(), 5)]);
+    }
+}
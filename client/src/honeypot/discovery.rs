@@ -0,0 +1,159 @@
+use std::{collections::HashMap, net::{Ipv4Addr, SocketAddrV4}, sync::Arc, time::{Duration, Instant}};
+
+use pnet::{datalink::DataLinkSender, packet::{ethernet::EthernetPacket, ip::IpNextHeaderProtocols, ipv4::Ipv4Packet, udp::UdpPacket, Packet}, util::MacAddr};
+use rand::Rng;
+use tokio::{sync::Mutex, time::sleep};
+use tracing::info;
+
+use crate::{graph::types::{NetworkGraph, NodeType}, honeypot::{budget::{charge, spend, Budget}, profile::HoneypotProfile, services::{session::EventSink, udp::{llmnr::LLMNR_PORT, mdns::{MdnsEmulator, MDNS_PORT}, nbns::NBNS_PORT, ssdp::{self, SSDP_PORT}}}}, interfaces::{sender::send_udp_datagram, ws::WsReporter}};
+
+pub const MDNS_GROUP: Ipv4Addr = Ipv4Addr::new(224, 0, 0, 251);
+pub const LLMNR_GROUP: Ipv4Addr = Ipv4Addr::new(224, 0, 0, 252);
+const SSDP_GROUP: Ipv4Addr = Ipv4Addr::new(239, 255, 255, 250);
+// mDNS packets must carry the maximum TTL, receivers drop the others (RFC 6762 §11)
+pub const MDNS_TTL: u8 = 255;
+// UPnP Device Architecture 1.0 §1.1.2
+const SSDP_TTL: u8 = 4;
+
+// How often new decoys are looked for
+const ADVERTISE_CHECK: Duration = Duration::from_secs(10);
+// Decoys announce again before SSDP's max-age and mDNS' service TTLs expire
+const ADVERTISE_PERIOD: Duration = Duration::from_secs(900);
+const ADVERTISE_JITTER: Duration = Duration::from_secs(60);
+
+
+pub struct Decoy {
+    pub mac: MacAddr,
+    pub ip: Ipv4Addr,
+    pub profile: Arc<HoneypotProfile>,
+}
+
+/// Virtual nodes with an IPv4 address
+pub async fn decoys(graph: &Arc<Mutex<NetworkGraph>>) -> Vec<Decoy> {
+    graph.lock().await.nodes()
+        .filter(|node| node.node_type == NodeType::Virtual)
+        .filter_map(|node| Some(Decoy { mac: node.mac_address, ip: node.ipv4_address?, profile: node.honeypot.clone()? }))
+        .collect()
+}
+
+/// Ethernet address an IPv4 multicast group is delivered to (RFC 1112 §6.4)
+pub fn multicast_mac(group: Ipv4Addr) -> MacAddr {
+    let o = group.octets();
+    MacAddr::new(0x01, 0x00, 0x5e, o[1] & 0x7f, o[2], o[3])
+}
+
+
+/// Multicast and broadcast name queries (mDNS, LLMNR, NetBIOS), answered by every virtual node
+/// whose OS runs that name service. Queries sent to a node's own address reach it as
+/// ordinary UDP through its registry
+pub async fn handle_discovery_packet(
+    ethernet_packet: &EthernetPacket<'_>,
+    graph: &Arc<Mutex<NetworkGraph>>,
+    tx: Arc<Mutex<Box<dyn DataLinkSender + Send>>>,
+    reporter: &WsReporter,
+) {
+    let Some(ipv4_packet) = Ipv4Packet::new(ethernet_packet.payload()) else {
+        return;
+    };
+    if ipv4_packet.get_next_level_protocol() != IpNextHeaderProtocols::Udp {
+        return;
+    }
+    let Some(udp_packet) = UdpPacket::new(ipv4_packet.payload()) else {
+        return;
+    };
+    let (port, destination) = (udp_packet.get_destination(), ipv4_packet.get_destination());
+    let wanted = match port {
+        MDNS_PORT => destination == MDNS_GROUP,
+        LLMNR_PORT => destination == LLMNR_GROUP,
+        NBNS_PORT => ethernet_packet.get_destination() == MacAddr::broadcast(),
+        _ => false,
+    };
+    if !wanted {
+        return;
+    }
+
+    let source_mac = ethernet_packet.get_source();
+    let source = SocketAddrV4::new(ipv4_packet.get_source(), udp_packet.get_source());
+    let decoys = decoys(graph).await;
+    // Our own announcements, looped back by the capture
+    if source.ip().is_unspecified() || decoys.iter().any(|decoy| decoy.mac == source_mac) {
+        return;
+    }
+
+    let mut budget_charged = false;
+    for decoy in decoys {
+        let Some(emulator) = decoy.profile.udp_services.get(port) else {
+            continue;
+        };
+        if !budget_charged {
+            if let Budget::Exceeded(_) = charge((*source.ip()).into()).await {
+                return;
+            }
+            budget_charged = true;
+        }
+
+        let local = SocketAddrV4::new(decoy.ip, port);
+        let events = EventSink::new(emulator.name(), decoy.mac, local.into(), source_mac, source.into(), reporter.clone());
+        let Some(reply) = emulator.on_datagram(&events, udp_packet.payload()) else {
+            continue;
+        };
+        if !spend((*source.ip()).into(), reply.len()).await {
+            return;
+        }
+
+        info!("📣 {} reply for {} to {}", emulator.name(), decoy.profile.hostname, source);
+        let mut fields = decoy.profile.os.ipv4_fields();
+        // mDNS responders answer the group, unless the querier isn't an mDNS stack itself
+        if port == MDNS_PORT && source.port() == MDNS_PORT {
+            fields.ttl = MDNS_TTL;
+            let group = SocketAddrV4::new(MDNS_GROUP, MDNS_PORT);
+            send_udp_datagram(tx.clone(), decoy.mac, local, multicast_mac(MDNS_GROUP), group, fields, &reply).await;
+        } else {
+            send_udp_datagram(tx.clone(), decoy.mac, local, source_mac, source, fields, &reply).await;
+        }
+    }
+}
+
+
+/// Makes the virtual nodes discoverable to passive listeners as real devices are: an mDNS
+/// announcement of their name and services and, for UPnP devices, SSDP `NOTIFY` messages,
+/// when they appear and again before the announcements expire
+pub async fn advertise_periodically(graph: Arc<Mutex<NetworkGraph>>, tx: Arc<Mutex<Box<dyn DataLinkSender + Send>>>) {
+    let mut next: HashMap<MacAddr, Instant> = HashMap::new();
+    loop {
+        sleep(ADVERTISE_CHECK).await;
+
+        let decoys = decoys(&graph).await;
+        next.retain(|mac, _| decoys.iter().any(|decoy| decoy.mac == *mac));
+        for decoy in decoys {
+            if next.get(&decoy.mac).is_some_and(|due| Instant::now() < *due) {
+                continue;
+            }
+            advertise(&tx, &decoy).await;
+            let jitter = rand::rng().random_range(Duration::ZERO..=ADVERTISE_JITTER);
+            next.insert(decoy.mac, Instant::now() + ADVERTISE_PERIOD + jitter);
+        }
+    }
+}
+
+async fn advertise(tx: &Arc<Mutex<Box<dyn DataLinkSender + Send>>>, decoy: &Decoy) {
+    let udp = &decoy.profile.udp_services;
+    if udp.get(MDNS_PORT).is_some() {
+        let announcement = MdnsEmulator::new(&decoy.profile.hostname, decoy.profile.mdns_services.clone()).announcement(decoy.ip);
+        let mut fields = decoy.profile.os.ipv4_fields();
+        fields.ttl = MDNS_TTL;
+        let (local, group) = (SocketAddrV4::new(decoy.ip, MDNS_PORT), SocketAddrV4::new(MDNS_GROUP, MDNS_PORT));
+        send_udp_datagram(tx.clone(), decoy.mac, local, multicast_mac(MDNS_GROUP), group, fields, &announcement).await;
+        info!("📣 Nodo virtuale {} annunciato via mDNS come {}.local", decoy.mac, decoy.profile.hostname);
+    }
+
+    if udp.get(SSDP_PORT).is_some() && let Some(upnp) = &decoy.profile.os.upnp {
+        let (local, group) = (SocketAddrV4::new(decoy.ip, SSDP_PORT), SocketAddrV4::new(SSDP_GROUP, SSDP_PORT));
+        for notify in ssdp::notify_alive(decoy.mac, upnp, decoy.ip) {
+            let mut fields = decoy.profile.os.ipv4_fields();
+            fields.ttl = SSDP_TTL;
+            send_udp_datagram(tx.clone(), decoy.mac, local, multicast_mac(SSDP_GROUP), group, fields, &notify).await;
+        }
+        info!("📣 Nodo virtuale {} annunciato via SSDP", decoy.mac);
+    }
+}
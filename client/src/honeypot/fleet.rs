@@ -0,0 +1,329 @@
+use std::{collections::HashSet, env, fs, io, ops::RangeInclusive, time::SystemTime};
+
+use pnet::util::MacAddr;
+use rand::seq::IndexedRandom;
+use serde::Deserialize;
+
+use super::{allocation::{parse_range, Placement}, identity::HoneypotIdentity, persona::{self, Personality}, services::{banner::CATALOGUE, http::HttpPersona, udp::UDP_PORTS, EMULATED_PORTS}};
+
+const FLEET_FILE: &str = "src/honeypot/fleet.json";
+/// Path of an alternative fleet configuration
+const FLEET_VARIABLE: &str = "HONEYPOT_FLEET";
+/// Group of the decoys created before fleets were configurable
+pub const DEFAULT_GROUP: &str = "default";
+const DEFAULT_COUNT: usize = 10;
+const MAX_DECOYS: usize = 256;
+/// Prefix of the groups the adaptive density creates
+pub const ADAPTIVE_PREFIX: &str = "adaptive";
+
+
+/// Decoys the client runs, as groups of identical specifications
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct FleetConfig {
+    pub decoys: Vec<DecoyGroup>,
+    /// Extra decoys sized on the real hosts seen, on top of the groups
+    pub adaptive: Option<AdaptiveDensity>,
+}
+
+impl Default for FleetConfig {
+    fn default() -> Self {
+        FleetConfig {
+            decoys: vec![DecoyGroup { name: DEFAULT_GROUP.to_string(), count: DEFAULT_COUNT, ..Default::default() }],
+            adaptive: None,
+        }
+    }
+}
+
+/// Decoys per physical host observed, kept between `min` and `max`. They mirror the vendors
+/// of the hosts and spread over the address space nobody uses
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct AdaptiveDensity {
+    pub ratio: f64,
+    #[serde(default)]
+    pub min: usize,
+    pub max: usize,
+    /// Range the adaptive decoys take their addresses from, the whole subnet if left out
+    pub addresses: Option<String>,
+}
+
+/// `count` decoys built from the same specification. Everything left out is chosen per decoy,
+/// consistently with its MAC vendor
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct DecoyGroup {
+    pub name: String,
+    pub count: usize,
+    /// IPv4 address, `a-b` range or CIDR the decoys take their address from
+    pub addresses: Option<String>,
+    /// MAC prefix, `XX:XX:XX`
+    pub vendor: Option<String>,
+    pub personality: Option<String>,
+    pub banner_profile: Option<String>,
+    #[serde(default)]
+    pub hostnames: Vec<String>,
+    /// Open TCP ports, every emulated one if left out
+    pub tcp_ports: Option<Vec<u16>>,
+    /// Open UDP ports, every emulated one if left out
+    pub udp_ports: Option<Vec<u16>>,
+    #[serde(default)]
+    pub settings: ServiceSettings,
+    /// DNS-SD services announced over mDNS, derived from the open ports if left out
+    pub mdns_services: Option<Vec<AdvertisedService>>,
+    /// Place decoys in the largest unused blocks of addresses instead of at random
+    #[serde(default)]
+    pub spread: bool,
+}
+
+/// A DNS-SD service instance, e.g. a printer's `_ipp._tcp`
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct AdvertisedService {
+    /// Service type, `_name._tcp` or `_name._udp`
+    pub service: String,
+    pub port: u16,
+    /// Instance name, the node's hostname if left out
+    pub instance: Option<String>,
+    /// `key=value` entries of the TXT record
+    #[serde(default)]
+    pub txt: Vec<String>,
+}
+
+/// Per-service options of a group
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ServiceSettings {
+    pub ssh: Option<LoginSettings>,
+    pub telnet: Option<LoginSettings>,
+    pub http: Option<HttpSettings>,
+}
+
+/// Credentials that let attackers into the fake shell
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct LoginSettings {
+    pub credentials: Vec<Credential>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Credential {
+    pub username: String,
+    pub password: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct HttpSettings {
+    /// One of `apache_default`, `wordpress`, `router_admin`, `ip_camera`
+    pub persona: String,
+}
+
+impl LoginSettings {
+    pub fn pairs(&self) -> Vec<(String, String)> {
+        self.credentials.iter().map(|c| (c.username.clone(), c.password.clone())).collect()
+    }
+}
+
+
+/// Fleet configuration in use: `HONEYPOT_FLEET` if set, the shipped one otherwise
+pub fn config_path() -> String {
+    env::var(FLEET_VARIABLE).unwrap_or_else(|_| FLEET_FILE.to_string())
+}
+
+/// Last modification of the configuration, to notice edits
+pub fn modified(path: &str) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|metadata| metadata.modified()).ok()
+}
+
+/// Reads and validates the fleet configuration; every problem found is returned.
+/// Without a file the fleet is the historical one, ten decoys chosen freely
+pub fn load(path: &str) -> Result<FleetConfig, Vec<String>> {
+    let text = match fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(e) if e.kind() == io::ErrorKind::NotFound && path == FLEET_FILE => return Ok(FleetConfig::default()),
+        Err(e) => return Err(vec![format!("{}: {}", path, e)]),
+    };
+    let config: FleetConfig = serde_json::from_str(&text).map_err(|e| vec![format!("{}: {}", path, e)])?;
+
+    let problems = config.validate();
+    if problems.is_empty() { Ok(config) } else { Err(problems) }
+}
+
+impl FleetConfig {
+    pub fn group(&self, name: &str) -> Option<&DecoyGroup> {
+        self.decoys.iter().find(|group| group.name == name)
+    }
+
+    fn validate(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+        let mut names = HashSet::new();
+        for group in &self.decoys {
+            if !names.insert(group.name.as_str()) {
+                problems.push(format!("gruppo {} definito più volte", group.name));
+            }
+            if group.name.starts_with(ADAPTIVE_PREFIX) {
+                problems.push(format!("gruppo {}: i nomi che iniziano con {} sono riservati", group.name, ADAPTIVE_PREFIX));
+            }
+            problems.extend(group.validate().into_iter().map(|problem| format!("gruppo {}: {}", group.name, problem)));
+        }
+
+        if let Some(adaptive) = &self.adaptive {
+            if !(adaptive.ratio > 0.0 && adaptive.ratio.is_finite()) {
+                problems.push(format!("adaptive: rapporto {} non valido", adaptive.ratio));
+            }
+            if adaptive.min > adaptive.max {
+                problems.push(format!("adaptive: min {} maggiore di max {}", adaptive.min, adaptive.max));
+            }
+            if let Some(addresses) = &adaptive.addresses && parse_range(addresses).is_none_or(|range| range.end() < range.start()) {
+                problems.push(format!("adaptive: indirizzi {} non validi", addresses));
+            }
+        }
+
+        let total: usize = self.decoys.iter().map(|group| group.count).sum::<usize>() + self.adaptive.as_ref().map_or(0, |adaptive| adaptive.max);
+        if total > MAX_DECOYS {
+            problems.push(format!("{} nodi virtuali in totale, al massimo {}", total, MAX_DECOYS));
+        }
+        problems
+    }
+}
+
+impl DecoyGroup {
+    fn validate(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+        if self.name.trim().is_empty() {
+            problems.push("nome vuoto".to_string());
+        }
+
+        if let Some(addresses) = &self.addresses {
+            match parse_range(addresses) {
+                None => problems.push(format!("indirizzi {} non validi", addresses)),
+                Some(range) if range.end() < range.start() => problems.push(format!("intervallo {} vuoto", addresses)),
+                Some(range) if ((range.end() - range.start()) as usize) < self.count.saturating_sub(1) => {
+                    problems.push(format!("{} nodi non entrano in {}", self.count, addresses));
+                }
+                Some(_) => {}
+            }
+        }
+
+        if let Some(vendor) = &self.vendor && parse_vendor(vendor).is_none() {
+            problems.push(format!("vendor {} non valido, atteso XX:XX:XX", vendor));
+        }
+
+        let personality = self.personality.as_ref().and_then(|name| {
+            let found = persona::personality_named(name);
+            if found.is_none() {
+                problems.push(format!("personalità {} sconosciuta", name));
+            }
+            found
+        });
+        if let (Some(personality), Some(vendor)) = (personality, &self.vendor)
+            && !personality.vendors.is_empty()
+            && !personality.vendors.iter().any(|v| v.eq_ignore_ascii_case(vendor))
+        {
+            problems.push(format!("la personalità {} non è usata dal vendor {}", personality.name, vendor));
+        }
+
+        if let Some(name) = &self.banner_profile {
+            if CATALOGUE.profile_named(name).is_none() {
+                problems.push(format!("profilo banner {} sconosciuto", name));
+            } else if let Some(personality) = personality
+                && !personality.profiles.is_empty()
+                && !personality.profiles.contains(name)
+            {
+                problems.push(format!("il profilo banner {} non è adatto alla personalità {}", name, personality.name));
+            }
+        }
+
+        if self.hostnames.iter().any(|hostname| hostname.is_empty() || hostname.contains(char::is_whitespace)) {
+            problems.push("hostname vuoti o con spazi".to_string());
+        }
+
+        let banner_ports: Vec<u16> = CATALOGUE.profiles.iter()
+            .filter(|profile| self.banner_profile.as_ref().is_none_or(|name| profile.name == *name))
+            .flat_map(|profile| profile.services.iter().flat_map(|service| service.ports.iter().copied()))
+            .collect();
+        // UPnP device descriptions are served on a port each personality picks
+        let upnp_ports: Vec<u16> = persona::personalities().iter()
+            .filter(|candidate| personality.is_none_or(|chosen| chosen.name == candidate.name))
+            .filter_map(|candidate| candidate.upnp.as_ref().map(|upnp| upnp.port))
+            .collect();
+        for port in self.tcp_ports.iter().flatten() {
+            if !EMULATED_PORTS.contains(port) && !banner_ports.contains(port) && !upnp_ports.contains(port) {
+                problems.push(format!("nessun servizio TCP emulato sulla porta {}", port));
+            }
+        }
+        for port in self.udp_ports.iter().flatten() {
+            if !UDP_PORTS.contains(port) {
+                problems.push(format!("nessun servizio UDP emulato sulla porta {}", port));
+            }
+        }
+
+        for advertised in self.mdns_services.iter().flatten() {
+            let labels: Vec<&str> = advertised.service.split('.').collect();
+            if labels.len() != 2 || !labels[0].starts_with('_') || labels[0].len() < 2 || !["_tcp", "_udp"].contains(&labels[1]) {
+                problems.push(format!("servizio mDNS {} non valido, atteso _nome._tcp o _nome._udp", advertised.service));
+            }
+            if advertised.instance.as_ref().is_some_and(|instance| instance.is_empty() || instance.len() > 63 || instance.contains('.'))
+                || advertised.txt.iter().any(|entry| entry.is_empty() || entry.len() > 255)
+            {
+                problems.push(format!("istanza o TXT del servizio mDNS {} non validi", advertised.service));
+            }
+        }
+
+        for (service, login) in [("ssh", &self.settings.ssh), ("telnet", &self.settings.telnet)] {
+            if let Some(login) = login && login.credentials.iter().any(|c| c.username.is_empty()) {
+                problems.push(format!("credenziali {} senza username", service));
+            }
+        }
+        if let Some(http) = &self.settings.http && HttpPersona::named(&http.persona).is_none() {
+            let known: Vec<&str> = HttpPersona::ALL.iter().map(HttpPersona::name).collect();
+            problems.push(format!("persona HTTP {} sconosciuta, disponibili: {}", http.persona, known.join(", ")));
+        }
+        problems
+    }
+
+    pub fn range(&self) -> Option<RangeInclusive<u32>> {
+        self.addresses.as_deref().and_then(parse_range)
+    }
+
+    pub fn placement(&self) -> Placement {
+        Placement { within: self.range(), spread: self.spread }
+    }
+
+    pub fn personality(&self) -> Option<&'static Personality> {
+        self.personality.as_deref().and_then(persona::personality_named)
+    }
+
+    /// MAC prefix for a new decoy: the configured vendor, or one shipping the configured personality
+    pub fn vendor_for_new_decoy(&self) -> Option<String> {
+        if self.vendor.is_some() {
+            return self.vendor.clone();
+        }
+        self.personality()?.vendors.choose(&mut rand::rng()).cloned()
+    }
+
+    /// Whether a decoy still fits the group after the configuration changed. Services, hostnames
+    /// and settings are applied to the decoys kept, the rest of the identity can't change in place
+    pub fn admits(&self, identity: &HoneypotIdentity) -> bool {
+        let vendor = format!("{:02X}:{:02X}:{:02X}", identity.mac.0, identity.mac.1, identity.mac.2);
+        self.vendor.as_ref().is_none_or(|v| v.eq_ignore_ascii_case(&vendor))
+            && self.personality.as_ref().is_none_or(|p| *p == identity.personality)
+            && self.banner_profile.as_ref().is_none_or(|b| identity.banner_profile.as_ref() == Some(b))
+            && self.range().is_none_or(|range| identity.ipv4.is_none_or(|ip| range.contains(&u32::from(ip))))
+    }
+
+    /// Hostname of a decoy of the group, stable for its MAC
+    pub fn hostname_for_mac(&self, mac: MacAddr) -> Option<String> {
+        match self.hostnames.len() {
+            0 => None,
+            n => Some(self.hostnames[mac.5 as usize % n].clone()),
+        }
+    }
+}
+
+fn parse_vendor(vendor: &str) -> Option<[u8; 3]> {
+    let bytes: Vec<u8> = vendor.split(':').map(|b| if b.len() == 2 { u8::from_str_radix(b, 16).ok() } else { None }).collect::<Option<_>>()?;
+    bytes.try_into().ok()
+}
@@ -1,66 +1,96 @@
-use pnet::{datalink::DataLinkSender, packet::{arp::{ArpOperations, ArpPacket}, ethernet::{EtherTypes, EthernetPacket}, ip::IpNextHeaderProtocols, tcp::{TcpFlags, TcpPacket}, Packet}};
+use pnet::{datalink::DataLinkSender, packet::{ethernet::{EtherTypes, EthernetPacket}, ip::IpNextHeaderProtocols, ipv4::Ipv4Flags, ipv6::Ipv6Packet, tcp::TcpPacket, Packet}};
 use tokio::sync::Mutex;
-use tracing::error;
+use tracing::{debug, error, info};
 //use crate::{network::sender::send_arp_reply, honeypot::proxy::ssh::handle_ssh_connection};
-use crate::interfaces::sender::send_arp_reply;
+use crate::interfaces::{sender::TcpEndpoint, ws::WsReporter};
 use std::sync::Arc;
 
+use super::profile::HoneypotProfile;
+use super::icmp::{handle_icmp_packet, reject_protocol};
+use super::icmpv6::handle_icmpv6_packet;
 use super::tcp::handle_tcp_packet;
+use super::udp::handle_udp_packet;
 
 
 pub async fn handle_virtual_packet<'a>(
     ethernet_packet: EthernetPacket<'a>,
-    tx: Arc<Mutex<Box<dyn DataLinkSender + Send>>>
+    tx: Arc<Mutex<Box<dyn DataLinkSender + Send>>>,
+    profile: Arc<HoneypotProfile>,
+    reporter: WsReporter,
 ) {
 
     match ethernet_packet.get_ethertype() {
-        EtherTypes::Arp => {
-            if let Some(arp_packet) = ArpPacket::new(ethernet_packet.payload()) {
-                if arp_packet.get_operation() == ArpOperations::Request{
-
-                    let _ = send_arp_reply(
-                        ethernet_packet.get_destination(), 
-                        arp_packet.get_target_proto_addr(), 
-                        arp_packet.get_sender_hw_addr(),
-                        arp_packet.get_sender_proto_addr(),
-                        tx.clone()
-                    ).await;
-
-                }
-            }
-        }
+        // Answered for every virtual node by the ARP presence manager, unicast or broadcast
+        EtherTypes::Arp => {}
 
         EtherTypes::Ipv4 => {
             if let Some(ipv4_packet) = pnet::packet::ipv4::Ipv4Packet::new(ethernet_packet.payload()) {
+                // Fragments are not reassembled: the first would be answered from a truncated
+                // transport header and the others parsed as if they started with one
+                if ipv4_packet.get_fragment_offset() != 0 || ipv4_packet.get_flags() & Ipv4Flags::MoreFragments != 0 {
+                    debug!("🧩 Frammento IPv4 scartato da {}", ipv4_packet.get_source());
+                    return;
+                }
                 let next_protocol = ipv4_packet.get_next_level_protocol();
                 match next_protocol {
                     IpNextHeaderProtocols::Tcp => {
 
                         if let Some(tcp_packet) = TcpPacket::new(ipv4_packet.payload()) {
-                            if tcp_packet.get_destination() == 22 && tcp_packet.get_flags() != TcpFlags::SYN {
-                                /*handle_ssh_connection(
-                                    tx.clone(), 
-                                    *virtual_mac, 
-                                    *virtual_ip, 
-                                    *source, 
-                                    ipv4_packet.get_source(), 
-                                    tcp_packet
-                                ).await;*/
-                            }else {
-                                handle_tcp_packet(
-                                    tx.clone(), 
-                                    tcp_packet, 
-                                    ethernet_packet.get_destination(), 
-                                    ipv4_packet.get_destination(),
-                                    ipv4_packet.get_source(), 
-                                    ethernet_packet.get_source(),
-                                ).await;
-                            }   
+                            let local = TcpEndpoint::new(ethernet_packet.get_destination(), ipv4_packet.get_destination().into(), tcp_packet.get_destination());
+                            let remote = TcpEndpoint::new(ethernet_packet.get_source(), ipv4_packet.get_source().into(), tcp_packet.get_source());
+                            handle_tcp_packet(tx.clone(), tcp_packet, local, remote, &profile, &reporter).await;
                         }
                     }
+                    IpNextHeaderProtocols::Udp => {
+                        handle_udp_packet(
+                            tx.clone(),
+                            &ipv4_packet,
+                            ethernet_packet.get_destination(),
+                            ethernet_packet.get_source(),
+                            &profile,
+                            &reporter,
+                        ).await;
+                    }
+                    IpNextHeaderProtocols::Icmp => {
+                        handle_icmp_packet(
+                            tx.clone(),
+                            &ipv4_packet,
+                            ethernet_packet.get_destination(),
+                            ethernet_packet.get_source(),
+                            &profile,
+                            &reporter,
+                        ).await;
+                    }
                     _ => {
-                        error!("Protocollo IP non supportato: {:?}", next_protocol);
+                        info!("Protocollo IP non supportato: {:?}", next_protocol);
+                        reject_protocol(tx.clone(), &ipv4_packet, ethernet_packet.get_destination(), ethernet_packet.get_source(), &profile.os).await;
+                    }
+                }
+            }
+        }
+
+        EtherTypes::Ipv6 => {
+            if let Some(ipv6_packet) = Ipv6Packet::new(ethernet_packet.payload()) {
+                match ipv6_packet.get_next_header() {
+                    IpNextHeaderProtocols::Tcp => {
+                        if let Some(tcp_packet) = TcpPacket::new(ipv6_packet.payload()) {
+                            let local = TcpEndpoint::new(ethernet_packet.get_destination(), ipv6_packet.get_destination().into(), tcp_packet.get_destination());
+                            let remote = TcpEndpoint::new(ethernet_packet.get_source(), ipv6_packet.get_source().into(), tcp_packet.get_source());
+                            handle_tcp_packet(tx.clone(), tcp_packet, local, remote, &profile, &reporter).await;
+                        }
+                    }
+                    IpNextHeaderProtocols::Icmpv6 => {
+                        handle_icmpv6_packet(
+                            tx.clone(),
+                            &ipv6_packet,
+                            ethernet_packet.get_destination(),
+                            ethernet_packet.get_source(),
+                            &profile,
+                            &reporter,
+                        ).await;
                     }
+                    // Only TCP services and pings are emulated over IPv6
+                    next_header => info!("Next header IPv6 non supportato: {:?}", next_header),
                 }
             }
         }
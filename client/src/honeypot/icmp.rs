@@ -0,0 +1,145 @@
+use std::{net::SocketAddrV4, sync::Arc, time::{SystemTime, UNIX_EPOCH}};
+
+use common::types::HoneypotEvent;
+use pnet::{datalink::DataLinkSender, packet::{icmp::{self, IcmpPacket}, ipv4::Ipv4Packet, Packet}, util::MacAddr};
+use tokio::sync::Mutex;
+use tracing::info;
+use crate::{honeypot::{budget::{charge, is_unicast, spend, Budget}, persona::OsPersona, profile::HoneypotProfile, services::session::EventSink}, interfaces::{sender::send_icmp, ws::WsReporter}};
+
+const TYPE_ECHO_REPLY: u8 = 0;
+const TYPE_DESTINATION_UNREACHABLE: u8 = 3;
+const TYPE_ECHO_REQUEST: u8 = 8;
+const TYPE_TIMESTAMP: u8 = 13;
+const TYPE_TIMESTAMP_REPLY: u8 = 14;
+const TYPE_INFORMATION_REQUEST: u8 = 15;
+const TYPE_ADDRESS_MASK_REQUEST: u8 = 17;
+const TIMESTAMP_LEN: usize = 20;
+// Destination unreachable code for an IP protocol the host doesn't speak
+const PROTOCOL_UNREACHABLE: u8 = 2;
+// Like Linux, ICMP errors quote as much of the offending datagram as fits in 576 bytes (RFC 1812)
+const ICMP_ERROR_MAX_LEN: usize = 576;
+const IPV4_HEADER_LEN: usize = 20;
+const ICMP_HEADER_LEN: usize = 8;
+
+
+/// Answers pings and timestamp requests the way the node's OS would, reporting every query
+pub async fn handle_icmp_packet<'a>(
+    tx: Arc<Mutex<Box<dyn DataLinkSender + Send>>>,
+    ipv4_packet: &Ipv4Packet<'a>,
+    virtual_mac: MacAddr,
+    source_mac: MacAddr,
+    profile: &HoneypotProfile,
+    reporter: &WsReporter,
+) {
+    let Some(icmp_packet) = IcmpPacket::new(ipv4_packet.payload()) else {
+        return;
+    };
+    let source_ip = ipv4_packet.get_source();
+    let icmp_type = icmp_packet.get_icmp_type().0;
+    // Errors and replies are answers to someone else's traffic: never react to them (RFC 1122)
+    if !is_unicast(source_ip.into()) || !matches!(icmp_type, TYPE_ECHO_REQUEST | TYPE_TIMESTAMP | TYPE_INFORMATION_REQUEST | TYPE_ADDRESS_MASK_REQUEST) {
+        return;
+    }
+
+    let message = icmp_packet.packet();
+    let identifier = message.get(4..6).map(|b| u16::from_be_bytes([b[0], b[1]])).unwrap_or(0);
+    let sequence = message.get(6..8).map(|b| u16::from_be_bytes([b[0], b[1]])).unwrap_or(0);
+
+    if let Budget::Exceeded(_) = charge(source_ip.into()).await {
+        return;
+    }
+    EventSink::new(
+        "icmp",
+        virtual_mac,
+        SocketAddrV4::new(ipv4_packet.get_destination(), 0).into(),
+        source_mac,
+        SocketAddrV4::new(source_ip, 0).into(),
+        reporter.clone(),
+    ).record(HoneypotEvent::IcmpRequest { icmp_type, code: icmp_packet.get_icmp_code().0, identifier, sequence, size: message.len() });
+
+    let mut reply = message.to_vec();
+    match icmp_type {
+        TYPE_ECHO_REQUEST if reply.len() >= ICMP_HEADER_LEN => {
+            reply[0] = TYPE_ECHO_REPLY;
+            if !profile.os.echo_code {
+                reply[1] = 0;
+            }
+        }
+        TYPE_TIMESTAMP if profile.os.answers_timestamp && reply.len() >= TIMESTAMP_LEN => {
+            reply.truncate(TIMESTAMP_LEN);
+            reply[0] = TYPE_TIMESTAMP_REPLY;
+            reply[1] = 0;
+            let now = milliseconds_since_midnight().to_be_bytes();
+            reply[12..16].copy_from_slice(&now);
+            reply[16..20].copy_from_slice(&now);
+        }
+        // Information and address mask requests are obsolete, no current stack answers them
+        _ => return,
+    }
+    set_checksum(&mut reply);
+
+    if !spend(source_ip.into(), reply.len()).await {
+        return;
+    }
+    info!("🏓 ICMP type {} reply to {} as {}", reply[0], source_ip, profile.os.name);
+    send_icmp(tx, virtual_mac, ipv4_packet.get_destination(), source_mac, source_ip, profile.os.ipv4_fields(), &reply).await;
+}
+
+/// Answers a datagram of an IP protocol the node doesn't implement with protocol unreachable,
+/// as a real stack does (and as IP protocol scans expect)
+pub async fn reject_protocol<'a>(
+    tx: Arc<Mutex<Box<dyn DataLinkSender + Send>>>,
+    ipv4_packet: &Ipv4Packet<'a>,
+    virtual_mac: MacAddr,
+    source_mac: MacAddr,
+    os: &OsPersona,
+) {
+    if !is_unicast(ipv4_packet.get_source().into()) {
+        return;
+    }
+    if let Budget::Exceeded(_) = charge(ipv4_packet.get_source().into()).await {
+        return;
+    }
+    send_unreachable(tx, ipv4_packet, virtual_mac, source_mac, PROTOCOL_UNREACHABLE, os).await;
+}
+
+/// Destination unreachable quoting the offending datagram; the caller has charged the source's budget
+pub async fn send_unreachable<'a>(
+    tx: Arc<Mutex<Box<dyn DataLinkSender + Send>>>,
+    ipv4_packet: &Ipv4Packet<'a>,
+    virtual_mac: MacAddr,
+    source_mac: MacAddr,
+    code: u8,
+    os: &OsPersona,
+) {
+    // Only the first fragment carries the headers the sender needs to match the error
+    if ipv4_packet.get_fragment_offset() != 0 {
+        return;
+    }
+    let datagram = ipv4_packet.packet();
+    let total_length = (ipv4_packet.get_total_length() as usize).min(datagram.len());
+    let quoted = &datagram[..total_length.min(ICMP_ERROR_MAX_LEN - IPV4_HEADER_LEN - ICMP_HEADER_LEN)];
+
+    // Bytes 4..8 are unused for these codes, the quoted datagram follows them
+    let mut message = vec![TYPE_DESTINATION_UNREACHABLE, code, 0, 0, 0, 0, 0, 0];
+    message.extend_from_slice(quoted);
+    set_checksum(&mut message);
+
+    if !spend(ipv4_packet.get_source().into(), message.len()).await {
+        return;
+    }
+    send_icmp(tx, virtual_mac, ipv4_packet.get_destination(), source_mac, ipv4_packet.get_source(), os.ipv4_fields(), &message).await;
+}
+
+
+fn set_checksum(message: &mut [u8]) {
+    message[2..4].fill(0);
+    let checksum = IcmpPacket::new(message).map(|packet| icmp::checksum(&packet)).unwrap_or(0);
+    message[2..4].copy_from_slice(&checksum.to_be_bytes());
+}
+
+/// ICMP timestamps are milliseconds since midnight UTC (RFC 792)
+fn milliseconds_since_midnight() -> u32 {
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+    (now.as_millis() % 86_400_000) as u32
+}
@@ -0,0 +1,58 @@
+use std::{net::SocketAddr, sync::Arc};
+
+use common::types::HoneypotEvent;
+use pnet::{datalink::DataLinkSender, packet::{ipv6::Ipv6Packet, Packet}, util::MacAddr};
+use tokio::sync::Mutex;
+use tracing::info;
+use crate::{honeypot::{budget::{charge, is_unicast, spend, Budget}, profile::HoneypotProfile, services::session::EventSink}, interfaces::{sender::send_icmpv6, ws::WsReporter}};
+
+const TYPE_ECHO_REQUEST: u8 = 128;
+const TYPE_ECHO_REPLY: u8 = 129;
+const ECHO_HEADER_LEN: usize = 8;
+
+
+/// Answers pings to the node's IPv6 addresses, reporting every one. Neighbor discovery is
+/// handled for every node by the NDP responder
+pub async fn handle_icmpv6_packet<'a>(
+    tx: Arc<Mutex<Box<dyn DataLinkSender + Send>>>,
+    ipv6_packet: &Ipv6Packet<'a>,
+    virtual_mac: MacAddr,
+    source_mac: MacAddr,
+    profile: &HoneypotProfile,
+    reporter: &WsReporter,
+) {
+    let message = ipv6_packet.payload();
+    let source_ip = ipv6_packet.get_source();
+    let virtual_ip = ipv6_packet.get_destination();
+    if !is_unicast(source_ip.into()) || virtual_ip.is_multicast() || message.len() < ECHO_HEADER_LEN || message[0] != TYPE_ECHO_REQUEST {
+        return;
+    }
+
+    let identifier = u16::from_be_bytes([message[4], message[5]]);
+    let sequence = u16::from_be_bytes([message[6], message[7]]);
+
+    if let Budget::Exceeded(_) = charge(source_ip.into()).await {
+        return;
+    }
+    EventSink::new(
+        "icmpv6",
+        virtual_mac,
+        SocketAddr::new(virtual_ip.into(), 0),
+        source_mac,
+        SocketAddr::new(source_ip.into(), 0),
+        reporter.clone(),
+    ).record(HoneypotEvent::IcmpRequest { icmp_type: TYPE_ECHO_REQUEST, code: message[1], identifier, sequence, size: message.len() });
+
+    let mut reply = message.to_vec();
+    reply[0] = TYPE_ECHO_REPLY;
+    if !profile.os.echo_code {
+        reply[1] = 0;
+    }
+
+    if !spend(source_ip.into(), reply.len()).await {
+        return;
+    }
+    info!("🏓 ICMPv6 echo reply to {} as {}", source_ip, profile.os.name);
+    // The hop limit starts where the OS starts its IPv4 TTL
+    send_icmpv6(tx, virtual_mac, virtual_ip, source_mac, source_ip, profile.os.ttl, &reply).await;
+}
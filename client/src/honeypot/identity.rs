@@ -0,0 +1,119 @@
+use std::{fs, io, net::Ipv4Addr, str::FromStr, sync::Arc};
+
+use pnet::util::MacAddr;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use tokio::sync::Mutex;
+use tracing::{error, info};
+
+use crate::{graph::types::NetworkGraph, honeypot::{fleet::DEFAULT_GROUP, services::{ssh, tls}}, state::write_json_atomic};
+
+const IDENTITY_FILE: &str = "state/honeypots.json";
+
+
+/// What makes a virtual node recognisable on the network, kept across restarts so decoys
+/// don't change MAC, address or fingerprint every time the client starts. Its IPv6 addresses
+/// derive from the MAC, its SSH host key and TLS certificate are stored under `state/` by MAC
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HoneypotIdentity {
+    #[serde(serialize_with = "serialize_mac", deserialize_with = "deserialize_mac")]
+    pub mac: MacAddr,
+    /// Fleet group the node was created for
+    #[serde(default = "default_group")]
+    pub group: String,
+    /// Address to claim again first
+    pub ipv4: Option<Ipv4Addr>,
+    pub personality: String,
+    pub banner_profile: Option<String>,
+    #[serde(default)]
+    pub hostname: Option<String>,
+    pub tcp_ports: Vec<u16>,
+    pub udp_ports: Vec<u16>,
+}
+
+/// On-disk format of the identity file
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct IdentityFile {
+    honeypots: Vec<HoneypotIdentity>,
+}
+
+fn default_group() -> String {
+    DEFAULT_GROUP.to_string()
+}
+
+fn serialize_mac<S: Serializer>(mac: &MacAddr, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(&mac.to_string())
+}
+
+fn deserialize_mac<'de, D: Deserializer<'de>>(deserializer: D) -> Result<MacAddr, D::Error> {
+    let text = String::deserialize(deserializer)?;
+    MacAddr::from_str(&text).map_err(serde::de::Error::custom)
+}
+
+
+/// Identities saved by the previous run; none on the first start. An unreadable file is
+/// set aside rather than overwritten, so the identities in it can be recovered by hand
+pub fn load() -> Vec<HoneypotIdentity> {
+    let text = match fs::read_to_string(IDENTITY_FILE) {
+        Ok(text) => text,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Vec::new(),
+        Err(e) => {
+            error!("❌ Impossibile leggere le identità dei nodi virtuali {}: {}", IDENTITY_FILE, e);
+            return Vec::new();
+        }
+    };
+
+    match serde_json::from_str::<IdentityFile>(&text) {
+        Ok(file) => file.honeypots,
+        Err(e) => {
+            let aside = format!("{}.corrupt", IDENTITY_FILE);
+            error!("❌ Identità dei nodi virtuali non valide in {} ({}), spostate in {}", IDENTITY_FILE, e, aside);
+            let _ = fs::rename(IDENTITY_FILE, aside);
+            Vec::new()
+        }
+    }
+}
+
+/// Identities of the virtual nodes currently in the graph, ordered by MAC
+pub fn snapshot(graph: &NetworkGraph) -> Vec<HoneypotIdentity> {
+    let mut honeypots: Vec<HoneypotIdentity> = graph.nodes()
+        .filter_map(|node| {
+            let profile = node.honeypot.as_ref()?;
+            Some(HoneypotIdentity {
+                mac: node.mac_address,
+                group: profile.group.clone(),
+                ipv4: node.ipv4_address,
+                personality: profile.os.name.clone(),
+                banner_profile: profile.banner_profile.clone(),
+                hostname: Some(profile.hostname.clone()),
+                tcp_ports: profile.services.ports(),
+                udp_ports: profile.udp_services.ports(),
+            })
+        })
+        .collect();
+    honeypots.sort_by_key(|identity| identity.mac.octets());
+    honeypots
+}
+
+/// Writes the identities of the virtual nodes currently in the graph
+pub async fn save(graph: &Arc<Mutex<NetworkGraph>>) {
+    let honeypots = snapshot(&*graph.lock().await);
+    if let Err(e) = write_json_atomic(IDENTITY_FILE, &IdentityFile { honeypots }) {
+        error!("❌ Impossibile salvare le identità dei nodi virtuali {}: {}", IDENTITY_FILE, e);
+    }
+}
+
+/// Forgets every saved identity together with its host key and certificates:
+/// the next start creates decoys with new MACs, addresses and keys
+pub fn rotate() {
+    let identities = load();
+    for identity in &identities {
+        ssh::remove_host_key(identity.mac);
+        tls::remove_certificate(identity.mac);
+    }
+
+    match fs::remove_file(IDENTITY_FILE) {
+        Ok(()) => info!("🔄 {} identità dei nodi virtuali rimosse, al prossimo avvio ne verranno create di nuove", identities.len()),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => info!("🔄 Nessuna identità dei nodi virtuali salvata in {}", IDENTITY_FILE),
+        Err(e) => error!("❌ Impossibile rimuovere {}: {}", IDENTITY_FILE, e),
+    }
+}
@@ -1,4 +1,18 @@
+pub mod allocation;
+pub mod arp;
+pub mod budget;
+pub mod fleet;
 pub mod handler;
+pub mod icmp;
+pub mod icmpv6;
+pub mod identity;
+pub mod ndp;
 pub mod tcp;
+pub mod udp;
 pub mod create_honeypots;
-//pub mod proxy;
\ No newline at end of file
+pub mod density;
+pub mod discovery;
+pub mod persona;
+pub mod profile;
+pub mod services;
+pub mod shell;
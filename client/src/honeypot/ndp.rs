@@ -0,0 +1,370 @@
+use std::{collections::HashMap, env, net::Ipv6Addr, sync::Arc, time::{Duration, Instant}};
+
+use lazy_static::lazy_static;
+use pnet::{datalink::DataLinkSender, packet::{ethernet::EthernetPacket, icmpv6::Icmpv6Packet, ip::IpNextHeaderProtocols, ipv6::Ipv6Packet, Packet}, util::MacAddr};
+use tokio::{sync::Mutex, time::sleep};
+use tracing::{info, warn};
+
+use crate::{graph::types::NetworkGraph, honeypot::arp::allow_reply, interfaces::sender::send_icmpv6};
+
+/// Set to `0` to keep decoys on their link-local address, ignoring router advertisements
+const SLAAC_VARIABLE: &str = "HONEYPOT_SLAAC";
+
+const TYPE_ROUTER_ADVERTISEMENT: u8 = 134;
+const TYPE_NEIGHBOR_SOLICITATION: u8 = 135;
+const TYPE_NEIGHBOR_ADVERTISEMENT: u8 = 136;
+const OPTION_SOURCE_LINK_ADDRESS: u8 = 1;
+const OPTION_TARGET_LINK_ADDRESS: u8 = 2;
+const OPTION_PREFIX_INFORMATION: u8 = 3;
+const FLAG_SOLICITED: u8 = 0x40;
+const FLAG_OVERRIDE: u8 = 0x20;
+const FLAG_AUTONOMOUS: u8 = 0x40;
+// Neighbor discovery is only valid with the maximum hop limit, proof it wasn't routed (RFC 4861 §7.1)
+const NDP_HOP_LIMIT: u8 = 255;
+// Fields of a router advertisement before its options
+const RA_HEADER_LEN: usize = 12;
+// Fields of a solicitation or advertisement before its options
+const NEIGHBOR_HEADER_LEN: usize = 20;
+// How long a tentative address waits for a duplicate (RetransTimer, RFC 4861 §10)
+const DAD_TIMEOUT: Duration = Duration::from_secs(1);
+// SLAAC addresses a node takes besides its link-local one, however many prefixes routers offer
+const MAX_SLAAC_ADDRESSES: usize = 4;
+// Prefixes configured from one router advertisement, the others are ignored
+const MAX_PREFIXES_PER_RA: usize = 4;
+const INFINITE_LIFETIME: u32 = u32::MAX;
+// Shortest valid lifetime an advertisement can cut an address down to (RFC 4862 §5.5.3 e)
+const MIN_VALID_LIFETIME: Duration = Duration::from_secs(2 * 60 * 60);
+// How often addresses past their valid lifetime are removed
+const EXPIRY_CHECK: Duration = Duration::from_secs(60);
+
+const ALL_NODES: Ipv6Addr = Ipv6Addr::new(0xff02, 0, 0, 0, 0, 0, 0, 1);
+
+
+lazy_static! {
+    // Addresses going through duplicate address detection
+    static ref TENTATIVE: Mutex<HashMap<Ipv6Addr, Tentative>> = Mutex::new(HashMap::new());
+    // End of the valid lifetime of each SLAAC address, none if infinite
+    static ref EXPIRES: Mutex<HashMap<Ipv6Addr, Option<Instant>>> = Mutex::new(HashMap::new());
+}
+
+struct Tentative {
+    mac: MacAddr,
+    duplicate: bool,
+}
+
+/// Link-local address of a node, with the modified EUI-64 interface identifier of its MAC (RFC 4291)
+pub fn link_local_address(mac: MacAddr) -> Ipv6Addr {
+    eui64_address([0xfe, 0x80, 0, 0, 0, 0, 0, 0], mac)
+}
+
+fn eui64_address(prefix: [u8; 8], mac: MacAddr) -> Ipv6Addr {
+    let mut octets = [0u8; 16];
+    octets[..8].copy_from_slice(&prefix);
+    octets[8..].copy_from_slice(&[mac.0 ^ 0x02, mac.1, mac.2, 0xff, 0xfe, mac.3, mac.4, mac.5]);
+    Ipv6Addr::from(octets)
+}
+
+/// Ethernet address a multicast group is delivered to (RFC 2464)
+fn multicast_mac(group: Ipv6Addr) -> MacAddr {
+    let o = group.octets();
+    MacAddr::new(0x33, 0x33, o[12], o[13], o[14], o[15])
+}
+
+fn solicited_node_group(address: Ipv6Addr) -> Ipv6Addr {
+    let o = address.octets();
+    Ipv6Addr::from([0xff, 0x02, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0x01, 0xff, o[13], o[14], o[15]])
+}
+
+fn is_link_local(address: &Ipv6Addr) -> bool {
+    address.octets()[..2] == [0xfe, 0x80]
+}
+
+/// Expiry of an address renewed by an advertisement (RFC 4862 §5.5.3 e): below two hours a
+/// lifetime can only be shortened by the router, so a forged one can't make it expire at once
+fn renewed_expiry(current: Option<Instant>, lifetime: Option<Duration>, now: Instant) -> Option<Instant> {
+    let lifetime = lifetime?;
+    // None for an infinite lifetime
+    let remaining = current.map(|expiry| expiry.saturating_duration_since(now));
+    if lifetime > MIN_VALID_LIFETIME || remaining.is_some_and(|remaining| lifetime > remaining) {
+        Some(now + lifetime)
+    } else if remaining.is_some_and(|remaining| remaining <= MIN_VALID_LIFETIME) {
+        current
+    } else {
+        Some(now + MIN_VALID_LIFETIME)
+    }
+}
+
+fn slaac_enabled() -> bool {
+    !matches!(env::var(SLAAC_VARIABLE).as_deref(), Ok("0"))
+}
+
+
+/// Neighbor discovery for the virtual nodes: answers solicitations for their addresses, gives way
+/// to hosts claiming the same address and, unless disabled, configures SLAAC addresses from the
+/// router advertisements seen on the link
+pub async fn handle_ndp_packet(
+    ethernet_packet: &EthernetPacket<'_>,
+    graph: &Arc<Mutex<NetworkGraph>>,
+    tx: Arc<Mutex<Box<dyn DataLinkSender + Send>>>,
+) {
+    let Some(ipv6_packet) = Ipv6Packet::new(ethernet_packet.payload()) else {
+        return;
+    };
+    if ipv6_packet.get_next_header() != IpNextHeaderProtocols::Icmpv6 || ipv6_packet.get_hop_limit() != NDP_HOP_LIMIT {
+        return;
+    }
+    let Some(icmpv6_packet) = Icmpv6Packet::new(ipv6_packet.payload()) else {
+        return;
+    };
+
+    let source_mac = ethernet_packet.get_source();
+    let body = icmpv6_packet.payload();
+    match icmpv6_packet.get_icmpv6_type().0 {
+        TYPE_NEIGHBOR_SOLICITATION => on_solicitation(ipv6_packet.get_source(), source_mac, body, graph, tx).await,
+        TYPE_NEIGHBOR_ADVERTISEMENT => on_advertisement(source_mac, body, graph).await,
+        TYPE_ROUTER_ADVERTISEMENT if slaac_enabled() => on_router_advertisement(body, graph, tx).await,
+        _ => {}
+    }
+}
+
+async fn on_solicitation(
+    source_ip: Ipv6Addr,
+    source_mac: MacAddr,
+    body: &[u8],
+    graph: &Arc<Mutex<NetworkGraph>>,
+    tx: Arc<Mutex<Box<dyn DataLinkSender + Send>>>,
+) {
+    let Some(target) = target_address(body) else {
+        return;
+    };
+    // Unspecified source: another host running duplicate address detection for `target`
+    if source_ip.is_unspecified() && let Some(tentative) = TENTATIVE.lock().await.get_mut(&target) && tentative.mac != source_mac {
+        tentative.duplicate = true;
+        return;
+    }
+
+    let Some(virtual_mac) = graph.lock().await.find_virtual_by_ipv6(target) else {
+        return;
+    };
+    if source_mac == virtual_mac {
+        return;
+    }
+    if source_ip.is_unspecified() {
+        // As with IPv4, the decoy moves out of the way of a real host rather than defending
+        release(graph, virtual_mac, target, source_mac).await;
+        return;
+    }
+    if !allow_reply(source_mac).await {
+        return;
+    }
+
+    let requester_mac = link_layer_option(&body[NEIGHBOR_HEADER_LEN..], OPTION_SOURCE_LINK_ADDRESS).unwrap_or(source_mac);
+    advertise(tx, virtual_mac, target, requester_mac, source_ip, FLAG_SOLICITED | FLAG_OVERRIDE).await;
+}
+
+async fn on_advertisement(source_mac: MacAddr, body: &[u8], graph: &Arc<Mutex<NetworkGraph>>) {
+    let Some(target) = target_address(body) else {
+        return;
+    };
+    if let Some(tentative) = TENTATIVE.lock().await.get_mut(&target) && tentative.mac != source_mac {
+        tentative.duplicate = true;
+        return;
+    }
+
+    let owner = graph.lock().await.find_virtual_by_ipv6(target);
+    if let Some(virtual_mac) = owner && virtual_mac != source_mac {
+        release(graph, virtual_mac, target, source_mac).await;
+    }
+}
+
+async fn on_router_advertisement(body: &[u8], graph: &Arc<Mutex<NetworkGraph>>, tx: Arc<Mutex<Box<dyn DataLinkSender + Send>>>) {
+    let Some(options) = body.get(RA_HEADER_LEN..) else {
+        return;
+    };
+
+    let prefixes = parse_options(options).into_iter()
+        .filter(|(kind, option)| *kind == OPTION_PREFIX_INFORMATION && option.len() == 32)
+        .filter_map(|(_, option)| {
+            let valid_lifetime = u32::from_be_bytes([option[4], option[5], option[6], option[7]]);
+            let prefix: [u8; 8] = option[16..24].try_into().unwrap();
+            // SLAAC needs a /64 the router lets hosts configure themselves in (RFC 4862 §5.5.3)
+            let usable = option[2] == 64 && option[3] & FLAG_AUTONOMOUS != 0 && valid_lifetime != 0 && prefix[..2] != [0xfe, 0x80];
+            let lifetime = (valid_lifetime != INFINITE_LIFETIME).then(|| Duration::from_secs(valid_lifetime.into()));
+            usable.then_some((prefix, lifetime))
+        })
+        .take(MAX_PREFIXES_PER_RA);
+
+    for (prefix, lifetime) in prefixes {
+        let (missing, configured) = {
+            let g = graph.lock().await;
+            let mut missing = Vec::new();
+            let mut configured = Vec::new();
+            for mac in g.virtual_macs() {
+                let address = eui64_address(prefix, mac);
+                let Some(node) = g.node(mac) else {
+                    continue;
+                };
+                if node.ipv6_addresses.contains(&address) {
+                    configured.push(address);
+                } else if node.ipv6_addresses.iter().filter(|owned| !is_link_local(owned)).count() < MAX_SLAAC_ADDRESSES
+                    && g.find_virtual_by_ipv6(address).is_none()
+                {
+                    missing.push((mac, address));
+                }
+            }
+            (missing, configured)
+        };
+
+        {
+            let mut expires = EXPIRES.lock().await;
+            let now = Instant::now();
+            for address in configured {
+                let current = expires.get(&address).copied().flatten();
+                expires.insert(address, renewed_expiry(current, lifetime, now));
+            }
+        }
+        for (mac, address) in missing {
+            tokio::spawn(claim(graph.clone(), tx.clone(), mac, address, lifetime));
+        }
+    }
+}
+
+/// Duplicate address detection for a new address of a virtual node (RFC 4862 §5.4),
+/// then announces it with an unsolicited advertisement
+async fn claim(graph: Arc<Mutex<NetworkGraph>>, tx: Arc<Mutex<Box<dyn DataLinkSender + Send>>>, mac: MacAddr, address: Ipv6Addr, lifetime: Option<Duration>) {
+    {
+        let mut tentative = TENTATIVE.lock().await;
+        if tentative.contains_key(&address) {
+            return;
+        }
+        tentative.insert(address, Tentative { mac, duplicate: false });
+    }
+
+    let group = solicited_node_group(address);
+    let mut solicitation = vec![TYPE_NEIGHBOR_SOLICITATION, 0, 0, 0, 0, 0, 0, 0];
+    solicitation.extend_from_slice(&address.octets());
+    send_icmpv6(tx.clone(), mac, Ipv6Addr::UNSPECIFIED, multicast_mac(group), group, NDP_HOP_LIMIT, &solicitation).await;
+    sleep(DAD_TIMEOUT).await;
+
+    if TENTATIVE.lock().await.remove(&address).is_some_and(|tentative| tentative.duplicate) {
+        warn!("⚠️ Indirizzo {} già in uso sulla rete, il nodo {} non lo configura", address, mac);
+        return;
+    }
+    {
+        let mut g = graph.lock().await;
+        let Some(node) = g.get_node_by_mac(mac) else {
+            return;
+        };
+        // Other prefixes may have filled the node up during detection
+        if node.ipv6_addresses.contains(&address) || node.ipv6_addresses.iter().filter(|owned| !is_link_local(owned)).count() >= MAX_SLAAC_ADDRESSES {
+            return;
+        }
+        node.ipv6_addresses.push(address);
+    }
+    EXPIRES.lock().await.insert(address, lifetime.map(|lifetime| Instant::now() + lifetime));
+
+    info!("🌐 Nodo virtuale {} configurato con {} (SLAAC)", mac, address);
+    advertise(tx, mac, address, multicast_mac(ALL_NODES), ALL_NODES, FLAG_OVERRIDE).await;
+}
+
+async fn release(graph: &Arc<Mutex<NetworkGraph>>, virtual_mac: MacAddr, address: Ipv6Addr, claimant: MacAddr) {
+    if let Some(node) = graph.lock().await.get_node_by_mac(virtual_mac) {
+        node.ipv6_addresses.retain(|owned| *owned != address);
+    }
+    EXPIRES.lock().await.remove(&address);
+    warn!("⚠️ {} usa l'indirizzo {}, rimosso dal nodo virtuale {}", claimant, address, virtual_mac);
+}
+
+/// Removes SLAAC addresses once their valid lifetime is over, as hosts do when the
+/// router stops advertising the prefix
+pub async fn expire_periodically(graph: Arc<Mutex<NetworkGraph>>) {
+    loop {
+        sleep(EXPIRY_CHECK).await;
+
+        let expired: Vec<Ipv6Addr> = {
+            let mut expires = EXPIRES.lock().await;
+            let now = Instant::now();
+            let expired = expires.iter()
+                .filter(|(_, expiry)| expiry.is_some_and(|expiry| expiry <= now))
+                .map(|(address, _)| *address)
+                .collect::<Vec<_>>();
+            for address in &expired {
+                expires.remove(address);
+            }
+            expired
+        };
+
+        let mut g = graph.lock().await;
+        for address in expired {
+            let Some(mac) = g.find_virtual_by_ipv6(address) else {
+                continue;
+            };
+            if let Some(node) = g.get_node_by_mac(mac) {
+                node.ipv6_addresses.retain(|owned| *owned != address);
+                info!("⌛ Indirizzo {} del nodo virtuale {} scaduto", address, mac);
+            }
+        }
+    }
+}
+
+async fn advertise(
+    tx: Arc<Mutex<Box<dyn DataLinkSender + Send>>>,
+    virtual_mac: MacAddr,
+    target: Ipv6Addr,
+    destination_mac: MacAddr,
+    destination_ip: Ipv6Addr,
+    flags: u8,
+) {
+    let mut advertisement = vec![TYPE_NEIGHBOR_ADVERTISEMENT, 0, 0, 0, flags, 0, 0, 0];
+    advertisement.extend_from_slice(&target.octets());
+    advertisement.extend_from_slice(&[OPTION_TARGET_LINK_ADDRESS, 1]);
+    advertisement.extend_from_slice(&virtual_mac.octets());
+    send_icmpv6(tx, virtual_mac, target, destination_mac, destination_ip, NDP_HOP_LIMIT, &advertisement).await;
+}
+
+/// Target address of a solicitation or advertisement, after the 4 bytes of flags
+fn target_address(body: &[u8]) -> Option<Ipv6Addr> {
+    let octets: [u8; 16] = body.get(4..NEIGHBOR_HEADER_LEN)?.try_into().ok()?;
+    Some(Ipv6Addr::from(octets))
+}
+
+fn link_layer_option(options: &[u8], kind: u8) -> Option<MacAddr> {
+    parse_options(options).into_iter()
+        .find(|(found, option)| *found == kind && option.len() == 8)
+        .map(|(_, option)| MacAddr::new(option[2], option[3], option[4], option[5], option[6], option[7]))
+}
+
+/// Type and bytes of each option; the length field counts units of 8 bytes
+fn parse_options(mut options: &[u8]) -> Vec<(u8, &[u8])> {
+    let mut parsed = Vec::new();
+    while options.len() >= 8 {
+        let len = options[1] as usize * 8;
+        if len == 0 || len > options.len() {
+            break;
+        }
+        parsed.push((options[0], &options[..len]));
+        options = &options[len..];
+    }
+    parsed
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn short_lifetimes_only_shorten_short_ones() {
+        let now = Instant::now();
+        let hour = Duration::from_secs(3600);
+
+        // Longer than two hours, or than what is left, is taken as is
+        assert_eq!(renewed_expiry(Some(now + hour), Some(3 * hour), now), Some(now + 3 * hour));
+        assert_eq!(renewed_expiry(Some(now + hour), Some(hour + hour / 2), now), Some(now + hour + hour / 2));
+        // A forged short lifetime stops at two hours, and can't cut one already shorter
+        assert_eq!(renewed_expiry(Some(now + 24 * hour), Some(Duration::from_secs(1)), now), Some(now + MIN_VALID_LIFETIME));
+        assert_eq!(renewed_expiry(None, Some(Duration::from_secs(1)), now), Some(now + MIN_VALID_LIFETIME));
+        assert_eq!(renewed_expiry(Some(now + hour), Some(Duration::from_secs(1)), now), Some(now + hour));
+        assert_eq!(renewed_expiry(Some(now + hour), None, now), None);
+    }
+}
@@ -0,0 +1,384 @@
+use std::{fs::File, io::BufReader, net::IpAddr, ops::Deref, sync::{atomic::{AtomicU16, Ordering}, Arc}, time::Instant};
+
+use lazy_static::lazy_static;
+use pnet::{packet::tcp::TcpOption, util::MacAddr};
+use serde::Deserialize;
+use tracing::error;
+
+use crate::interfaces::sender::Ipv4Fields;
+
+const PERSONALITY_DATABASE: &str = "src/honeypot/personalities.json";
+
+
+lazy_static! {
+    /// OS personalities, loaded once and shared by every node
+    static ref PERSONALITIES: Vec<Personality> = load(PERSONALITY_DATABASE);
+    // Reference instant of the nodes' TCP timestamp clocks
+    static ref CLOCK_START: Instant = Instant::now();
+}
+
+/// On-disk format of the personality database
+#[derive(Debug, Deserialize)]
+struct DatabaseFile {
+    personalities: Vec<Personality>,
+}
+
+/// How a stack fills the IPv4 identification field
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum IpIdMode {
+    /// One counter shared by every packet the host sends (Windows, older Linux)
+    Incremental,
+    /// A fresh random value per packet (macOS, OpenBSD)
+    Random,
+    /// Zero on TCP packets with DF set, a counter elsewhere (modern Linux)
+    Zero,
+}
+
+/// TCP options a personality can put in its SYN and SYN-ACK, in the order listed
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OptionKind {
+    Mss,
+    Nop,
+    Wscale,
+    Sack,
+    Timestamp,
+}
+
+/// Local name resolution protocols a host answers for its own name
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum NameService {
+    /// Multicast DNS and DNS-SD (Apple, Avahi, Windows 10+)
+    Mdns,
+    /// Link-Local Multicast Name Resolution (Windows, systemd-resolved)
+    Llmnr,
+    /// NetBIOS name service (Windows)
+    Nbns,
+}
+
+/// TCP stack behaviour of a personality
+#[derive(Debug, Clone, Deserialize)]
+pub struct TcpPersonality {
+    /// Window advertised in the SYN-ACK
+    pub window: u16,
+    pub mss: u16,
+    /// Window scale shift offered, `None` if the stack doesn't scale
+    pub wscale: Option<u8>,
+    pub sack: bool,
+    pub timestamps: bool,
+    /// Tick rate of the timestamp clock
+    pub timestamp_hz: u32,
+    pub options: Vec<OptionKind>,
+    /// Whether segments without ACK (FIN, NULL, Xmas scans) to an open port get a reset
+    /// instead of being dropped (Windows)
+    pub reset_stray: bool,
+}
+
+/// How the OS's SSH server presents itself
+#[derive(Debug, Clone, Deserialize)]
+pub struct SshPersonality {
+    /// Identification string sent before the key exchange
+    pub server_id: String,
+    /// Distribution the login banner welcomes to, none on systems that don't print one
+    pub release: Option<String>,
+    /// Whether logins land in a BusyBox shell, as on embedded devices
+    pub busybox: bool,
+}
+
+impl Default for SshPersonality {
+    fn default() -> Self {
+        SshPersonality {
+            server_id: "SSH-2.0-OpenSSH_8.9p1 Ubuntu-3ubuntu0.6".to_string(),
+            release: Some("Ubuntu 22.04.4 LTS".to_string()),
+            busybox: false,
+        }
+    }
+}
+
+/// System group the OS's SNMP agent exposes
+#[derive(Debug, Clone, Deserialize)]
+pub struct SnmpPersonality {
+    /// `sysDescr`, with `{hostname}` replaced by the node's name
+    pub descr: String,
+    pub object_id: String,
+    pub contact: String,
+    pub location: String,
+    /// `sysServices` bitmask of the OSI layers the device works at
+    pub services: i64,
+}
+
+impl Default for SnmpPersonality {
+    fn default() -> Self {
+        SnmpPersonality {
+            descr: "Linux {hostname} 5.15.0-105-generic #115-Ubuntu SMP Mon Apr 15 09:52:04 UTC 2024 x86_64".to_string(),
+            object_id: "1.3.6.1.4.1.8072.3.2.10".to_string(),
+            contact: "Me <me@example.org>".to_string(),
+            location: "Sitting on the Dock of the Bay".to_string(),
+            services: 72,
+        }
+    }
+}
+
+/// What the OS's DNS server says about itself
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct DnsPersonality {
+    /// `version.bind` answer, none for servers that refuse it
+    pub version: Option<String>,
+}
+
+/// Identity of the OS's UPnP stack in SSDP messages and in its device description
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct UpnpPersonality {
+    pub server: String,
+    pub device_type: String,
+    /// TCP port and path of the device description SSDP points to
+    pub port: u16,
+    pub path: String,
+    pub friendly_name: String,
+    pub manufacturer: String,
+    pub model_name: String,
+}
+
+/// Network stack behaviour of one OS, as described in the personality database
+#[derive(Debug, Clone, Deserialize)]
+pub struct Personality {
+    pub name: String,
+    #[serde(default)]
+    pub description: String,
+    /// MAC prefixes of the vendors that ship this OS; empty for the fallback personality
+    pub vendors: Vec<String>,
+    /// Banner profiles that fit the OS; empty for any
+    pub profiles: Vec<String>,
+    #[serde(default)]
+    pub name_services: Vec<NameService>,
+    pub ttl: u8,
+    pub ip_id: IpIdMode,
+    pub dont_fragment: bool,
+    /// Whether ICMP timestamp requests get a reply
+    pub answers_timestamp: bool,
+    /// Whether echo replies copy a non-zero code from the request (nmap's IE probe)
+    pub echo_code: bool,
+    #[serde(default)]
+    pub ssh: SshPersonality,
+    #[serde(default)]
+    pub snmp: SnmpPersonality,
+    #[serde(default)]
+    pub dns: DnsPersonality,
+    /// UPnP device the OS announces, none if it doesn't run one
+    #[serde(default)]
+    pub upnp: Option<UpnpPersonality>,
+    pub tcp: TcpPersonality,
+}
+
+impl Personality {
+    /// Used when the database can't be loaded
+    fn fallback() -> Self {
+        Personality {
+            name: "linux".to_string(),
+            description: "Generic Linux".to_string(),
+            vendors: Vec::new(),
+            profiles: Vec::new(),
+            name_services: vec![NameService::Mdns, NameService::Llmnr],
+            ttl: 64,
+            ip_id: IpIdMode::Zero,
+            dont_fragment: true,
+            answers_timestamp: true,
+            echo_code: true,
+            ssh: SshPersonality::default(),
+            snmp: SnmpPersonality::default(),
+            dns: DnsPersonality { version: Some("9.18.18-0ubuntu0.22.04.2-Ubuntu".to_string()) },
+            upnp: None,
+            tcp: TcpPersonality {
+                window: 64240,
+                mss: 1460,
+                wscale: Some(7),
+                sack: true,
+                timestamps: true,
+                timestamp_hz: 1000,
+                options: vec![OptionKind::Mss, OptionKind::Sack, OptionKind::Timestamp, OptionKind::Nop, OptionKind::Wscale],
+                reset_stray: false,
+            },
+        }
+    }
+}
+
+fn load(path: &str) -> Vec<Personality> {
+    let personalities = File::open(path)
+        .map_err(|e| e.to_string())
+        .and_then(|file| serde_json::from_reader::<_, DatabaseFile>(BufReader::new(file)).map_err(|e| e.to_string()));
+
+    match personalities {
+        Ok(file) if !file.personalities.is_empty() => file.personalities,
+        Ok(_) => {
+            error!("❌ Nessuna personalità OS in {}", path);
+            vec![Personality::fallback()]
+        }
+        Err(e) => {
+            error!("❌ Impossibile caricare le personalità OS {}: {}", path, e);
+            vec![Personality::fallback()]
+        }
+    }
+}
+
+/// Personality of a node, chosen among those shipped by the vendor of its MAC and stable for it
+pub fn personality_for_mac(mac: MacAddr) -> &'static Personality {
+    let oui = format!("{:02X}:{:02X}:{:02X}", mac.0, mac.1, mac.2);
+    let mut candidates: Vec<&Personality> = PERSONALITIES.iter()
+        .filter(|p| p.vendors.iter().any(|vendor| vendor.eq_ignore_ascii_case(&oui)))
+        .collect();
+    if candidates.is_empty() {
+        candidates = PERSONALITIES.iter().filter(|p| p.vendors.is_empty()).collect();
+    }
+    if candidates.is_empty() {
+        return &PERSONALITIES[0];
+    }
+    candidates[mac.5 as usize % candidates.len()]
+}
+
+/// Every personality of the database
+pub fn personalities() -> &'static [Personality] {
+    &PERSONALITIES
+}
+
+/// Personality with the given name, as recorded in a node's persisted identity
+pub fn personality_named(name: &str) -> Option<&'static Personality> {
+    PERSONALITIES.iter().find(|p| p.name == name)
+}
+
+
+/// TCP extensions in use on a connection, or offered by a SYN
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TcpFeatures {
+    pub wscale: bool,
+    pub sack: bool,
+    pub timestamps: bool,
+}
+
+/// Network stack behaviour of the OS a virtual node impersonates, kept consistent
+/// across every packet the node sends
+#[derive(Debug, Clone)]
+pub struct OsPersona {
+    personality: &'static Personality,
+    next_ip_id: Arc<AtomicU16>,
+    // Timestamp clock value when the decoy started
+    timestamp_offset: u32,
+}
+
+impl Deref for OsPersona {
+    type Target = Personality;
+
+    fn deref(&self) -> &Personality {
+        self.personality
+    }
+}
+
+impl OsPersona {
+    /// Persona matching the vendor of the node's MAC
+    pub fn for_mac(mac: MacAddr) -> Self {
+        OsPersona::with_personality(mac, personality_for_mac(mac))
+    }
+
+    pub fn with_personality(mac: MacAddr, personality: &'static Personality) -> Self {
+        OsPersona {
+            personality,
+            // Hosts have been up for a while, their counters don't start at zero
+            next_ip_id: Arc::new(AtomicU16::new(u16::from_be_bytes([mac.4, mac.5]))),
+            timestamp_offset: u32::from_be_bytes([mac.2, mac.3, mac.4, mac.5]),
+        }
+    }
+
+    /// Database entry of the persona, for services that outlive it
+    pub fn personality(&self) -> &'static Personality {
+        self.personality
+    }
+
+    /// Header fields for the next ICMP or UDP packet the node sends
+    pub fn ipv4_fields(&self) -> Ipv4Fields {
+        let identification = match self.ip_id {
+            IpIdMode::Incremental | IpIdMode::Zero => self.next_ip_id.fetch_add(1, Ordering::Relaxed),
+            IpIdMode::Random => rand::random(),
+        };
+        Ipv4Fields { ttl: self.ttl, identification, dont_fragment: false }
+    }
+
+    /// Header fields for the next TCP segment the node sends
+    pub fn tcp_fields(&self) -> Ipv4Fields {
+        let identification = match self.ip_id {
+            IpIdMode::Zero if self.dont_fragment => 0,
+            IpIdMode::Incremental | IpIdMode::Zero => self.next_ip_id.fetch_add(1, Ordering::Relaxed),
+            IpIdMode::Random => rand::random(),
+        };
+        Ipv4Fields { ttl: self.ttl, identification, dont_fragment: self.dont_fragment }
+    }
+
+    /// MSS advertised on connections over `ip`'s family: the IPv6 header is 20 bytes longer
+    pub fn mss(&self, ip: IpAddr) -> u16 {
+        if ip.is_ipv6() { self.tcp.mss - 20 } else { self.tcp.mss }
+    }
+
+    /// Extensions the stack offers in its own SYN
+    pub fn offered(&self) -> TcpFeatures {
+        TcpFeatures {
+            wscale: self.tcp.wscale.is_some(),
+            sack: self.tcp.sack,
+            timestamps: self.tcp.timestamps,
+        }
+    }
+
+    /// Extensions in use once the peer's SYN offered `peer`: each needs both sides
+    pub fn negotiate(&self, peer: TcpFeatures) -> TcpFeatures {
+        let offered = self.offered();
+        TcpFeatures {
+            wscale: offered.wscale && peer.wscale,
+            sack: offered.sack && peer.sack,
+            timestamps: offered.timestamps && peer.timestamps,
+        }
+    }
+
+    /// Shift applied to the windows we advertise
+    pub fn window_shift(&self, features: TcpFeatures) -> u8 {
+        if features.wscale { self.tcp.wscale.unwrap_or(0) } else { 0 }
+    }
+
+    /// Current value of the node's TCP timestamp clock
+    pub fn timestamp(&self) -> u32 {
+        let ticks = CLOCK_START.elapsed().as_millis() as u64 * self.tcp.timestamp_hz as u64 / 1000;
+        self.timestamp_offset.wrapping_add(ticks as u32)
+    }
+
+    /// SYN or SYN-ACK options in the personality's order. Options not in `features` are left out
+    /// together with the NOPs padding them, as real stacks do
+    pub fn syn_options(&self, features: TcpFeatures, mss: u16, ts_echo: u32) -> Vec<TcpOption> {
+        let mut options = Vec::new();
+        let mut padding = 0;
+
+        for kind in &self.tcp.options {
+            let option = match kind {
+                OptionKind::Nop => {
+                    padding += 1;
+                    continue;
+                }
+                OptionKind::Mss => Some(TcpOption::mss(mss)),
+                OptionKind::Wscale => self.tcp.wscale.filter(|_| features.wscale).map(TcpOption::wscale),
+                OptionKind::Sack => features.sack.then(TcpOption::sack_perm),
+                OptionKind::Timestamp => features.timestamps.then(|| TcpOption::timestamp(self.timestamp(), ts_echo)),
+            };
+            if let Some(option) = option {
+                options.extend((0..padding).map(|_| TcpOption::nop()));
+                options.push(option);
+            }
+            padding = 0;
+        }
+        options
+    }
+
+    /// Options of every other segment of a connection
+    pub fn segment_options(&self, features: TcpFeatures, ts_echo: u32) -> Vec<TcpOption> {
+        if !features.timestamps {
+            return Vec::new();
+        }
+        vec![TcpOption::nop(), TcpOption::nop(), TcpOption::timestamp(self.timestamp(), ts_echo)]
+    }
+}
@@ -0,0 +1,129 @@
+use pnet::util::MacAddr;
+use tracing::warn;
+
+use super::{fleet::{AdvertisedService, DecoyGroup}, identity::HoneypotIdentity, persona::{self, OsPersona}, services::{banner::{BannerProfile, CATALOGUE}, udp::{ssdp::SSDP_PORT, UdpRegistry}, ServiceRegistry}};
+
+// Hostname of nodes whose banner profile gives them none
+const UNNAMED: &str = "localhost";
+// DNS-SD service types of the ports decoys commonly open
+const DNS_SD_TYPES: &[(u16, &str)] = &[
+    (21, "_ftp._tcp"),
+    (22, "_ssh._tcp"),
+    (23, "_telnet._tcp"),
+    (80, "_http._tcp"),
+    (443, "_https._tcp"),
+    (445, "_smb._tcp"),
+    (515, "_printer._tcp"),
+    (631, "_ipp._tcp"),
+    (1883, "_mqtt._tcp"),
+    (3389, "_rdp._tcp"),
+    (5900, "_rfb._tcp"),
+    (9100, "_pdl-datastream._tcp"),
+];
+
+/// Everything a virtual node needs to impersonate a host, shared with the packet handlers
+#[derive(Debug, Clone)]
+pub struct HoneypotProfile {
+    pub services: ServiceRegistry,
+    pub udp_services: UdpRegistry,
+    pub os: OsPersona,
+    /// Banner profile the services were built from
+    pub banner_profile: Option<String>,
+    /// Name the node gives in banners, DNS and SNMP
+    pub hostname: String,
+    /// Fleet group the node belongs to
+    pub group: String,
+    /// DNS-SD services announced over mDNS
+    pub mdns_services: Vec<AdvertisedService>,
+}
+
+impl HoneypotProfile {
+    /// Profile of a new node of `group`: whatever the group leaves open is chosen from the MAC
+    pub fn new(mac: MacAddr, group: &DecoyGroup) -> Self {
+        let os = match group.personality() {
+            Some(personality) => OsPersona::with_personality(mac, personality),
+            None => OsPersona::for_mac(mac),
+        };
+        let banner_profile = match &group.banner_profile {
+            Some(name) => CATALOGUE.profile_named(name),
+            None => CATALOGUE.profile_for_mac(mac, &os),
+        };
+        let hostname = group.hostname_for_mac(mac);
+
+        HoneypotProfile::build(mac, os, banner_profile, hostname, group, None)
+    }
+
+    /// Profile of a node recreated from its saved identity: same persona, banners and open ports,
+    /// even if the catalogues would now pick differently for its MAC. Ports, hostnames and
+    /// service settings the group configures take precedence
+    pub fn restore(identity: &HoneypotIdentity, group: &DecoyGroup) -> Self {
+        let mac = identity.mac;
+        let os = match persona::personality_named(&identity.personality) {
+            Some(personality) => OsPersona::with_personality(mac, personality),
+            None => {
+                warn!("⚠️ Personalità {} del nodo {} non più disponibile, ne scelgo un'altra", identity.personality, mac);
+                OsPersona::for_mac(mac)
+            }
+        };
+        let banner_profile = match &identity.banner_profile {
+            Some(name) => CATALOGUE.profile_named(name).or_else(|| {
+                warn!("⚠️ Profilo banner {} del nodo {} non più disponibile, ne scelgo un altro", name, mac);
+                CATALOGUE.profile_for_mac(mac, &os)
+            }),
+            None => None,
+        };
+        let hostname = match &identity.hostname {
+            Some(hostname) if group.hostnames.is_empty() || group.hostnames.contains(hostname) => Some(hostname.clone()),
+            _ => group.hostname_for_mac(mac),
+        };
+
+        HoneypotProfile::build(mac, os, banner_profile, hostname, group, Some(identity))
+    }
+
+    // Ports the group lists win over the saved ones, which win over everything the node could open
+    fn build(mac: MacAddr, os: OsPersona, banner_profile: Option<&BannerProfile>, hostname: Option<String>, group: &DecoyGroup, saved: Option<&HoneypotIdentity>) -> Self {
+        let hostname = hostname
+            .or_else(|| banner_profile.map(|p| p.hostname_for_mac(mac)))
+            .unwrap_or_else(|| UNNAMED.to_string());
+
+        let mut services = ServiceRegistry::with_default_services(mac, &os, banner_profile, &hostname, &group.settings);
+        if let Some(ports) = group.tcp_ports.as_ref().or(saved.map(|identity| &identity.tcp_ports)) {
+            services.retain(ports);
+        }
+        let mdns_services = group.mdns_services.clone().unwrap_or_else(|| advertised_services(&services.ports()));
+
+        let mut udp_services = UdpRegistry::with_default_services(mac, &os, &hostname);
+        // Name services have nothing to answer for a node without a name of its own
+        if hostname != UNNAMED {
+            udp_services.register_name_services(&os.name_services, &hostname, &mdns_services);
+        }
+        if let Some(ports) = group.udp_ports.as_ref().or(saved.map(|identity| &identity.udp_ports)) {
+            udp_services.retain(ports);
+        }
+        // SSDP points to the device description, a node that doesn't serve it stays quiet
+        if let Some(upnp) = &os.upnp && !services.ports().contains(&upnp.port) {
+            let open: Vec<u16> = udp_services.ports().into_iter().filter(|port| *port != SSDP_PORT).collect();
+            udp_services.retain(&open);
+        }
+
+        HoneypotProfile {
+            services,
+            udp_services,
+            os,
+            banner_profile: banner_profile.map(|p| p.name.clone()),
+            hostname,
+            group: group.name.clone(),
+            mdns_services,
+        }
+    }
+}
+
+/// One DNS-SD service per open port with a well-known type
+fn advertised_services(ports: &[u16]) -> Vec<AdvertisedService> {
+    ports.iter()
+        .filter_map(|port| {
+            let service = DNS_SD_TYPES.iter().find(|(known, _)| known == port)?.1;
+            Some(AdvertisedService { service: service.to_string(), port: *port, instance: None, txt: Vec::new() })
+        })
+        .collect()
+}
@@ -1 +0,0 @@
-pub mod ssh;
\ No newline at end of file
@@ -0,0 +1,78 @@
+use std::{fs, io, path::{Path, PathBuf}};
+
+use lazy_static::lazy_static;
+use sha2::{Digest, Sha256};
+use tokio::{sync::Mutex, task::spawn_blocking};
+use tracing::{error, info, warn};
+
+const ARTIFACT_DIR: &str = "state/artifacts";
+// Most the artifacts may take on disk together; past it new payloads are hashed but not kept
+const ARTIFACT_QUOTA: u64 = 1024 * 1024 * 1024;
+
+
+lazy_static! {
+    /// Bytes in the artifact directory, measured on the first store
+    static ref STORED: Mutex<Option<u64>> = Mutex::new(None);
+}
+
+/// Hashes of a file dropped by an attacker
+pub struct Artifact {
+    pub sha256: String,
+    pub md5: String,
+}
+
+/// Keeps a copy of attacker-supplied content under its SHA-256, so the same payload is stored once
+pub async fn store(data: Vec<u8>) -> Artifact {
+    let mut stored = STORED.lock().await;
+    let used = match *stored {
+        Some(used) => used,
+        None => spawn_blocking(|| directory_size(Path::new(ARTIFACT_DIR))).await.unwrap_or(0),
+    };
+
+    let (artifact, written) = spawn_blocking(move || save(&data, used)).await.unwrap_or_else(|e| {
+        error!("❌ Salvataggio dell'artefatto interrotto: {}", e);
+        (Artifact { sha256: String::new(), md5: String::new() }, 0)
+    });
+    *stored = Some(used + written);
+    artifact
+}
+
+/// Hashes `data` and writes it unless already there or over the quota; returns the bytes written
+fn save(data: &[u8], used: u64) -> (Artifact, u64) {
+    let sha256 = Sha256::digest(data).iter().map(|b| format!("{:02x}", b)).collect::<String>();
+    let md5 = format!("{:x}", md5::compute(data));
+
+    let path = PathBuf::from(ARTIFACT_DIR).join(&sha256);
+    let mut written = 0;
+    if !path.exists() {
+        if used + data.len() as u64 > ARTIFACT_QUOTA {
+            warn!("💾 Quota degli artefatti esaurita ({} byte), {} non salvato", used, sha256);
+        } else {
+            match fs::create_dir_all(ARTIFACT_DIR).and_then(|_| fs::write(&path, data)) {
+                Ok(()) => {
+                    info!("📦 Captured artifact {} ({} bytes)", sha256, data.len());
+                    written = data.len() as u64;
+                }
+                Err(e) => error!("❌ Impossibile salvare l'artefatto {:?}: {}", path, e),
+            }
+        }
+    }
+
+    (Artifact { sha256, md5 }, written)
+}
+
+fn directory_size(dir: &Path) -> u64 {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return 0,
+        Err(e) => {
+            error!("❌ Impossibile leggere {:?}: {}", dir, e);
+            return 0;
+        }
+    };
+    entries.flatten()
+        .filter_map(|entry| entry.metadata().ok())
+        .filter(|metadata| metadata.is_file())
+        .map(|metadata| metadata.len())
+        .sum()
+}
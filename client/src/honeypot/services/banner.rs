@@ -0,0 +1,483 @@
+use std::{fs::File, io::BufReader, net::SocketAddr, sync::Arc};
+
+use common::types::HoneypotEvent;
+use lazy_static::lazy_static;
+use pnet::util::MacAddr;
+use regex::bytes::Regex;
+use serde::Deserialize;
+use tracing::error;
+
+use super::{session::ServiceSession, time::{format_date, now}, ServiceEmulator, ServiceReply};
+use crate::honeypot::persona::Personality;
+
+const BANNER_CATALOGUE: &str = "src/honeypot/services/banners.json";
+
+// Unanswered input kept by binary services before it is discarded
+const MAX_PENDING_INPUT: usize = 8 * 1024;
+
+
+lazy_static! {
+    /// Port → banner/probe templates, loaded once and shared by every node
+    pub static ref CATALOGUE: BannerCatalogue = BannerCatalogue::load(BANNER_CATALOGUE);
+}
+
+/// On-disk format of the catalogue
+#[derive(Debug, Deserialize)]
+struct CatalogueFile {
+    profiles: Vec<ProfileEntry>,
+    services: Vec<ServiceEntry>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ProfileEntry {
+    name: String,
+    hostnames: Vec<String>,
+    services: Vec<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ServiceEntry {
+    name: String,
+    ports: Vec<u16>,
+    #[serde(default)]
+    mode: InputMode,
+    #[serde(default)]
+    banner: String,
+    #[serde(default)]
+    responses: Vec<ResponseEntry>,
+    default: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ResponseEntry {
+    #[serde(rename = "match")]
+    pattern: String,
+    reply: String,
+    #[serde(default)]
+    close: bool,
+    #[serde(default)]
+    expand: bool,
+}
+
+/// How client input is split before matching
+#[derive(Debug, Clone, Copy, Default, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum InputMode {
+    /// Text protocols: every line is matched on its own
+    #[default]
+    Line,
+    /// Everything received since the last reply is matched as a whole
+    Binary,
+}
+
+/// Probe → reply template; replies may use `{hostname}`, `{ip}`, `{date}` and, when `expand` is set,
+/// `$1`-style capture groups (`$$` for a literal `$`). Binary replies such as Redis bulk strings
+/// contain `$` of their own, so they are sent as written unless they opt in.
+/// Named groups `username` and `password` in the pattern are reported as a login attempt
+#[derive(Debug)]
+pub struct ProbeResponse {
+    pattern: Regex,
+    reply: Vec<u8>,
+    close: bool,
+    expand: bool,
+}
+
+/// Banner-only emulation of one service, as described in the catalogue
+#[derive(Debug)]
+pub struct BannerService {
+    pub name: &'static str,
+    pub ports: Vec<u16>,
+    mode: InputMode,
+    banner: Vec<u8>,
+    responses: Vec<ProbeResponse>,
+    default: Option<Vec<u8>>,
+}
+
+/// Set of banner services a node exposes, with the hostnames it announces in them
+#[derive(Debug)]
+pub struct BannerProfile {
+    pub name: String,
+    pub hostnames: Vec<String>,
+    pub services: Vec<Arc<BannerService>>,
+}
+
+#[derive(Debug, Default)]
+pub struct BannerCatalogue {
+    pub profiles: Vec<BannerProfile>,
+}
+
+impl BannerCatalogue {
+    pub fn load(path: &str) -> Self {
+        let file = match File::open(path)
+            .map_err(|e| e.to_string())
+            .and_then(|file| serde_json::from_reader::<_, CatalogueFile>(BufReader::new(file)).map_err(|e| e.to_string()))
+        {
+            Ok(file) => file,
+            Err(e) => {
+                error!("❌ Impossibile caricare il catalogo dei banner {}: {}", path, e);
+                return BannerCatalogue::default();
+            }
+        };
+
+        let services: Vec<Arc<BannerService>> = file.services.into_iter().filter_map(|entry| {
+            let name = entry.name.clone();
+            BannerService::from_entry(entry)
+                .map(Arc::new)
+                .map_err(|e| error!("❌ Servizio {} non valido nel catalogo dei banner: {}", name, e))
+                .ok()
+        }).collect();
+
+        let profiles = file.profiles.into_iter().map(|profile| {
+            let services = profile.services.iter().filter_map(|name| {
+                let service = services.iter().find(|s| s.name == name).cloned();
+                if service.is_none() {
+                    error!("❌ Il profilo {} usa il servizio sconosciuto {}", profile.name, name);
+                }
+                service
+            }).collect();
+            BannerProfile { name: profile.name, hostnames: profile.hostnames, services }
+        }).collect();
+
+        BannerCatalogue { profiles }
+    }
+
+    /// Profile a node gets, stable for a given MAC and fitting its OS personality
+    pub fn profile_for_mac(&self, mac: MacAddr, personality: &Personality) -> Option<&BannerProfile> {
+        let allowed = &personality.profiles;
+        let candidates: Vec<&BannerProfile> = self.profiles.iter()
+            .filter(|profile| allowed.is_empty() || allowed.contains(&profile.name))
+            .collect();
+        if candidates.is_empty() {
+            return None;
+        }
+        Some(candidates[mac.4 as usize % candidates.len()])
+    }
+
+    pub fn profile_named(&self, name: &str) -> Option<&BannerProfile> {
+        self.profiles.iter().find(|profile| profile.name == name)
+    }
+}
+
+impl BannerProfile {
+    pub fn hostname_for_mac(&self, mac: MacAddr) -> String {
+        match self.hostnames.len() {
+            0 => "localhost".to_string(),
+            n => self.hostnames[mac.5 as usize % n].clone(),
+        }
+    }
+}
+
+impl BannerService {
+    fn from_entry(entry: ServiceEntry) -> Result<Self, String> {
+        let responses = entry.responses.into_iter().map(|response| {
+            Ok(ProbeResponse {
+                pattern: Regex::new(&response.pattern).map_err(|e| e.to_string())?,
+                reply: unescape(&response.reply),
+                close: response.close,
+                expand: response.expand,
+            })
+        }).collect::<Result<Vec<_>, String>>()?;
+
+        Ok(BannerService {
+            // Catalogue entries live for the whole run, so their names can be reported as `&'static str`
+            name: Box::leak(entry.name.into_boxed_str()),
+            ports: entry.ports,
+            mode: entry.mode,
+            banner: unescape(&entry.banner),
+            responses,
+            default: entry.default.as_deref().map(unescape),
+        })
+    }
+}
+
+
+/// Serves one catalogue service: sends the banner, answers probes from the templates
+/// and reports credentials the client sends along the way
+pub struct BannerEmulator {
+    service: Arc<BannerService>,
+    hostname: String,
+    username: Option<String>,
+    pending: Vec<u8>,
+}
+
+impl BannerEmulator {
+    pub fn new(service: Arc<BannerService>, hostname: String) -> Self {
+        BannerEmulator { service, hostname, username: None, pending: Vec::new() }
+    }
+
+    fn render(&self, template: &[u8], addr: SocketAddr) -> Vec<u8> {
+        if !template.contains(&b'{') {
+            return template.to_vec();
+        }
+        let mut output = template.to_vec();
+        for (placeholder, value) in [
+            (&b"{hostname}"[..], self.hostname.as_bytes()),
+            (b"{ip}", addr.ip().to_string().as_bytes()),
+            (b"{date}", format_date(now()).as_bytes()),
+        ] {
+            output = replace(&output, placeholder, value);
+        }
+        output
+    }
+
+    /// Answers one unit of input; `None` when no template applies and there is no default
+    fn respond(&mut self, session: &ServiceSession, input: &[u8]) -> Option<(Vec<u8>, bool)> {
+        let service = self.service.clone();
+        for response in &service.responses {
+            let Some(captures) = response.pattern.captures(input) else {
+                continue;
+            };
+
+            let group = |name: &str| captures.name(name).map(|m| String::from_utf8_lossy(m.as_bytes()).to_string());
+            if let Some(username) = group("username") {
+                self.username = Some(username);
+            }
+            if let Some(password) = group("password") {
+                let username = self.username.take().unwrap_or_default();
+                self.record_login(session, username, Some(password));
+            }
+
+            let mut reply = Vec::new();
+            if response.expand {
+                captures.expand(&response.reply, &mut reply);
+            } else {
+                reply.extend_from_slice(&response.reply);
+            }
+            return Some((self.render(&reply, session.honeypot_addr), response.close));
+        }
+
+        service.default.as_ref().map(|default| (self.render(default, session.honeypot_addr), false))
+    }
+
+    fn record_login(&self, session: &ServiceSession, username: String, password: Option<String>) {
+        session.record(HoneypotEvent::LoginAttempt { username, password, public_key: None, accepted: false });
+    }
+}
+
+impl ServiceEmulator for BannerEmulator {
+    fn name(&self) -> &'static str {
+        self.service.name
+    }
+
+    fn on_connect(&mut self, session: &mut ServiceSession) -> Vec<u8> {
+        self.render(&self.service.banner, session.honeypot_addr)
+    }
+
+    fn on_close(&mut self, session: &mut ServiceSession) {
+        // Username sent without a usable password (e.g. a MySQL scramble)
+        if let Some(username) = self.username.take() {
+            self.record_login(session, username, None);
+        }
+    }
+
+    fn on_data(&mut self, session: &mut ServiceSession, data: &[u8]) -> ServiceReply {
+        self.pending.extend_from_slice(data);
+        let mut reply = ServiceReply::none();
+
+        match self.service.mode {
+            InputMode::Line => {
+                while let Some(end) = self.pending.iter().position(|b| *b == b'\n') {
+                    let line: Vec<u8> = self.pending.drain(..=end).collect();
+                    let line = line.strip_suffix(b"\r\n").or_else(|| line.strip_suffix(b"\n")).unwrap_or(&line);
+                    if let Some((data, close)) = self.respond(session, line) {
+                        reply.data.extend(data);
+                        if close {
+                            reply.close = true;
+                            return reply;
+                        }
+                    }
+                }
+            }
+            InputMode::Binary => {
+                let input = std::mem::take(&mut self.pending);
+                match self.respond(session, &input) {
+                    Some((data, close)) => {
+                        reply.data = data;
+                        reply.close = close;
+                    }
+                    // Maybe a partial probe: wait for the rest
+                    None => self.pending = input,
+                }
+            }
+        }
+
+        if self.pending.len() > MAX_PENDING_INPUT {
+            self.pending.clear();
+        }
+        reply
+    }
+}
+
+
+/// Catalogue strings use C-style escapes (`\r`, `\n`, `\t`, `\0`, `\xHH`) so binary greetings fit in JSON
+fn unescape(text: &str) -> Vec<u8> {
+    let mut output = Vec::new();
+    let mut bytes = text.bytes();
+    while let Some(byte) = bytes.next() {
+        if byte != b'\\' {
+            output.push(byte);
+            continue;
+        }
+        match bytes.next() {
+            Some(b'r') => output.push(b'\r'),
+            Some(b'n') => output.push(b'\n'),
+            Some(b't') => output.push(b'\t'),
+            Some(b'0') => output.push(0),
+            Some(b'x') => {
+                let high = bytes.next().and_then(|c| (c as char).to_digit(16));
+                let low = bytes.next().and_then(|c| (c as char).to_digit(16));
+                if let (Some(high), Some(low)) = (high, low) {
+                    output.push((high * 16 + low) as u8);
+                }
+            }
+            Some(other) => output.push(other),
+            None => output.push(b'\\'),
+        }
+    }
+    output
+}
+
+fn replace(haystack: &[u8], needle: &[u8], value: &[u8]) -> Vec<u8> {
+    let mut output = Vec::with_capacity(haystack.len());
+    let mut rest = haystack;
+    while let Some(position) = rest.windows(needle.len()).position(|window| window == needle) {
+        output.extend_from_slice(&rest[..position]);
+        output.extend_from_slice(value);
+        rest = &rest[position + needle.len()..];
+    }
+    output.extend_from_slice(rest);
+    output
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::honeypot::services::session::test_support;
+
+    fn emulator(name: &str) -> BannerEmulator {
+        let service = CATALOGUE.profiles.iter()
+            .flat_map(|profile| &profile.services)
+            .find(|service| service.name == name)
+            .unwrap();
+        BannerEmulator::new(service.clone(), "db01".to_string())
+    }
+
+    fn exchange(emulator: &mut BannerEmulator, input: &[u8]) -> ServiceReply {
+        let mut session = test_support::session(emulator.service.name, "10.0.0.5:3306", "10.0.0.7:40000");
+        emulator.on_data(&mut session, input)
+    }
+
+    fn entry(reply: &str, expand: bool) -> ServiceEntry {
+        ServiceEntry {
+            name: "test".to_string(),
+            ports: vec![1],
+            mode: InputMode::Line,
+            banner: String::new(),
+            responses: vec![ResponseEntry { pattern: "^GET (\\S+)$".to_string(), reply: reply.to_string(), close: false, expand }],
+            default: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn captures_expand_only_when_asked() {
+        let mut literal = BannerEmulator::new(Arc::new(BannerService::from_entry(entry("$5\\r\\nhello\\r\\n", false)).unwrap()), String::new());
+        assert_eq!(exchange(&mut literal, b"GET key\r\n").data, b"$5\r\nhello\r\n");
+
+        let mut expanded = BannerEmulator::new(Arc::new(BannerService::from_entry(entry("$$${1}$$ $2.\\n", true)).unwrap()), String::new());
+        assert_eq!(exchange(&mut expanded, b"GET key\n").data, b"$key$ .\n");
+    }
+
+    #[tokio::test]
+    async fn imap_echoes_the_tag() {
+        let mut imap = emulator("imap");
+        let reply = exchange(&mut imap, b"a001 CAPABILITY\r\nA2 LOGIN bob \"s3cret\"\r\nx.3 NOOP\r\nfoo\r\n");
+        let text = String::from_utf8(reply.data).unwrap();
+        let tagged: Vec<&str> = text.lines().filter(|line| !line.starts_with('*')).collect();
+        assert_eq!(tagged, [
+            "a001 OK Pre-login capabilities listed, post-login capabilities have more.",
+            "A2 NO [AUTHENTICATIONFAILED] Authentication failed.",
+            "x.3 BAD Error in IMAP command received by server.",
+        ]);
+        assert!(text.ends_with("* BAD Error in IMAP command received by server.\r\n"));
+
+        let reply = exchange(&mut imap, b"Z LOGOUT\r\n");
+        assert_eq!((reply.data, reply.close), (b"* BYE Logging out\r\nZ OK Logout completed.\r\n".to_vec(), true));
+    }
+
+    #[tokio::test]
+    async fn mysql_handshake_and_denial_are_well_formed() {
+        let mut mysql = emulator("mysql");
+        let mut session = test_support::session("mysql", "10.0.0.5:3306", "10.0.0.7:40000");
+        let greeting = mysql.on_connect(&mut session);
+        // 3-byte little-endian payload length, sequence 0, protocol 10
+        assert_eq!(u32::from_le_bytes([greeting[0], greeting[1], greeting[2], 0]) as usize, greeting.len() - 4);
+        assert_eq!(greeting[3..5], [0, 10]);
+        assert!(greeting.ends_with(b"mysql_native_password\0"));
+
+        // HandshakeResponse41 for root with a 20-byte scramble
+        let mut login = vec![0, 0, 0, 1, 0x85, 0xa6, 0xff, 0x01, 0, 0, 0, 1, 0x21];
+        login.extend_from_slice(&[0; 23]);
+        login.extend_from_slice(b"root\0\x14");
+        login.extend_from_slice(&[0xaa; 20]);
+        login[0] = (login.len() - 4) as u8;
+        let reply = exchange(&mut mysql, &login);
+        assert!(reply.close);
+        assert_eq!(reply.data[0] as usize, reply.data.len() - 4);
+        assert_eq!(reply.data[3..7], [2, 0xff, 0x15, 0x04]);
+        assert_eq!(&reply.data[7..13], b"#28000");
+    }
+
+    #[tokio::test]
+    async fn postgres_refuses_ssl_and_asks_for_md5() {
+        let mut postgres = emulator("postgresql");
+        let reply = exchange(&mut postgres, &[0, 0, 0, 8, 0x04, 0xd2, 0x16, 0x2f]);
+        assert_eq!((reply.data.as_slice(), reply.close), (&b"N"[..], false));
+
+        let mut startup = vec![0, 0, 0, 0, 0, 3, 0, 0];
+        startup.extend_from_slice(b"user\0postgres\0database\0postgres\0\0");
+        startup[3] = startup.len() as u8;
+        let reply = exchange(&mut postgres, &startup);
+        // AuthenticationMD5Password: length counts itself, then code 5 and a 4-byte salt
+        assert_eq!(reply.data[0], b'R');
+        assert_eq!(u32::from_be_bytes(reply.data[1..5].try_into().unwrap()) as usize, reply.data.len() - 1);
+        assert_eq!(reply.data[5..9], [0, 0, 0, 5]);
+
+        let mut password = b"p\0\0\0\x28md5".to_vec();
+        password.extend_from_slice(&[b'0'; 32]);
+        password.push(0);
+        let reply = exchange(&mut postgres, &password);
+        assert!(reply.close);
+        assert_eq!(reply.data[0], b'E');
+        assert_eq!(u32::from_be_bytes(reply.data[1..5].try_into().unwrap()) as usize, reply.data.len() - 1);
+        assert!(reply.data.windows(7).any(|window| window == b"C28P01\0"));
+    }
+
+    #[tokio::test]
+    async fn rdp_confirms_with_a_negotiation_response() {
+        let mut rdp = emulator("rdp");
+        // TPKT + X.224 Connection Request + RDP_NEG_REQ for TLS and CredSSP
+        let request = [3, 0, 0, 19, 14, 0xe0, 0, 0, 0, 0, 0, 1, 0, 8, 0, 3, 0, 0, 0];
+        let reply = exchange(&mut rdp, &request);
+        assert_eq!(u16::from_be_bytes([reply.data[2], reply.data[3]]) as usize, reply.data.len());
+        assert_eq!((reply.data[4] as usize, reply.data[5]), (reply.data.len() - 5, 0xd0));
+        // RDP_NEG_RSP selecting CredSSP
+        assert_eq!(reply.data[11..], [2, 1, 8, 0, 2, 0, 0, 0]);
+        assert!(!reply.close);
+    }
+
+    #[tokio::test]
+    async fn redis_wants_auth_in_both_encodings() {
+        let mut redis = emulator("redis");
+        let wrongpass = b"-WRONGPASS invalid username-password pair or user is disabled.\r\n";
+        assert_eq!(exchange(&mut redis, b"*2\r\n$4\r\nAUTH\r\n$6\r\nfoobar\r\n").data, wrongpass);
+        assert_eq!(exchange(&mut redis, b"*3\r\n$4\r\nAUTH\r\n$7\r\ndefault\r\n$6\r\nfoobar\r\n").data, wrongpass);
+        assert_eq!(exchange(&mut redis, b"AUTH foobar\r\n").data, wrongpass);
+        assert_eq!(exchange(&mut redis, b"*1\r\n$4\r\nINFO\r\n").data, b"-NOAUTH Authentication required.\r\n");
+        // A command split mid-bulk is answered once
+        assert!(exchange(&mut redis, b"*2\r\n$3\r\nGET\r").data.is_empty());
+        assert_eq!(exchange(&mut redis, b"\n$1\r\nk\r\n").data, b"-NOAUTH Authentication required.\r\n");
+        let reply = exchange(&mut redis, b"*1\r\n$4\r\nQUIT\r\n");
+        assert_eq!((reply.data.as_slice(), reply.close), (&b"+OK\r\n"[..], true));
+    }
+}
@@ -0,0 +1,543 @@
+use std::{net::{IpAddr, Ipv4Addr, SocketAddr}, ops::Range, sync::Arc, time::Duration};
+
+use common::types::HoneypotEvent;
+use futures_util::future::BoxFuture;
+use tokio::{io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt}, sync::oneshot, time::timeout};
+use tracing::info;
+
+use super::{artifacts, session::{CapturedStream, ServiceSession}, ServiceEmulator, ServiceReply};
+use crate::honeypot::{shell::filesystem::{self, FsEntry, FsError, VirtualFs, DEFAULT_FILESYSTEM, SESSION_QUOTA}, tcp::stream::VirtualTcpStream};
+
+const BANNER: &str = "220 (vsFTPd 3.0.5)";
+const ANONYMOUS_USERS: &[&str] = &["anonymous", "ftp"];
+const ANONYMOUS_ROOT: &str = "/srv/ftp";
+
+// Weak accounts that get a session on the fake filesystem, on top of anonymous access
+const DEFAULT_CREDENTIALS: &[(&str, &str)] = &[("admin", "admin"), ("user", "user"), ("test", "test"), ("root", "123456")];
+
+// vsftpd's max_login_fails
+const MAX_LOGIN_FAILURES: usize = 3;
+const MAX_COMMAND_LINE: usize = 4096;
+// Uploads are hashed and stored up to this size, the rest is drained and dropped
+const MAX_UPLOAD: usize = 16 * 1024 * 1024;
+const DATA_CONNECTION_TIMEOUT: Duration = Duration::from_secs(30);
+const PASSIVE_PORTS: Range<u16> = 40_000..50_000;
+// Active mode data connections come from ftp-data, like a real server's
+const ACTIVE_DATA_PORT: u16 = 20;
+
+
+/// FTP server emulation: logs every login, serves LIST/RETR from the fake filesystem over
+/// passive or active data connections and keeps whatever is uploaded as a captured artifact
+#[derive(Clone)]
+pub struct FtpEmulator {
+    credentials: Arc<Vec<(String, String)>>,
+}
+
+impl Default for FtpEmulator {
+    fn default() -> Self {
+        let credentials = DEFAULT_CREDENTIALS.iter().map(|(u, p)| (u.to_string(), p.to_string())).collect();
+        FtpEmulator { credentials: Arc::new(credentials) }
+    }
+}
+
+impl ServiceEmulator for FtpEmulator {
+    fn name(&self) -> &'static str {
+        "ftp"
+    }
+
+    fn on_data(&mut self, _session: &mut ServiceSession, _data: &[u8]) -> ServiceReply {
+        ServiceReply::none()
+    }
+
+    fn serve<'a>(&'a mut self, mut stream: CapturedStream, session: &'a mut ServiceSession) -> BoxFuture<'a, ()> {
+        Box::pin(async move {
+            let mut ftp = FtpSession::new(self.credentials.clone());
+            if send_reply(&mut stream, BANNER).await.is_err() {
+                return;
+            }
+
+            let mut buffer = Vec::new();
+            let mut chunk = [0u8; 4096];
+            'connection: loop {
+                let n = match stream.read(&mut chunk).await {
+                    Ok(0) | Err(_) => break,
+                    Ok(n) => n,
+                };
+                buffer.extend_from_slice(&chunk[..n]);
+
+                while let Some(end) = buffer.iter().position(|b| *b == b'\n') {
+                    let line: Vec<u8> = buffer.drain(..=end).collect();
+                    let line = String::from_utf8_lossy(&line).trim_end_matches(['\r', '\n']).to_string();
+
+                    let reply = ftp.handle(&line, &mut stream, session).await;
+                    if send_reply(&mut stream, &reply).await.is_err() || ftp.quit {
+                        break 'connection;
+                    }
+                }
+
+                if buffer.len() > MAX_COMMAND_LINE {
+                    let _ = send_reply(&mut stream, "500 Input line too long.").await;
+                    break;
+                }
+            }
+
+            let _ = stream.shutdown().await;
+        })
+    }
+}
+
+async fn send_reply<S: AsyncWrite + Unpin>(stream: &mut S, reply: &str) -> std::io::Result<()> {
+    stream.write_all(format!("{}\r\n", reply).as_bytes()).await
+}
+
+
+/// Where the next transfer's data connection comes from
+enum DataChannel {
+    None,
+    Passive(oneshot::Receiver<VirtualTcpStream>),
+    Active(SocketAddr),
+}
+
+/// Per-connection protocol state
+struct FtpSession {
+    credentials: Arc<Vec<(String, String)>>,
+    username: Option<String>,
+    logged_in: bool,
+    failures: usize,
+    quit: bool,
+    // Anonymous users are chrooted here; paths shown to the client are relative to it
+    root: String,
+    cwd: String,
+    fs: VirtualFs,
+    data: DataChannel,
+    rename_from: Option<String>,
+}
+
+impl FtpSession {
+    fn new(credentials: Arc<Vec<(String, String)>>) -> Self {
+        FtpSession {
+            credentials,
+            username: None,
+            logged_in: false,
+            failures: 0,
+            quit: false,
+            root: "/".to_string(),
+            cwd: "/".to_string(),
+            fs: VirtualFs::clone(&DEFAULT_FILESYSTEM).with_quota(SESSION_QUOTA),
+            data: DataChannel::None,
+            rename_from: None,
+        }
+    }
+
+    /// Runs one command and returns the final reply; commands of logged-in users are reported
+    async fn handle(&mut self, line: &str, control: &mut CapturedStream, session: &ServiceSession) -> String {
+        let (verb, arg) = match line.split_once(' ') {
+            Some((verb, arg)) => (verb.to_ascii_uppercase(), arg.trim().to_string()),
+            None => (line.trim().to_ascii_uppercase(), String::new()),
+        };
+
+        let was_logged_in = self.logged_in;
+        let cwd = self.cwd.clone();
+        let reply = self.execute(&verb, &arg, control, session).await;
+
+        if was_logged_in {
+            session.record(HoneypotEvent::Command {
+                line: line.to_string(),
+                cwd,
+                output: reply.clone(),
+                exit_status: reply.get(..3).and_then(|code| code.parse().ok()).unwrap_or(0),
+            });
+        }
+        reply
+    }
+
+    async fn execute(&mut self, verb: &str, arg: &str, control: &mut CapturedStream, session: &ServiceSession) -> String {
+        match verb {
+            "USER" if self.logged_in => "530 Can't change to another user.".to_string(),
+            "USER" => {
+                self.username = Some(arg.to_string());
+                "331 Please specify the password.".to_string()
+            }
+            "PASS" if self.logged_in => "230 Already logged in.".to_string(),
+            "PASS" => self.login(arg, session),
+            "QUIT" => {
+                self.quit = true;
+                "221 Goodbye.".to_string()
+            }
+            "FEAT" => "211-Features:\r\n EPRT\r\n EPSV\r\n MDTM\r\n PASV\r\n REST STREAM\r\n SIZE\r\n TVFS\r\n UTF8\r\n211 End".to_string(),
+            "OPTS" if arg.to_ascii_uppercase().starts_with("UTF8") => "200 Always in UTF8 mode.".to_string(),
+            _ if !self.logged_in => "530 Please login with USER and PASS.".to_string(),
+
+            "SYST" => "215 UNIX Type: L8".to_string(),
+            "NOOP" => "200 NOOP ok.".to_string(),
+            "PWD" | "XPWD" => format!("257 \"{}\" is the current directory", self.cwd),
+            "CWD" | "XCWD" => self.change_directory(arg),
+            "CDUP" | "XCUP" => self.change_directory(".."),
+            "TYPE" => match arg.to_ascii_uppercase().as_str() {
+                "A" | "A N" => "200 Switching to ASCII mode.".to_string(),
+                "I" | "L 8" => "200 Switching to Binary mode.".to_string(),
+                _ => "500 Unrecognised TYPE command.".to_string(),
+            },
+            "MODE" if arg.eq_ignore_ascii_case("S") => "200 Mode set to S.".to_string(),
+            "STRU" if arg.eq_ignore_ascii_case("F") => "200 Structure set to F.".to_string(),
+            "MODE" | "STRU" => "504 Bad MODE command.".to_string(),
+            "PASV" => self.passive(session, false).await,
+            "EPSV" => self.passive(session, true).await,
+            "PORT" => self.active(parse_port(arg), session.attacker_addr.ip(), "PORT"),
+            "EPRT" => self.active(parse_eprt(arg), session.attacker_addr.ip(), "EPRT"),
+            "LIST" | "NLST" => {
+                let path = arg.split_whitespace().find(|a| !a.starts_with('-')).unwrap_or(".");
+                // Like vsftpd, a missing path is just an empty listing
+                let listing = self.listing(path, verb == "NLST").unwrap_or_default();
+                self.send_data(control, session, "150 Here comes the directory listing.", listing.as_bytes(), "226 Directory send OK.").await
+            }
+            "RETR" => {
+                let path = self.resolve(arg);
+                match self.fs.read(&path) {
+                    Ok(content) => {
+                        let content = content.to_vec();
+                        let opening = format!("150 Opening BINARY mode data connection for {} ({} bytes).", arg, content.len());
+                        self.send_data(control, session, &opening, &content, "226 Transfer complete.").await
+                    }
+                    Err(_) => "550 Failed to open file.".to_string(),
+                }
+            }
+            "STOR" | "APPE" | "STOU" => self.store(verb, arg, control, session).await,
+            "SIZE" => match self.fs.read(&self.resolve(arg)) {
+                Ok(content) => format!("213 {}", content.len()),
+                Err(_) => "550 Could not get file size.".to_string(),
+            },
+            "MDTM" => match self.fs.get(&self.resolve(arg)) {
+                Some(FsEntry::File(_)) => "213 20240415095204".to_string(),
+                _ => "550 Could not get file modification time.".to_string(),
+            },
+            "DELE" => {
+                let path = self.resolve(arg);
+                let is_file = matches!(self.fs.get(&path), Some(FsEntry::File(_)));
+                if is_file && self.fs.remove(&path, false).is_ok() {
+                    "250 Delete operation successful.".to_string()
+                } else {
+                    "550 Delete operation failed.".to_string()
+                }
+            }
+            "MKD" | "XMKD" => {
+                let path = self.resolve(arg);
+                if self.fs.get(&path).is_none() && self.fs.mkdir(&path).is_ok() {
+                    format!("257 \"{}\" created", self.display(&path))
+                } else {
+                    "550 Create directory operation failed.".to_string()
+                }
+            }
+            "RMD" | "XRMD" => {
+                let path = self.resolve(arg);
+                let empty = self.fs.list(&path).is_ok_and(|children| children.is_empty());
+                if empty && self.fs.remove(&path, true).is_ok() {
+                    "250 Remove directory operation successful.".to_string()
+                } else {
+                    "550 Remove directory operation failed.".to_string()
+                }
+            }
+            "RNFR" => {
+                let path = self.resolve(arg);
+                if self.fs.get(&path).is_some() {
+                    self.rename_from = Some(path);
+                    "350 Ready for RNTO.".to_string()
+                } else {
+                    "550 RNFR command failed.".to_string()
+                }
+            }
+            "RNTO" => {
+                let Some(from) = self.rename_from.take() else {
+                    return "503 RNFR required first.".to_string();
+                };
+                let to = self.resolve(arg);
+                match self.fs.read(&from).map(<[u8]>::to_vec) {
+                    Ok(content) if self.fs.write(&to, &content, false).is_ok() => {
+                        let _ = self.fs.remove(&from, false);
+                        "250 Rename successful.".to_string()
+                    }
+                    _ => "550 Rename failed.".to_string(),
+                }
+            }
+            "REST" => format!("350 Restart position accepted ({}).", arg.parse::<u64>().unwrap_or(0)),
+            "ABOR" => "225 No transfer to ABOR.".to_string(),
+            "SITE" if arg.to_ascii_uppercase().starts_with("HELP") => "214 CHMOD UMASK HELP".to_string(),
+            "SITE" => "500 Unknown SITE command.".to_string(),
+            "HELP" => "214-The following commands are recognized.\r\n ABOR ACCT ALLO APPE CDUP CWD  DELE EPRT EPSV FEAT HELP LIST MDTM MKD\r\n MODE NLST NOOP OPTS PASS PASV PORT PWD  QUIT REIN REST RETR RMD  RNFR\r\n RNTO SITE SIZE SMNT STAT STOR STOU STRU SYST TYPE USER XCUP XCWD XMKD\r\n XPWD XRMD\r\n214 Help OK.".to_string(),
+            _ => "500 Unknown command.".to_string(),
+        }
+    }
+
+    fn login(&mut self, password: &str, session: &ServiceSession) -> String {
+        let Some(username) = self.username.take() else {
+            return "503 Login with USER first.".to_string();
+        };
+
+        let anonymous = ANONYMOUS_USERS.contains(&username.as_str());
+        let accepted = anonymous || self.credentials.iter().any(|(u, p)| *u == username && p == password);
+        session.record(HoneypotEvent::LoginAttempt {
+            username: username.clone(),
+            password: Some(password.to_string()),
+            public_key: None,
+            accepted,
+        });
+
+        if !accepted {
+            self.failures += 1;
+            self.quit = self.failures >= MAX_LOGIN_FAILURES;
+            return "530 Login incorrect.".to_string();
+        }
+
+        let home = if anonymous {
+            self.root = ANONYMOUS_ROOT.to_string();
+            let _ = self.fs.mkdir(ANONYMOUS_ROOT);
+            let _ = self.fs.mkdir(&format!("{}/pub", ANONYMOUS_ROOT));
+            "/".to_string()
+        } else if username == "root" {
+            "/root".to_string()
+        } else {
+            let home = format!("/home/{}", username);
+            let _ = self.fs.mkdir(&home);
+            home
+        };
+
+        self.cwd = home;
+        self.logged_in = true;
+        info!("🍯 FTP login as {} from {}", username, session.attacker_addr);
+        "230 Login successful.".to_string()
+    }
+
+    /// Path on the fake filesystem for a path given by the client
+    fn resolve(&self, path: &str) -> String {
+        let visible = filesystem::normalize(&self.cwd, path);
+        match (self.root.as_str(), visible.as_str()) {
+            ("/", _) => visible,
+            (root, "/") => root.to_string(),
+            (root, visible) => format!("{}{}", root, visible),
+        }
+    }
+
+    /// Path as the client sees it, inside the chroot
+    fn display(&self, path: &str) -> String {
+        if self.root == "/" {
+            return path.to_string();
+        }
+        match path.strip_prefix(&self.root) {
+            Some("") | None => "/".to_string(),
+            Some(rest) => rest.to_string(),
+        }
+    }
+
+    fn change_directory(&mut self, path: &str) -> String {
+        let target = self.resolve(path);
+        if !self.fs.is_dir(&target) {
+            return "550 Failed to change directory.".to_string();
+        }
+        self.cwd = self.display(&target);
+        "250 Directory successfully changed.".to_string()
+    }
+
+    fn listing(&self, path: &str, names_only: bool) -> Option<String> {
+        let absolute = self.resolve(path);
+        let entries: Vec<(String, &FsEntry)> = match self.fs.list(&absolute) {
+            Ok(children) => children.into_iter().filter(|(name, _)| !name.starts_with('.')).map(|(name, entry)| (name.to_string(), entry)).collect(),
+            Err(filesystem::FsError::NotADirectory) => vec![(path.to_string(), self.fs.get(&absolute)?)],
+            Err(_) => return None,
+        };
+
+        let owner = if absolute.starts_with("/home/") { 1000 } else { 0 };
+        Some(entries.into_iter().map(|(name, entry)| {
+            if names_only {
+                return format!("{}\r\n", name);
+            }
+            let (mode, links, size) = match entry {
+                FsEntry::Directory => ("drwxr-xr-x", 2, 4096),
+                FsEntry::File(content) => ("-rw-r--r--", 1, content.len()),
+            };
+            format!("{}    {} {:<8} {:<8} {:>12} Apr 15 09:52 {}\r\n", mode, links, owner, owner, size, name)
+        }).collect())
+    }
+
+    async fn passive(&mut self, session: &ServiceSession, extended: bool) -> String {
+        // PASV can only carry an IPv4 address, IPv6 clients have to use EPSV (RFC 2428)
+        let pasv_ip = match session.honeypot_addr.ip() {
+            IpAddr::V4(ip) => Some(ip.octets()),
+            IpAddr::V6(_) if extended => None,
+            IpAddr::V6(_) => return "500 Illegal PASV command.".to_string(),
+        };
+
+        let mut listener = None;
+        for _ in 0..8 {
+            let port = PASSIVE_PORTS.start + rand::random::<u16>() % (PASSIVE_PORTS.end - PASSIVE_PORTS.start);
+            if let Some(accepted) = session.accept_from_attacker(port).await {
+                listener = Some((port, accepted));
+                break;
+            }
+        }
+        let Some((port, accepted)) = listener else {
+            return "425 Could not listen on passive port.".to_string();
+        };
+
+        self.data = DataChannel::Passive(accepted);
+        match pasv_ip {
+            Some(ip) if !extended => format!("227 Entering Passive Mode ({},{},{},{},{},{}).", ip[0], ip[1], ip[2], ip[3], port >> 8, port & 0xff),
+            _ => format!("229 Entering Extended Passive Mode (|||{}|)", port),
+        }
+    }
+
+    /// PORT/EPRT: only the control connection's own address is accepted, which refuses FTP bounce
+    fn active(&mut self, target: Option<SocketAddr>, attacker: IpAddr, verb: &str) -> String {
+        match target {
+            Some(target) if target.ip() == attacker && target.port() != 0 => {
+                self.data = DataChannel::Active(target);
+                if verb == "EPRT" {
+                    "200 EPRT command successful. Consider using EPSV.".to_string()
+                } else {
+                    "200 PORT command successful. Consider using PASV.".to_string()
+                }
+            }
+            _ => format!("500 Illegal {} command.", verb),
+        }
+    }
+
+    async fn open_data_connection(&mut self, session: &ServiceSession) -> Option<VirtualTcpStream> {
+        match std::mem::replace(&mut self.data, DataChannel::None) {
+            DataChannel::None => None,
+            DataChannel::Passive(accepted) => timeout(DATA_CONNECTION_TIMEOUT, accepted).await.ok()?.ok(),
+            DataChannel::Active(target) => session.connect_back(ACTIVE_DATA_PORT, target).await.ok(),
+        }
+    }
+
+    async fn send_data(&mut self, control: &mut CapturedStream, session: &ServiceSession, opening: &str, data: &[u8], done: &str) -> String {
+        if matches!(self.data, DataChannel::None) {
+            return "425 Use PORT or PASV first.".to_string();
+        }
+        let Some(mut stream) = self.open_data_connection(session).await else {
+            return "425 Failed to establish connection.".to_string();
+        };
+
+        let _ = send_reply(control, opening).await;
+        let sent = stream.write_all(data).await.is_ok();
+        let _ = stream.shutdown().await;
+        if sent { done.to_string() } else { "426 Failure writing network stream.".to_string() }
+    }
+
+    async fn store(&mut self, verb: &str, arg: &str, control: &mut CapturedStream, session: &ServiceSession) -> String {
+        let name = if verb == "STOU" { format!("{}.{}", if arg.is_empty() { "file" } else { arg }, rand::random::<u16>()) } else { arg.to_string() };
+        let path = self.resolve(&name);
+        if name.is_empty() || !self.fs.is_dir(&filesystem::parent(&path)) || self.fs.is_dir(&path) {
+            return "553 Could not create file.".to_string();
+        }
+        if matches!(self.data, DataChannel::None) {
+            return "425 Use PORT or PASV first.".to_string();
+        }
+        let Some(mut stream) = self.open_data_connection(session).await else {
+            return "425 Failed to establish connection.".to_string();
+        };
+
+        let _ = send_reply(control, "150 Ok to send data.").await;
+        let (content, size) = read_upload(&mut stream).await;
+        let kept = content.len() as u64;
+        let written = self.fs.write(&path, &content, verb == "APPE");
+        let artifact = artifacts::store(content).await;
+
+        session.record(HoneypotEvent::Upload {
+            path: path.clone(),
+            size,
+            sha256: artifact.sha256,
+            md5: artifact.md5,
+            truncated: size > kept,
+        });
+        if let Err(FsError::NoSpace) = written {
+            return "552 Requested file action aborted. Exceeded storage allocation.".to_string();
+        }
+
+        if verb == "STOU" {
+            return format!("226 Transfer complete. FILE: {}", name);
+        }
+        "226 Transfer complete.".to_string()
+    }
+}
+
+/// Reads an upload until EOF, keeping at most `MAX_UPLOAD` bytes; returns the kept bytes and the total size
+async fn read_upload<S: AsyncRead + Unpin>(stream: &mut S) -> (Vec<u8>, u64) {
+    let mut content = Vec::new();
+    let mut size = 0u64;
+    let mut chunk = vec![0u8; 16 * 1024];
+    loop {
+        match stream.read(&mut chunk).await {
+            Ok(0) | Err(_) => break,
+            Ok(n) => {
+                size += n as u64;
+                let room = MAX_UPLOAD.saturating_sub(content.len());
+                content.extend_from_slice(&chunk[..n.min(room)]);
+            }
+        }
+    }
+    (content, size)
+}
+
+/// `PORT h1,h2,h3,h4,p1,p2`
+fn parse_port(arg: &str) -> Option<SocketAddr> {
+    let numbers: Vec<u8> = arg.split(',').map(|n| n.trim().parse().ok()).collect::<Option<_>>()?;
+    let [a, b, c, d, high, low] = numbers[..] else {
+        return None;
+    };
+    Some(SocketAddr::new(Ipv4Addr::new(a, b, c, d).into(), u16::from_be_bytes([high, low])))
+}
+
+/// `EPRT |1|address|port|` for IPv4, `EPRT |2|address|port|` for IPv6
+fn parse_eprt(arg: &str) -> Option<SocketAddr> {
+    // Any printable ASCII character may delimit the fields (RFC 2428 §2)
+    let delimiter = arg.chars().next().filter(char::is_ascii_graphic)?;
+    let fields: Vec<&str> = arg.split(delimiter).collect();
+    match fields[..] {
+        ["", "1", address, port, ""] => Some(SocketAddr::new(IpAddr::V4(address.parse().ok()?), port.parse().ok()?)),
+        ["", "2", address, port, ""] => Some(SocketAddr::new(IpAddr::V6(address.parse().ok()?), port.parse().ok()?)),
+        _ => None,
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn port_arguments() {
+        // RFC 959 host-port encoding
+        assert_eq!(parse_port("192,168,1,2,7,138"), Some("192.168.1.2:1930".parse().unwrap()));
+        assert_eq!(parse_port(" 10, 0,0,5 ,0,21"), Some("10.0.0.5:21".parse().unwrap()));
+        for malformed in ["", "1,2,3,4,5", "1,2,3,4,5,6,7", "256,0,0,1,0,21", "1,2,3,4,-1,0", "a,b,c,d,e,f", ",,,,,"] {
+            assert_eq!(parse_port(malformed), None, "{:?}", malformed);
+        }
+    }
+
+    #[test]
+    fn eprt_arguments() {
+        // RFC 2428 §2 examples
+        assert_eq!(parse_eprt("|1|132.235.1.2|6275|"), Some("132.235.1.2:6275".parse().unwrap()));
+        assert_eq!(parse_eprt("|2|1080::8:800:200C:417A|5282|"), Some("[1080::8:800:200c:417a]:5282".parse().unwrap()));
+        assert_eq!(parse_eprt("!1!10.0.0.5!2121!"), Some("10.0.0.5:2121".parse().unwrap()));
+        for malformed in ["", "|", "|1|10.0.0.5|21", "|2|10.0.0.5|21|", "|1|::1|21|", "|3|10.0.0.5|21|", "|1|10.0.0.5|70000|", "|1|10.0.0.5|21|x|", "é1é10.0.0.5é21é"] {
+            assert_eq!(parse_eprt(malformed), None, "{:?}", malformed);
+        }
+    }
+
+    #[test]
+    fn data_connections_only_go_back_to_the_client() {
+        let attacker: IpAddr = "10.0.0.7".parse().unwrap();
+        let mut ftp = FtpSession::new(Arc::new(Vec::new()));
+
+        // FTP bounce: the data connection would be opened towards a third host
+        assert_eq!(ftp.active(parse_port("10,0,0,8,0,25"), attacker, "PORT"), "500 Illegal PORT command.");
+        assert_eq!(ftp.active(parse_eprt("|1|192.168.1.1|80|"), attacker, "EPRT"), "500 Illegal EPRT command.");
+        assert_eq!(ftp.active(parse_port("10,0,0,7,0,0"), attacker, "PORT"), "500 Illegal PORT command.");
+        assert_eq!(ftp.active(None, attacker, "PORT"), "500 Illegal PORT command.");
+        assert!(matches!(ftp.data, DataChannel::None));
+
+        assert!(ftp.active(parse_port("10,0,0,7,156,64"), attacker, "PORT").starts_with("200 PORT"));
+        assert!(matches!(ftp.data, DataChannel::Active(target) if target == "10.0.0.7:40000".parse().unwrap()));
+        assert!(ftp.active(parse_eprt("|1|10.0.0.7|40001|"), attacker, "EPRT").starts_with("200 EPRT"));
+    }
+}
@@ -0,0 +1,268 @@
+use pnet::util::MacAddr;
+
+use super::{HttpRequest, HttpResponse};
+use crate::honeypot::{persona::UpnpPersonality, services::udp::ssdp::device_uuid};
+
+const APACHE_SERVER: &str = "Apache/2.4.52 (Ubuntu)";
+
+const APACHE_DEFAULT_PAGE: &str = r#"<!DOCTYPE html PUBLIC "-//W3C//DTD XHTML 1.0 Transitional//EN" "http://www.w3.org/TR/xhtml1/DTD/xhtml1-transitional.dtd">
+<html xmlns="http://www.w3.org/1999/xhtml">
+  <head>
+    <meta http-equiv="Content-Type" content="text/html; charset=UTF-8" />
+    <title>Apache2 Ubuntu Default Page: It works</title>
+  </head>
+  <body>
+    <div class="main_page">
+      <div class="page_header floating_element">
+        <span class="floating_element">Apache2 Ubuntu Default Page</span>
+      </div>
+      <div class="content_section floating_element">
+        <div class="section_header section_header_red">It works!</div>
+        <p>
+          This is the default welcome page used to test the correct operation of the Apache2 server after
+          installation on Ubuntu systems. If you can read this page, it means that the Apache HTTP server
+          installed at this site is working properly. You should <b>replace this file</b> (located at
+          <tt>/var/www/html/index.html</tt>) before continuing to operate your HTTP server.
+        </p>
+      </div>
+    </div>
+  </body>
+</html>
+"#;
+
+const WORDPRESS_HOME: &str = r#"<!DOCTYPE html>
+<html lang="en-US">
+<head>
+<meta charset="UTF-8" />
+<meta name="viewport" content="width=device-width, initial-scale=1" />
+<meta name="generator" content="WordPress 6.2.2" />
+<title>Northwind Logistics &#8211; Just another WordPress site</title>
+<link rel='stylesheet' id='wp-block-library-css' href='/wp-includes/css/dist/block-library/style.min.css?ver=6.2.2' media='all' />
+<link rel="https://api.w.org/" href="/wp-json/" />
+<link rel="EditURI" type="application/rsd+xml" title="RSD" href="/xmlrpc.php?rsd" />
+</head>
+<body class="home blog">
+<header class="wp-block-template-part"><p class="wp-block-site-title"><a href="/" rel="home">Northwind Logistics</a></p></header>
+<main class="wp-block-group">
+<h2 class="wp-block-post-title"><a href="/?p=1">Hello world!</a></h2>
+<p>Welcome to WordPress. This is your first post. Edit or delete it, then start writing!</p>
+</main>
+<footer class="wp-block-template-part"><p class="has-text-align-center">Proudly powered by <a href="https://wordpress.org">WordPress</a></p></footer>
+</body>
+</html>
+"#;
+
+const WORDPRESS_LOGIN: &str = r#"<!DOCTYPE html>
+<html lang="en-US">
+<head>
+<meta http-equiv="Content-Type" content="text/html; charset=UTF-8" />
+<title>Log In &lsaquo; Northwind Logistics &#8212; WordPress</title>
+<meta name='robots' content='max-image-preview:large, noindex, noarchive' />
+<link rel='stylesheet' id='login-css' href='/wp-admin/css/login.min.css?ver=6.2.2' media='all' />
+</head>
+<body class="login no-js login-action-login wp-core-ui">
+<div id="login">
+<h1><a href="https://wordpress.org/">Powered by WordPress</a></h1>
+{error}
+<form name="loginform" id="loginform" action="/wp-login.php" method="post">
+<p><label for="user_login">Username or Email Address</label>
+<input type="text" name="log" id="user_login" class="input" value="" size="20" autocapitalize="off" autocomplete="username" /></p>
+<div class="user-pass-wrap"><label for="user_pass">Password</label>
+<input type="password" name="pwd" id="user_pass" class="input password-input" value="" size="20" autocomplete="current-password" /></div>
+<p class="forgetmenot"><input name="rememberme" type="checkbox" id="rememberme" value="forever" /> <label for="rememberme">Remember Me</label></p>
+<p class="submit"><input type="submit" name="wp-submit" id="wp-submit" class="button button-primary button-large" value="Log In" />
+<input type="hidden" name="redirect_to" value="/wp-admin/" /><input type="hidden" name="testcookie" value="1" /></p>
+</form>
+<p id="nav"><a href="/wp-login.php?action=lostpassword">Lost your password?</a></p>
+</div>
+</body>
+</html>
+"#;
+
+const ROUTER_UNAUTHORIZED: &str = "<HTML><HEAD><TITLE>401 Unauthorized</TITLE></HEAD>\n<BODY BGCOLOR=\"#cc9999\"><H4>401 Unauthorized</H4>\nAuthorization required.\n</BODY></HTML>\n";
+const ROUTER_NOT_FOUND: &str = "<HTML><HEAD><TITLE>404 Not Found</TITLE></HEAD>\n<BODY BGCOLOR=\"#cc9999\"><H4>404 Not Found</H4>\nFile not found.\n</BODY></HTML>\n";
+
+const CAMERA_LOGIN: &str = r#"<!DOCTYPE html>
+<html>
+<head>
+<meta http-equiv="Content-Type" content="text/html; charset=utf-8" />
+<meta http-equiv="X-UA-Compatible" content="IE=edge" />
+<title>Network Camera</title>
+<link rel="stylesheet" type="text/css" href="/doc/css/login.css" />
+</head>
+<body>
+<div class="login-wrap">
+  <div class="login-logo">IP CAMERA</div>
+  <form id="loginForm" action="/doc/page/login.asp" method="post">
+    <div class="login-item"><label>User Name</label><input id="username" name="username" type="text" maxlength="32" /></div>
+    <div class="login-item"><label>Password</label><input id="password" name="password" type="password" maxlength="16" /></div>
+    {error}
+    <div class="login-item"><button type="submit" class="btn-login">Login</button></div>
+  </form>
+  <div class="login-footer">&copy;2023 All Rights Reserved. Web version V4.0.1 build 220805</div>
+</div>
+</body>
+</html>
+"#;
+
+
+/// Kind of web application a decoy pretends to run
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HttpPersona {
+    ApacheDefault,
+    WordPress,
+    RouterAdmin,
+    IpCamera,
+    /// Description document of a UPnP device, where its SSDP messages point
+    Upnp(&'static UpnpPersonality, MacAddr),
+}
+
+impl HttpPersona {
+    pub const ALL: [HttpPersona; 4] = [HttpPersona::ApacheDefault, HttpPersona::WordPress, HttpPersona::RouterAdmin, HttpPersona::IpCamera];
+
+    /// Stable choice per node so the same decoy always shows the same site
+    pub fn for_mac(mac: MacAddr) -> Self {
+        HttpPersona::ALL[mac.5 as usize % HttpPersona::ALL.len()]
+    }
+
+    pub fn named(name: &str) -> Option<Self> {
+        HttpPersona::ALL.into_iter().find(|persona| persona.name() == name)
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            HttpPersona::ApacheDefault => "apache_default",
+            HttpPersona::WordPress => "wordpress",
+            HttpPersona::RouterAdmin => "router_admin",
+            HttpPersona::IpCamera => "ip_camera",
+            HttpPersona::Upnp(..) => "upnp",
+        }
+    }
+
+    pub fn server(&self) -> &'static str {
+        match self {
+            HttpPersona::ApacheDefault | HttpPersona::WordPress => APACHE_SERVER,
+            HttpPersona::RouterAdmin => "micro_httpd",
+            HttpPersona::IpCamera => "App-webs/",
+            HttpPersona::Upnp(upnp, _) => &upnp.server,
+        }
+    }
+
+    pub fn respond(&self, request: &HttpRequest) -> HttpResponse {
+        if !["GET", "HEAD", "POST", "OPTIONS"].contains(&request.method.as_str()) {
+            return self.error(405);
+        }
+
+        match self {
+            HttpPersona::ApacheDefault => match request.path() {
+                "/" | "/index.html" => HttpResponse::html(200, APACHE_DEFAULT_PAGE),
+                _ => self.error(404),
+            },
+            HttpPersona::WordPress => wordpress(request),
+            HttpPersona::RouterAdmin => router(request),
+            HttpPersona::IpCamera => camera(request),
+            HttpPersona::Upnp(upnp, mac) => match request.path() {
+                path if path == upnp.path => HttpResponse::new(200, "text/xml; charset=\"utf-8\"", device_description(upnp, *mac)),
+                _ => self.error(404),
+            },
+        }
+    }
+
+    pub fn bad_request(&self) -> HttpResponse {
+        self.error(400)
+    }
+
+    fn error(&self, status: u16) -> HttpResponse {
+        match self {
+            HttpPersona::RouterAdmin if status == 404 => HttpResponse::html(404, ROUTER_NOT_FOUND),
+            HttpPersona::IpCamera | HttpPersona::Upnp(..) => HttpResponse::html(status, ""),
+            _ => {
+                let (title, text) = match status {
+                    400 => ("Bad Request", "Your browser sent a request that this server could not understand.".to_string()),
+                    405 => ("Method Not Allowed", "The requested method is not allowed for this URL.".to_string()),
+                    _ => ("Not Found", "The requested URL was not found on this server.".to_string()),
+                };
+                HttpResponse::html(
+                    status,
+                    format!(
+                        "<!DOCTYPE HTML PUBLIC \"-//IETF//DTD HTML 2.0//EN\">\n<html><head>\n<title>{} {}</title>\n</head><body>\n<h1>{}</h1>\n<p>{}</p>\n<hr>\n<address>{} Server at localhost Port 80</address>\n</body></html>\n",
+                        status, title, title, text, APACHE_SERVER,
+                    ),
+                )
+            }
+        }
+    }
+}
+
+
+fn wordpress(request: &HttpRequest) -> HttpResponse {
+    let php = |response: HttpResponse| response.with_header("X-Powered-By", "PHP/7.4.33");
+
+    match (request.method.as_str(), request.path()) {
+        (_, "/" | "/index.php") => php(HttpResponse::html(200, WORDPRESS_HOME).with_header("Link", "</wp-json/>; rel=\"https://api.w.org/\"")),
+        ("POST", "/wp-login.php") => {
+            let (username, _) = request.credentials();
+            let error = format!(
+                "<div id=\"login_error\"><strong>Error:</strong> The password you entered for the username <strong>{}</strong> is incorrect. <a href=\"/wp-login.php?action=lostpassword\">Lost your password?</a></div>",
+                html_escape(&username.unwrap_or_default()),
+            );
+            php(HttpResponse::html(200, WORDPRESS_LOGIN.replace("{error}", &error)))
+        }
+        (_, "/wp-login.php") => php(HttpResponse::html(200, WORDPRESS_LOGIN.replace("{error}", ""))),
+        (_, path) if path.starts_with("/wp-admin") => HttpResponse::redirect("/wp-login.php?redirect_to=%2Fwp-admin%2F&reauth=1"),
+        ("POST", "/xmlrpc.php") => php(HttpResponse::new(
+            200,
+            "text/xml; charset=UTF-8",
+            "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<methodResponse>\n  <fault>\n    <value>\n      <struct>\n        <member><name>faultCode</name><value><int>403</int></value></member>\n        <member><name>faultString</name><value><string>Incorrect username or password.</string></value></member>\n      </struct>\n    </value>\n  </fault>\n</methodResponse>\n",
+        )),
+        (_, "/xmlrpc.php") => php(HttpResponse::new(405, "text/plain;charset=UTF-8", "XML-RPC server accepts POST requests only.")),
+        (_, "/robots.txt") => php(HttpResponse::new(200, "text/plain; charset=utf-8", "User-agent: *\nDisallow: /wp-admin/\nAllow: /wp-admin/admin-ajax.php\n")),
+        (_, "/wp-json/wp/v2/users") => php(HttpResponse::new(
+            200,
+            "application/json; charset=UTF-8",
+            "[{\"id\":1,\"name\":\"admin\",\"url\":\"\",\"description\":\"\",\"link\":\"/author/admin/\",\"slug\":\"admin\"}]",
+        )),
+        _ => HttpPersona::WordPress.error(404),
+    }
+}
+
+fn router(request: &HttpRequest) -> HttpResponse {
+    // Every page sits behind Basic auth, which is how most consumer routers still do it
+    let unauthorized = HttpResponse::html(401, ROUTER_UNAUTHORIZED).with_header("WWW-Authenticate", "Basic realm=\"TP-LINK Wireless N Router WR840N\"");
+
+    match request.path() {
+        "/" | "/index.htm" | "/index.html" | "/userRpm/LoginRpm.htm" => unauthorized,
+        path if path.starts_with("/userRpm/") || path.starts_with("/cgi-bin/") => unauthorized,
+        _ => HttpPersona::RouterAdmin.error(404),
+    }
+}
+
+fn camera(request: &HttpRequest) -> HttpResponse {
+    match (request.method.as_str(), request.path()) {
+        (_, "/") => HttpResponse::redirect("/doc/page/login.asp"),
+        ("POST", "/doc/page/login.asp") => HttpResponse::html(
+            200,
+            CAMERA_LOGIN.replace("{error}", "<div class=\"login-error\">Incorrect user name or password.</div>"),
+        ),
+        (_, "/doc/page/login.asp") => HttpResponse::html(200, CAMERA_LOGIN.replace("{error}", "")),
+        (_, "/ISAPI/Security/userCheck") => HttpResponse::new(
+            401,
+            "application/xml",
+            "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<userCheck>\n<statusValue>401</statusValue>\n<statusString>Unauthorized</statusString>\n</userCheck>\n",
+        )
+        .with_header("WWW-Authenticate", "Digest qop=\"auth\", realm=\"IP Camera(C6492)\", nonce=\"4e5468694e7a42694e7a4d364f4449354d7a6b354d54513d\", stale=\"FALSE\""),
+        _ => HttpPersona::IpCamera.error(404),
+    }
+}
+
+/// Root device description, with the identity and UUID its SSDP messages carry
+fn device_description(upnp: &UpnpPersonality, mac: MacAddr) -> String {
+    format!(
+        "<?xml version=\"1.0\"?>\n<root xmlns=\"urn:schemas-upnp-org:device-1-0\">\n<specVersion><major>1</major><minor>0</minor></specVersion>\n<device>\n<deviceType>{}</deviceType>\n<friendlyName>{}</friendlyName>\n<manufacturer>{}</manufacturer>\n<modelName>{}</modelName>\n<UDN>uuid:{}</UDN>\n</device>\n</root>\n",
+        html_escape(&upnp.device_type), html_escape(&upnp.friendly_name), html_escape(&upnp.manufacturer), html_escape(&upnp.model_name), device_uuid(mac),
+    )
+}
+
+fn html_escape(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
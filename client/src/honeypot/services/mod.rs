@@ -0,0 +1,200 @@
+pub mod artifacts;
+pub mod banner;
+pub mod ftp;
+pub mod http;
+pub mod modbus;
+pub mod mqtt;
+pub mod session;
+pub mod ssh;
+pub mod telnet;
+pub mod time;
+pub mod tls;
+pub mod udp;
+
+use std::{collections::HashMap, fmt, sync::Arc};
+
+use futures_util::future::BoxFuture;
+use pnet::util::MacAddr;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+use banner::{BannerEmulator, BannerProfile};
+use ftp::FtpEmulator;
+use http::{HttpEmulator, HttpPersona};
+use modbus::ModbusEmulator;
+use mqtt::MqttEmulator;
+use session::{CapturedStream, ServiceSession};
+use ssh::SshEmulator;
+use telnet::TelnetEmulator;
+use tls::TlsEmulator;
+use crate::{honeypot::{fleet::ServiceSettings, persona::OsPersona, tcp::stream::VirtualTcpStream}, interfaces::ws::WsReporter};
+
+
+const READ_BUFFER: usize = 4096;
+
+/// Ports of the full emulators `ServiceRegistry::with_default_services` registers
+pub const EMULATED_PORTS: [u16; 8] = [21, 22, 23, 2323, 80, 443, 502, 1883];
+
+/// Outcome of feeding attacker data to an emulator
+#[derive(Debug, Default)]
+pub struct ServiceReply {
+    pub data: Vec<u8>,
+    pub close: bool,
+}
+
+impl ServiceReply {
+    pub fn none() -> Self {
+        ServiceReply::default()
+    }
+
+    pub fn send(data: impl Into<Vec<u8>>) -> Self {
+        ServiceReply { data: data.into(), close: false }
+    }
+
+    pub fn close(data: impl Into<Vec<u8>>) -> Self {
+        ServiceReply { data: data.into(), close: true }
+    }
+}
+
+
+/// A decoy application protocol served on a virtual node's port.
+/// One instance is created per connection, so emulators keep their per-session state in `self`.
+/// Simple request/response protocols only implement the hooks; protocols that need to own the
+/// byte stream (TLS, SSH, ...) override `serve` instead.
+pub trait ServiceEmulator: Send {
+    fn name(&self) -> &'static str;
+
+    /// Banner sent as soon as the connection is accepted
+    fn on_connect(&mut self, _session: &mut ServiceSession) -> Vec<u8> {
+        Vec::new()
+    }
+
+    fn on_data(&mut self, session: &mut ServiceSession, data: &[u8]) -> ServiceReply;
+
+    fn on_close(&mut self, _session: &mut ServiceSession) {}
+
+    fn serve<'a>(&'a mut self, stream: CapturedStream, session: &'a mut ServiceSession) -> BoxFuture<'a, ()> {
+        Box::pin(run_emulator(self, stream, session))
+    }
+}
+
+/// Default connection loop driving the `ServiceEmulator` hooks, also usable by emulators
+/// that unwrap the stream first (e.g. HTTP behind TLS)
+pub async fn run_emulator<E, S>(emulator: &mut E, mut stream: S, session: &mut ServiceSession)
+where
+    E: ServiceEmulator + ?Sized,
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    let banner = emulator.on_connect(session);
+
+    if banner.is_empty() || stream.write_all(&banner).await.is_ok() {
+        let mut buffer = vec![0u8; READ_BUFFER];
+        loop {
+            let n = match stream.read(&mut buffer).await {
+                Ok(0) | Err(_) => break,
+                Ok(n) => n,
+            };
+
+            let reply = emulator.on_data(session, &buffer[..n]);
+            if !reply.data.is_empty() && stream.write_all(&reply.data).await.is_err() {
+                break;
+            }
+            if reply.close {
+                break;
+            }
+        }
+    }
+
+    emulator.on_close(session);
+    let _ = stream.shutdown().await;
+}
+
+
+/// Runs an accepted connection through its emulator and reports the captured session
+pub async fn run_service(mut emulator: Box<dyn ServiceEmulator>, stream: VirtualTcpStream, reporter: WsReporter) {
+    let (mut session, captured) = ServiceSession::open(emulator.name(), stream, reporter);
+    emulator.serve(captured, &mut session).await;
+    session.report().await;
+}
+
+
+pub type EmulatorFactory = Arc<dyn Fn() -> Box<dyn ServiceEmulator> + Send + Sync>;
+
+/// Ports a virtual node listens on, each mapped to the factory of its emulator
+#[derive(Clone, Default)]
+pub struct ServiceRegistry {
+    services: HashMap<u16, EmulatorFactory>,
+}
+
+impl ServiceRegistry {
+    pub fn with_default_services(mac: MacAddr, os: &OsPersona, banner_profile: Option<&BannerProfile>, hostname: &str, settings: &ServiceSettings) -> Self {
+        let mut registry = ServiceRegistry::default();
+        // Banner-only services first, so the full emulators win on shared ports
+        if let Some(profile) = banner_profile {
+            for service in &profile.services {
+                for port in &service.ports {
+                    let (service, hostname) = (service.clone(), hostname.to_string());
+                    registry.register(*port, move || Box::new(BannerEmulator::new(service.clone(), hostname.clone())));
+                }
+            }
+        }
+        registry.register(21, || Box::new(FtpEmulator::default()));
+        let ssh = match &settings.ssh {
+            Some(login) => SshEmulator::with_credentials(mac, &os.ssh, hostname, login.pairs()),
+            None => SshEmulator::new(mac, &os.ssh, hostname),
+        };
+        registry.register(22, move || Box::new(ssh.clone()));
+        let telnet_credentials = settings.telnet.as_ref().map(|login| Arc::new(login.pairs()));
+        for port in [23, 2323] {
+            let credentials = telnet_credentials.clone();
+            registry.register(port, move || match &credentials {
+                Some(credentials) => Box::new(TelnetEmulator::with_credentials(mac, credentials.clone())),
+                None => Box::new(TelnetEmulator::new(mac)),
+            });
+        }
+        let persona = settings.http.as_ref()
+            .and_then(|http| HttpPersona::named(&http.persona))
+            .unwrap_or_else(|| HttpPersona::for_mac(mac));
+        registry.register(80, move || Box::new(HttpEmulator::new(persona)));
+        let tls = TlsEmulator::new(mac, persona);
+        registry.register(443, move || Box::new(tls.clone()));
+        let modbus = ModbusEmulator::new(mac);
+        registry.register(502, move || Box::new(modbus.clone()));
+        registry.register(1883, || Box::new(MqttEmulator::default()));
+        if let Some(upnp) = &os.personality().upnp {
+            registry.register(upnp.port, move || Box::new(HttpEmulator::with_name("upnp", HttpPersona::Upnp(upnp, mac))));
+        }
+        registry
+    }
+
+    pub fn register<F>(&mut self, port: u16, factory: F)
+    where
+        F: Fn() -> Box<dyn ServiceEmulator> + Send + Sync + 'static,
+    {
+        self.services.insert(port, Arc::new(factory));
+    }
+
+    /// Closes every port not in `ports`
+    pub fn retain(&mut self, ports: &[u16]) {
+        self.services.retain(|port, _| ports.contains(port));
+    }
+
+    pub fn create(&self, port: u16) -> Option<Box<dyn ServiceEmulator>> {
+        self.services.get(&port).map(|factory| factory())
+    }
+
+    pub fn contains(&self, port: u16) -> bool {
+        self.services.contains_key(&port)
+    }
+
+    pub fn ports(&self) -> Vec<u16> {
+        let mut ports: Vec<u16> = self.services.keys().copied().collect();
+        ports.sort_unstable();
+        ports
+    }
+}
+
+impl fmt::Debug for ServiceRegistry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ServiceRegistry").field("ports", &self.ports()).finish()
+    }
+}
@@ -0,0 +1,385 @@
+use std::{sync::{Arc, Mutex}, time::{SystemTime, UNIX_EPOCH}};
+
+use common::types::HoneypotEvent;
+use pnet::util::MacAddr;
+
+use super::{session::ServiceSession, ServiceEmulator, ServiceReply};
+
+const MBAP_LEN: usize = 7;
+// Largest ADU a Modbus/TCP device accepts (7 byte MBAP + 253 byte PDU)
+const MAX_ADU_LEN: usize = 260;
+// Addresses 0..TABLE_SIZE exist in every table
+const TABLE_SIZE: usize = 1000;
+
+const READ_COILS: u8 = 0x01;
+const READ_DISCRETE_INPUTS: u8 = 0x02;
+const READ_HOLDING_REGISTERS: u8 = 0x03;
+const READ_INPUT_REGISTERS: u8 = 0x04;
+const WRITE_SINGLE_COIL: u8 = 0x05;
+const WRITE_SINGLE_REGISTER: u8 = 0x06;
+const WRITE_MULTIPLE_COILS: u8 = 0x0f;
+const WRITE_MULTIPLE_REGISTERS: u8 = 0x10;
+const REPORT_SERVER_ID: u8 = 0x11;
+const MASK_WRITE_REGISTER: u8 = 0x16;
+const READ_WRITE_MULTIPLE_REGISTERS: u8 = 0x17;
+const ENCAPSULATED_INTERFACE: u8 = 0x2b;
+const MEI_READ_DEVICE_ID: u8 = 0x0e;
+
+const ILLEGAL_FUNCTION: u8 = 0x01;
+const ILLEGAL_DATA_ADDRESS: u8 = 0x02;
+const ILLEGAL_DATA_VALUE: u8 = 0x03;
+
+// PLCs the decoys pretend to be: vendor, product code, revision, picked per node
+const DEVICES: &[(&str, &str, &str)] = &[
+    ("Schneider Electric", "BMX P34 2020", "v2.70"),
+    ("Siemens", "6ES7 214-1AG40-0XB0", "V4.4.0"),
+    ("Rockwell Automation", "1769-L33ER", "30.011"),
+];
+
+
+/// Process image of the emulated PLC, shared by every connection to the node
+/// so writes stick like they would on the real device
+struct RegisterMap {
+    coils: Vec<bool>,
+    holding: Vec<u16>,
+}
+
+impl RegisterMap {
+    fn new(mac: MacAddr) -> Self {
+        // A small process cell repeated across the map: temperature setpoint (x10 °C), motor speed (rpm),
+        // pressure (kPa), operating mode, valve opening (%), flow (l/h), alarm word, serial
+        let holding = (0..TABLE_SIZE).map(|i| match i % 8 {
+            0 => 215 + (i / 8 % 5) as u16 * 10,
+            1 => 1450,
+            2 => 600 + (i / 8 % 3) as u16 * 25,
+            3 => 1,
+            4 => 35 + (i / 8 % 4) as u16 * 10,
+            5 => 3000,
+            6 => 0,
+            _ => u16::from_be_bytes([mac.4, mac.5]),
+        }).collect();
+        let coils = (0..TABLE_SIZE).map(|i| i % 8 < 3 || i % 13 == 0).collect();
+        RegisterMap { coils, holding }
+    }
+
+    /// Sensor readings: the holding value they track, with some noise that changes over time
+    fn input_register(&self, address: usize) -> u16 {
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs() as usize;
+        let noise = ((now / 3 + address * 7) % 5) as u16;
+        self.holding[address].saturating_add(noise).saturating_sub(2)
+    }
+
+    fn discrete_input(&self, address: usize) -> bool {
+        self.coils[address] ^ (address % 5 == 4)
+    }
+}
+
+
+/// Modbus/TCP server of a PLC: serves a plausible register map, answers device identification
+/// and reports every request, flagging writes
+#[derive(Clone)]
+pub struct ModbusEmulator {
+    map: Arc<Mutex<RegisterMap>>,
+    device: (&'static str, &'static str, &'static str),
+    pending: Vec<u8>,
+}
+
+impl ModbusEmulator {
+    pub fn new(mac: MacAddr) -> Self {
+        ModbusEmulator {
+            map: Arc::new(Mutex::new(RegisterMap::new(mac))),
+            device: DEVICES[mac.5 as usize % DEVICES.len()],
+            pending: Vec::new(),
+        }
+    }
+
+    /// Response PDU for a request PDU, with the event describing it
+    fn handle_pdu(&self, unit_id: u8, pdu: &[u8]) -> (Vec<u8>, HoneypotEvent) {
+        let function = pdu[0];
+        let field = |offset: usize| pdu.get(offset..offset + 2).map(|b| u16::from_be_bytes([b[0], b[1]]));
+        let address = field(1);
+        let quantity = field(3);
+        let mut values = Vec::new();
+
+        let result = self.execute(function, pdu, &mut values);
+        let exception = result.as_ref().err().copied();
+        let response = result.unwrap_or_else(|code| vec![function | 0x80, code]);
+
+        let event = HoneypotEvent::ModbusRequest {
+            unit_id,
+            function,
+            address: (function != REPORT_SERVER_ID && function != ENCAPSULATED_INTERFACE).then_some(address).flatten(),
+            quantity: matches!(function, READ_COILS..=READ_INPUT_REGISTERS | WRITE_MULTIPLE_COILS | WRITE_MULTIPLE_REGISTERS | READ_WRITE_MULTIPLE_REGISTERS).then_some(quantity).flatten(),
+            values,
+            write: matches!(function, WRITE_SINGLE_COIL | WRITE_SINGLE_REGISTER | WRITE_MULTIPLE_COILS | WRITE_MULTIPLE_REGISTERS | MASK_WRITE_REGISTER | READ_WRITE_MULTIPLE_REGISTERS),
+            exception,
+        };
+        (response, event)
+    }
+
+    fn execute(&self, function: u8, pdu: &[u8], values: &mut Vec<u16>) -> Result<Vec<u8>, u8> {
+        let field = |offset: usize| pdu.get(offset..offset + 2).map(|b| u16::from_be_bytes([b[0], b[1]])).ok_or(ILLEGAL_DATA_VALUE);
+        let mut map = self.map.lock().unwrap();
+
+        match function {
+            READ_COILS | READ_DISCRETE_INPUTS => {
+                let (address, quantity) = (field(1)? as usize, field(3)? as usize);
+                check_range(address, quantity, 2000)?;
+                let mut bytes = vec![0u8; quantity.div_ceil(8)];
+                for i in 0..quantity {
+                    let bit = if function == READ_COILS { map.coils[address + i] } else { map.discrete_input(address + i) };
+                    if bit {
+                        bytes[i / 8] |= 1 << (i % 8);
+                    }
+                }
+                Ok([vec![function, bytes.len() as u8], bytes].concat())
+            }
+            READ_HOLDING_REGISTERS | READ_INPUT_REGISTERS => {
+                let (address, quantity) = (field(1)? as usize, field(3)? as usize);
+                check_range(address, quantity, 125)?;
+                let mut response = vec![function, (quantity * 2) as u8];
+                for i in address..address + quantity {
+                    let value = if function == READ_HOLDING_REGISTERS { map.holding[i] } else { map.input_register(i) };
+                    response.extend_from_slice(&value.to_be_bytes());
+                }
+                Ok(response)
+            }
+            WRITE_SINGLE_COIL => {
+                let (address, value) = (field(1)? as usize, field(3)?);
+                if value != 0xff00 && value != 0x0000 {
+                    return Err(ILLEGAL_DATA_VALUE);
+                }
+                check_range(address, 1, 1)?;
+                values.push(value);
+                map.coils[address] = value == 0xff00;
+                Ok(pdu[..5].to_vec())
+            }
+            WRITE_SINGLE_REGISTER => {
+                let (address, value) = (field(1)? as usize, field(3)?);
+                check_range(address, 1, 1)?;
+                values.push(value);
+                map.holding[address] = value;
+                Ok(pdu[..5].to_vec())
+            }
+            WRITE_MULTIPLE_COILS => {
+                let (address, quantity) = (field(1)? as usize, field(3)? as usize);
+                let bytes = pdu.get(6..6 + *pdu.get(5).ok_or(ILLEGAL_DATA_VALUE)? as usize).ok_or(ILLEGAL_DATA_VALUE)?;
+                if bytes.len() != quantity.div_ceil(8) {
+                    return Err(ILLEGAL_DATA_VALUE);
+                }
+                check_range(address, quantity, 1968)?;
+                for i in 0..quantity {
+                    let bit = bytes[i / 8] & (1 << (i % 8)) != 0;
+                    values.push(bit as u16);
+                    map.coils[address + i] = bit;
+                }
+                Ok(pdu[..5].to_vec())
+            }
+            WRITE_MULTIPLE_REGISTERS => {
+                let (address, quantity) = (field(1)? as usize, field(3)? as usize);
+                let bytes = pdu.get(6..6 + *pdu.get(5).ok_or(ILLEGAL_DATA_VALUE)? as usize).ok_or(ILLEGAL_DATA_VALUE)?;
+                if bytes.len() != quantity * 2 {
+                    return Err(ILLEGAL_DATA_VALUE);
+                }
+                check_range(address, quantity, 123)?;
+                for (i, chunk) in bytes.chunks(2).enumerate() {
+                    let value = u16::from_be_bytes([chunk[0], chunk[1]]);
+                    values.push(value);
+                    map.holding[address + i] = value;
+                }
+                Ok(pdu[..5].to_vec())
+            }
+            MASK_WRITE_REGISTER => {
+                let (address, and_mask, or_mask) = (field(1)? as usize, field(3)?, field(5)?);
+                check_range(address, 1, 1)?;
+                values.extend([and_mask, or_mask]);
+                map.holding[address] = (map.holding[address] & and_mask) | (or_mask & !and_mask);
+                Ok(pdu[..7].to_vec())
+            }
+            READ_WRITE_MULTIPLE_REGISTERS => {
+                let (read_address, read_quantity) = (field(1)? as usize, field(3)? as usize);
+                let (write_address, write_quantity) = (field(5)? as usize, field(7)? as usize);
+                let bytes = pdu.get(10..10 + *pdu.get(9).ok_or(ILLEGAL_DATA_VALUE)? as usize).ok_or(ILLEGAL_DATA_VALUE)?;
+                if bytes.len() != write_quantity * 2 {
+                    return Err(ILLEGAL_DATA_VALUE);
+                }
+                check_range(read_address, read_quantity, 125)?;
+                check_range(write_address, write_quantity, 121)?;
+                // The write happens before the read (Modbus application protocol, 6.17)
+                for (i, chunk) in bytes.chunks(2).enumerate() {
+                    let value = u16::from_be_bytes([chunk[0], chunk[1]]);
+                    values.push(value);
+                    map.holding[write_address + i] = value;
+                }
+                let mut response = vec![function, (read_quantity * 2) as u8];
+                for value in &map.holding[read_address..read_address + read_quantity] {
+                    response.extend_from_slice(&value.to_be_bytes());
+                }
+                Ok(response)
+            }
+            REPORT_SERVER_ID => {
+                let id = format!("{} {}", self.device.0, self.device.1);
+                // Server ID, run indicator (0xff = running), then device-specific data
+                let mut data = id.into_bytes();
+                data.insert(0, 0x01);
+                data.insert(1, 0xff);
+                Ok([vec![function, data.len() as u8], data].concat())
+            }
+            ENCAPSULATED_INTERFACE if pdu.get(1) == Some(&MEI_READ_DEVICE_ID) => {
+                // Basic identification: vendor name, product code, major/minor revision, in one go
+                let objects = [self.device.0, self.device.1, self.device.2];
+                let mut response = vec![function, MEI_READ_DEVICE_ID, *pdu.get(2).unwrap_or(&1), 0x01, 0x00, 0x00, objects.len() as u8];
+                for (id, value) in objects.iter().enumerate() {
+                    response.extend_from_slice(&[id as u8, value.len() as u8]);
+                    response.extend_from_slice(value.as_bytes());
+                }
+                Ok(response)
+            }
+            _ => Err(ILLEGAL_FUNCTION),
+        }
+    }
+}
+
+impl ServiceEmulator for ModbusEmulator {
+    fn name(&self) -> &'static str {
+        "modbus"
+    }
+
+    fn on_data(&mut self, session: &mut ServiceSession, data: &[u8]) -> ServiceReply {
+        self.pending.extend_from_slice(data);
+        let mut reply = ServiceReply::none();
+
+        while self.pending.len() >= MBAP_LEN {
+            let protocol = u16::from_be_bytes([self.pending[2], self.pending[3]]);
+            let length = u16::from_be_bytes([self.pending[4], self.pending[5]]) as usize;
+            // Not Modbus: a real device drops the connection
+            if protocol != 0 || length < 2 || MBAP_LEN - 1 + length > MAX_ADU_LEN {
+                reply.close = true;
+                return reply;
+            }
+            if self.pending.len() < MBAP_LEN - 1 + length {
+                break;
+            }
+
+            let adu: Vec<u8> = self.pending.drain(..MBAP_LEN - 1 + length).collect();
+            let unit_id = adu[6];
+            let (pdu, event) = self.handle_pdu(unit_id, &adu[MBAP_LEN..]);
+            session.record(event);
+
+            reply.data.extend_from_slice(&adu[..4]);
+            reply.data.extend_from_slice(&((pdu.len() + 1) as u16).to_be_bytes());
+            reply.data.push(unit_id);
+            reply.data.extend(pdu);
+        }
+        reply
+    }
+}
+
+
+fn check_range(address: usize, quantity: usize, max_quantity: usize) -> Result<(), u8> {
+    if quantity == 0 || quantity > max_quantity {
+        return Err(ILLEGAL_DATA_VALUE);
+    }
+    if address + quantity > TABLE_SIZE {
+        return Err(ILLEGAL_DATA_ADDRESS);
+    }
+    Ok(())
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::honeypot::services::session::test_support;
+
+    fn adu(transaction: u16, pdu: &[u8]) -> Vec<u8> {
+        let mut adu = transaction.to_be_bytes().to_vec();
+        adu.extend_from_slice(&[0, 0]);
+        adu.extend_from_slice(&((pdu.len() + 1) as u16).to_be_bytes());
+        adu.push(1);
+        adu.extend_from_slice(pdu);
+        adu
+    }
+
+    /// Response PDU to a single request
+    fn request(modbus: &mut ModbusEmulator, pdu: &[u8]) -> Vec<u8> {
+        let mut session = test_support::session("modbus", "10.0.0.5:502", "10.0.0.7:40000");
+        let reply = modbus.on_data(&mut session, &adu(7, pdu));
+        assert!(!reply.close);
+        assert_eq!(reply.data[..7], adu(7, &reply.data[MBAP_LEN..])[..7]);
+        reply.data[MBAP_LEN..].to_vec()
+    }
+
+    #[tokio::test]
+    async fn frames_follow_the_mbap_length() {
+        let mut modbus = ModbusEmulator::new(MacAddr::zero());
+        let mut session = test_support::session("modbus", "10.0.0.5:502", "10.0.0.7:40000");
+        let first = adu(1, &[READ_HOLDING_REGISTERS, 0, 0, 0, 2]);
+        let second = adu(2, &[READ_COILS, 0, 0, 0, 8]);
+
+        // Split mid-header and mid-PDU, then two requests in one segment
+        assert!(modbus.on_data(&mut session, &first[..4]).data.is_empty());
+        assert!(modbus.on_data(&mut session, &first[4..9]).data.is_empty());
+        let stream = [&first[9..], &second[..]].concat();
+        let reply = modbus.on_data(&mut session, &stream);
+        assert_eq!(reply.data[..9], [0, 1, 0, 0, 0, 7, 1, READ_HOLDING_REGISTERS, 4]);
+        assert_eq!(reply.data[13..], [0, 2, 0, 0, 0, 4, 1, READ_COILS, 1, 0b0000_0111]);
+    }
+
+    #[tokio::test]
+    async fn non_modbus_framing_closes() {
+        let mut session = test_support::session("modbus", "10.0.0.5:502", "10.0.0.7:40000");
+        for header in [
+            [0, 1, 0, 1, 0, 6, 1],
+            [0, 1, 0, 0, 0, 1, 1],
+            [0, 1, 0, 0, 0, 0, 1],
+            [0, 1, 0, 0, 0x01, 0x00, 1],
+        ] {
+            let reply = ModbusEmulator::new(MacAddr::zero()).on_data(&mut session, &header);
+            assert!(reply.close && reply.data.is_empty(), "{:?}", header);
+        }
+        // The largest ADU is still accepted
+        let mut pdu = vec![WRITE_MULTIPLE_REGISTERS, 0, 0, 0, 123, 246];
+        pdu.resize(253, 0);
+        let reply = ModbusEmulator::new(MacAddr::zero()).on_data(&mut session, &adu(1, &pdu));
+        assert_eq!((reply.close, &reply.data[MBAP_LEN..]), (false, &pdu[..5]));
+    }
+
+    #[tokio::test]
+    async fn bad_requests_get_exceptions() {
+        let mut modbus = ModbusEmulator::new(MacAddr::zero());
+        assert_eq!(request(&mut modbus, &[0x08, 0, 0, 0, 0]), [0x88, ILLEGAL_FUNCTION]);
+        assert_eq!(request(&mut modbus, &[ENCAPSULATED_INTERFACE, 0x0d]), [0xab, ILLEGAL_FUNCTION]);
+        assert_eq!(request(&mut modbus, &[READ_HOLDING_REGISTERS, 0, 0]), [0x83, ILLEGAL_DATA_VALUE]);
+        assert_eq!(request(&mut modbus, &[READ_HOLDING_REGISTERS, 0, 0, 0, 0]), [0x83, ILLEGAL_DATA_VALUE]);
+        assert_eq!(request(&mut modbus, &[READ_HOLDING_REGISTERS, 0, 0, 0, 126]), [0x83, ILLEGAL_DATA_VALUE]);
+        assert_eq!(request(&mut modbus, &[WRITE_SINGLE_COIL, 0, 1, 0x12, 0x34]), [0x85, ILLEGAL_DATA_VALUE]);
+        assert_eq!(request(&mut modbus, &[WRITE_MULTIPLE_REGISTERS, 0, 0, 0, 2, 2, 0, 1]), [0x90, ILLEGAL_DATA_VALUE]);
+        assert_eq!(request(&mut modbus, &[WRITE_MULTIPLE_COILS, 0, 0, 0, 9, 1, 0xff]), [0x8f, ILLEGAL_DATA_VALUE]);
+        assert_eq!(request(&mut modbus, &[READ_WRITE_MULTIPLE_REGISTERS, 0, 0, 0, 1, 0, 0, 0, 1, 4, 0, 1]), [0x97, ILLEGAL_DATA_VALUE]);
+    }
+
+    #[tokio::test]
+    async fn out_of_range_addresses_get_illegal_data_address() {
+        let mut modbus = ModbusEmulator::new(MacAddr::zero());
+        let last = (TABLE_SIZE - 1) as u16;
+        let [high, low] = last.to_be_bytes();
+        assert_eq!(request(&mut modbus, &[READ_HOLDING_REGISTERS, high, low, 0, 1])[..2], [READ_HOLDING_REGISTERS, 2]);
+        assert_eq!(request(&mut modbus, &[READ_HOLDING_REGISTERS, high, low, 0, 2]), [0x83, ILLEGAL_DATA_ADDRESS]);
+        assert_eq!(request(&mut modbus, &[READ_INPUT_REGISTERS, 0xff, 0xff, 0, 1]), [0x84, ILLEGAL_DATA_ADDRESS]);
+        assert_eq!(request(&mut modbus, &[READ_DISCRETE_INPUTS, high, low, 0, 8]), [0x82, ILLEGAL_DATA_ADDRESS]);
+        assert_eq!(request(&mut modbus, &[WRITE_SINGLE_COIL, 0x03, 0xe8, 0xff, 0x00]), [0x85, ILLEGAL_DATA_ADDRESS]);
+        assert_eq!(request(&mut modbus, &[WRITE_SINGLE_REGISTER, 0x03, 0xe8, 0, 1]), [0x86, ILLEGAL_DATA_ADDRESS]);
+        assert_eq!(request(&mut modbus, &[MASK_WRITE_REGISTER, 0x03, 0xe8, 0, 0, 0, 0]), [0x96, ILLEGAL_DATA_ADDRESS]);
+        assert_eq!(request(&mut modbus, &[READ_WRITE_MULTIPLE_REGISTERS, 0, 0, 0, 1, high, low, 0, 2, 4, 0, 1, 0, 2]), [0x97, ILLEGAL_DATA_ADDRESS]);
+    }
+
+    #[tokio::test]
+    async fn writes_stick_across_connections() {
+        let mut modbus = ModbusEmulator::new(MacAddr::zero());
+        let mut other = modbus.clone();
+        assert_eq!(request(&mut modbus, &[WRITE_SINGLE_REGISTER, 0, 10, 0xbe, 0xef]), [WRITE_SINGLE_REGISTER, 0, 10, 0xbe, 0xef]);
+        assert_eq!(request(&mut modbus, &[MASK_WRITE_REGISTER, 0, 10, 0xff, 0x00, 0x00, 0x12]), [MASK_WRITE_REGISTER, 0, 10, 0xff, 0x00, 0x00, 0x12]);
+        assert_eq!(request(&mut other, &[READ_HOLDING_REGISTERS, 0, 10, 0, 1]), [READ_HOLDING_REGISTERS, 2, 0xbe, 0x12]);
+    }
+}
@@ -0,0 +1,449 @@
+use common::types::HoneypotEvent;
+
+use super::{session::ServiceSession, ServiceEmulator, ServiceReply};
+
+const CONNECT: u8 = 1;
+const CONNACK: u8 = 2;
+const PUBLISH: u8 = 3;
+const PUBACK: u8 = 4;
+const PUBREC: u8 = 5;
+const PUBREL: u8 = 6;
+const PUBCOMP: u8 = 7;
+const SUBSCRIBE: u8 = 8;
+const SUBACK: u8 = 9;
+const UNSUBSCRIBE: u8 = 10;
+const UNSUBACK: u8 = 11;
+const PINGREQ: u8 = 12;
+const PINGRESP: u8 = 13;
+const DISCONNECT: u8 = 14;
+
+const MQTT_5: u8 = 5;
+// Packets larger than this are not worth buffering
+const MAX_PACKET_LEN: usize = 256 * 1024;
+// Bytes of a published payload kept in the event
+const MAX_PAYLOAD_REPORTED: usize = 4096;
+
+// Retained messages of the broker: what a subscription to `#` shows an attacker
+const RETAINED: &[(&str, &str)] = &[
+    ("$SYS/broker/version", "mosquitto version 2.0.11"),
+    ("$SYS/broker/uptime", "1843265 seconds"),
+    ("$SYS/broker/clients/connected", "7"),
+    ("plant/line2/plc1/temperature", "21.6"),
+    ("plant/line2/plc1/pressure", "4.02"),
+    ("plant/line2/pump3/state", "ON"),
+    ("plant/line2/pump3/speed", "1450"),
+    ("building/hvac/ahu1/setpoint", "22.0"),
+    ("building/hvac/ahu1/mode", "auto"),
+];
+
+
+/// Open Mosquitto-like broker: accepts every client, reports credentials, subscriptions and
+/// publications, and answers subscriptions with a few retained plant telemetry topics
+#[derive(Default)]
+pub struct MqttEmulator {
+    connected: bool,
+    protocol_level: u8,
+    pending: Vec<u8>,
+}
+
+impl MqttEmulator {
+    fn handle_packet(&mut self, session: &mut ServiceSession, packet_type: u8, flags: u8, body: &[u8], reply: &mut ServiceReply) {
+        if !self.connected && packet_type != CONNECT {
+            reply.close = true;
+            return;
+        }
+
+        match packet_type {
+            CONNECT => {
+                let Some(connect) = parse_connect(body) else {
+                    reply.close = true;
+                    return;
+                };
+                self.protocol_level = connect.protocol_level;
+                self.connected = true;
+                session.record(HoneypotEvent::MqttConnect {
+                    client_id: connect.client_id,
+                    protocol_level: connect.protocol_level,
+                    username: connect.username,
+                    password: connect.password,
+                });
+                // Session present = 0, return/reason code 0 (accepted)
+                let variable = if self.protocol_level == MQTT_5 { vec![0, 0, 0] } else { vec![0, 0] };
+                reply.data.extend(packet(CONNACK << 4, &variable));
+            }
+            SUBSCRIBE => {
+                let mut reader = Reader::new(body);
+                let Some(packet_id) = reader.u16() else {
+                    reply.close = true;
+                    return;
+                };
+                if self.protocol_level == MQTT_5 {
+                    reader.skip_properties();
+                }
+                let mut topics = Vec::new();
+                let mut granted = Vec::new();
+                while let (Some(topic), Some(options)) = (reader.string(), reader.u8()) {
+                    granted.push((options & 0x03).min(1));
+                    topics.push(topic);
+                }
+
+                let mut variable = packet_id.to_be_bytes().to_vec();
+                if self.protocol_level == MQTT_5 {
+                    variable.push(0);
+                }
+                variable.extend(&granted);
+                reply.data.extend(packet(SUBACK << 4, &variable));
+
+                for (topic, payload) in RETAINED {
+                    if topics.iter().any(|filter| topic_matches(filter, topic)) {
+                        let mut variable = encode_string(topic);
+                        if self.protocol_level == MQTT_5 {
+                            variable.push(0);
+                        }
+                        variable.extend_from_slice(payload.as_bytes());
+                        // QoS 0, retain flag set
+                        reply.data.extend(packet((PUBLISH << 4) | 0x01, &variable));
+                    }
+                }
+                session.record(HoneypotEvent::MqttSubscribe { topics });
+            }
+            UNSUBSCRIBE => {
+                let Some(packet_id) = Reader::new(body).u16() else {
+                    reply.close = true;
+                    return;
+                };
+                let mut variable = packet_id.to_be_bytes().to_vec();
+                if self.protocol_level == MQTT_5 {
+                    variable.push(0);
+                }
+                reply.data.extend(packet(UNSUBACK << 4, &variable));
+            }
+            PUBLISH => {
+                let qos = (flags >> 1) & 0x03;
+                let mut reader = Reader::new(body);
+                let Some(topic) = reader.string() else {
+                    reply.close = true;
+                    return;
+                };
+                let packet_id = if qos > 0 { reader.u16() } else { None };
+                if self.protocol_level == MQTT_5 {
+                    reader.skip_properties();
+                }
+                let payload = reader.rest();
+                session.record(HoneypotEvent::MqttPublish {
+                    topic,
+                    payload: String::from_utf8_lossy(&payload[..payload.len().min(MAX_PAYLOAD_REPORTED)]).to_string(),
+                    qos,
+                    retain: flags & 0x01 != 0,
+                });
+                match (qos, packet_id) {
+                    (1, Some(id)) => reply.data.extend(packet(PUBACK << 4, &id.to_be_bytes())),
+                    (2, Some(id)) => reply.data.extend(packet(PUBREC << 4, &id.to_be_bytes())),
+                    _ => {}
+                }
+            }
+            PUBREL => {
+                if let Some(id) = Reader::new(body).u16() {
+                    reply.data.extend(packet(PUBCOMP << 4, &id.to_be_bytes()));
+                }
+            }
+            PINGREQ => reply.data.extend(packet(PINGRESP << 4, &[])),
+            DISCONNECT => reply.close = true,
+            // Acknowledgements of our QoS 0 messages can't exist: protocol error
+            _ => reply.close = true,
+        }
+    }
+}
+
+impl ServiceEmulator for MqttEmulator {
+    fn name(&self) -> &'static str {
+        "mqtt"
+    }
+
+    fn on_data(&mut self, session: &mut ServiceSession, data: &[u8]) -> ServiceReply {
+        self.pending.extend_from_slice(data);
+        let mut reply = ServiceReply::none();
+
+        while let Some(&first) = self.pending.first() {
+            let (length, header_len) = match decode_length(&self.pending[1..]) {
+                Some(Ok(decoded)) => decoded,
+                // Incomplete header, wait for more
+                None => break,
+                Some(Err(())) => {
+                    reply.close = true;
+                    break;
+                }
+            };
+            if length > MAX_PACKET_LEN {
+                reply.close = true;
+                break;
+            }
+            if self.pending.len() < 1 + header_len + length {
+                break;
+            }
+
+            let body: Vec<u8> = self.pending.drain(..1 + header_len + length).skip(1 + header_len).collect();
+            self.handle_packet(session, first >> 4, first & 0x0f, &body, &mut reply);
+            if reply.close {
+                break;
+            }
+        }
+        reply
+    }
+}
+
+
+struct Connect {
+    protocol_level: u8,
+    client_id: String,
+    username: Option<String>,
+    password: Option<String>,
+}
+
+fn parse_connect(body: &[u8]) -> Option<Connect> {
+    let mut reader = Reader::new(body);
+    let protocol = reader.string()?;
+    if protocol != "MQTT" && protocol != "MQIsdp" {
+        return None;
+    }
+    let protocol_level = reader.u8()?;
+    let flags = reader.u8()?;
+    reader.u16()?;
+    if protocol_level == MQTT_5 {
+        reader.skip_properties();
+    }
+    let client_id = reader.string()?;
+
+    if flags & 0x04 != 0 {
+        if protocol_level == MQTT_5 {
+            reader.skip_properties();
+        }
+        reader.string()?;
+        reader.binary()?;
+    }
+    let username = if flags & 0x80 != 0 { reader.string() } else { None };
+    let password = if flags & 0x40 != 0 { reader.binary().map(|p| String::from_utf8_lossy(&p).to_string()) } else { None };
+
+    Some(Connect { protocol_level, client_id, username, password })
+}
+
+/// Remaining length: `None` if more bytes are needed, `Err` if malformed; otherwise value and bytes used
+fn decode_length(data: &[u8]) -> Option<Result<(usize, usize), ()>> {
+    let mut length = 0usize;
+    for (i, byte) in data.iter().enumerate() {
+        if i == 4 {
+            return Some(Err(()));
+        }
+        length |= ((byte & 0x7f) as usize) << (7 * i);
+        if byte & 0x80 == 0 {
+            return Some(Ok((length, i + 1)));
+        }
+    }
+    if data.len() >= 4 { Some(Err(())) } else { None }
+}
+
+fn packet(header: u8, variable: &[u8]) -> Vec<u8> {
+    let mut output = vec![header];
+    let mut length = variable.len();
+    loop {
+        let mut byte = (length % 128) as u8;
+        length /= 128;
+        if length > 0 {
+            byte |= 0x80;
+        }
+        output.push(byte);
+        if length == 0 {
+            break;
+        }
+    }
+    output.extend_from_slice(variable);
+    output
+}
+
+fn encode_string(text: &str) -> Vec<u8> {
+    let mut output = (text.len() as u16).to_be_bytes().to_vec();
+    output.extend_from_slice(text.as_bytes());
+    output
+}
+
+/// MQTT topic filter matching with `+` and `#` wildcards; `$` topics only match explicit filters
+fn topic_matches(filter: &str, topic: &str) -> bool {
+    if topic.starts_with('$') && (filter.starts_with('#') || filter.starts_with('+')) {
+        return false;
+    }
+    let mut levels = topic.split('/');
+    for part in filter.split('/') {
+        match (part, levels.next()) {
+            ("#", _) => return true,
+            ("+", Some(_)) => {}
+            (part, Some(level)) if part == level => {}
+            _ => return false,
+        }
+    }
+    levels.next().is_none()
+}
+
+
+struct Reader<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Reader { data }
+    }
+
+    fn u8(&mut self) -> Option<u8> {
+        let (&byte, rest) = self.data.split_first()?;
+        self.data = rest;
+        Some(byte)
+    }
+
+    fn u16(&mut self) -> Option<u16> {
+        let value = u16::from_be_bytes([*self.data.first()?, *self.data.get(1)?]);
+        self.data = &self.data[2..];
+        Some(value)
+    }
+
+    fn binary(&mut self) -> Option<Vec<u8>> {
+        let len = self.u16()? as usize;
+        let value = self.data.get(..len)?.to_vec();
+        self.data = &self.data[len..];
+        Some(value)
+    }
+
+    fn string(&mut self) -> Option<String> {
+        self.binary().map(|bytes| String::from_utf8_lossy(&bytes).to_string())
+    }
+
+    fn skip_properties(&mut self) {
+        if let Some(Ok((length, used))) = decode_length(self.data) {
+            self.data = self.data.get(used + length..).unwrap_or(&[]);
+        }
+    }
+
+    fn rest(&mut self) -> &'a [u8] {
+        std::mem::take(&mut self.data)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::honeypot::services::session::test_support;
+
+    fn connect(level: u8, flags: u8, properties: &[u8], payload: &[&[u8]]) -> Vec<u8> {
+        let mut body = encode_string("MQTT");
+        body.extend_from_slice(&[level, flags, 0, 60]);
+        if level == MQTT_5 {
+            body.extend_from_slice(properties);
+        }
+        for field in payload {
+            body.extend_from_slice(field);
+        }
+        packet(CONNECT << 4, &body)
+    }
+
+    /// Packets of a reply, as (type, body)
+    fn packets(mut data: &[u8]) -> Vec<(u8, Vec<u8>)> {
+        let mut packets = Vec::new();
+        while let Some(&first) = data.first() {
+            let (length, used) = decode_length(&data[1..]).unwrap().unwrap();
+            packets.push((first >> 4, data[1 + used..1 + used + length].to_vec()));
+            data = &data[1 + used + length..];
+        }
+        packets
+    }
+
+    #[test]
+    fn remaining_length_decoding() {
+        assert_eq!(decode_length(&[0x00]), Some(Ok((0, 1))));
+        assert_eq!(decode_length(&[0x7f, 0xff]), Some(Ok((127, 1))));
+        assert_eq!(decode_length(&[0x80, 0x01]), Some(Ok((128, 2))));
+        assert_eq!(decode_length(&[0xc1, 0x02]), Some(Ok((321, 2))));
+        assert_eq!(decode_length(&[0xff, 0xff, 0xff, 0x7f]), Some(Ok((268_435_455, 4))));
+        // Waiting for the rest of the header
+        assert_eq!(decode_length(&[]), None);
+        assert_eq!(decode_length(&[0x80, 0x80, 0x80]), None);
+        // Five bytes are never valid
+        assert_eq!(decode_length(&[0x80, 0x80, 0x80, 0x80]), Some(Err(())));
+        assert_eq!(decode_length(&[0xff, 0xff, 0xff, 0xff, 0x01]), Some(Err(())));
+
+        for length in [0, 127, 128, 16_383, 16_384, 2_097_151, 2_097_152] {
+            let encoded = packet(0, &vec![0; length]);
+            assert_eq!(decode_length(&encoded[1..]), Some(Ok((length, encoded.len() - 1 - length))));
+        }
+    }
+
+    #[test]
+    fn connect_with_will_and_credentials() {
+        let will = [encode_string("clients/lost"), encode_string("gone")];
+        let fields = [encode_string("sensor-7"), will[0].clone(), will[1].clone(), encode_string("admin"), encode_string("hunter2")];
+        let payload: Vec<&[u8]> = fields.iter().map(Vec::as_slice).collect();
+        // Username, password, will retain, will QoS 1, will, clean session
+        let flags = 0x80 | 0x40 | 0x20 | 0x08 | 0x04 | 0x02;
+
+        let data = connect(4, flags, &[], &payload);
+        let parsed = parse_connect(&data[2..]).unwrap();
+        assert_eq!((parsed.protocol_level, parsed.client_id.as_str()), (4, "sensor-7"));
+        assert_eq!((parsed.username.as_deref(), parsed.password.as_deref()), (Some("admin"), Some("hunter2")));
+
+        // v5 puts properties after the header and before the will; both are skipped
+        let mut fields = fields.to_vec();
+        fields.insert(1, vec![5, 0x01, 0x01, 0x02, 0, 0]);
+        let payload: Vec<&[u8]> = fields.iter().map(Vec::as_slice).collect();
+        let data = connect(MQTT_5, flags, &[5, 0x11, 0, 0, 0x0e, 0x10], &payload);
+        let parsed = parse_connect(&data[2..]).unwrap();
+        assert_eq!((parsed.protocol_level, parsed.client_id.as_str()), (MQTT_5, "sensor-7"));
+        assert_eq!((parsed.username.as_deref(), parsed.password.as_deref()), (Some("admin"), Some("hunter2")));
+
+        // A will announced but missing is malformed
+        let data = connect(4, 0x04, &[], &[&encode_string("sensor-7")]);
+        assert!(parse_connect(&data[2..]).is_none());
+        let data = packet(CONNECT << 4, &[encode_string("HTTP"), vec![4, 0, 0, 60], encode_string("x")].concat());
+        assert!(parse_connect(&data[2..]).is_none());
+    }
+
+    #[tokio::test]
+    async fn v5_properties_are_skipped() {
+        let mut session = test_support::session("mqtt", "10.0.0.5:1883", "10.0.0.7:40000");
+        let mut mqtt = MqttEmulator::default();
+        let reply = mqtt.on_data(&mut session, &connect(MQTT_5, 0x02, &[0], &[&encode_string("")]));
+        assert_eq!(packets(&reply.data), [(CONNACK, vec![0, 0, 0])]);
+
+        // Subscription identifier 300 as a property, then two filters
+        let subscribe = [vec![0x12, 0x34, 3, 0x0b, 0xac, 0x02], encode_string("plant/#"), vec![1], encode_string("$SYS/broker/version"), vec![0]].concat();
+        let reply = mqtt.on_data(&mut session, &packet((SUBSCRIBE << 4) | 0x02, &subscribe));
+        let replies = packets(&reply.data);
+        assert_eq!(replies[0], (SUBACK, vec![0x12, 0x34, 0, 1, 0]));
+        assert_eq!(replies.len(), 1 + 1 + 4);
+        assert!(replies[1..].iter().all(|(kind, body)| *kind == PUBLISH && body[2 + u16::from_be_bytes([body[0], body[1]]) as usize] == 0));
+
+        // QoS 1 publish with a content type property
+        let publish = [encode_string("plant/line2/pump3/state"), vec![0, 9, 6, 0x03, 0, 3, b't', b'x', b't'], b"OFF".to_vec()].concat();
+        let reply = mqtt.on_data(&mut session, &packet((PUBLISH << 4) | 0x02, &publish));
+        assert_eq!(packets(&reply.data), [(PUBACK, vec![0, 9])]);
+    }
+
+    #[tokio::test]
+    async fn framing_errors_close() {
+        let mut session = test_support::session("mqtt", "10.0.0.5:1883", "10.0.0.7:40000");
+        let reply = MqttEmulator::default().on_data(&mut session, &packet(PINGREQ << 4, &[]));
+        assert!(reply.close && reply.data.is_empty());
+        let reply = MqttEmulator::default().on_data(&mut session, &[CONNECT << 4, 0x80, 0x80, 0x80, 0x80, 0x01]);
+        assert!(reply.close);
+        // Over MAX_PACKET_LEN: closed before the body arrives
+        let reply = MqttEmulator::default().on_data(&mut session, &[CONNECT << 4, 0x81, 0x80, 0x10]);
+        assert!(reply.close);
+
+        // A CONNECT split anywhere is answered once complete
+        let data = connect(4, 0x02, &[], &[&encode_string("split")]);
+        for at in 1..data.len() {
+            let mut mqtt = MqttEmulator::default();
+            assert!(mqtt.on_data(&mut session, &data[..at]).data.is_empty());
+            let reply = mqtt.on_data(&mut session, &data[at..]);
+            assert_eq!(packets(&reply.data), [(CONNACK, vec![0, 0])], "split at {}", at);
+        }
+    }
+}
@@ -0,0 +1,295 @@
+use std::{io, net::SocketAddr, pin::Pin, sync::{Arc, Mutex}, task::{Context, Poll}, time::{Instant, SystemTime, UNIX_EPOCH}};
+
+use common::types::{DataType, HoneypotEvent, HoneypotEventPayload, HoneypotSessionPayload, PayloadType, PriorityLevel, TranscriptChunk};
+use pnet::{datalink::DataLinkSender, util::MacAddr};
+use tokio::{io::{AsyncRead, AsyncWrite, ReadBuf}, sync::oneshot};
+use tracing::{info, warn};
+
+use crate::{honeypot::{persona::OsPersona, tcp::{self, stream::VirtualTcpStream}}, interfaces::ws::WsReporter};
+
+// Bytes kept per session; counters keep going after the transcript is truncated
+const MAX_TRANSCRIPT_BYTES: usize = 64 * 1024;
+
+
+#[derive(Default)]
+struct Transcript {
+    chunks: Vec<TranscriptChunk>,
+    stored: usize,
+    truncated: bool,
+    bytes_in: u64,
+    bytes_out: u64,
+}
+
+impl Transcript {
+    fn record(&mut self, inbound: bool, started: Instant, data: &[u8]) {
+        if inbound {
+            self.bytes_in += data.len() as u64;
+        } else {
+            self.bytes_out += data.len() as u64;
+        }
+
+        let room = MAX_TRANSCRIPT_BYTES - self.stored;
+        if data.len() > room {
+            self.truncated = true;
+        }
+        let data = &data[..data.len().min(room)];
+        if data.is_empty() {
+            return;
+        }
+        self.stored += data.len();
+
+        let offset_ms = started.elapsed().as_millis() as u64;
+        match self.chunks.last_mut() {
+            // Coalesce consecutive writes in the same direction
+            Some(last) if last.inbound == inbound && last.offset_ms == offset_ms => last.data.extend_from_slice(data),
+            _ => self.chunks.push(TranscriptChunk { inbound, offset_ms, data: data.to_vec() }),
+        }
+    }
+}
+
+
+/// Metadata and capture of one attacker connection to a decoy service, reported to the server when it ends
+pub struct ServiceSession {
+    pub service: &'static str,
+    pub honeypot_mac: MacAddr,
+    pub honeypot_addr: SocketAddr,
+    pub attacker_mac: MacAddr,
+    pub attacker_addr: SocketAddr,
+    started_at: SystemTime,
+    started: Instant,
+    transcript: Arc<Mutex<Transcript>>,
+    reporter: WsReporter,
+    sender: Arc<tokio::sync::Mutex<Box<dyn DataLinkSender + Send>>>,
+    os: OsPersona,
+}
+
+impl ServiceSession {
+    pub fn open(service: &'static str, stream: VirtualTcpStream, reporter: WsReporter) -> (Self, CapturedStream) {
+        let started = Instant::now();
+        let transcript = Arc::new(Mutex::new(Transcript::default()));
+
+        let session = ServiceSession {
+            service,
+            honeypot_mac: stream.local_mac(),
+            honeypot_addr: stream.local_addr(),
+            attacker_mac: stream.peer_mac(),
+            attacker_addr: stream.peer_addr(),
+            started_at: SystemTime::now(),
+            started,
+            transcript: transcript.clone(),
+            reporter,
+            sender: stream.sender(),
+            os: stream.os(),
+        };
+
+        (session, CapturedStream { inner: stream, transcript, started })
+    }
+
+    pub fn events(&self) -> EventSink {
+        EventSink {
+            service: self.service,
+            honeypot_mac: self.honeypot_mac,
+            honeypot_addr: self.honeypot_addr,
+            attacker_mac: self.attacker_mac,
+            attacker_addr: self.attacker_addr,
+            reporter: self.reporter.clone(),
+        }
+    }
+
+    pub fn record(&self, event: HoneypotEvent) {
+        self.events().record(event);
+    }
+
+    /// Opens a second connection from the decoy to the attacker (e.g. FTP active mode)
+    pub async fn connect_back(&self, local_port: u16, remote: SocketAddr) -> io::Result<VirtualTcpStream> {
+        let local = SocketAddr::new(self.honeypot_addr.ip(), local_port);
+        tcp::connect(self.sender.clone(), self.honeypot_mac, local, self.attacker_mac, remote, self.os.clone()).await
+    }
+
+    /// Waits for the attacker to open a second connection to `port` of the decoy (e.g. FTP passive mode)
+    pub async fn accept_from_attacker(&self, port: u16) -> Option<oneshot::Receiver<VirtualTcpStream>> {
+        tcp::accept_once(self.honeypot_addr.ip(), port, self.attacker_addr.ip()).await
+    }
+
+    pub async fn report(self) {
+        let payload = {
+            let mut transcript = self.transcript.lock().unwrap();
+
+            HoneypotSessionPayload {
+                honeypot_mac: self.honeypot_mac.octets(),
+                honeypot_ip: self.honeypot_addr.ip().to_string(),
+                honeypot_port: self.honeypot_addr.port(),
+                attacker_mac: self.attacker_mac.octets(),
+                attacker_ip: self.attacker_addr.ip().to_string(),
+                attacker_port: self.attacker_addr.port(),
+                service: self.service.to_string(),
+                started_at: self.started_at.duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0),
+                duration_ms: self.started.elapsed().as_millis() as u64,
+                bytes_in: transcript.bytes_in,
+                bytes_out: transcript.bytes_out,
+                transcript: std::mem::take(&mut transcript.chunks),
+                transcript_truncated: transcript.truncated,
+            }
+        };
+
+        let priority = if payload.bytes_in > 0 { PriorityLevel::Medium } else { PriorityLevel::Low };
+
+        info!("🍯 {} session from {} closed: {} bytes in, {} bytes out", self.service, self.attacker_addr, payload.bytes_in, payload.bytes_out);
+        self.reporter.send(DataType::HoneypotSession, priority, self.attacker_mac, PayloadType::HoneypotSession(payload)).await;
+    }
+}
+
+
+/// Cloneable handle reporting what happens inside a session as it happens,
+/// usable from tasks that don't own the `ServiceSession`
+#[derive(Clone)]
+pub struct EventSink {
+    service: &'static str,
+    honeypot_mac: MacAddr,
+    honeypot_addr: SocketAddr,
+    attacker_mac: MacAddr,
+    attacker_addr: SocketAddr,
+    reporter: WsReporter,
+}
+
+impl EventSink {
+    /// Sink for traffic that has no `ServiceSession` (e.g. UDP datagrams)
+    pub fn new(service: &'static str, honeypot_mac: MacAddr, honeypot_addr: SocketAddr, attacker_mac: MacAddr, attacker_addr: SocketAddr, reporter: WsReporter) -> Self {
+        EventSink { service, honeypot_mac, honeypot_addr, attacker_mac, attacker_addr, reporter }
+    }
+
+    pub fn honeypot_addr(&self) -> SocketAddr {
+        self.honeypot_addr
+    }
+
+    pub fn attacker_addr(&self) -> SocketAddr {
+        self.attacker_addr
+    }
+
+    pub fn record(&self, event: HoneypotEvent) {
+        let priority = match &event {
+            HoneypotEvent::LoginAttempt { accepted: true, .. } => PriorityLevel::Critical,
+            HoneypotEvent::LoginAttempt { .. } | HoneypotEvent::Command { .. } | HoneypotEvent::Download { .. } => PriorityLevel::High,
+            HoneypotEvent::Upload { .. } | HoneypotEvent::ModbusRequest { write: true, .. } => PriorityLevel::Critical,
+            HoneypotEvent::MqttConnect { username: Some(_), .. } | HoneypotEvent::MqttPublish { .. } => PriorityLevel::High,
+            HoneypotEvent::ModbusRequest { .. } | HoneypotEvent::MqttConnect { .. } | HoneypotEvent::MqttSubscribe { .. } => PriorityLevel::Medium,
+            HoneypotEvent::HttpRequest { detections, username, .. } if !detections.is_empty() || username.is_some() => PriorityLevel::High,
+            HoneypotEvent::SnmpRequest { accepted, pdu, .. } if *accepted || pdu == "set" => PriorityLevel::High,
+            HoneypotEvent::SshClientHello { .. } | HoneypotEvent::TlsClientHello { .. } | HoneypotEvent::HttpRequest { .. } => PriorityLevel::Medium,
+            HoneypotEvent::SnmpRequest { .. } | HoneypotEvent::UdpRateLimited { .. } => PriorityLevel::Medium,
+            HoneypotEvent::DnsQuery { .. } | HoneypotEvent::NtpRequest { .. } | HoneypotEvent::SsdpSearch { .. } | HoneypotEvent::IcmpRequest { .. } => PriorityLevel::Low,
+        };
+
+        warn!("🍯 {} {} from {}: {:?}", self.service, event.kind(), self.attacker_addr, event);
+
+        let payload = HoneypotEventPayload {
+            honeypot_mac: self.honeypot_mac.octets(),
+            honeypot_ip: self.honeypot_addr.ip().to_string(),
+            honeypot_port: self.honeypot_addr.port(),
+            attacker_mac: self.attacker_mac.octets(),
+            attacker_ip: self.attacker_addr.ip().to_string(),
+            attacker_port: self.attacker_addr.port(),
+            service: self.service.to_string(),
+            event,
+        };
+
+        let reporter = self.reporter.clone();
+        let attacker_mac = self.attacker_mac;
+        tokio::spawn(async move {
+            reporter.send(DataType::HoneypotEvent, priority, attacker_mac, PayloadType::HoneypotEvent(payload)).await;
+        });
+    }
+}
+
+
+/// Virtual TCP stream that copies everything read and written into the session transcript
+pub struct CapturedStream {
+    inner: VirtualTcpStream,
+    transcript: Arc<Mutex<Transcript>>,
+    started: Instant,
+}
+
+impl AsyncRead for CapturedStream {
+    fn poll_read(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut ReadBuf<'_>) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        let before = buf.filled().len();
+        let poll = Pin::new(&mut this.inner).poll_read(cx, buf);
+
+        if let Poll::Ready(Ok(())) = poll {
+            this.transcript.lock().unwrap().record(true, this.started, &buf.filled()[before..]);
+        }
+        poll
+    }
+}
+
+impl AsyncWrite for CapturedStream {
+    fn poll_write(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8]) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        let poll = Pin::new(&mut this.inner).poll_write(cx, buf);
+
+        if let Poll::Ready(Ok(n)) = poll {
+            this.transcript.lock().unwrap().record(false, this.started, &buf[..n]);
+        }
+        poll
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().inner).poll_shutdown(cx)
+    }
+}
+
+
+#[cfg(test)]
+pub mod test_support {
+    use std::{io, sync::Arc, time::{Instant, SystemTime}};
+
+    use pnet::{datalink::{DataLinkSender, NetworkInterface}, util::MacAddr};
+    use tokio::sync::Mutex;
+
+    use super::{EventSink, ServiceSession};
+    use crate::{honeypot::persona::OsPersona, interfaces::ws::WsReporter};
+
+    /// Link that swallows every frame
+    struct Discard;
+
+    impl DataLinkSender for Discard {
+        fn build_and_send(&mut self, _: usize, _: usize, _: &mut dyn FnMut(&mut [u8])) -> Option<io::Result<()>> {
+            None
+        }
+
+        fn send_to(&mut self, _: &[u8], _: Option<NetworkInterface>) -> Option<io::Result<()>> {
+            Some(Ok(()))
+        }
+    }
+
+    fn reporter() -> WsReporter {
+        let (ws_tx, _) = futures_channel::mpsc::unbounded();
+        WsReporter::new(ws_tx, Arc::new(Mutex::new(0)))
+    }
+
+    /// Sink for emulator tests: its events go to a channel nobody reads
+    pub fn events(service: &'static str, honeypot_addr: &str, attacker_addr: &str) -> EventSink {
+        EventSink::new(service, MacAddr::zero(), honeypot_addr.parse().unwrap(), MacAddr::zero(), attacker_addr.parse().unwrap(), reporter())
+    }
+
+    /// Session for emulator tests, not backed by a connection
+    pub fn session(service: &'static str, honeypot_addr: &str, attacker_addr: &str) -> ServiceSession {
+        ServiceSession {
+            service,
+            honeypot_mac: MacAddr::zero(),
+            honeypot_addr: honeypot_addr.parse().unwrap(),
+            attacker_mac: MacAddr::zero(),
+            attacker_addr: attacker_addr.parse().unwrap(),
+            started_at: SystemTime::now(),
+            started: Instant::now(),
+            transcript: Default::default(),
+            reporter: reporter(),
+            sender: Arc::new(Mutex::new(Box::new(Discard))),
+            os: OsPersona::for_mac(MacAddr::zero()),
+        }
+    }
+}
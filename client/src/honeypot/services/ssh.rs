@@ -0,0 +1,500 @@
+use std::{borrow::Cow, collections::HashMap, fs, io, path::PathBuf, pin::Pin, sync::Arc, task::{Context, Poll}, time::Duration};
+
+use common::types::HoneypotEvent;
+use futures_util::future::BoxFuture;
+use pnet::util::MacAddr;
+use russh::{
+    keys::ssh_key::{private::Ed25519Keypair, LineEnding, PrivateKey, PublicKey},
+    server::{run_stream, Auth, Config, Handler, Msg, Response, Session},
+    Channel, ChannelId, MethodKind, MethodSet, SshId,
+};
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use tracing::{error, info};
+
+use super::{session::{CapturedStream, EventSink, ServiceSession}, ServiceEmulator, ServiceReply};
+use crate::honeypot::{persona::SshPersonality, shell::{to_terminal, FakeShell, LineEditor}};
+
+const HOST_KEY_DIR: &str = "state/ssh_host_keys";
+
+// Weak credentials that let the attacker into the emulated shell
+const DEFAULT_CREDENTIALS: &[(&str, &str)] = &[("root", "root"), ("root", "123456"), ("admin", "admin")];
+
+// Stop sniffing the cleartext handshake after this many bytes
+const MAX_HELLO_BYTES: usize = 64 * 1024;
+const SSH_MSG_KEXINIT: u8 = 20;
+
+
+/// SSH server emulation: completes the key exchange with a per-node persistent host key,
+/// reports every authentication attempt and lets configured credentials into the fake shell
+#[derive(Clone)]
+pub struct SshEmulator {
+    config: Arc<Config>,
+    credentials: Arc<Vec<(String, String)>>,
+    login: Arc<LoginShell>,
+}
+
+/// What a logged-in attacker lands in, taken from the node's persona
+struct LoginShell {
+    hostname: String,
+    release: Option<String>,
+    busybox: bool,
+}
+
+impl SshEmulator {
+    pub fn new(mac: MacAddr, persona: &SshPersonality, hostname: &str) -> Self {
+        let credentials = DEFAULT_CREDENTIALS.iter().map(|(u, p)| (u.to_string(), p.to_string())).collect();
+        SshEmulator::with_credentials(mac, persona, hostname, credentials)
+    }
+
+    pub fn with_credentials(mac: MacAddr, persona: &SshPersonality, hostname: &str, credentials: Vec<(String, String)>) -> Self {
+        let config = Config {
+            server_id: SshId::Standard(Cow::Owned(persona.server_id.clone())),
+            methods: MethodSet::from(&[MethodKind::Password, MethodKind::PublicKey, MethodKind::KeyboardInteractive][..]),
+            keys: vec![load_or_create_host_key(mac)],
+            auth_rejection_time: Duration::from_millis(800),
+            auth_rejection_time_initial: Some(Duration::ZERO),
+            max_auth_attempts: 6,
+            inactivity_timeout: Some(Duration::from_secs(300)),
+            ..Default::default()
+        };
+
+        let login = LoginShell { hostname: hostname.to_string(), release: persona.release.clone(), busybox: persona.busybox };
+        SshEmulator { config: Arc::new(config), credentials: Arc::new(credentials), login: Arc::new(login) }
+    }
+}
+
+impl ServiceEmulator for SshEmulator {
+    fn name(&self) -> &'static str {
+        "ssh"
+    }
+
+    fn on_data(&mut self, _session: &mut ServiceSession, _data: &[u8]) -> ServiceReply {
+        ServiceReply::none()
+    }
+
+    fn serve<'a>(&'a mut self, stream: CapturedStream, session: &'a mut ServiceSession) -> BoxFuture<'a, ()> {
+        Box::pin(async move {
+            let events = session.events();
+            let handler = SshHandler::new(events.clone(), self.credentials.clone(), self.login.clone());
+            let stream = HelloSniffer::new(stream, events);
+
+            let result = match run_stream(self.config.clone(), stream, handler).await {
+                Ok(running) => running.await,
+                Err(e) => Err(e),
+            };
+            if let Err(e) = result {
+                info!("🔑 SSH session from {} ended: {}", session.attacker_addr, e);
+            }
+        })
+    }
+}
+
+
+/// Loads the node's host key from disk, generating it on first use so the fingerprint survives restarts
+fn host_key_path(mac: MacAddr) -> PathBuf {
+    PathBuf::from(HOST_KEY_DIR).join(format!("ssh_host_ed25519_key_{}", mac.to_string().replace(':', "")))
+}
+
+fn load_or_create_host_key(mac: MacAddr) -> PrivateKey {
+    let path = host_key_path(mac);
+
+    if let Ok(pem) = fs::read_to_string(&path) {
+        match PrivateKey::from_openssh(pem) {
+            Ok(key) => return key,
+            Err(e) => error!("❌ Host key {:?} non valida, ne genero una nuova: {}", path, e),
+        }
+    }
+
+    let key = PrivateKey::from(Ed25519Keypair::from_seed(&rand::random::<[u8; 32]>()));
+
+    let saved = fs::create_dir_all(HOST_KEY_DIR)
+        .map_err(|e| e.to_string())
+        .and_then(|_| key.to_openssh(LineEnding::LF).map_err(|e| e.to_string()))
+        .and_then(|pem| fs::write(&path, pem.as_bytes()).map_err(|e| e.to_string()));
+    if let Err(e) = saved {
+        error!("❌ Impossibile salvare la host key {:?}: {}", path, e);
+    }
+
+    key
+}
+
+/// Deletes the node's host key, the next one generated for its MAC will differ
+pub fn remove_host_key(mac: MacAddr) {
+    let path = host_key_path(mac);
+    if let Err(e) = fs::remove_file(&path) && e.kind() != io::ErrorKind::NotFound {
+        error!("❌ Impossibile rimuovere la host key {:?}: {}", path, e);
+    }
+}
+
+
+#[derive(Default)]
+struct ShellChannel {
+    editor: LineEditor,
+    pty: bool,
+    shell: Option<FakeShell>,
+}
+
+/// Per-connection russh handler
+struct SshHandler {
+    events: EventSink,
+    credentials: Arc<Vec<(String, String)>>,
+    login: Arc<LoginShell>,
+    username: String,
+    channels: HashMap<ChannelId, ShellChannel>,
+}
+
+impl SshHandler {
+    fn new(events: EventSink, credentials: Arc<Vec<(String, String)>>, login: Arc<LoginShell>) -> Self {
+        SshHandler { events, credentials, login, username: String::new(), channels: HashMap::new() }
+    }
+
+    fn open_shell(&self) -> FakeShell {
+        let login = &self.login;
+        if login.busybox {
+            FakeShell::busybox(&self.username, &login.hostname, self.events.clone())
+        } else {
+            FakeShell::new(&self.username, &login.hostname, self.events.clone()).with_release(login.release.as_deref())
+        }
+    }
+
+    fn check_password(&mut self, user: &str, password: &str) -> Auth {
+        let accepted = self.credentials.iter().any(|(u, p)| u == user && p == password);
+
+        self.events.record(HoneypotEvent::LoginAttempt {
+            username: user.to_string(),
+            password: Some(password.to_string()),
+            public_key: None,
+            accepted,
+        });
+
+        if accepted {
+            self.username = user.to_string();
+            Auth::Accept
+        } else {
+            Auth::reject()
+        }
+    }
+}
+
+fn close_channel(channel: ChannelId, exit_status: u32, session: &mut Session) -> Result<(), russh::Error> {
+    session.exit_status_request(channel, exit_status)?;
+    session.eof(channel)?;
+    session.close(channel)
+}
+
+impl Handler for SshHandler {
+    type Error = russh::Error;
+
+    async fn auth_password(&mut self, user: &str, password: &str) -> Result<Auth, Self::Error> {
+        Ok(self.check_password(user, password))
+    }
+
+    async fn auth_publickey_offered(&mut self, user: &str, public_key: &PublicKey) -> Result<Auth, Self::Error> {
+        // Keys are only collected, rejecting at the offer keeps the client trying its other keys and then passwords
+        self.events.record(HoneypotEvent::LoginAttempt {
+            username: user.to_string(),
+            password: None,
+            public_key: Some(public_key.to_openssh().unwrap_or_else(|_| public_key.algorithm().to_string())),
+            accepted: false,
+        });
+        Ok(Auth::reject())
+    }
+
+    async fn auth_keyboard_interactive<'a>(&'a mut self, user: &str, _submethods: &str, response: Option<Response<'a>>) -> Result<Auth, Self::Error> {
+        match response.and_then(|mut answers| answers.next()) {
+            Some(answer) => Ok(self.check_password(user, &String::from_utf8_lossy(&answer))),
+            None => Ok(Auth::Partial {
+                name: Cow::Borrowed(""),
+                instructions: Cow::Borrowed(""),
+                prompts: Cow::Owned(vec![(Cow::Borrowed("Password: "), false)]),
+            }),
+        }
+    }
+
+    async fn channel_open_session(&mut self, channel: Channel<Msg>, reply: russh::server::ChannelOpenHandle, _session: &mut Session) -> Result<(), Self::Error> {
+        self.channels.insert(channel.id(), ShellChannel::default());
+        reply.accept().await;
+        Ok(())
+    }
+
+    async fn pty_request(
+        &mut self,
+        channel: ChannelId,
+        _term: &str,
+        _col_width: u32,
+        _row_height: u32,
+        _pix_width: u32,
+        _pix_height: u32,
+        _modes: &[(russh::Pty, u32)],
+        session: &mut Session,
+    ) -> Result<(), Self::Error> {
+        if let Some(shell) = self.channels.get_mut(&channel) {
+            shell.pty = true;
+        }
+        session.channel_success(channel)
+    }
+
+    async fn shell_request(&mut self, channel: ChannelId, session: &mut Session) -> Result<(), Self::Error> {
+        if !self.channels.contains_key(&channel) {
+            return session.channel_failure(channel);
+        }
+        session.channel_success(channel)?;
+
+        let shell = self.open_shell();
+        let Some(state) = self.channels.get_mut(&channel) else {
+            return Ok(());
+        };
+        session.data(channel, to_terminal(&format!("{}\n{}", shell.motd(), shell.prompt())))?;
+        state.shell = Some(shell);
+        Ok(())
+    }
+
+    async fn exec_request(&mut self, channel: ChannelId, data: &[u8], session: &mut Session) -> Result<(), Self::Error> {
+        session.channel_success(channel)?;
+
+        let mut shell = self.open_shell();
+        let result = shell.execute(&String::from_utf8_lossy(data));
+        if !result.output.is_empty() {
+            session.data(channel, result.output.into_bytes())?;
+        }
+        close_channel(channel, result.exit_status, session)
+    }
+
+    async fn data(&mut self, channel: ChannelId, data: &[u8], session: &mut Session) -> Result<(), Self::Error> {
+        let Some(state) = self.channels.get_mut(&channel) else {
+            return Ok(());
+        };
+        let Some(shell) = state.shell.as_mut() else {
+            return Ok(());
+        };
+
+        let input = state.editor.feed(data);
+        let lines = input.lines.into_iter().map(|line| (line.text, line.echo));
+        for (line, echo) in lines.chain(input.eof.then(|| ("exit".to_string(), Vec::new()))) {
+            if state.pty && !echo.is_empty() {
+                session.data(channel, echo)?;
+            }
+            let result = shell.execute(&line);
+            let output = if state.pty { to_terminal(&result.output) } else { result.output.into_bytes() };
+            if !output.is_empty() {
+                session.data(channel, output)?;
+            }
+            if result.exit {
+                self.channels.remove(&channel);
+                return close_channel(channel, result.exit_status, session);
+            }
+            session.data(channel, shell.prompt().into_bytes())?;
+        }
+        if state.pty && !input.echo.is_empty() {
+            session.data(channel, input.echo)?;
+        }
+        Ok(())
+    }
+
+    async fn channel_close(&mut self, channel: ChannelId, _session: &mut Session) -> Result<(), Self::Error> {
+        self.channels.remove(&channel);
+        Ok(())
+    }
+}
+
+
+/// Passes the stream through to russh while parsing the client's version line and
+/// cleartext KEXINIT, which carry the client fingerprint (HASSH)
+struct HelloSniffer {
+    inner: CapturedStream,
+    events: EventSink,
+    buffer: Vec<u8>,
+    done: bool,
+}
+
+enum Hello {
+    Incomplete,
+    Parsed(Box<HoneypotEvent>),
+    Invalid,
+}
+
+impl HelloSniffer {
+    fn new(inner: CapturedStream, events: EventSink) -> Self {
+        HelloSniffer { inner, events, buffer: Vec::new(), done: false }
+    }
+
+    fn observe(&mut self, data: &[u8]) {
+        if self.done || data.is_empty() {
+            return;
+        }
+        self.buffer.extend_from_slice(data);
+
+        match parse_client_hello(&self.buffer) {
+            Hello::Incomplete if self.buffer.len() < MAX_HELLO_BYTES => return,
+            Hello::Parsed(event) => self.events.record(*event),
+            _ => {}
+        }
+        self.done = true;
+        self.buffer = Vec::new();
+    }
+}
+
+impl AsyncRead for HelloSniffer {
+    fn poll_read(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut ReadBuf<'_>) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        let before = buf.filled().len();
+        let poll = Pin::new(&mut this.inner).poll_read(cx, buf);
+
+        if let Poll::Ready(Ok(())) = poll {
+            this.observe(&buf.filled()[before..]);
+        }
+        poll
+    }
+}
+
+impl AsyncWrite for HelloSniffer {
+    fn poll_write(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8]) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.get_mut().inner).poll_write(cx, buf)
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().inner).poll_shutdown(cx)
+    }
+}
+
+
+/// Parses "SSH-2.0-...\r\n" followed by the client's first binary packet (RFC 4253 §4.2, §7.1)
+fn parse_client_hello(data: &[u8]) -> Hello {
+    let Some(line_end) = data.iter().position(|&b| b == b'\n') else {
+        return if data.len() > 255 { Hello::Invalid } else { Hello::Incomplete };
+    };
+    let version = String::from_utf8_lossy(&data[..line_end]).trim_end_matches('\r').to_string();
+    if !version.starts_with("SSH-") {
+        return Hello::Invalid;
+    }
+
+    let packet = &data[line_end + 1..];
+    if packet.len() < 5 {
+        return Hello::Incomplete;
+    }
+    let packet_length = u32::from_be_bytes([packet[0], packet[1], packet[2], packet[3]]) as usize;
+    let padding_length = packet[4] as usize;
+    if packet_length > 35000 || padding_length + 1 > packet_length {
+        return Hello::Invalid;
+    }
+    if packet.len() < 4 + packet_length {
+        return Hello::Incomplete;
+    }
+
+    let payload = &packet[5..4 + packet_length - padding_length];
+    if payload.first() != Some(&SSH_MSG_KEXINIT) || payload.len() < 17 {
+        return Hello::Invalid;
+    }
+
+    // Message type and 16 byte cookie, then the ten algorithm name-lists
+    let mut cursor = &payload[17..];
+    let mut lists = Vec::with_capacity(10);
+    for _ in 0..10 {
+        match read_name_list(&mut cursor) {
+            Some(list) => lists.push(list),
+            None => return Hello::Invalid,
+        }
+    }
+
+    let hassh_input = format!("{};{};{};{}", lists[0], lists[2], lists[4], lists[6]);
+    let split = |list: &str| -> Vec<String> { list.split(',').filter(|s| !s.is_empty()).map(String::from).collect() };
+
+    Hello::Parsed(Box::new(HoneypotEvent::SshClientHello {
+        version,
+        kex_algorithms: split(&lists[0]),
+        host_key_algorithms: split(&lists[1]),
+        ciphers: split(&lists[2]),
+        macs: split(&lists[4]),
+        compression: split(&lists[6]),
+        hassh: format!("{:x}", md5::compute(hassh_input)),
+    }))
+}
+
+fn read_name_list(cursor: &mut &[u8]) -> Option<String> {
+    let length = u32::from_be_bytes(cursor.get(..4)?.try_into().ok()?) as usize;
+    let list = cursor.get(4..4 + length)?;
+    let list = String::from_utf8_lossy(list).to_string();
+    *cursor = &cursor[4 + length..];
+    Some(list)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KEX: &str = "curve25519-sha256,curve25519-sha256@libssh.org,ecdh-sha2-nistp256,ecdh-sha2-nistp384,ecdh-sha2-nistp521,diffie-hellman-group-exchange-sha256,diffie-hellman-group16-sha512,diffie-hellman-group18-sha512,diffie-hellman-group-exchange-sha1,diffie-hellman-group14-sha256,diffie-hellman-group14-sha1,ext-info-c";
+    const HOST_KEYS: &str = "ssh-ed25519-cert-v01@openssh.com,rsa-sha2-512,rsa-sha2-256,ssh-ed25519";
+    const CIPHERS: &str = "chacha20-poly1305@openssh.com,aes128-ctr,aes192-ctr,aes256-ctr,aes128-gcm@openssh.com,aes256-gcm@openssh.com,aes128-cbc,aes192-cbc,aes256-cbc";
+    const MACS: &str = "umac-64-etm@openssh.com,umac-128-etm@openssh.com,hmac-sha2-256-etm@openssh.com,hmac-sha2-512-etm@openssh.com,hmac-sha1-etm@openssh.com,umac-64@openssh.com,umac-128@openssh.com,hmac-sha2-256,hmac-sha2-512,hmac-sha1";
+    const COMPRESSION: &str = "none,zlib@openssh.com,zlib";
+
+    /// Version line and KEXINIT packet of an OpenSSH 7.6 client
+    fn openssh_hello() -> Vec<u8> {
+        let mut payload = vec![SSH_MSG_KEXINIT];
+        payload.extend_from_slice(&[0x5a; 16]);
+        for list in [KEX, HOST_KEYS, CIPHERS, CIPHERS, MACS, MACS, COMPRESSION, COMPRESSION, "", ""] {
+            payload.extend_from_slice(&(list.len() as u32).to_be_bytes());
+            payload.extend_from_slice(list.as_bytes());
+        }
+        // first_kex_packet_follows and the reserved field
+        payload.extend_from_slice(&[0; 5]);
+
+        let padding = 8 - (payload.len() + 5) % 8 + 4;
+        let mut hello = b"SSH-2.0-OpenSSH_7.6p1 Ubuntu-4ubuntu0.3\r\n".to_vec();
+        hello.extend_from_slice(&((payload.len() + padding + 1) as u32).to_be_bytes());
+        hello.push(padding as u8);
+        hello.extend_from_slice(&payload);
+        hello.extend(std::iter::repeat_n(0, padding));
+        hello
+    }
+
+    #[test]
+    fn hassh_of_an_openssh_client() {
+        let Hello::Parsed(event) = parse_client_hello(&openssh_hello()) else {
+            panic!("hello not parsed");
+        };
+        let HoneypotEvent::SshClientHello { version, kex_algorithms, ciphers, compression, hassh, .. } = *event else {
+            panic!("unexpected event");
+        };
+        assert_eq!(version, "SSH-2.0-OpenSSH_7.6p1 Ubuntu-4ubuntu0.3");
+        assert_eq!(kex_algorithms.len(), 12);
+        assert_eq!(ciphers[0], "chacha20-poly1305@openssh.com");
+        assert_eq!(compression, ["none", "zlib@openssh.com", "zlib"]);
+        // MD5 of "kex;ciphers;macs;compression", client to server lists
+        assert_eq!(hassh, "0df0d56bb50c6b2426d8d40234bf1826");
+    }
+
+    #[test]
+    fn partial_hello_waits_for_more_data() {
+        let hello = openssh_hello();
+        for cut in [10, 42, 46, hello.len() - 1] {
+            assert!(matches!(parse_client_hello(&hello[..cut]), Hello::Incomplete), "cut at {}", cut);
+        }
+    }
+
+    #[test]
+    fn malformed_hellos_are_rejected() {
+        assert!(matches!(parse_client_hello(b"GET / HTTP/1.1\r\n\r\n"), Hello::Invalid));
+        assert!(matches!(parse_client_hello(&[b'A'; 300]), Hello::Invalid));
+
+        // Packet length past the limit, and padding longer than the packet
+        assert!(matches!(parse_client_hello(b"SSH-2.0-x\r\n\xff\xff\xff\xff\x04"), Hello::Invalid));
+        assert!(matches!(parse_client_hello(b"SSH-2.0-x\r\n\x00\x00\x00\x08\x20\x14\x00\x00\x00\x00\x00\x00\x00"), Hello::Invalid));
+
+        // A name-list whose length runs past the end of the packet
+        let mut hello = openssh_hello();
+        let first_list = 41 + 5 + 17;
+        hello[first_list..first_list + 4].copy_from_slice(&0xffff_fff0u32.to_be_bytes());
+        assert!(matches!(parse_client_hello(&hello), Hello::Invalid));
+
+        // Not a KEXINIT
+        let mut hello = openssh_hello();
+        hello[41 + 5] = 21;
+        assert!(matches!(parse_client_hello(&hello), Hello::Invalid));
+    }
+}
@@ -0,0 +1,308 @@
+use std::sync::Arc;
+
+use common::types::HoneypotEvent;
+use pnet::util::MacAddr;
+
+use super::{session::ServiceSession, ServiceEmulator, ServiceReply};
+use crate::honeypot::shell::{to_terminal, FakeShell, LineEditor};
+
+const IAC: u8 = 255;
+const DONT: u8 = 254;
+const DO: u8 = 253;
+const WONT: u8 = 252;
+const WILL: u8 = 251;
+const SB: u8 = 250;
+const SE: u8 = 240;
+const OPT_ECHO: u8 = 1;
+const OPT_SUPPRESS_GO_AHEAD: u8 = 3;
+const OPT_NAWS: u8 = 31;
+
+// Longest unterminated subnegotiation kept waiting for its IAC SE; past it the rest is discarded
+const MAX_PENDING: usize = 1024;
+
+// Failed logins before the connection is dropped, like BusyBox login
+const MAX_LOGIN_ATTEMPTS: usize = 3;
+
+// Factory credentials of the devices IoT botnets go after
+const DEFAULT_CREDENTIALS: &[(&str, &str)] = &[
+    ("root", "xc3511"),
+    ("root", "vizxv"),
+    ("root", "admin"),
+    ("root", "888888"),
+    ("root", "default"),
+    ("root", "12345"),
+    ("root", "hi3518"),
+    ("root", "Zte521"),
+    ("admin", "admin"),
+    ("admin", "password"),
+    ("support", "support"),
+    ("default", "default"),
+];
+
+// `hostname` of the emulated devices, picked per node
+const HOSTNAMES: &[&str] = &["(none)", "dvrdvs", "localhost", "HG8245H"];
+
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum LoginState {
+    Username,
+    Password,
+    Shell,
+}
+
+/// Telnet daemon of an embedded device: negotiates options, asks for login and password,
+/// reports every attempt and drops accepted logins into a BusyBox shell
+pub struct TelnetEmulator {
+    hostname: &'static str,
+    credentials: Arc<Vec<(String, String)>>,
+    state: LoginState,
+    username: String,
+    attempts: usize,
+    // Bytes of an option negotiation split across segments
+    pending: Vec<u8>,
+    // Inside an over-long subnegotiation, discarded up to its IAC SE
+    skipping: bool,
+    editor: LineEditor,
+    shell: Option<FakeShell>,
+}
+
+impl TelnetEmulator {
+    pub fn new(mac: MacAddr) -> Self {
+        let credentials = DEFAULT_CREDENTIALS.iter().map(|(u, p)| (u.to_string(), p.to_string())).collect();
+        TelnetEmulator::with_credentials(mac, Arc::new(credentials))
+    }
+
+    pub fn with_credentials(mac: MacAddr, credentials: Arc<Vec<(String, String)>>) -> Self {
+        TelnetEmulator {
+            hostname: HOSTNAMES[mac.5 as usize % HOSTNAMES.len()],
+            credentials,
+            state: LoginState::Username,
+            username: String::new(),
+            attempts: 0,
+            pending: Vec::new(),
+            skipping: false,
+            editor: LineEditor::default(),
+            shell: None,
+        }
+    }
+
+    fn login_prompt(&self) -> Vec<u8> {
+        format!("{} login: ", self.hostname).into_bytes()
+    }
+
+    /// Strips option negotiation from the stream, leaving only what the user typed
+    fn strip_negotiation(&mut self, data: &[u8]) -> Vec<u8> {
+        let mut input = std::mem::take(&mut self.pending);
+        input.extend_from_slice(data);
+
+        let mut text = Vec::new();
+        let mut i = 0;
+        if self.skipping {
+            match input.windows(2).position(|w| w == [IAC, SE]) {
+                Some(end) => {
+                    self.skipping = false;
+                    i = end + 2;
+                }
+                None => {
+                    // Only a trailing IAC can still start the IAC SE
+                    if input.last() == Some(&IAC) {
+                        self.pending.push(IAC);
+                    }
+                    return text;
+                }
+            }
+        }
+
+        while i < input.len() {
+            if input[i] != IAC {
+                text.push(input[i]);
+                i += 1;
+                continue;
+            }
+
+            match input.get(i + 1) {
+                None => break,
+                Some(&IAC) => {
+                    text.push(IAC);
+                    i += 2;
+                }
+                Some(&(WILL | WONT | DO | DONT)) if i + 2 < input.len() => i += 3,
+                Some(&(WILL | WONT | DO | DONT)) => break,
+                Some(&SB) => match input[i..].windows(2).position(|w| w == [IAC, SE]) {
+                    Some(end) => i += end + 2,
+                    None if input.len() - i > MAX_PENDING => {
+                        self.skipping = true;
+                        i = input.len() - usize::from(input.last() == Some(&IAC));
+                        break;
+                    }
+                    None => break,
+                },
+                Some(_) => i += 2,
+            }
+        }
+
+        self.pending = input[i..].to_vec();
+        text
+    }
+
+    fn handle_line(&mut self, session: &mut ServiceSession, line: String, reply: &mut ServiceReply) {
+        match self.state {
+            LoginState::Username => {
+                let username = line.trim();
+                if username.is_empty() {
+                    reply.data.extend(self.login_prompt());
+                    return;
+                }
+                self.username = username.to_string();
+                self.state = LoginState::Password;
+                reply.data.extend_from_slice(b"Password: ");
+            }
+            LoginState::Password => {
+                let accepted = self.credentials.iter().any(|(u, p)| *u == self.username && *p == line);
+                session.record(HoneypotEvent::LoginAttempt {
+                    username: self.username.clone(),
+                    password: Some(line),
+                    public_key: None,
+                    accepted,
+                });
+                reply.data.extend_from_slice(b"\r\n");
+
+                if accepted {
+                    let shell = FakeShell::busybox(&self.username, self.hostname, session.events());
+                    reply.data.extend(to_terminal(&shell.motd()));
+                    reply.data.extend(to_terminal(&shell.prompt()));
+                    self.shell = Some(shell);
+                    self.state = LoginState::Shell;
+                    return;
+                }
+
+                self.attempts += 1;
+                reply.data.extend_from_slice(b"Login incorrect\r\n");
+                if self.attempts >= MAX_LOGIN_ATTEMPTS {
+                    reply.close = true;
+                    return;
+                }
+                self.state = LoginState::Username;
+                reply.data.extend(self.login_prompt());
+            }
+            LoginState::Shell => {
+                let Some(shell) = self.shell.as_mut() else {
+                    return;
+                };
+                let result = shell.execute(&line);
+                reply.data.extend(to_terminal(&result.output));
+                if result.exit {
+                    reply.close = true;
+                    return;
+                }
+                reply.data.extend(to_terminal(&shell.prompt()));
+            }
+        }
+    }
+}
+
+impl ServiceEmulator for TelnetEmulator {
+    fn name(&self) -> &'static str {
+        "telnet"
+    }
+
+    fn on_connect(&mut self, _session: &mut ServiceSession) -> Vec<u8> {
+        // Same negotiation busybox telnetd opens with: the server echoes, character at a time
+        let mut banner = vec![IAC, DO, OPT_ECHO, IAC, DO, OPT_NAWS, IAC, WILL, OPT_ECHO, IAC, WILL, OPT_SUPPRESS_GO_AHEAD];
+        banner.extend_from_slice(b"\r\n");
+        banner.extend(self.login_prompt());
+        banner
+    }
+
+    fn on_data(&mut self, session: &mut ServiceSession, data: &[u8]) -> ServiceReply {
+        let text = self.strip_negotiation(data);
+        let input = self.editor.feed(&text);
+
+        let mut reply = ServiceReply::none();
+        // The server owns the echo, and passwords are not echoed; the state can change
+        // between lines of the same segment, so echo is decided per line
+        for line in input.lines {
+            if self.state != LoginState::Password {
+                reply.data.extend_from_slice(&line.echo);
+            }
+            self.handle_line(session, line.text, &mut reply);
+            if reply.close {
+                return reply;
+            }
+        }
+        if self.state != LoginState::Password {
+            reply.data.extend_from_slice(&input.echo);
+        }
+
+        if input.eof {
+            if self.state == LoginState::Shell {
+                reply.data.extend_from_slice(b"\r\n");
+            }
+            reply.close = true;
+        }
+        reply
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::honeypot::services::session::test_support;
+
+    fn telnet() -> (TelnetEmulator, ServiceSession) {
+        (TelnetEmulator::new(MacAddr::zero()), test_support::session("telnet", "10.0.0.5:23", "10.0.0.7:40000"))
+    }
+
+    #[test]
+    fn negotiation_is_stripped_across_segments() {
+        let (mut telnet, _) = telnet();
+        assert_eq!(telnet.strip_negotiation(&[IAC, WILL, OPT_NAWS, b'a', IAC, IAC, b'b']), [b'a', IAC, b'b']);
+
+        // Option and subnegotiation split over three segments
+        assert_eq!(telnet.strip_negotiation(&[b'c', IAC, DO]), b"c");
+        assert_eq!(telnet.strip_negotiation(&[OPT_ECHO, IAC, SB, OPT_NAWS, 0, 80]), b"");
+        assert_eq!(telnet.strip_negotiation(&[0, 24, IAC, SE, b'd']), b"d");
+        assert!(telnet.pending.is_empty());
+    }
+
+    #[test]
+    fn unterminated_subnegotiation_stays_bounded() {
+        let (mut telnet, _) = telnet();
+        assert_eq!(telnet.strip_negotiation(&[b'x', IAC, SB, OPT_NAWS]), b"x");
+        for _ in 0..100 {
+            assert_eq!(telnet.strip_negotiation(&[b'A'; 512]), b"");
+            assert!(telnet.pending.len() <= MAX_PENDING);
+        }
+        assert!(telnet.skipping);
+
+        // The IAC SE closing it may straddle segments
+        assert_eq!(telnet.strip_negotiation(&[b'A', IAC]), b"");
+        assert_eq!(telnet.strip_negotiation(&[SE, b'i', b'd']), b"id");
+        assert!(!telnet.skipping);
+    }
+
+    #[tokio::test]
+    async fn password_is_not_echoed_when_sent_with_the_username() {
+        let (mut telnet, mut session) = telnet();
+        telnet.on_connect(&mut session);
+
+        let reply = telnet.on_data(&mut session, b"root\r\nxc3511\r\nid\r\n");
+        let output = String::from_utf8_lossy(&reply.data);
+        assert!(!reply.close);
+        assert!(output.starts_with("root\r\nPassword: \r\n"), "{output}");
+        assert!(!output.contains("xc3511"), "{output}");
+        assert!(output.contains("id\r\nuid=0(root)"), "{output}");
+    }
+
+    #[tokio::test]
+    async fn failed_logins_close_the_connection() {
+        let (mut telnet, mut session) = telnet();
+        let reply = telnet.on_data(&mut session, b"root\r\na\r\nroot\r\nb\r\n");
+        assert!(!reply.close);
+        assert_eq!(String::from_utf8_lossy(&reply.data).matches("Login incorrect").count(), 2);
+
+        let reply = telnet.on_data(&mut session, b"admin\r\nc\r\n");
+        assert!(reply.close);
+    }
+}
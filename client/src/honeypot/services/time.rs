@@ -0,0 +1,79 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// Weekdays from the unix epoch, a Thursday
+const DAYS: [&str; 7] = ["Thu", "Fri", "Sat", "Sun", "Mon", "Tue", "Wed"];
+const MONTHS: [&str; 12] = ["Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec"];
+
+
+pub fn now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0)
+}
+
+/// Civil date from a unix timestamp (UTC)
+pub fn civil(timestamp: u64) -> (i64, u32, u32, u64) {
+    let days = (timestamp / 86_400) as i64;
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day, timestamp % 86_400)
+}
+
+fn weekday(timestamp: u64) -> &'static str {
+    DAYS[((timestamp / 86_400) % 7) as usize]
+}
+
+fn month_name(month: u32) -> &'static str {
+    MONTHS[(month - 1) as usize]
+}
+
+/// "09:52:04"
+pub fn format_time(timestamp: u64) -> String {
+    let seconds = timestamp % 86_400;
+    format!("{:02}:{:02}:{:02}", seconds / 3600, (seconds % 3600) / 60, seconds % 60)
+}
+
+/// "2024-04-15 09:52:04"
+pub fn format_timestamp(timestamp: u64) -> String {
+    let (year, month, day, _) = civil(timestamp);
+    format!("{}-{:02}-{:02} {}", year, month, day, format_time(timestamp))
+}
+
+/// `date` style: "Mon Apr 15 09:52:04 UTC 2024"
+pub fn format_date(timestamp: u64) -> String {
+    let (year, month, day, _) = civil(timestamp);
+    format!("{} {} {:>2} {} UTC {}", weekday(timestamp), month_name(month), day, format_time(timestamp), year)
+}
+
+/// RFC 7231 IMF-fixdate: "Mon, 15 Apr 2024 09:52:04 GMT"
+pub fn http_date(timestamp: u64) -> String {
+    let (year, month, day, _) = civil(timestamp);
+    format!("{}, {:02} {} {} {} GMT", weekday(timestamp), day, month_name(month), year, format_time(timestamp))
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn civil_dates() {
+        assert_eq!(civil(0), (1970, 1, 1, 0));
+        assert_eq!(civil(951_782_400), (2000, 2, 29, 0));
+        assert_eq!(civil(1_713_174_724), (2024, 4, 15, 35_524));
+    }
+
+    #[test]
+    fn formats() {
+        assert_eq!(format_date(1_713_174_724), "Mon Apr 15 09:52:04 UTC 2024");
+        assert_eq!(format_date(0), "Thu Jan  1 00:00:00 UTC 1970");
+        assert_eq!(format_timestamp(1_713_174_724), "2024-04-15 09:52:04");
+        // RFC 7231 §7.1.1.1 example
+        assert_eq!(http_date(784_111_777), "Sun, 06 Nov 1994 08:49:37 GMT");
+    }
+}
@@ -0,0 +1,540 @@
+use std::{fs, io, path::PathBuf, pin::Pin, sync::Arc, task::{Context, Poll}};
+
+use common::types::HoneypotEvent;
+use futures_util::future::BoxFuture;
+use pnet::util::MacAddr;
+use rcgen::{CertificateParams, DistinguishedName, DnType, KeyPair, SerialNumber};
+use sha2::{Digest, Sha256};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, ReadBuf};
+use tokio_rustls::{rustls::{pki_types::{pem::PemObject, CertificateDer, PrivateKeyDer}, ServerConfig}, TlsAcceptor};
+use tracing::{error, info};
+
+use super::{http::{HttpEmulator, HttpPersona}, run_emulator, session::{CapturedStream, ServiceSession}, time::{civil, now}, ServiceEmulator, ServiceReply};
+
+const CERTIFICATE_DIR: &str = "state/tls";
+
+// Largest ClientHello we are willing to buffer before the handshake
+const MAX_CLIENT_HELLO: usize = 16 * 1024;
+const TLS_HANDSHAKE: u8 = 0x16;
+const CLIENT_HELLO: u8 = 0x01;
+
+const EXTENSION_SERVER_NAME: u16 = 0x0000;
+const EXTENSION_SUPPORTED_GROUPS: u16 = 0x000a;
+const EXTENSION_EC_POINT_FORMATS: u16 = 0x000b;
+const EXTENSION_SIGNATURE_ALGORITHMS: u16 = 0x000d;
+const EXTENSION_ALPN: u16 = 0x0010;
+const EXTENSION_SUPPORTED_VERSIONS: u16 = 0x002b;
+
+
+/// HTTPS decoy: terminates TLS with a per-node self-signed certificate, fingerprints the
+/// ClientHello (JA3/JA4) and serves the node's HTTP persona behind it
+#[derive(Clone)]
+pub struct TlsEmulator {
+    acceptor: Option<TlsAcceptor>,
+    persona: HttpPersona,
+}
+
+impl TlsEmulator {
+    pub fn new(mac: MacAddr, persona: HttpPersona) -> Self {
+        let acceptor = match load_or_create_certificate(mac, persona) {
+            Ok((certificate, key)) => ServerConfig::builder()
+                .with_no_client_auth()
+                .with_single_cert(vec![certificate], key)
+                .map(|config| TlsAcceptor::from(Arc::new(config)))
+                .map_err(|e| error!("❌ Configurazione TLS non valida per {}: {}", mac, e))
+                .ok(),
+            Err(e) => {
+                error!("❌ Impossibile creare il certificato TLS per {}: {}", mac, e);
+                None
+            }
+        };
+
+        TlsEmulator { acceptor, persona }
+    }
+}
+
+impl ServiceEmulator for TlsEmulator {
+    fn name(&self) -> &'static str {
+        "https"
+    }
+
+    fn on_data(&mut self, _session: &mut ServiceSession, _data: &[u8]) -> ServiceReply {
+        ServiceReply::none()
+    }
+
+    fn serve<'a>(&'a mut self, mut stream: CapturedStream, session: &'a mut ServiceSession) -> BoxFuture<'a, ()> {
+        Box::pin(async move {
+            let mut http = HttpEmulator::with_name("https", self.persona);
+
+            let (prefix, hello) = read_client_hello(&mut stream).await;
+            let stream = PrefixedStream { prefix, inner: stream };
+
+            let (Some(hello), Some(acceptor)) = (hello, self.acceptor.clone()) else {
+                // Plain HTTP (or garbage) sent to the TLS port still gets an answer
+                run_emulator(&mut http, stream, session).await;
+                return;
+            };
+            session.record(hello);
+
+            match acceptor.accept(stream).await {
+                Ok(tls) => run_emulator(&mut http, tls, session).await,
+                Err(e) => info!("🔒 TLS handshake with {} failed: {}", session.attacker_addr, e),
+            }
+        })
+    }
+}
+
+
+/// Loads the node's certificate from disk or issues a new self-signed one whose subject
+/// matches what the persona's device would present
+fn load_or_create_certificate(mac: MacAddr, persona: HttpPersona) -> Result<(CertificateDer<'static>, PrivateKeyDer<'static>), String> {
+    let (certificate_path, key_path) = certificate_paths(mac);
+
+    if let (Ok(certificate), Ok(key)) = (CertificateDer::from_pem_file(&certificate_path), PrivateKeyDer::from_pem_file(&key_path)) {
+        return Ok((certificate, key));
+    }
+
+    let (common_name, organization, unit, sans) = match persona {
+        HttpPersona::ApacheDefault => ("ubuntu", None, None, vec!["ubuntu".to_string()]),
+        HttpPersona::WordPress => ("northwind-logistics.com", None, None, vec!["northwind-logistics.com".to_string(), "www.northwind-logistics.com".to_string()]),
+        HttpPersona::RouterAdmin => ("tplinkwifi.net", Some("TP-LINK"), Some("TP-LINK"), vec!["tplinkwifi.net".to_string(), "192.168.0.1".to_string()]),
+        HttpPersona::IpCamera => ("IPC", Some("Network Camera"), Some("IPC"), Vec::new()),
+        HttpPersona::Upnp(upnp, _) => (upnp.model_name.as_str(), Some(upnp.manufacturer.as_str()), None, Vec::new()),
+    };
+
+    let mut params = CertificateParams::new(sans).map_err(|e| e.to_string())?;
+    let mut subject = DistinguishedName::new();
+    if let Some(organization) = organization {
+        subject.push(DnType::CountryName, "CN");
+        subject.push(DnType::OrganizationName, organization);
+    }
+    if let Some(unit) = unit {
+        subject.push(DnType::OrganizationalUnitName, unit);
+    }
+    subject.push(DnType::CommonName, common_name);
+    params.distinguished_name = subject;
+
+    // Issued some time ago, valid for years, like a certificate made at install time
+    let (year, month, day, _) = civil(now() - 86_400 * (60 + u64::from(rand::random::<u16>() % 600)));
+    params.not_before = rcgen::date_time_ymd(year as i32, month as u8, day as u8);
+    params.not_after = rcgen::date_time_ymd(year as i32 + 10, month as u8, day.min(28) as u8);
+    params.serial_number = Some(SerialNumber::from(rand::random::<[u8; 16]>().to_vec()));
+
+    let key_pair = KeyPair::generate().map_err(|e| e.to_string())?;
+    let certificate = params.self_signed(&key_pair).map_err(|e| e.to_string())?;
+
+    let saved = fs::create_dir_all(CERTIFICATE_DIR)
+        .and_then(|_| fs::write(&certificate_path, certificate.pem()))
+        .and_then(|_| fs::write(&key_path, key_pair.serialize_pem()));
+    if let Err(e) = saved {
+        error!("❌ Impossibile salvare il certificato TLS {:?}: {}", certificate_path, e);
+    }
+
+    let key = PrivateKeyDer::try_from(key_pair.serialize_der())?;
+    Ok((certificate.der().clone(), key))
+}
+
+fn certificate_paths(mac: MacAddr) -> (PathBuf, PathBuf) {
+    let name = mac.to_string().replace(':', "");
+    (PathBuf::from(CERTIFICATE_DIR).join(format!("{}.crt", name)), PathBuf::from(CERTIFICATE_DIR).join(format!("{}.key", name)))
+}
+
+/// Deletes the node's certificate and key, a new one is issued on next use
+pub fn remove_certificate(mac: MacAddr) {
+    let (certificate_path, key_path) = certificate_paths(mac);
+    for path in [certificate_path, key_path] {
+        if let Err(e) = fs::remove_file(&path) && e.kind() != io::ErrorKind::NotFound {
+            error!("❌ Impossibile rimuovere {:?}: {}", path, e);
+        }
+    }
+}
+
+
+/// Replays the bytes consumed while sniffing the ClientHello before reading from the stream
+struct PrefixedStream {
+    prefix: Vec<u8>,
+    inner: CapturedStream,
+}
+
+impl AsyncRead for PrefixedStream {
+    fn poll_read(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut ReadBuf<'_>) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        if !this.prefix.is_empty() {
+            let n = this.prefix.len().min(buf.remaining());
+            buf.put_slice(&this.prefix[..n]);
+            this.prefix.drain(..n);
+            return Poll::Ready(Ok(()));
+        }
+        Pin::new(&mut this.inner).poll_read(cx, buf)
+    }
+}
+
+impl AsyncWrite for PrefixedStream {
+    fn poll_write(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8]) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.get_mut().inner).poll_write(cx, buf)
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().inner).poll_shutdown(cx)
+    }
+}
+
+
+/// Reads TLS records until the whole ClientHello handshake message is available.
+/// Returns everything read, plus the parsed hello if the client actually speaks TLS.
+async fn read_client_hello(stream: &mut CapturedStream) -> (Vec<u8>, Option<HoneypotEvent>) {
+    let mut raw = Vec::new();
+    let mut handshake = Vec::new();
+    let mut chunk = [0u8; 4096];
+
+    loop {
+        // Reassemble handshake records read so far
+        handshake.clear();
+        let mut offset = 0;
+        while raw.len() >= offset + 5 && raw[offset] == TLS_HANDSHAKE {
+            let length = u16::from_be_bytes([raw[offset + 3], raw[offset + 4]]) as usize;
+            if raw.len() < offset + 5 + length {
+                break;
+            }
+            handshake.extend_from_slice(&raw[offset + 5..offset + 5 + length]);
+            offset += 5 + length;
+        }
+
+        if raw.first().is_some_and(|b| *b != TLS_HANDSHAKE) {
+            return (raw, None);
+        }
+        if handshake.len() >= 4 {
+            if handshake[0] != CLIENT_HELLO {
+                return (raw, None);
+            }
+            let length = u32::from_be_bytes([0, handshake[1], handshake[2], handshake[3]]) as usize;
+            if handshake.len() >= 4 + length {
+                return (raw.clone(), parse_client_hello(&handshake[4..4 + length]));
+            }
+        }
+        if raw.len() > MAX_CLIENT_HELLO {
+            return (raw, None);
+        }
+
+        match stream.read(&mut chunk).await {
+            Ok(0) | Err(_) => return (raw, None),
+            Ok(n) => raw.extend_from_slice(&chunk[..n]),
+        }
+    }
+}
+
+
+/// GREASE values (RFC 8701) are random by design and left out of fingerprints
+fn is_grease(value: u16) -> bool {
+    value & 0x0f0f == 0x0a0a && (value >> 8) == (value & 0xff)
+}
+
+fn read_u8(data: &mut &[u8]) -> Option<u8> {
+    let (&value, rest) = data.split_first()?;
+    *data = rest;
+    Some(value)
+}
+
+fn read_u16(data: &mut &[u8]) -> Option<u16> {
+    let bytes = data.get(..2)?;
+    let value = u16::from_be_bytes([bytes[0], bytes[1]]);
+    *data = &data[2..];
+    Some(value)
+}
+
+fn read_bytes<'a>(data: &mut &'a [u8], length: usize) -> Option<&'a [u8]> {
+    let bytes = data.get(..length)?;
+    *data = &data[length..];
+    Some(bytes)
+}
+
+fn read_u16_list(mut data: &[u8]) -> Vec<u16> {
+    let mut values = Vec::new();
+    while let Some(value) = read_u16(&mut data) {
+        values.push(value);
+    }
+    values
+}
+
+/// Parses a ClientHello body (RFC 8446 §4.1.2) into the reported event with its JA3 and JA4 fingerprints
+fn parse_client_hello(mut data: &[u8]) -> Option<HoneypotEvent> {
+    let legacy_version = read_u16(&mut data)?;
+    read_bytes(&mut data, 32)?;
+    let session_id_length = read_u8(&mut data)? as usize;
+    read_bytes(&mut data, session_id_length)?;
+    let ciphers_length = read_u16(&mut data)? as usize;
+    let cipher_suites = read_u16_list(read_bytes(&mut data, ciphers_length)?);
+    let compression_length = read_u8(&mut data)? as usize;
+    read_bytes(&mut data, compression_length)?;
+
+    let mut extensions = Vec::new();
+    let mut sni = None;
+    let mut alpn = Vec::new();
+    let mut groups = Vec::new();
+    let mut point_formats = Vec::new();
+    let mut signature_algorithms = Vec::new();
+    let mut supported_versions = Vec::new();
+
+    if let Some(length) = read_u16(&mut data) {
+        let mut block = read_bytes(&mut data, length as usize)?;
+        while let Some(kind) = read_u16(&mut block) {
+            let length = read_u16(&mut block)? as usize;
+            let mut body = read_bytes(&mut block, length)?;
+            extensions.push(kind);
+
+            match kind {
+                EXTENSION_SERVER_NAME => {
+                    read_u16(&mut body)?;
+                    if read_u8(&mut body)? == 0 {
+                        let length = read_u16(&mut body)? as usize;
+                        sni = Some(String::from_utf8_lossy(read_bytes(&mut body, length)?).to_string());
+                    }
+                }
+                EXTENSION_ALPN => {
+                    read_u16(&mut body)?;
+                    while let Some(length) = read_u8(&mut body) {
+                        alpn.push(String::from_utf8_lossy(read_bytes(&mut body, length as usize)?).to_string());
+                    }
+                }
+                EXTENSION_SUPPORTED_GROUPS => {
+                    read_u16(&mut body)?;
+                    groups = read_u16_list(body);
+                }
+                EXTENSION_EC_POINT_FORMATS => {
+                    let length = read_u8(&mut body)? as usize;
+                    point_formats = read_bytes(&mut body, length)?.to_vec();
+                }
+                EXTENSION_SIGNATURE_ALGORITHMS => {
+                    read_u16(&mut body)?;
+                    signature_algorithms = read_u16_list(body);
+                }
+                EXTENSION_SUPPORTED_VERSIONS => {
+                    read_u8(&mut body)?;
+                    supported_versions = read_u16_list(body);
+                }
+                _ => {}
+            }
+        }
+    }
+
+    let join = |values: &[u16]| values.iter().filter(|v| !is_grease(**v)).map(|v| v.to_string()).collect::<Vec<_>>().join("-");
+    let ja3 = format!(
+        "{},{},{},{},{}",
+        legacy_version,
+        join(&cipher_suites),
+        join(&extensions),
+        join(&groups),
+        point_formats.iter().map(|v| v.to_string()).collect::<Vec<_>>().join("-"),
+    );
+
+    let version = supported_versions.iter().copied().filter(|v| !is_grease(*v)).max().unwrap_or(legacy_version);
+    let ja4 = ja4(version, sni.is_some(), &cipher_suites, &extensions, alpn.first().map(String::as_str), &signature_algorithms);
+
+    Some(HoneypotEvent::TlsClientHello {
+        sni,
+        alpn,
+        version: version_name(version).to_string(),
+        cipher_suites: cipher_suites.into_iter().filter(|v| !is_grease(*v)).collect(),
+        extensions: extensions.into_iter().filter(|v| !is_grease(*v)).collect(),
+        ja3_hash: format!("{:x}", md5::compute(&ja3)),
+        ja3,
+        ja4,
+    })
+}
+
+fn version_name(version: u16) -> &'static str {
+    match version {
+        0x0304 => "TLSv1.3",
+        0x0303 => "TLSv1.2",
+        0x0302 => "TLSv1.1",
+        0x0301 => "TLSv1.0",
+        0x0300 => "SSLv3",
+        _ => "unknown",
+    }
+}
+
+/// JA4 TLS client fingerprint (FoxIO specification), for TCP transport
+fn ja4(version: u16, has_sni: bool, ciphers: &[u16], extensions: &[u16], alpn: Option<&str>, signature_algorithms: &[u16]) -> String {
+    let ciphers: Vec<u16> = ciphers.iter().copied().filter(|v| !is_grease(*v)).collect();
+    let extensions: Vec<u16> = extensions.iter().copied().filter(|v| !is_grease(*v)).collect();
+
+    let version = match version {
+        0x0304 => "13",
+        0x0303 => "12",
+        0x0302 => "11",
+        0x0301 => "10",
+        0x0300 => "s3",
+        _ => "00",
+    };
+    let alpn = match alpn.map(str::as_bytes) {
+        Some([first, .., last]) if first.is_ascii_alphanumeric() && last.is_ascii_alphanumeric() => format!("{}{}", *first as char, *last as char),
+        Some([only]) if only.is_ascii_alphanumeric() => format!("{}{}", *only as char, *only as char),
+        Some([first, .., last]) => format!("{:x}{:x}", first >> 4, last & 0x0f),
+        _ => "00".to_string(),
+    };
+
+    let a = format!(
+        "t{}{}{:02}{:02}{}",
+        version,
+        if has_sni { 'd' } else { 'i' },
+        ciphers.len().min(99),
+        extensions.len().min(99),
+        alpn,
+    );
+
+    let hex_list = |values: &[u16]| values.iter().map(|v| format!("{:04x}", v)).collect::<Vec<_>>().join(",");
+    let truncated_hash = |input: &str| {
+        if input.is_empty() {
+            return "000000000000".to_string();
+        }
+        let digest = Sha256::digest(input.as_bytes());
+        digest.iter().map(|b| format!("{:02x}", b)).collect::<String>()[..12].to_string()
+    };
+
+    let mut sorted_ciphers = ciphers.clone();
+    sorted_ciphers.sort_unstable();
+    let b = truncated_hash(&hex_list(&sorted_ciphers));
+
+    let mut sorted_extensions: Vec<u16> = extensions.into_iter().filter(|v| *v != EXTENSION_SERVER_NAME && *v != EXTENSION_ALPN).collect();
+    sorted_extensions.sort_unstable();
+    let mut c_input = hex_list(&sorted_extensions);
+    if !signature_algorithms.is_empty() {
+        c_input = format!("{}_{}", c_input, hex_list(signature_algorithms));
+    }
+    let c = truncated_hash(&c_input);
+
+    format!("{}_{}_{}", a, b, c)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const GREASE: u16 = 0x3a3a;
+
+    fn u16_list(values: &[u16]) -> Vec<u8> {
+        values.iter().flat_map(|v| v.to_be_bytes()).collect()
+    }
+
+    fn prefixed(data: Vec<u8>) -> Vec<u8> {
+        let mut prefixed = (data.len() as u16).to_be_bytes().to_vec();
+        prefixed.extend(data);
+        prefixed
+    }
+
+    /// ClientHello body with a 32 byte session id and no compression
+    fn client_hello(version: u16, ciphers: &[u16], extensions: &[(u16, Vec<u8>)]) -> Vec<u8> {
+        let mut hello = version.to_be_bytes().to_vec();
+        hello.extend([0x11; 32]);
+        hello.push(32);
+        hello.extend([0x22; 32]);
+        hello.extend(prefixed(u16_list(ciphers)));
+        hello.extend([1, 0]);
+        let block = extensions.iter().flat_map(|(kind, body)| {
+            let mut extension = kind.to_be_bytes().to_vec();
+            extension.extend(prefixed(body.clone()));
+            extension
+        }).collect();
+        hello.extend(prefixed(block));
+        hello
+    }
+
+    fn server_name(name: &str) -> Vec<u8> {
+        let mut entry = vec![0];
+        entry.extend(prefixed(name.as_bytes().to_vec()));
+        prefixed(entry)
+    }
+
+    /// Chrome's hello from the JA4 specification, GREASE included
+    fn chrome_hello() -> Vec<u8> {
+        let ciphers = [GREASE, 0x1301, 0x1302, 0x1303, 0xc02b, 0xc02f, 0xc02c, 0xc030, 0xcca9, 0xcca8, 0xc013, 0xc014, 0x009c, 0x009d, 0x002f, 0x0035];
+        let mut alpn = vec![2];
+        alpn.extend(b"h2");
+        alpn.push(8);
+        alpn.extend(b"http/1.1");
+        let extensions = [
+            (GREASE, Vec::new()),
+            (EXTENSION_SERVER_NAME, server_name("example.com")),
+            (0x0017, Vec::new()),
+            (0xff01, vec![0]),
+            (EXTENSION_SUPPORTED_GROUPS, prefixed(u16_list(&[GREASE, 0x001d, 0x0017, 0x0018]))),
+            (EXTENSION_EC_POINT_FORMATS, vec![1, 0]),
+            (0x0023, Vec::new()),
+            (EXTENSION_ALPN, prefixed(alpn)),
+            (0x0005, vec![1, 0, 0, 0, 0]),
+            (EXTENSION_SIGNATURE_ALGORITHMS, prefixed(u16_list(&[0x0403, 0x0804, 0x0401, 0x0503, 0x0805, 0x0501, 0x0806, 0x0601]))),
+            (0x0012, Vec::new()),
+            (0x0033, prefixed(Vec::new())),
+            (0x002d, vec![1, 1]),
+            (EXTENSION_SUPPORTED_VERSIONS, [vec![6], u16_list(&[GREASE, 0x0304, 0x0303])].concat()),
+            (0x001b, vec![2, 0, 2]),
+            (0x4469, Vec::new()),
+            (0x0015, vec![0; 8]),
+            (0x1a1a, vec![0]),
+        ];
+        client_hello(0x0303, &ciphers, &extensions)
+    }
+
+    #[test]
+    fn ja3_of_the_reference_hello() {
+        // Example from the JA3 README
+        let ciphers = [47, 53, 5, 10, 49161, 49162, 49171, 49172, 50, 56, 19, 4];
+        let extensions = [
+            (EXTENSION_SERVER_NAME, server_name("example.com")),
+            (EXTENSION_SUPPORTED_GROUPS, prefixed(u16_list(&[23, 24, 25]))),
+            (EXTENSION_EC_POINT_FORMATS, vec![1, 0]),
+        ];
+        let Some(HoneypotEvent::TlsClientHello { ja3, ja3_hash, version, sni, .. }) = parse_client_hello(&client_hello(0x0301, &ciphers, &extensions)) else {
+            panic!("hello not parsed");
+        };
+        assert_eq!(ja3, "769,47-53-5-10-49161-49162-49171-49172-50-56-19-4,0-10-11,23-24-25,0");
+        assert_eq!(ja3_hash, "ada70206e40642a3e4461f35503241d5");
+        assert_eq!(version, "TLSv1.0");
+        assert_eq!(sni.as_deref(), Some("example.com"));
+    }
+
+    #[test]
+    fn ja4_of_the_reference_hello() {
+        let Some(HoneypotEvent::TlsClientHello { ja3, ja4, version, alpn, cipher_suites, extensions, .. }) = parse_client_hello(&chrome_hello()) else {
+            panic!("hello not parsed");
+        };
+        assert_eq!(ja4, "t13d1516h2_8daaf6152771_e5627efa2ab1");
+        assert_eq!(version, "TLSv1.3");
+        assert_eq!(alpn, ["h2", "http/1.1"]);
+        assert_eq!((cipher_suites.len(), extensions.len()), (15, 16));
+        // GREASE values are left out of JA3 too
+        assert!(ja3.starts_with("771,4865-4866-4867-49195-"));
+        assert!(ja3.ends_with(",29-23-24,0"));
+    }
+
+    #[test]
+    fn ja4_alpn_and_version_edge_cases() {
+        assert_eq!(ja4(0x0303, false, &[0x002f], &[], None, &[]), "t12i010000_ba72b8082249_000000000000");
+        assert!(ja4(0x0304, true, &[], &[], Some("x"), &[]).starts_with("t13d0000xx_"));
+        // An ALPN value not starting and ending with alphanumerics is written as two hex nibbles
+        assert!(ja4(0x0200, true, &[], &[], Some("\u{0}h2\u{ff}"), &[]).starts_with("t00d00000f_"));
+        assert!(is_grease(0x0a0a) && is_grease(0xfafa) && !is_grease(0x0a1a) && !is_grease(0x1301));
+    }
+
+    #[test]
+    fn truncated_hellos_do_not_panic() {
+        let hello = chrome_hello();
+        for cut in 0..hello.len() {
+            let _ = parse_client_hello(&hello[..cut]);
+        }
+        // Fixed fields cut short never parse
+        assert!(parse_client_hello(&hello[..34]).is_none());
+        assert!(parse_client_hello(&hello[..100]).is_none());
+
+        // An extension longer than the block that holds it
+        let mut broken = client_hello(0x0303, &[0x1301], &[(EXTENSION_ALPN, vec![0, 3, 2, b'h'])]);
+        let length_at = broken.len() - 6;
+        broken[length_at..length_at + 2].copy_from_slice(&0xfff0u16.to_be_bytes());
+        assert!(parse_client_hello(&broken).is_none());
+    }
+}
@@ -0,0 +1,198 @@
+use common::types::HoneypotEvent;
+
+use super::UdpEmulator;
+use crate::honeypot::{persona::DnsPersonality, services::session::EventSink};
+
+const HEADER_LEN: usize = 12;
+const TYPE_TXT: u16 = 16;
+const CLASS_CHAOS: u16 = 3;
+const RCODE_REFUSED: u16 = 5;
+const RCODE_FORMERR: u16 = 1;
+const FLAG_QR: u16 = 0x8000;
+const FLAG_AA: u16 = 0x0400;
+const FLAG_RD: u16 = 0x0100;
+
+
+
+struct Question<'a> {
+    name: String,
+    qtype: u16,
+    qclass: u16,
+    // Raw question section, echoed back in the reply
+    raw: &'a [u8],
+}
+
+/// Authoritative-only name server: answers the CHAOS `version.bind`/`hostname.bind` probes
+/// used by fingerprinting tools and refuses recursion, so it can't be used as an open resolver
+pub struct DnsEmulator {
+    version: Option<String>,
+    hostname: String,
+}
+
+impl DnsEmulator {
+    pub fn new(persona: &DnsPersonality, hostname: &str) -> Self {
+        DnsEmulator {
+            version: persona.version.clone(),
+            hostname: hostname.to_string(),
+        }
+    }
+
+    fn chaos_txt(&self, name: &str) -> Option<&str> {
+        match name {
+            "version.bind" | "version.server" => self.version.as_deref(),
+            "hostname.bind" | "id.server" => Some(&self.hostname),
+            _ => None,
+        }
+    }
+}
+
+impl UdpEmulator for DnsEmulator {
+    fn name(&self) -> &'static str {
+        "dns"
+    }
+
+    fn on_datagram(&self, events: &EventSink, data: &[u8]) -> Option<Vec<u8>> {
+        if data.len() < HEADER_LEN {
+            return None;
+        }
+        let id = &data[..2];
+        let flags = u16::from_be_bytes([data[2], data[3]]);
+        // Never answer responses, it would let two servers bounce packets forever
+        if flags & FLAG_QR != 0 {
+            return None;
+        }
+        let qdcount = u16::from_be_bytes([data[4], data[5]]);
+
+        let Some(question) = (qdcount == 1).then(|| parse_question(&data[HEADER_LEN..])).flatten() else {
+            return Some(header(id, flags, RCODE_FORMERR, 0, 0));
+        };
+
+        events.record(HoneypotEvent::DnsQuery { name: question.name.clone(), qtype: question.qtype, qclass: question.qclass });
+
+        let answer = (question.qtype == TYPE_TXT && question.qclass == CLASS_CHAOS)
+            .then(|| self.chaos_txt(&question.name.to_ascii_lowercase()))
+            .flatten();
+
+        let mut reply = match answer {
+            Some(_) => header(id, flags | FLAG_AA, 0, 1, 1),
+            None => header(id, flags, RCODE_REFUSED, 1, 0),
+        };
+        reply.extend_from_slice(question.raw);
+
+        if let Some(text) = answer {
+            let text = &text.as_bytes()[..text.len().min(255)];
+            // Name is a pointer to the question at offset 12
+            reply.extend_from_slice(&[0xc0, 0x0c]);
+            reply.extend_from_slice(&TYPE_TXT.to_be_bytes());
+            reply.extend_from_slice(&CLASS_CHAOS.to_be_bytes());
+            reply.extend_from_slice(&0u32.to_be_bytes());
+            reply.extend_from_slice(&(text.len() as u16 + 1).to_be_bytes());
+            reply.push(text.len() as u8);
+            reply.extend_from_slice(text);
+        }
+        Some(reply)
+    }
+}
+
+
+fn header(id: &[u8], query_flags: u16, rcode: u16, qdcount: u16, ancount: u16) -> Vec<u8> {
+    // Opcode and RD are echoed from the query, recursion is never available
+    let flags = FLAG_QR | (query_flags & (0x7800 | FLAG_RD | FLAG_AA)) | rcode;
+    let mut header = id.to_vec();
+    header.extend_from_slice(&flags.to_be_bytes());
+    for count in [qdcount, ancount, 0, 0] {
+        header.extend_from_slice(&count.to_be_bytes());
+    }
+    header
+}
+
+fn parse_question(data: &[u8]) -> Option<Question<'_>> {
+    let mut labels = Vec::new();
+    let mut offset = 0;
+    loop {
+        let len = *data.get(offset)? as usize;
+        offset += 1;
+        if len == 0 {
+            break;
+        }
+        // Compression pointers have no business in a query's only question
+        if len > 63 {
+            return None;
+        }
+        labels.push(String::from_utf8_lossy(data.get(offset..offset + len)?).to_string());
+        offset += len;
+    }
+    let qtype = u16::from_be_bytes([*data.get(offset)?, *data.get(offset + 1)?]);
+    let qclass = u16::from_be_bytes([*data.get(offset + 2)?, *data.get(offset + 3)?]);
+
+    Some(Question { name: labels.join("."), qtype, qclass, raw: &data[..offset + 4] })
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::honeypot::services::session::test_support;
+
+    fn server() -> DnsEmulator {
+        DnsEmulator::new(&DnsPersonality { version: Some("9.16.1".to_string()) }, "ns1")
+    }
+
+    fn query(flags: u16, name: &str, qtype: u16, qclass: u16) -> Vec<u8> {
+        let mut data = vec![0xab, 0xcd];
+        data.extend_from_slice(&flags.to_be_bytes());
+        data.extend_from_slice(&[0, 1, 0, 0, 0, 0, 0, 0]);
+        for label in name.split('.') {
+            data.push(label.len() as u8);
+            data.extend_from_slice(label.as_bytes());
+        }
+        data.push(0);
+        data.extend_from_slice(&qtype.to_be_bytes());
+        data.extend_from_slice(&qclass.to_be_bytes());
+        data
+    }
+
+    fn rcode(reply: &[u8]) -> u16 {
+        u16::from_be_bytes([reply[2], reply[3]]) & 0x000f
+    }
+
+    #[tokio::test]
+    async fn answers_version_bind_and_refuses_recursion() {
+        let events = test_support::events("dns", "10.0.0.5:53", "10.0.0.7:40000");
+        let reply = server().on_datagram(&events, &query(0, "VERSION.bind", TYPE_TXT, CLASS_CHAOS)).unwrap();
+        assert_eq!((rcode(&reply), &reply[6..8]), (0, &[0, 1][..]));
+        assert!(reply.ends_with(b"\x069.16.1"));
+
+        let reply = server().on_datagram(&events, &query(FLAG_RD, "example.com", 1, 1)).unwrap();
+        assert_eq!(&reply[..2], [0xab, 0xcd]);
+        assert_eq!(rcode(&reply), RCODE_REFUSED);
+        assert_eq!(u16::from_be_bytes([reply[2], reply[3]]) & 0x0080, 0);
+    }
+
+    #[tokio::test]
+    async fn malformed_queries_get_formerr_or_nothing() {
+        let events = test_support::events("dns", "10.0.0.5:53", "10.0.0.7:40000");
+        let server = server();
+        let query = query(0, "hostname.bind", TYPE_TXT, CLASS_CHAOS);
+
+        for len in 0..HEADER_LEN {
+            assert!(server.on_datagram(&events, &query[..len]).is_none());
+        }
+        for len in HEADER_LEN..query.len() {
+            let reply = server.on_datagram(&events, &query[..len]).unwrap();
+            assert_eq!((rcode(&reply), reply.len()), (RCODE_FORMERR, HEADER_LEN), "truncated at {}", len);
+        }
+
+        // Responses are never answered
+        let mut response = query.clone();
+        response[2] |= 0x80;
+        assert!(server.on_datagram(&events, &response).is_none());
+        // Nor are several questions or a compression pointer parsed
+        let mut two = query.clone();
+        two[5] = 2;
+        assert_eq!(rcode(&server.on_datagram(&events, &two).unwrap()), RCODE_FORMERR);
+        let mut pointer = query[..HEADER_LEN].to_vec();
+        pointer.extend_from_slice(&[0xc0, 0x0c, 0, 16, 0, 3]);
+        assert_eq!(rcode(&server.on_datagram(&events, &pointer).unwrap()), RCODE_FORMERR);
+    }
+}
@@ -0,0 +1,93 @@
+use std::net::IpAddr;
+
+use common::types::HoneypotEvent;
+
+use super::{message::{parse_query, response, Record, CLASS_IN, FLAG_QR, TYPE_A}, UdpEmulator};
+use crate::honeypot::services::session::EventSink;
+
+pub const LLMNR_PORT: u16 = 5355;
+// Default TTL of Windows responders (RFC 4795 §2.8 suggests 30 s)
+const TTL: u32 = 30;
+
+
+/// LLMNR responder for the node's single-label name, as Windows and systemd-resolved answer
+/// on the link when DNS doesn't know a name (RFC 4795)
+pub struct LlmnrEmulator {
+    hostname: String,
+}
+
+impl LlmnrEmulator {
+    pub fn new(hostname: &str) -> Self {
+        LlmnrEmulator { hostname: hostname.split('.').next().unwrap_or(hostname).to_string() }
+    }
+}
+
+impl UdpEmulator for LlmnrEmulator {
+    fn name(&self) -> &'static str {
+        "llmnr"
+    }
+
+    fn on_datagram(&self, events: &EventSink, data: &[u8]) -> Option<Vec<u8>> {
+        let IpAddr::V4(ip) = events.honeypot_addr().ip() else {
+            return None;
+        };
+        let query = parse_query(data)?;
+        // Exactly one question, and only standard queries (RFC 4795 §2.1.1)
+        let [question] = query.questions.as_slice() else {
+            return None;
+        };
+        if query.flags & 0x7800 != 0 || !question.asks(&self.hostname, TYPE_A) {
+            return None;
+        }
+
+        events.record(HoneypotEvent::DnsQuery { name: question.name.clone(), qtype: question.qtype, qclass: question.qclass });
+        let answer = Record::a(&question.name, CLASS_IN, TTL, ip);
+        Some(response(query.id, FLAG_QR, &query.questions, &[answer], &[]))
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::net::Ipv4Addr;
+
+    use super::*;
+    use crate::honeypot::services::{session::test_support, udp::message::{parse_answers, query, HEADER_LEN, TYPE_ANY}};
+
+    fn events() -> EventSink {
+        test_support::events("llmnr", "10.0.0.5:5355", "10.0.0.7:51000")
+    }
+
+    #[tokio::test]
+    async fn answers_its_single_label_name() {
+        let llmnr = LlmnrEmulator::new("fileserver.corp.local");
+        let events = events();
+
+        let reply = llmnr.on_datagram(&events, &query(0x0101, 0, "FILESERVER", TYPE_A)).unwrap();
+        assert_eq!(&reply[..2], &[0x01, 0x01]);
+        let answers = parse_answers(&reply).unwrap();
+        assert_eq!((answers[0].name.as_str(), answers[0].address), ("FILESERVER", Some(Ipv4Addr::new(10, 0, 0, 5))));
+        assert!(llmnr.on_datagram(&events, &query(2, 0, "fileserver", TYPE_ANY)).is_some());
+
+        assert!(llmnr.on_datagram(&events, &query(3, 0, "wpad", TYPE_A)).is_none());
+        // Anything but a standard query (opcode 0) is left unanswered
+        assert!(llmnr.on_datagram(&events, &query(4, 0x2800, "fileserver", TYPE_A)).is_none());
+        assert!(llmnr.on_datagram(&events, &reply).is_none());
+    }
+
+    #[tokio::test]
+    async fn malformed_queries_are_ignored() {
+        let llmnr = LlmnrEmulator::new("fileserver");
+        let events = events();
+        let data = query(5, 0, "fileserver", TYPE_A);
+        for cut in 0..data.len() {
+            assert!(llmnr.on_datagram(&events, &data[..cut]).is_none());
+        }
+
+        // Two questions in one query
+        let mut twice = data.clone();
+        twice[5] = 2;
+        twice.extend_from_slice(&data[HEADER_LEN..]);
+        assert!(llmnr.on_datagram(&events, &twice).is_none());
+    }
+}
@@ -0,0 +1,201 @@
+use std::net::{IpAddr, Ipv4Addr};
+
+use common::types::HoneypotEvent;
+
+use super::{message::{parse_query, response, Question, Record, CLASS_IN, FLAG_AA, FLAG_QR, TYPE_A, TYPE_PTR, TYPE_SRV, TYPE_TXT}, UdpEmulator};
+use crate::honeypot::{fleet::AdvertisedService, services::session::EventSink};
+
+pub const MDNS_PORT: u16 = 5353;
+const SERVICES_BROWSE: &str = "_services._dns-sd._udp.local";
+// Set on records no other host may hold, so caches replace them (RFC 6762 §10.2)
+const CACHE_FLUSH: u16 = 0x8000;
+// RFC 6762 §10 recommendations
+const HOST_TTL: u32 = 120;
+const SERVICE_TTL: u32 = 4500;
+// Replies to one-shot resolvers that didn't send from port 5353 (RFC 6762 §6.7)
+const LEGACY_TTL: u32 = 10;
+
+
+/// Multicast DNS responder for the node's `.local` name, its reverse address and the DNS-SD
+/// services it advertises, answering browsers the way Avahi or Bonjour would
+pub struct MdnsEmulator {
+    hostname: String,
+    services: Vec<AdvertisedService>,
+}
+
+impl MdnsEmulator {
+    pub fn new(hostname: &str, services: Vec<AdvertisedService>) -> Self {
+        MdnsEmulator { hostname: hostname.split('.').next().unwrap_or(hostname).to_string(), services }
+    }
+
+    fn host(&self) -> String {
+        format!("{}.local", self.hostname)
+    }
+
+    fn instance(&self, service: &AdvertisedService) -> String {
+        format!("{}.{}.local", service.instance.as_deref().unwrap_or(&self.hostname), service.service)
+    }
+
+    fn service_records(&self, service: &AdvertisedService) -> [Record; 2] {
+        let instance = self.instance(service);
+        [
+            Record::srv(&instance, CLASS_IN | CACHE_FLUSH, HOST_TTL, service.port, &self.host()),
+            Record::txt(&instance, CLASS_IN | CACHE_FLUSH, SERVICE_TTL, &service.txt),
+        ]
+    }
+
+    /// Records answering `question`, and the ones a resolver would ask for next
+    fn answer(&self, question: &Question, ip: Ipv4Addr) -> (Vec<Record>, Vec<Record>) {
+        let (mut answers, mut additional) = (Vec::new(), Vec::new());
+        let host = self.host();
+        let address = Record::a(&host, CLASS_IN | CACHE_FLUSH, HOST_TTL, ip);
+
+        if question.asks(&host, TYPE_A) {
+            answers.push(address.clone());
+        }
+        if question.asks(&reverse_name(ip), TYPE_PTR) {
+            answers.push(Record::ptr(&reverse_name(ip), HOST_TTL, &host));
+        }
+        if question.asks(SERVICES_BROWSE, TYPE_PTR) {
+            for service in &self.services {
+                let record = Record::ptr(SERVICES_BROWSE, SERVICE_TTL, &format!("{}.local", service.service));
+                if !answers.contains(&record) {
+                    answers.push(record);
+                }
+            }
+        }
+        for service in &self.services {
+            let instance = self.instance(service);
+            let [srv, txt] = self.service_records(service);
+            if question.asks(&format!("{}.local", service.service), TYPE_PTR) {
+                answers.push(Record::ptr(&format!("{}.local", service.service), SERVICE_TTL, &instance));
+                additional.extend([srv, txt, address.clone()]);
+                continue;
+            }
+            if question.asks(&instance, TYPE_SRV) {
+                answers.push(srv);
+                additional.push(address.clone());
+            }
+            if question.asks(&instance, TYPE_TXT) {
+                answers.push(txt);
+            }
+        }
+        (answers, additional)
+    }
+
+    /// Every record of the node, multicast unsolicited when it joins the network (RFC 6762 §8.3)
+    pub fn announcement(&self, ip: Ipv4Addr) -> Vec<u8> {
+        let host = self.host();
+        let mut records = vec![Record::a(&host, CLASS_IN | CACHE_FLUSH, HOST_TTL, ip), Record::ptr(&reverse_name(ip), HOST_TTL, &host)];
+        for service in &self.services {
+            let service_type = format!("{}.local", service.service);
+            records.push(Record::ptr(&service_type, SERVICE_TTL, &self.instance(service)));
+            records.extend(self.service_records(service));
+            records.push(Record::ptr(SERVICES_BROWSE, SERVICE_TTL, &service_type));
+        }
+        response(0, FLAG_QR | FLAG_AA, &[], &records, &[])
+    }
+}
+
+impl UdpEmulator for MdnsEmulator {
+    fn name(&self) -> &'static str {
+        "mdns"
+    }
+
+    fn on_datagram(&self, events: &EventSink, data: &[u8]) -> Option<Vec<u8>> {
+        let IpAddr::V4(ip) = events.honeypot_addr().ip() else {
+            return None;
+        };
+        let query = parse_query(data)?;
+
+        let (mut answers, mut additional) = (Vec::new(), Vec::new());
+        for question in &query.questions {
+            let (found, extra) = self.answer(question, ip);
+            if !found.is_empty() {
+                events.record(HoneypotEvent::DnsQuery { name: question.name.clone(), qtype: question.qtype, qclass: question.qclass });
+            }
+            answers.extend(found);
+            for record in extra {
+                if !answers.contains(&record) && !additional.contains(&record) {
+                    additional.push(record);
+                }
+            }
+        }
+        if answers.is_empty() {
+            return None;
+        }
+
+        // Resolvers that aren't mDNS-aware need their question and ID back
+        if events.attacker_addr().port() != MDNS_PORT {
+            let cap = |records: Vec<Record>| records.into_iter().map(|record| record.for_legacy(LEGACY_TTL)).collect::<Vec<_>>();
+            return Some(response(query.id, FLAG_QR | FLAG_AA, &query.questions, &cap(answers), &cap(additional)));
+        }
+        Some(response(0, FLAG_QR | FLAG_AA, &[], &answers, &additional))
+    }
+}
+
+/// `in-addr.arpa` name of an address, for reverse lookups
+fn reverse_name(ip: Ipv4Addr) -> String {
+    let o = ip.octets();
+    format!("{}.{}.{}.{}.in-addr.arpa", o[3], o[2], o[1], o[0])
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::honeypot::services::{session::test_support, udp::message::{parse_answers, query}};
+
+    fn events(attacker: &str) -> EventSink {
+        test_support::events("mdns", "10.0.0.5:5353", attacker)
+    }
+
+    fn printer() -> MdnsEmulator {
+        let ipp = AdvertisedService { service: "_ipp._tcp".to_string(), port: 631, instance: Some("Office Printer".to_string()), txt: vec!["rp=ipp/print".to_string()] };
+        MdnsEmulator::new("printer.corp", vec![ipp])
+    }
+
+    #[tokio::test]
+    async fn answers_host_reverse_and_service_queries() {
+        let mdns = printer();
+        let events = events("10.0.0.7:5353");
+
+        let reply = mdns.on_datagram(&events, &query(0, 0, "printer.local", TYPE_A)).unwrap();
+        let answers = parse_answers(&reply).unwrap();
+        assert_eq!(answers[0].address, Some(Ipv4Addr::new(10, 0, 0, 5)));
+
+        let reply = mdns.on_datagram(&events, &query(0, 0, "5.0.0.10.in-addr.arpa", TYPE_PTR)).unwrap();
+        assert_eq!(parse_answers(&reply).unwrap()[0].rtype, TYPE_PTR);
+
+        // Browsing a service type also hands out its SRV, TXT and address
+        let reply = mdns.on_datagram(&events, &query(0, 0, "_ipp._tcp.local", TYPE_PTR)).unwrap();
+        let types: Vec<u16> = parse_answers(&reply).unwrap().iter().map(|answer| answer.rtype).collect();
+        assert_eq!(types, [TYPE_PTR, TYPE_SRV, TYPE_TXT, TYPE_A]);
+
+        assert!(mdns.on_datagram(&events, &query(0, 0, "other.local", TYPE_A)).is_none());
+        assert!(mdns.on_datagram(&events, &reply).is_none());
+    }
+
+    #[tokio::test]
+    async fn legacy_resolvers_get_their_id_and_question_back() {
+        let mdns = printer();
+        let reply = mdns.on_datagram(&events("10.0.0.7:40000"), &query(0xbeef, 0, "printer.local", TYPE_A)).unwrap();
+        assert_eq!(&reply[..2], &[0xbe, 0xef]);
+        // One echoed question, and the answer with a capped TTL and no cache-flush bit
+        assert_eq!(&reply[4..6], &[0, 1]);
+        assert_eq!(&reply[reply.len() - 14..reply.len() - 6], &[0, 1, 0, 1, 0, 0, 0, LEGACY_TTL as u8]);
+
+        let announcement = parse_answers(&mdns.announcement(Ipv4Addr::new(10, 0, 0, 5))).unwrap();
+        assert_eq!(announcement.len(), 6);
+    }
+
+    #[tokio::test]
+    async fn truncated_queries_are_ignored() {
+        let mdns = printer();
+        let events = events("10.0.0.7:5353");
+        let data = query(0, 0, "printer.local", TYPE_A);
+        for cut in 0..data.len() {
+            assert!(mdns.on_datagram(&events, &data[..cut]).is_none());
+        }
+    }
+}
@@ -0,0 +1,298 @@
+use std::net::Ipv4Addr;
+
+pub const HEADER_LEN: usize = 12;
+pub const TYPE_A: u16 = 1;
+pub const TYPE_PTR: u16 = 12;
+pub const TYPE_TXT: u16 = 16;
+pub const TYPE_SRV: u16 = 33;
+pub const TYPE_ANY: u16 = 255;
+pub const CLASS_IN: u16 = 1;
+pub const FLAG_QR: u16 = 0x8000;
+pub const FLAG_AA: u16 = 0x0400;
+// Compression pointers followed in a single name, more is a loop
+const MAX_POINTERS: usize = 16;
+
+
+/// DNS-format message as used by mDNS and LLMNR: only what the name service emulators read
+pub struct Query {
+    pub id: u16,
+    pub flags: u16,
+    pub questions: Vec<Question>,
+}
+
+/// Resource record of a response as the poisoning detector reads it
+pub struct Answer {
+    pub name: String,
+    pub rtype: u16,
+    /// Address of an `A` record
+    pub address: Option<Ipv4Addr>,
+}
+
+pub struct Question {
+    pub name: String,
+    pub qtype: u16,
+    /// Class without the mDNS unicast-response bit
+    pub qclass: u16,
+    /// mDNS `QU` bit: the querier asks for a unicast reply
+    pub unicast: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Record {
+    name: String,
+    rtype: u16,
+    class: u16,
+    ttl: u32,
+    data: Vec<u8>,
+}
+
+impl Question {
+    pub fn asks(&self, name: &str, rtype: u16) -> bool {
+        (self.qtype == rtype || self.qtype == TYPE_ANY) && self.qclass == CLASS_IN && self.name.eq_ignore_ascii_case(name)
+    }
+}
+
+impl Record {
+    /// `class` may carry the mDNS cache-flush bit
+    pub fn a(name: &str, class: u16, ttl: u32, ip: Ipv4Addr) -> Self {
+        Record { name: name.to_string(), rtype: TYPE_A, class, ttl, data: ip.octets().to_vec() }
+    }
+
+    pub fn ptr(name: &str, ttl: u32, target: &str) -> Self {
+        Record { name: name.to_string(), rtype: TYPE_PTR, class: CLASS_IN, ttl, data: encode_name(target) }
+    }
+
+    pub fn srv(name: &str, class: u16, ttl: u32, port: u16, target: &str) -> Self {
+        // Priority and weight 0
+        let mut data = vec![0, 0, 0, 0];
+        data.extend_from_slice(&port.to_be_bytes());
+        data.extend_from_slice(&encode_name(target));
+        Record { name: name.to_string(), rtype: TYPE_SRV, class, ttl, data }
+    }
+
+    pub fn txt(name: &str, class: u16, ttl: u32, entries: &[String]) -> Self {
+        let mut data = Vec::new();
+        for entry in entries {
+            let entry = &entry.as_bytes()[..entry.len().min(255)];
+            data.push(entry.len() as u8);
+            data.extend_from_slice(entry);
+        }
+        // An empty TXT record still holds one empty string (RFC 6763 §6.1)
+        if data.is_empty() {
+            data.push(0);
+        }
+        Record { name: name.to_string(), rtype: TYPE_TXT, class, ttl, data }
+    }
+
+    /// Same record as a legacy unicast mDNS reply carries it: TTL capped, no cache-flush bit (RFC 6762 §6.7)
+    pub fn for_legacy(mut self, ttl: u32) -> Self {
+        self.ttl = self.ttl.min(ttl);
+        self.class &= 0x7fff;
+        self
+    }
+
+    fn encode(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(&encode_name(&self.name));
+        out.extend_from_slice(&self.rtype.to_be_bytes());
+        out.extend_from_slice(&self.class.to_be_bytes());
+        out.extend_from_slice(&self.ttl.to_be_bytes());
+        out.extend_from_slice(&(self.data.len() as u16).to_be_bytes());
+        out.extend_from_slice(&self.data);
+    }
+}
+
+
+/// Queries only: responses are never parsed, answering them could make two hosts loop
+pub fn parse_query(data: &[u8]) -> Option<Query> {
+    if data.len() < HEADER_LEN {
+        return None;
+    }
+    let id = u16::from_be_bytes([data[0], data[1]]);
+    let flags = u16::from_be_bytes([data[2], data[3]]);
+    if flags & FLAG_QR != 0 {
+        return None;
+    }
+    let qdcount = u16::from_be_bytes([data[4], data[5]]);
+
+    let mut offset = HEADER_LEN;
+    let mut questions = Vec::new();
+    for _ in 0..qdcount {
+        let (name, end) = parse_name(data, offset)?;
+        let qtype = u16::from_be_bytes([*data.get(end)?, *data.get(end + 1)?]);
+        let qclass = u16::from_be_bytes([*data.get(end + 2)?, *data.get(end + 3)?]);
+        questions.push(Question { name, qtype, qclass: qclass & 0x7fff, unicast: qclass & 0x8000 != 0 });
+        offset = end + 4;
+    }
+    Some(Query { id, flags, questions })
+}
+
+/// Records of a response, every section included: what someone claimed about a name
+pub fn parse_answers(data: &[u8]) -> Option<Vec<Answer>> {
+    if data.len() < HEADER_LEN {
+        return None;
+    }
+    let flags = u16::from_be_bytes([data[2], data[3]]);
+    if flags & FLAG_QR == 0 {
+        return None;
+    }
+    let count = |at: usize| u16::from_be_bytes([data[at], data[at + 1]]) as usize;
+    let (qdcount, rrcount) = (count(4), count(6) + count(8) + count(10));
+
+    let mut offset = HEADER_LEN;
+    for _ in 0..qdcount {
+        offset = parse_name(data, offset)?.1 + 4;
+    }
+    let mut answers = Vec::new();
+    for _ in 0..rrcount {
+        let (name, end) = parse_name(data, offset)?;
+        let field = |at: usize| Some(u16::from_be_bytes([*data.get(end + at)?, *data.get(end + at + 1)?]));
+        let (rtype, length) = (field(0)?, field(8)? as usize);
+        let rdata = data.get(end + 10..end + 10 + length)?;
+        let address = match (rtype, rdata) {
+            (TYPE_A, &[a, b, c, d]) => Some(Ipv4Addr::new(a, b, c, d)),
+            _ => None,
+        };
+        answers.push(Answer { name, rtype, address });
+        offset = end + 10 + length;
+    }
+    Some(answers)
+}
+
+/// Name starting at `offset` and the offset right after it, following compression pointers
+fn parse_name(data: &[u8], mut offset: usize) -> Option<(String, usize)> {
+    let mut labels = Vec::new();
+    let mut end = None;
+    let mut pointers = 0;
+    loop {
+        let len = *data.get(offset)? as usize;
+        match len {
+            0 => break,
+            0xc0.. => {
+                pointers += 1;
+                if pointers > MAX_POINTERS {
+                    return None;
+                }
+                end.get_or_insert(offset + 2);
+                offset = ((len & 0x3f) << 8) | *data.get(offset + 1)? as usize;
+            }
+            64.. => return None,
+            _ => {
+                labels.push(String::from_utf8_lossy(data.get(offset + 1..offset + 1 + len)?).to_string());
+                offset += 1 + len;
+            }
+        }
+    }
+    Some((labels.join("."), end.unwrap_or(offset + 1)))
+}
+
+pub fn encode_name(name: &str) -> Vec<u8> {
+    let mut encoded = Vec::new();
+    for label in name.split('.').filter(|label| !label.is_empty()) {
+        let label = &label.as_bytes()[..label.len().min(63)];
+        encoded.push(label.len() as u8);
+        encoded.extend_from_slice(label);
+    }
+    encoded.push(0);
+    encoded
+}
+
+/// Query with a single question, as a resolver sends it
+pub fn query(id: u16, flags: u16, name: &str, qtype: u16) -> Vec<u8> {
+    let mut message = id.to_be_bytes().to_vec();
+    message.extend_from_slice(&flags.to_be_bytes());
+    message.extend_from_slice(&[0, 1, 0, 0, 0, 0, 0, 0]);
+    message.extend_from_slice(&encode_name(name));
+    message.extend_from_slice(&qtype.to_be_bytes());
+    message.extend_from_slice(&CLASS_IN.to_be_bytes());
+    message
+}
+
+/// Response with the given sections; `echoed` questions are copied back as the query had them
+pub fn response(id: u16, flags: u16, echoed: &[Question], answers: &[Record], additional: &[Record]) -> Vec<u8> {
+    let mut message = id.to_be_bytes().to_vec();
+    message.extend_from_slice(&flags.to_be_bytes());
+    for count in [echoed.len(), answers.len(), 0, additional.len()] {
+        message.extend_from_slice(&(count as u16).to_be_bytes());
+    }
+    for question in echoed {
+        message.extend_from_slice(&encode_name(&question.name));
+        message.extend_from_slice(&question.qtype.to_be_bytes());
+        message.extend_from_slice(&question.qclass.to_be_bytes());
+    }
+    for record in answers.iter().chain(additional) {
+        record.encode(&mut message);
+    }
+    message
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn queries_round_trip() {
+        let mut data = query(0x1234, 0, "_ipp._tcp.local", TYPE_PTR);
+        // mDNS QU bit on the class
+        let class_at = data.len() - 2;
+        data[class_at] |= 0x80;
+
+        let query = parse_query(&data).unwrap();
+        assert_eq!((query.id, query.flags), (0x1234, 0));
+        let [question] = query.questions.as_slice() else {
+            panic!("expected one question");
+        };
+        assert_eq!((question.name.as_str(), question.qtype, question.qclass, question.unicast), ("_ipp._tcp.local", TYPE_PTR, CLASS_IN, true));
+        assert!(question.asks("_IPP._tcp.local", TYPE_PTR));
+        assert!(!question.asks("_ipp._tcp.local", TYPE_A));
+
+        assert_eq!(encode_name("printer.local."), b"\x07printer\x05local\x00");
+    }
+
+    #[test]
+    fn answers_follow_compression_pointers() {
+        let answer = Record::a("printer.local", CLASS_IN, 120, Ipv4Addr::new(10, 0, 0, 9));
+        let mut data = response(0, FLAG_QR | FLAG_AA, &[], &[answer], &[]);
+        // A second A record whose name points back at the first one (RFC 1035 §4.1.4)
+        data[7] = 2;
+        data.extend_from_slice(&[0xc0, 12, 0, 1, 0, 1, 0, 0, 0, 120, 0, 4, 10, 0, 0, 66]);
+
+        let answers = parse_answers(&data).unwrap();
+        assert_eq!(answers.len(), 2);
+        assert_eq!((answers[1].name.as_str(), answers[1].rtype, answers[1].address), ("printer.local", TYPE_A, Some(Ipv4Addr::new(10, 0, 0, 66))));
+
+        // Queries and responses are never mistaken for each other
+        assert!(parse_query(&data).is_none());
+        assert!(parse_answers(&query(1, 0, "printer.local", TYPE_A)).is_none());
+    }
+
+    #[test]
+    fn malformed_messages_do_not_panic() {
+        let data = query(7, 0, "workstation.local", TYPE_A);
+        for cut in 0..data.len() {
+            assert!(parse_query(&data[..cut]).is_none(), "cut at {}", cut);
+        }
+
+        // A name pointing at itself, a pointer cut short and a label longer than 63 bytes
+        let mut looping = data[..HEADER_LEN].to_vec();
+        looping.extend_from_slice(&[0xc0, 12, 0, 1, 0, 1]);
+        assert!(parse_query(&looping).is_none());
+        looping.truncate(HEADER_LEN + 1);
+        assert!(parse_query(&looping).is_none());
+        let mut long_label = data[..HEADER_LEN].to_vec();
+        long_label.push(64);
+        long_label.extend_from_slice(&[b'a'; 64]);
+        long_label.extend_from_slice(&[0, 0, 1, 0, 1]);
+        assert!(parse_query(&long_label).is_none());
+
+        // More questions announced than present, and rdata running past the end
+        let answer = Record::a("x.local", CLASS_IN, 120, Ipv4Addr::new(10, 0, 0, 9));
+        let mut data = response(0, FLAG_QR, &[], &[answer], &[]);
+        data[5] = 9;
+        assert!(parse_answers(&data).is_none());
+        data[5] = 0;
+        let length_at = data.len() - 6;
+        data[length_at] = 0xff;
+        assert!(parse_answers(&data).is_none());
+    }
+}
@@ -0,0 +1,92 @@
+pub mod dns;
+pub mod llmnr;
+pub mod mdns;
+pub mod message;
+pub mod nbns;
+pub mod ntp;
+pub mod snmp;
+pub mod ssdp;
+
+use std::{collections::HashMap, fmt, sync::Arc};
+
+use pnet::util::MacAddr;
+
+use dns::DnsEmulator;
+use llmnr::{LlmnrEmulator, LLMNR_PORT};
+use mdns::{MdnsEmulator, MDNS_PORT};
+use nbns::{NbnsEmulator, NBNS_PORT};
+use ntp::NtpEmulator;
+use snmp::SnmpEmulator;
+use ssdp::{SsdpEmulator, SSDP_PORT};
+use super::session::EventSink;
+use crate::honeypot::{fleet::AdvertisedService, persona::{NameService, Personality}};
+
+/// Ports a `UdpRegistry` can answer on; the name services and SSDP depend on the OS
+pub const UDP_PORTS: [u16; 7] = [53, 123, 137, 161, 1900, 5353, 5355];
+
+
+/// A decoy datagram protocol served on a virtual node's UDP port.
+/// Datagrams are independent, so one shared instance answers every request.
+pub trait UdpEmulator: Send + Sync {
+    fn name(&self) -> &'static str;
+
+    /// Reply to one datagram, `None` to stay silent as a real daemon would
+    fn on_datagram(&self, events: &EventSink, data: &[u8]) -> Option<Vec<u8>>;
+}
+
+
+/// UDP ports a virtual node answers on; every other port is closed
+#[derive(Clone, Default)]
+pub struct UdpRegistry {
+    services: HashMap<u16, Arc<dyn UdpEmulator>>,
+}
+
+impl UdpRegistry {
+    pub fn with_default_services(mac: MacAddr, os: &Personality, hostname: &str) -> Self {
+        let mut registry = UdpRegistry::default();
+        registry.register(53, DnsEmulator::new(&os.dns, hostname));
+        registry.register(123, NtpEmulator);
+        registry.register(161, SnmpEmulator::new(mac, &os.snmp, hostname));
+        // Only UPnP devices (routers, media hosts) answer SSDP; a server that does stands out
+        if let Some(upnp) = &os.upnp {
+            registry.register(SSDP_PORT, SsdpEmulator::new(mac, upnp));
+        }
+        registry
+    }
+
+    /// Responders for the local name services the node's OS runs
+    pub fn register_name_services(&mut self, protocols: &[NameService], hostname: &str, advertised: &[AdvertisedService]) {
+        for protocol in protocols {
+            match protocol {
+                NameService::Mdns => self.register(MDNS_PORT, MdnsEmulator::new(hostname, advertised.to_vec())),
+                NameService::Llmnr => self.register(LLMNR_PORT, LlmnrEmulator::new(hostname)),
+                NameService::Nbns => self.register(NBNS_PORT, NbnsEmulator::new(hostname)),
+            }
+        }
+    }
+
+    pub fn register<E: UdpEmulator + 'static>(&mut self, port: u16, emulator: E) {
+        self.services.insert(port, Arc::new(emulator));
+    }
+
+    /// Closes every port not in `ports`
+    pub fn retain(&mut self, ports: &[u16]) {
+        self.services.retain(|port, _| ports.contains(port));
+    }
+
+    pub fn get(&self, port: u16) -> Option<Arc<dyn UdpEmulator>> {
+        self.services.get(&port).cloned()
+    }
+
+    pub fn ports(&self) -> Vec<u16> {
+        let mut ports: Vec<u16> = self.services.keys().copied().collect();
+        ports.sort_unstable();
+        ports
+    }
+}
+
+impl fmt::Debug for UdpRegistry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("UdpRegistry").field("ports", &self.ports()).finish()
+    }
+}
@@ -0,0 +1,198 @@
+use std::net::{IpAddr, Ipv4Addr};
+
+use common::types::HoneypotEvent;
+
+use super::UdpEmulator;
+use crate::honeypot::services::session::EventSink;
+
+pub const NBNS_PORT: u16 = 137;
+const HEADER_LEN: usize = 12;
+// Length byte, 32 bytes of half-ASCII encoding, empty scope
+const ENCODED_NAME_LEN: usize = 34;
+const TYPE_NB: u16 = 0x0020;
+const CLASS_IN: u16 = 0x0001;
+const FLAG_RESPONSE: u16 = 0x8000;
+const OPCODE_MASK: u16 = 0x7800;
+// Recursion desired, broadcast
+const QUERY_FLAGS: u16 = 0x0110;
+// Response, authoritative answer, recursion desired
+const ANSWER_FLAGS: u16 = 0x8500;
+// What Windows hands out, in seconds
+const TTL: u32 = 300_000;
+// Workstation and file server services, registered by every Windows host
+const SUFFIXES: [u8; 2] = [0x00, 0x20];
+const NETBIOS_NAME_LEN: usize = 15;
+
+
+/// NetBIOS name service responder for the node's computer name, answering the broadcast
+/// name queries Windows falls back to (RFC 1002 §4.2.13)
+pub struct NbnsEmulator {
+    name: String,
+}
+
+impl NbnsEmulator {
+    pub fn new(hostname: &str) -> Self {
+        let name = hostname.split('.').next().unwrap_or(hostname).to_ascii_uppercase();
+        NbnsEmulator { name: name.chars().take(NETBIOS_NAME_LEN).collect() }
+    }
+}
+
+impl UdpEmulator for NbnsEmulator {
+    fn name(&self) -> &'static str {
+        "nbns"
+    }
+
+    fn on_datagram(&self, events: &EventSink, data: &[u8]) -> Option<Vec<u8>> {
+        let IpAddr::V4(ip) = events.honeypot_addr().ip() else {
+            return None;
+        };
+        let question_end = HEADER_LEN + ENCODED_NAME_LEN + 4;
+        if data.len() < question_end {
+            return None;
+        }
+        let flags = u16::from_be_bytes([data[2], data[3]]);
+        let qdcount = u16::from_be_bytes([data[4], data[5]]);
+        if flags & (FLAG_RESPONSE | OPCODE_MASK) != 0 || qdcount != 1 {
+            return None;
+        }
+        let encoded = &data[HEADER_LEN..HEADER_LEN + ENCODED_NAME_LEN];
+        let qtype = u16::from_be_bytes([data[question_end - 4], data[question_end - 3]]);
+        let qclass = u16::from_be_bytes([data[question_end - 2], data[question_end - 1]]);
+        let (name, suffix) = decode_name(encoded)?;
+        if qtype != TYPE_NB || qclass != CLASS_IN || !name.eq_ignore_ascii_case(&self.name) || !SUFFIXES.contains(&suffix) {
+            return None;
+        }
+
+        events.record(HoneypotEvent::DnsQuery { name: format!("{}<{:02X}>", name, suffix), qtype, qclass });
+        let mut reply = data[..2].to_vec();
+        reply.extend_from_slice(&ANSWER_FLAGS.to_be_bytes());
+        reply.extend_from_slice(&[0, 0, 0, 1, 0, 0, 0, 0]);
+        reply.extend_from_slice(encoded);
+        reply.extend_from_slice(&TYPE_NB.to_be_bytes());
+        reply.extend_from_slice(&CLASS_IN.to_be_bytes());
+        reply.extend_from_slice(&TTL.to_be_bytes());
+        reply.extend_from_slice(&6u16.to_be_bytes());
+        // B-node, unique name
+        reply.extend_from_slice(&[0, 0]);
+        reply.extend_from_slice(&ip.octets());
+        Some(reply)
+    }
+}
+
+/// Broadcast name query for `name` with the given suffix, as a Windows host resolving it sends
+pub fn query(id: u16, name: &str, suffix: u8) -> Vec<u8> {
+    let mut message = id.to_be_bytes().to_vec();
+    message.extend_from_slice(&QUERY_FLAGS.to_be_bytes());
+    message.extend_from_slice(&[0, 1, 0, 0, 0, 0, 0, 0]);
+    message.extend_from_slice(&encode_name(name, suffix));
+    message.extend_from_slice(&TYPE_NB.to_be_bytes());
+    message.extend_from_slice(&CLASS_IN.to_be_bytes());
+    message
+}
+
+/// Name a positive name query response is about and the first address it gives
+pub fn parse_answer(data: &[u8]) -> Option<(String, Option<Ipv4Addr>)> {
+    let answer_end = HEADER_LEN + ENCODED_NAME_LEN + 10;
+    if data.len() < answer_end {
+        return None;
+    }
+    let flags = u16::from_be_bytes([data[2], data[3]]);
+    let ancount = u16::from_be_bytes([data[6], data[7]]);
+    if flags & (FLAG_RESPONSE | OPCODE_MASK) != FLAG_RESPONSE || ancount == 0 {
+        return None;
+    }
+    let (name, _) = decode_name(&data[HEADER_LEN..HEADER_LEN + ENCODED_NAME_LEN])?;
+    // Each entry is two bytes of flags and the address
+    let address = match data.get(answer_end..answer_end + 6) {
+        Some(&[_, _, a, b, c, d]) => Some(Ipv4Addr::new(a, b, c, d)),
+        _ => None,
+    };
+    Some((name, address))
+}
+
+fn encode_name(name: &str, suffix: u8) -> Vec<u8> {
+    let mut bytes: Vec<u8> = name.to_ascii_uppercase().bytes().take(NETBIOS_NAME_LEN).collect();
+    bytes.resize(NETBIOS_NAME_LEN, b' ');
+    bytes.push(suffix);
+    let mut encoded = vec![32];
+    for byte in bytes {
+        encoded.extend_from_slice(&[b'A' + (byte >> 4), b'A' + (byte & 0x0f)]);
+    }
+    encoded.push(0);
+    encoded
+}
+
+/// Name and suffix of a first-level encoded NetBIOS name: each byte split in two nibbles added to `A`
+fn decode_name(encoded: &[u8]) -> Option<(String, u8)> {
+    if encoded[0] != 32 || encoded[33] != 0 {
+        return None;
+    }
+    let bytes = encoded[1..33].chunks(2)
+        .map(|pair| match pair {
+            [high @ b'A'..=b'P', low @ b'A'..=b'P'] => Some(((high - b'A') << 4) | (low - b'A')),
+            _ => None,
+        })
+        .collect::<Option<Vec<u8>>>()?;
+    let name = String::from_utf8_lossy(&bytes[..NETBIOS_NAME_LEN]).trim_end().to_string();
+    Some((name, bytes[NETBIOS_NAME_LEN]))
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::honeypot::services::session::test_support;
+
+    fn events() -> EventSink {
+        test_support::events("nbns", "10.0.0.5:137", "10.0.0.7:137")
+    }
+
+    #[test]
+    fn first_level_encoding() {
+        // RFC 1001 §14.1 example
+        let encoded = encode_name("Fred", 0x20);
+        assert_eq!(&encoded[1..33], b"EGFCEFEECACACACACACACACACACACACA");
+        assert_eq!(decode_name(&encoded), Some(("FRED".to_string(), 0x20)));
+
+        assert_eq!(decode_name(&encode_name("A-VERY-LONG-COMPUTER-NAME", 0x00)), Some(("A-VERY-LONG-COM".to_string(), 0x00)));
+        let mut invalid = encoded.clone();
+        invalid[5] = b'Z';
+        assert_eq!(decode_name(&invalid), None);
+        invalid[0] = 31;
+        assert_eq!(decode_name(&invalid), None);
+    }
+
+    #[tokio::test]
+    async fn answers_queries_for_its_own_name() {
+        let nbns = NbnsEmulator::new("desktop-7f3k2.corp.local");
+        let events = events();
+
+        let reply = nbns.on_datagram(&events, &query(0x4242, "desktop-7f3k2", 0x20)).unwrap();
+        assert_eq!(&reply[..2], &[0x42, 0x42]);
+        assert_eq!(parse_answer(&reply), Some(("DESKTOP-7F3K2".to_string(), Some(Ipv4Addr::new(10, 0, 0, 5)))));
+
+        assert!(nbns.on_datagram(&events, &query(1, "fileserver", 0x20)).is_none());
+        // Domain controller suffix, never registered by a workstation
+        assert!(nbns.on_datagram(&events, &query(1, "desktop-7f3k2", 0x1c)).is_none());
+        // Responses are not answered
+        assert!(nbns.on_datagram(&events, &reply).is_none());
+        assert_eq!(parse_answer(&query(1, "desktop-7f3k2", 0x20)), None);
+    }
+
+    #[tokio::test]
+    async fn truncated_datagrams_do_not_panic() {
+        let nbns = NbnsEmulator::new("desktop-7f3k2");
+        let events = events();
+        let query = query(7, "desktop-7f3k2", 0x00);
+        let reply = nbns.on_datagram(&events, &query).unwrap();
+
+        for cut in 0..query.len() {
+            assert!(nbns.on_datagram(&events, &query[..cut]).is_none());
+        }
+        for cut in 0..HEADER_LEN + ENCODED_NAME_LEN + 10 {
+            assert_eq!(parse_answer(&reply[..cut]), None);
+        }
+        // An answer cut inside the address still names the host
+        assert_eq!(parse_answer(&reply[..reply.len() - 1]), Some(("DESKTOP-7F3K2".to_string(), None)));
+    }
+}
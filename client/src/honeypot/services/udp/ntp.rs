@@ -0,0 +1,103 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use common::types::HoneypotEvent;
+
+use super::UdpEmulator;
+use crate::honeypot::services::session::EventSink;
+
+const PACKET_LEN: usize = 48;
+const MODE_CLIENT: u8 = 3;
+const MODE_SERVER: u8 = 4;
+// Seconds between the NTP era (1900) and the Unix epoch
+const NTP_UNIX_OFFSET: u64 = 2_208_988_800;
+const STRATUM: u8 = 2;
+// Upstream the decoy claims to be synchronised to
+const REFERENCE_ID: [u8; 4] = [185, 125, 190, 56];
+
+
+/// Stratum 2 time server. Control (mode 6) and private (mode 7, `monlist`) queries are logged
+/// but never answered, as on any ntpd configured after the 2014 amplification attacks
+pub struct NtpEmulator;
+
+impl UdpEmulator for NtpEmulator {
+    fn name(&self) -> &'static str {
+        "ntp"
+    }
+
+    fn on_datagram(&self, events: &EventSink, data: &[u8]) -> Option<Vec<u8>> {
+        let first = *data.first()?;
+        let version = (first >> 3) & 0x07;
+        let mode = first & 0x07;
+        events.record(HoneypotEvent::NtpRequest { version, mode });
+
+        if mode != MODE_CLIENT || data.len() < PACKET_LEN || !(1..=4).contains(&version) {
+            return None;
+        }
+
+        let now = timestamp();
+        let mut reply = vec![0u8; PACKET_LEN];
+        reply[0] = (version << 3) | MODE_SERVER;
+        reply[1] = STRATUM;
+        reply[2] = data[2];
+        // Precision 2^-23 s, like ntpd on a modern CPU
+        reply[3] = (-23i8) as u8;
+        reply[4..8].copy_from_slice(&0x0000_0a3du32.to_be_bytes());
+        reply[8..12].copy_from_slice(&0x0000_1c4bu32.to_be_bytes());
+        reply[12..16].copy_from_slice(&REFERENCE_ID);
+        reply[16..24].copy_from_slice(&(now - (37 << 32)).to_be_bytes());
+        // Originate timestamp is the client's transmit timestamp
+        reply[24..32].copy_from_slice(&data[40..48]);
+        reply[32..40].copy_from_slice(&now.to_be_bytes());
+        reply[40..48].copy_from_slice(&now.to_be_bytes());
+        Some(reply)
+    }
+}
+
+
+/// Current time as a 64-bit NTP timestamp (32.32 fixed point since 1900)
+fn timestamp() -> u64 {
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+    let seconds = now.as_secs() + NTP_UNIX_OFFSET;
+    let fraction = ((now.subsec_nanos() as u64) << 32) / 1_000_000_000;
+    (seconds << 32) | fraction
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::honeypot::services::session::test_support;
+
+    fn request(version: u8, mode: u8) -> Vec<u8> {
+        let mut data = vec![0u8; PACKET_LEN];
+        data[0] = (version << 3) | mode;
+        data[2] = 6;
+        data[40..48].copy_from_slice(&[1, 2, 3, 4, 5, 6, 7, 8]);
+        data
+    }
+
+    #[tokio::test]
+    async fn answers_clients_with_their_transmit_timestamp() {
+        let events = test_support::events("ntp", "10.0.0.5:123", "10.0.0.7:40000");
+        let reply = NtpEmulator.on_datagram(&events, &request(4, MODE_CLIENT)).unwrap();
+        assert_eq!(reply.len(), PACKET_LEN);
+        assert_eq!(reply[..3], [(4 << 3) | MODE_SERVER, STRATUM, 6]);
+        assert_eq!(reply[24..32], [1, 2, 3, 4, 5, 6, 7, 8]);
+        assert!(u64::from_be_bytes(reply[40..48].try_into().unwrap()) >> 32 > NTP_UNIX_OFFSET);
+    }
+
+    #[tokio::test]
+    async fn malformed_and_control_packets_get_no_reply() {
+        let events = test_support::events("ntp", "10.0.0.5:123", "10.0.0.7:40000");
+        let client = request(3, MODE_CLIENT);
+        for len in 0..PACKET_LEN {
+            assert!(NtpEmulator.on_datagram(&events, &client[..len]).is_none(), "truncated at {}", len);
+        }
+        assert!(NtpEmulator.on_datagram(&events, &request(0, MODE_CLIENT)).is_none());
+        assert!(NtpEmulator.on_datagram(&events, &request(7, MODE_CLIENT)).is_none());
+        assert!(NtpEmulator.on_datagram(&events, &request(4, MODE_SERVER)).is_none());
+        // monlist
+        assert!(NtpEmulator.on_datagram(&events, &[0x17, 0x00, 0x03, 0x2a, 0, 0, 0, 0]).is_none());
+        assert!(NtpEmulator.on_datagram(&events, &request(2, 6)).is_none());
+    }
+}
@@ -0,0 +1,383 @@
+use std::time::Instant;
+
+use common::types::HoneypotEvent;
+use pnet::util::MacAddr;
+
+use super::UdpEmulator;
+use crate::honeypot::{persona::SnmpPersonality, services::session::EventSink};
+
+const TAG_INTEGER: u8 = 0x02;
+const TAG_OCTET_STRING: u8 = 0x04;
+const TAG_OID: u8 = 0x06;
+const TAG_SEQUENCE: u8 = 0x30;
+const TAG_TIMETICKS: u8 = 0x43;
+const TAG_NO_SUCH_OBJECT: u8 = 0x80;
+const TAG_END_OF_MIB_VIEW: u8 = 0x82;
+
+const PDU_GET: u8 = 0xa0;
+const PDU_GET_NEXT: u8 = 0xa1;
+const PDU_RESPONSE: u8 = 0xa2;
+const PDU_SET: u8 = 0xa3;
+const PDU_GET_BULK: u8 = 0xa5;
+
+// SNMPv1 error-status values
+const NO_SUCH_NAME: i64 = 2;
+
+const READ_COMMUNITY: &str = "public";
+const WRITE_COMMUNITY: &str = "private";
+
+
+#[derive(Debug, Clone)]
+enum Value {
+    Integer(i64),
+    String(String),
+    Oid(Vec<u32>),
+    Uptime,
+}
+
+struct Request {
+    version: i64,
+    community: String,
+    pdu: u8,
+    request_id: i64,
+    varbinds: Vec<(Vec<u32>, Vec<u8>)>,
+}
+
+/// SNMP v1/v2c agent: captures every community string tried, answers the system group
+/// to the default read community and pretends to accept writes with the default write one
+pub struct SnmpEmulator {
+    mib: Vec<(Vec<u32>, Value)>,
+    started: Instant,
+    // Uptime the agent already had when the decoy started, in hundredths of a second
+    uptime_offset: u32,
+}
+
+impl SnmpEmulator {
+    pub fn new(mac: MacAddr, persona: &SnmpPersonality, hostname: &str) -> Self {
+        let mut mib = vec![
+            (parse_oid("1.3.6.1.2.1.1.1.0"), Value::String(persona.descr.replace("{hostname}", hostname))),
+            (parse_oid("1.3.6.1.2.1.1.2.0"), Value::Oid(parse_oid(&persona.object_id))),
+            (parse_oid("1.3.6.1.2.1.1.3.0"), Value::Uptime),
+            (parse_oid("1.3.6.1.2.1.1.4.0"), Value::String(persona.contact.clone())),
+            (parse_oid("1.3.6.1.2.1.1.5.0"), Value::String(hostname.to_string())),
+            (parse_oid("1.3.6.1.2.1.1.6.0"), Value::String(persona.location.clone())),
+            (parse_oid("1.3.6.1.2.1.1.7.0"), Value::Integer(persona.services)),
+            (parse_oid("1.3.6.1.2.1.2.1.0"), Value::Integer(2)),
+        ];
+        mib.sort_by(|a, b| a.0.cmp(&b.0));
+
+        SnmpEmulator {
+            mib,
+            started: Instant::now(),
+            uptime_offset: (mac.3 as u32 * 86_400 + mac.4 as u32 * 337) * 100,
+        }
+    }
+
+    fn encode_value(&self, value: &Value) -> Vec<u8> {
+        match value {
+            Value::Integer(n) => tlv(TAG_INTEGER, &encode_integer(*n)),
+            Value::String(s) => tlv(TAG_OCTET_STRING, s.as_bytes()),
+            Value::Oid(oid) => tlv(TAG_OID, &encode_oid(oid)),
+            Value::Uptime => {
+                let ticks = self.uptime_offset.wrapping_add((self.started.elapsed().as_millis() / 10) as u32);
+                tlv(TAG_TIMETICKS, &encode_integer(ticks as i64))
+            }
+        }
+    }
+
+    /// Varbinds of the response and the v1 error index, if any
+    fn lookup(&self, request: &Request) -> (Vec<Vec<u8>>, Option<usize>) {
+        let mut varbinds = Vec::new();
+        let mut error_index = None;
+
+        for (index, (oid, value)) in request.varbinds.iter().enumerate() {
+            let found = match request.pdu {
+                PDU_GET => self.mib.iter().find(|(known, _)| known == oid),
+                _ => self.mib.iter().find(|(known, _)| known > oid),
+            };
+            let varbind = match (found, request.pdu) {
+                // Writes "succeed" and echo the value back, as the agent would
+                (_, PDU_SET) => [tlv(TAG_OID, &encode_oid(oid)), value.clone()].concat(),
+                (Some((known, value)), _) => [tlv(TAG_OID, &encode_oid(known)), self.encode_value(value)].concat(),
+                (None, PDU_GET) => [tlv(TAG_OID, &encode_oid(oid)), tlv(TAG_NO_SUCH_OBJECT, &[])].concat(),
+                (None, _) => [tlv(TAG_OID, &encode_oid(oid)), tlv(TAG_END_OF_MIB_VIEW, &[])].concat(),
+            };
+            if found.is_none() && request.pdu != PDU_SET && error_index.is_none() {
+                error_index = Some(index + 1);
+            }
+            varbinds.push(tlv(TAG_SEQUENCE, &varbind));
+        }
+        (varbinds, error_index)
+    }
+}
+
+impl UdpEmulator for SnmpEmulator {
+    fn name(&self) -> &'static str {
+        "snmp"
+    }
+
+    fn on_datagram(&self, events: &EventSink, data: &[u8]) -> Option<Vec<u8>> {
+        let request = parse_request(data)?;
+
+        let accepted = match request.pdu {
+            PDU_SET => request.community == WRITE_COMMUNITY,
+            _ => request.community == READ_COMMUNITY || request.community == WRITE_COMMUNITY,
+        };
+        events.record(HoneypotEvent::SnmpRequest {
+            version: version_name(request.version),
+            community: request.community.clone(),
+            pdu: match request.pdu {
+                PDU_GET => "get",
+                PDU_GET_NEXT => "get-next",
+                PDU_SET => "set",
+                PDU_GET_BULK => "get-bulk",
+                _ => "unknown",
+            }.to_string(),
+            oids: request.varbinds.iter().map(|(oid, _)| format_oid(oid)).collect(),
+            accepted,
+        });
+
+        // Wrong communities are dropped silently, like net-snmp does
+        if !accepted || request.version > 1 || !matches!(request.pdu, PDU_GET | PDU_GET_NEXT | PDU_SET | PDU_GET_BULK) {
+            return None;
+        }
+        // GetBulk is only allowed in v2c; one repetition per OID keeps the reply small
+        if request.pdu == PDU_GET_BULK && request.version == 0 {
+            return None;
+        }
+
+        let (mut varbinds, missing) = self.lookup(&request);
+        let (error_status, error_index) = match (request.version, missing) {
+            // v1 has no exception values: the whole request fails and the varbinds are echoed
+            (0, Some(index)) => {
+                varbinds = request.varbinds.iter()
+                    .map(|(oid, value)| tlv(TAG_SEQUENCE, &[tlv(TAG_OID, &encode_oid(oid)), value.clone()].concat()))
+                    .collect();
+                (NO_SUCH_NAME, index as i64)
+            }
+            _ => (0, 0),
+        };
+
+        let pdu = [
+            tlv(TAG_INTEGER, &encode_integer(request.request_id)),
+            tlv(TAG_INTEGER, &encode_integer(error_status)),
+            tlv(TAG_INTEGER, &encode_integer(error_index)),
+            tlv(TAG_SEQUENCE, &varbinds.concat()),
+        ].concat();
+        let message = [
+            tlv(TAG_INTEGER, &encode_integer(request.version)),
+            tlv(TAG_OCTET_STRING, request.community.as_bytes()),
+            tlv(PDU_RESPONSE, &pdu),
+        ].concat();
+        Some(tlv(TAG_SEQUENCE, &message))
+    }
+}
+
+
+/// Name of an SNMP message version field: 2 was the abandoned v2u/v2*, which nothing speaks
+fn version_name(version: i64) -> String {
+    match version {
+        0 => "v1".to_string(),
+        1 => "v2c".to_string(),
+        3 => "v3".to_string(),
+        other => format!("version {}", other),
+    }
+}
+
+fn parse_request(data: &[u8]) -> Option<Request> {
+    let (tag, message, _) = read_tlv(data)?;
+    if tag != TAG_SEQUENCE {
+        return None;
+    }
+    let (_, version, rest) = read_tlv(message)?;
+    let version = decode_integer(version);
+    // v3 messages carry a USM header instead of a community
+    if version > 1 {
+        return Some(Request { version, community: String::new(), pdu: 0, request_id: 0, varbinds: Vec::new() });
+    }
+    let (_, community, rest) = read_tlv(rest)?;
+    let (pdu, body, _) = read_tlv(rest)?;
+
+    let (_, request_id, rest) = read_tlv(body)?;
+    let (_, _, rest) = read_tlv(rest)?;
+    let (_, _, rest) = read_tlv(rest)?;
+    let (_, mut list, _) = read_tlv(rest)?;
+
+    let mut varbinds = Vec::new();
+    while !list.is_empty() {
+        let (_, varbind, rest) = read_tlv(list)?;
+        let (_, oid, value) = read_tlv(varbind)?;
+        varbinds.push((decode_oid(oid), value.to_vec()));
+        list = rest;
+    }
+
+    Some(Request {
+        version,
+        community: String::from_utf8_lossy(community).to_string(),
+        pdu,
+        request_id: decode_integer(request_id),
+        varbinds,
+    })
+}
+
+/// Splits one BER element off `data`: tag, contents and what follows
+fn read_tlv(data: &[u8]) -> Option<(u8, &[u8], &[u8])> {
+    let tag = *data.first()?;
+    let first = *data.get(1)? as usize;
+    let (len, header) = match first {
+        0..=0x7f => (first, 2),
+        0x81 => (*data.get(2)? as usize, 3),
+        0x82 => (u16::from_be_bytes([*data.get(2)?, *data.get(3)?]) as usize, 4),
+        _ => return None,
+    };
+    let contents = data.get(header..header + len)?;
+    Some((tag, contents, &data[header + len..]))
+}
+
+fn tlv(tag: u8, contents: &[u8]) -> Vec<u8> {
+    let mut output = vec![tag];
+    match contents.len() {
+        len @ 0..=0x7f => output.push(len as u8),
+        len @ 0x80..=0xff => output.extend_from_slice(&[0x81, len as u8]),
+        len => {
+            output.push(0x82);
+            output.extend_from_slice(&(len as u16).to_be_bytes());
+        }
+    }
+    output.extend_from_slice(contents);
+    output
+}
+
+fn decode_integer(contents: &[u8]) -> i64 {
+    let negative = contents.first().is_some_and(|b| b & 0x80 != 0);
+    contents.iter().take(8).fold(if negative { -1 } else { 0 }, |n, b| (n << 8) | *b as i64)
+}
+
+fn encode_integer(n: i64) -> Vec<u8> {
+    let bytes = n.to_be_bytes();
+    // Drop leading bytes that only repeat the sign
+    let mut start = 0;
+    while start < 7 && ((bytes[start] == 0 && bytes[start + 1] & 0x80 == 0) || (bytes[start] == 0xff && bytes[start + 1] & 0x80 != 0)) {
+        start += 1;
+    }
+    bytes[start..].to_vec()
+}
+
+fn decode_oid(contents: &[u8]) -> Vec<u32> {
+    let mut oid = Vec::new();
+    let mut value: u32 = 0;
+    for byte in contents {
+        value = value.wrapping_shl(7) | (byte & 0x7f) as u32;
+        if byte & 0x80 != 0 {
+            continue;
+        }
+        if oid.is_empty() {
+            oid.push((value / 40).min(2));
+            oid.push(value - oid[0] * 40);
+        } else {
+            oid.push(value);
+        }
+        value = 0;
+    }
+    oid
+}
+
+fn encode_oid(oid: &[u32]) -> Vec<u8> {
+    let mut output = Vec::new();
+    let first = oid.first().copied().unwrap_or(0) * 40 + oid.get(1).copied().unwrap_or(0);
+    for arc in std::iter::once(first).chain(oid.iter().skip(2).copied()) {
+        let mut chunk = vec![(arc & 0x7f) as u8];
+        let mut rest = arc >> 7;
+        while rest > 0 {
+            chunk.push((rest & 0x7f) as u8 | 0x80);
+            rest >>= 7;
+        }
+        output.extend(chunk.iter().rev());
+    }
+    output
+}
+
+fn parse_oid(text: &str) -> Vec<u32> {
+    text.split('.').filter_map(|arc| arc.parse().ok()).collect()
+}
+
+fn format_oid(oid: &[u32]) -> String {
+    oid.iter().map(|arc| arc.to_string()).collect::<Vec<_>>().join(".")
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::honeypot::services::session::test_support;
+
+    fn agent() -> SnmpEmulator {
+        SnmpEmulator::new(MacAddr::zero(), &SnmpPersonality::default(), "printer")
+    }
+
+    fn get(version: i64, community: &str, oid: &str) -> Vec<u8> {
+        let varbind = tlv(TAG_SEQUENCE, &[tlv(TAG_OID, &encode_oid(&parse_oid(oid))), tlv(0x05, &[])].concat());
+        let pdu = [
+            tlv(TAG_INTEGER, &encode_integer(0x1234)),
+            tlv(TAG_INTEGER, &[0]),
+            tlv(TAG_INTEGER, &[0]),
+            tlv(TAG_SEQUENCE, &varbind),
+        ].concat();
+        let message = [
+            tlv(TAG_INTEGER, &encode_integer(version)),
+            tlv(TAG_OCTET_STRING, community.as_bytes()),
+            tlv(PDU_GET, &pdu),
+        ].concat();
+        tlv(TAG_SEQUENCE, &message)
+    }
+
+    #[test]
+    fn versions_are_named_as_on_the_wire() {
+        assert_eq!(version_name(0), "v1");
+        assert_eq!(version_name(1), "v2c");
+        assert_eq!(version_name(3), "v3");
+    }
+
+    #[tokio::test]
+    async fn answers_the_system_group() {
+        let events = test_support::events("snmp", "10.0.0.5:161", "10.0.0.7:40000");
+        let reply = agent().on_datagram(&events, &get(1, "public", "1.3.6.1.2.1.1.5.0")).unwrap();
+        let (_, message, rest) = read_tlv(&reply).unwrap();
+        assert!(rest.is_empty());
+        let (_, _, message) = read_tlv(message).unwrap();
+        let (_, _, message) = read_tlv(message).unwrap();
+        let (pdu, _, _) = read_tlv(message).unwrap();
+        assert_eq!(pdu, PDU_RESPONSE);
+        assert!(reply.ends_with(&tlv(TAG_OCTET_STRING, b"printer")));
+    }
+
+    #[tokio::test]
+    async fn malformed_messages_get_no_reply() {
+        let events = test_support::events("snmp", "10.0.0.5:161", "10.0.0.7:40000");
+        let agent = agent();
+        let request = get(1, "public", "1.3.6.1.2.1.1.1.0");
+        for len in 0..request.len() {
+            assert!(agent.on_datagram(&events, &request[..len]).is_none(), "truncated at {}", len);
+        }
+
+        // Indefinite and over-long lengths aren't BER an agent accepts
+        assert!(agent.on_datagram(&events, &[TAG_SEQUENCE, 0x80, 0x02, 0x01, 0x01, 0x00, 0x00]).is_none());
+        assert!(agent.on_datagram(&events, &[TAG_SEQUENCE, 0x84, 0xff, 0xff, 0xff, 0xff]).is_none());
+        assert!(agent.on_datagram(&events, &[TAG_INTEGER, 0x01, 0x01]).is_none());
+        // SNMPv3 is recognised but never answered
+        assert!(agent.on_datagram(&events, &tlv(TAG_SEQUENCE, &tlv(TAG_INTEGER, &[3]))).is_none());
+        assert!(agent.on_datagram(&events, &get(0, "secret", "1.3.6.1.2.1.1.1.0")).is_none());
+    }
+
+    #[test]
+    fn odd_integers_and_oids_decode_without_panicking() {
+        assert_eq!(decode_integer(&[]), 0);
+        assert_eq!(decode_integer(&[0xff, 0x38]), -200);
+        decode_integer(&[0x7f; 16]);
+        assert_eq!(decode_oid(&[0x2b, 0x06, 0x01]), [1, 3, 6, 1]);
+        // A dangling continuation byte is dropped, an oversized arc wraps
+        assert_eq!(decode_oid(&[0x2b, 0x86]), [1, 3]);
+        decode_oid(&[0xff; 12]);
+        assert!(decode_oid(&[]).is_empty());
+    }
+}
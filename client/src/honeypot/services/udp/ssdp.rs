@@ -0,0 +1,144 @@
+use std::net::{IpAddr, Ipv4Addr};
+
+use common::types::HoneypotEvent;
+use pnet::util::MacAddr;
+
+use super::UdpEmulator;
+use crate::honeypot::{persona::UpnpPersonality, services::session::EventSink};
+
+pub const SSDP_PORT: u16 = 1900;
+
+
+/// Answers unicast `M-SEARCH` discovery with a single root device, however broad the search target:
+/// `ssdp:all` gets one response instead of one per service, so it can't be used for amplification
+pub struct SsdpEmulator {
+    persona: UpnpPersonality,
+    uuid: String,
+}
+
+/// UUID of the node's root device, in the layout many devices use: a fixed prefix followed by the MAC
+pub fn device_uuid(mac: MacAddr) -> String {
+    format!("2f402f80-da50-11e1-9b23-{:02x}{:02x}{:02x}{:02x}{:02x}{:02x}", mac.0, mac.1, mac.2, mac.3, mac.4, mac.5)
+}
+
+/// `NOTIFY ssdp:alive` messages a UPnP device multicasts for its root device, UUID and
+/// device type, with the same identity its search responses carry
+pub fn notify_alive(mac: MacAddr, persona: &UpnpPersonality, ip: Ipv4Addr) -> Vec<Vec<u8>> {
+    let ssdp = SsdpEmulator::new(mac, persona);
+    let uuid = format!("uuid:{}", ssdp.uuid);
+    [
+        ("upnp:rootdevice".to_string(), format!("{}::upnp:rootdevice", uuid)),
+        (uuid.clone(), uuid.clone()),
+        (ssdp.persona.device_type.clone(), format!("{}::{}", uuid, ssdp.persona.device_type)),
+    ]
+    .into_iter()
+    .map(|(nt, usn)| format!(
+        "NOTIFY * HTTP/1.1\r\nHOST: 239.255.255.250:1900\r\nCACHE-CONTROL: max-age=1800\r\nLOCATION: {}\r\nNT: {}\r\nNTS: ssdp:alive\r\nSERVER: {}\r\nUSN: {}\r\n\r\n",
+        ssdp.location(ip.into()), nt, ssdp.persona.server, usn,
+    ).into_bytes())
+    .collect()
+}
+
+impl SsdpEmulator {
+    pub fn new(mac: MacAddr, persona: &UpnpPersonality) -> Self {
+        SsdpEmulator { persona: persona.clone(), uuid: device_uuid(mac) }
+    }
+
+    /// Device description, served by the node's `upnp` HTTP service
+    fn location(&self, ip: IpAddr) -> String {
+        format!("http://{}:{}{}", ip, self.persona.port, self.persona.path)
+    }
+}
+
+impl UdpEmulator for SsdpEmulator {
+    fn name(&self) -> &'static str {
+        "ssdp"
+    }
+
+    fn on_datagram(&self, events: &EventSink, data: &[u8]) -> Option<Vec<u8>> {
+        let text = String::from_utf8_lossy(data);
+        let mut lines = text.split("\r\n");
+        if !lines.next()?.trim().eq_ignore_ascii_case("M-SEARCH * HTTP/1.1") {
+            return None;
+        }
+
+        let header = |name: &str| text.split("\r\n").skip(1).find_map(|line| {
+            let (key, value) = line.split_once(':')?;
+            key.trim().eq_ignore_ascii_case(name).then(|| value.trim().to_string())
+        });
+        let target = header("ST")?;
+        events.record(HoneypotEvent::SsdpSearch { target: target.clone(), user_agent: header("USER-AGENT") });
+
+        let (st, usn) = match target.as_str() {
+            "ssdp:all" | "upnp:rootdevice" => ("upnp:rootdevice".to_string(), format!("uuid:{}::upnp:rootdevice", self.uuid)),
+            st if st == self.persona.device_type => (st.to_string(), format!("uuid:{}::{}", self.uuid, st)),
+            st if st == format!("uuid:{}", self.uuid) => (st.to_string(), st.to_string()),
+            _ => return None,
+        };
+
+        Some(format!(
+            "HTTP/1.1 200 OK\r\nCACHE-CONTROL: max-age=1800\r\nEXT:\r\nLOCATION: {}\r\nSERVER: {}\r\nST: {}\r\nUSN: {}\r\n\r\n",
+            self.location(events.honeypot_addr().ip()), self.persona.server, st, usn,
+        ).into_bytes())
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::honeypot::{persona::personality_named, services::{http::{HttpPersona, HttpRequest}, session::test_support}};
+
+    fn router() -> (&'static UpnpPersonality, MacAddr) {
+        (personality_named("tplink-router").unwrap().upnp.as_ref().unwrap(), MacAddr::new(0xd8, 0x21, 0xda, 1, 2, 3))
+    }
+
+    #[tokio::test]
+    async fn location_serves_the_announced_device() {
+        let (upnp, mac) = router();
+        let events = test_support::events("ssdp", "10.0.0.5:1900", "10.0.0.7:50000");
+        let search = b"M-SEARCH * HTTP/1.1\r\nHOST: 239.255.255.250:1900\r\nMAN: \"ssdp:discover\"\r\nMX: 1\r\nST: ssdp:all\r\n\r\n";
+        let reply = String::from_utf8(SsdpEmulator::new(mac, upnp).on_datagram(&events, search).unwrap()).unwrap();
+
+        let location = reply.split("\r\n").find_map(|line| line.strip_prefix("LOCATION: ")).unwrap();
+        assert_eq!(location, "http://10.0.0.5:1900/igd.xml");
+        assert!(reply.contains(&format!("SERVER: {}\r\n", upnp.server)));
+
+        let request = HttpRequest { method: "GET".to_string(), target: "/igd.xml".to_string(), version: "HTTP/1.1".to_string(), headers: Vec::new(), body: Vec::new() };
+        let persona = HttpPersona::Upnp(upnp, mac);
+        let response = persona.respond(&request);
+        let description = String::from_utf8(response.body).unwrap();
+        assert_eq!((response.status, persona.server()), (200, upnp.server.as_str()));
+        assert!(description.contains(&format!("<deviceType>{}</deviceType>", upnp.device_type)));
+        assert!(description.contains("<UDN>uuid:2f402f80-da50-11e1-9b23-d821da010203</UDN>"));
+        assert!(reply.contains("USN: uuid:2f402f80-da50-11e1-9b23-d821da010203::upnp:rootdevice"));
+    }
+
+    #[tokio::test]
+    async fn malformed_searches_get_no_reply() {
+        let (upnp, mac) = router();
+        let events = test_support::events("ssdp", "10.0.0.5:1900", "10.0.0.7:50000");
+        let ssdp = SsdpEmulator::new(mac, upnp);
+        let search: &[u8] = b"M-SEARCH * HTTP/1.1\r\nHOST: 239.255.255.250:1900\r\nMAN: \"ssdp:discover\"\r\nST: upnp:rootdevice\r\n\r\n";
+        let st = search.windows(3).position(|window| window == b"ST:").unwrap();
+        for len in 0..st + 3 {
+            assert!(ssdp.on_datagram(&events, &search[..len]).is_none(), "truncated at {}", len);
+        }
+
+        for datagram in [
+            &b""[..],
+            b"\xff\xfe\x00\x01",
+            b"NOTIFY * HTTP/1.1\r\nNT: upnp:rootdevice\r\n\r\n",
+            b"M-SEARCH * HTTP/1.1\r\nMAN: \"ssdp:discover\"\r\n\r\n",
+            b"M-SEARCH * HTTP/1.1\r\nST urn:schemas-upnp-org:device:MediaRenderer:1\r\n\r\n",
+            b"M-SEARCH * HTTP/1.1\r\nST: urn:schemas-upnp-org:device:MediaRenderer:1\r\n\r\n",
+            b"M-SEARCH * HTTP/1.1\r\nST: uuid:00000000-0000-0000-0000-000000000000\r\n\r\n",
+        ] {
+            assert!(ssdp.on_datagram(&events, datagram).is_none(), "{:?}", String::from_utf8_lossy(datagram));
+        }
+
+        // Header names are case-insensitive and a missing final CRLF is tolerated
+        let reply = ssdp.on_datagram(&events, b"m-search * http/1.1\r\nst:ssdp:all").unwrap();
+        assert!(reply.starts_with(b"HTTP/1.1 200 OK\r\n"));
+    }
+}
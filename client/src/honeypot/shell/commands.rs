@@ -0,0 +1,753 @@
+
+use common::types::HoneypotEvent;
+
+use super::{filesystem::{self, FsEntry}, FakeShell, ShellFlavor};
+use crate::honeypot::services::time::{format_date, format_time, format_timestamp, now};
+
+pub const KERNEL_RELEASE: &str = "5.15.0-105-generic";
+const KERNEL_VERSION: &str = "#115-Ubuntu SMP Mon Apr 15 09:52:04 UTC 2024";
+const UPTIME_SECONDS: u64 = 3_894_411;
+const SHELL_PID: u32 = 2841;
+
+pub const BUSYBOX_VERSION: &str = "BusyBox v1.19.4 (2014-04-10 14:52:08 CST)";
+const BUSYBOX_KERNEL_RELEASE: &str = "3.10.14";
+const BUSYBOX_KERNEL_VERSION: &str = "#1 SMP PREEMPT Thu Jul 20 10:38:25 CST 2017";
+const BUSYBOX_MACHINE: &str = "armv7l";
+// Applets compiled into the emulated BusyBox binary
+const BUSYBOX_APPLETS: &[&str] = &[
+    "cat", "chmod", "chown", "cp", "date", "df", "echo", "env", "false", "free", "grep", "head", "hostname", "id", "kill",
+    "killall", "ls", "mkdir", "mount", "mv", "ps", "pwd", "rm", "sh", "sleep", "tail", "tftp", "touch", "true", "uname",
+    "uptime", "wc", "wget", "which",
+];
+
+
+/// Streams and exit status of a built-in
+#[derive(Debug, Default)]
+pub struct CommandOutput {
+    pub stdout: String,
+    pub stderr: String,
+    pub status: u32,
+}
+
+impl CommandOutput {
+    fn ok(stdout: impl Into<String>) -> Self {
+        CommandOutput { stdout: stdout.into(), stderr: String::new(), status: 0 }
+    }
+
+    pub fn error(stderr: impl Into<String>, status: u32) -> Self {
+        CommandOutput { stdout: String::new(), stderr: stderr.into(), status }
+    }
+}
+
+
+/// Dispatches one simple command to its built-in
+pub fn run(shell: &mut FakeShell, argv: &[String], stdin: &str) -> CommandOutput {
+    let program = argv[0].as_str();
+    let args = &argv[1..];
+
+    if shell.flavor == ShellFlavor::BusyBox {
+        match program.rsplit('/').next().unwrap_or(program) {
+            "busybox" => return busybox(shell, args, stdin),
+            "ps" => return busybox_ps(shell),
+            "id" if shell.user == "root" => return CommandOutput::ok("uid=0(root) gid=0(root)\n"),
+            "tftp" => return tftp(shell, args),
+            // Vendor CLI escapes that bots try before asking for a shell
+            "enable" | "system" | "shell" | "linuxshell" | "start" => return CommandOutput::default(),
+            _ => {}
+        }
+    }
+
+    match program.rsplit('/').next().unwrap_or(program) {
+        "cd" => cd(shell, args),
+        "pwd" => CommandOutput::ok(format!("{}\n", shell.cwd)),
+        "ls" | "dir" => ls(shell, args),
+        "ll" => ls(shell, &[vec!["-alF".to_string()], args.to_vec()].concat()),
+        "cat" => cat(shell, program, args, stdin),
+        "echo" => echo(args),
+        "uname" => uname(shell, args),
+        "whoami" => CommandOutput::ok(format!("{}\n", shell.user)),
+        "id" => CommandOutput::ok(id(&shell.user)),
+        "hostname" => CommandOutput::ok(format!("{}\n", shell.hostname)),
+        "uptime" => CommandOutput::ok(format!("{}\n", uptime_line())),
+        "w" => w(shell),
+        "ps" => ps(shell, args),
+        "free" => free(args),
+        "df" => df(args),
+        "nproc" => CommandOutput::ok("2\n"),
+        "date" => CommandOutput::ok(format!("{}\n", format_date(now()))),
+        "history" => history(shell),
+        "export" => export(shell, args),
+        "env" | "printenv" => CommandOutput::ok(shell.env.iter().map(|(k, v)| format!("{}={}\n", k, v)).collect::<String>()),
+        "which" => which(shell, args),
+        "mkdir" => mkdir(shell, args),
+        "touch" => touch(shell, args),
+        "rm" => rm(shell, args),
+        "cp" | "mv" => copy(shell, program, args),
+        "grep" | "egrep" => grep(shell, args, stdin),
+        "head" | "tail" => head_tail(shell, program, args, stdin),
+        "wc" => wc(shell, args, stdin),
+        "wget" => wget(shell, args),
+        "curl" => curl(shell, args),
+        "sudo" if !args.is_empty() => run(shell, args, stdin),
+        "sudo" => CommandOutput::error("usage: sudo -h | -K | -k | -V\n", 1),
+        "su" => CommandOutput::error("Password: \nsu: Authentication failure\n", 1),
+        "passwd" => CommandOutput::error("passwd: Authentication token manipulation error\npasswd: password unchanged\n", 10),
+        "crontab" if args.iter().any(|a| a == "-l") => CommandOutput::error(format!("no crontab for {}\n", shell.user), 1),
+        "apt" | "apt-get" | "dpkg" => CommandOutput::error(
+            "E: Could not get lock /var/lib/dpkg/lock-frontend. It is held by process 1873 (unattended-upgr)\n\
+             E: Unable to acquire the dpkg frontend lock (/var/lib/dpkg/lock-frontend), is another process using it?\n",
+            100,
+        ),
+        "clear" => CommandOutput::ok("\x1b[H\x1b[2J"),
+        "true" | ":" | "chmod" | "chown" | "kill" | "pkill" | "killall" | "sleep" | "unset" | "alias" | "crontab" => CommandOutput::default(),
+        "false" => CommandOutput::error("", 1),
+        _ => external(shell, program),
+    }
+}
+
+
+fn cd(shell: &mut FakeShell, args: &[String]) -> CommandOutput {
+    let target = match args.first().map(String::as_str) {
+        None | Some("~") => shell.home.clone(),
+        Some(path) => filesystem::normalize(&shell.cwd, &path.replacen('~', &shell.home, 1)),
+    };
+
+    match shell.fs.get(&target) {
+        Some(FsEntry::Directory) => {
+            shell.cwd = target.clone();
+            shell.env.insert("PWD".to_string(), target);
+            CommandOutput::default()
+        }
+        Some(FsEntry::File(_)) => CommandOutput::error(format!("{}: cd: {}: Not a directory\n", shell.name(), args[0]), 1),
+        None => CommandOutput::error(format!("{}: cd: {}: No such file or directory\n", shell.name(), args[0]), 1),
+    }
+}
+
+fn ls(shell: &FakeShell, args: &[String]) -> CommandOutput {
+    let flags: String = args.iter().filter(|a| a.starts_with('-')).flat_map(|a| a.chars().skip(1)).collect();
+    let long = flags.contains('l');
+    let all = flags.contains('a') || flags.contains('A');
+    let mut paths: Vec<&String> = args.iter().filter(|a| !a.starts_with('-')).collect();
+    let cwd = shell.cwd.clone();
+    if paths.is_empty() {
+        paths.push(&cwd);
+    }
+
+    let mut output = CommandOutput::default();
+    let multiple = paths.len() > 1;
+    for path in paths {
+        let absolute = filesystem::normalize(&shell.cwd, path);
+        let entries: Vec<(String, &FsEntry, String)> = match shell.fs.list(&absolute) {
+            Ok(children) => {
+                let mut entries: Vec<(String, &FsEntry, String)> = children
+                    .into_iter()
+                    .filter(|(name, _)| all || !name.starts_with('.'))
+                    .map(|(name, entry)| (name.to_string(), entry, filesystem::normalize(&absolute, name)))
+                    .collect();
+                if flags.contains('a') {
+                    entries.insert(0, ("..".to_string(), &FsEntry::Directory, filesystem::parent(&absolute)));
+                    entries.insert(0, (".".to_string(), &FsEntry::Directory, absolute.clone()));
+                }
+                entries
+            }
+            Err(filesystem::FsError::NotADirectory) => vec![(path.to_string(), shell.fs.get(&absolute).unwrap(), absolute.clone())],
+            Err(e) => {
+                output.stderr.push_str(&format!("ls: cannot access '{}': {}\n", path, e.message()));
+                output.status = 2;
+                continue;
+            }
+        };
+
+        if multiple {
+            output.stdout.push_str(&format!("{}:\n", path));
+        }
+        if long {
+            output.stdout.push_str(&format!("total {}\n", entries.len() * 4));
+            for (name, entry, full_path) in &entries {
+                output.stdout.push_str(&format!("{}\n", long_entry(name, entry, full_path)));
+            }
+        } else if !entries.is_empty() {
+            let names: Vec<&str> = entries.iter().map(|(name, _, _)| name.as_str()).collect();
+            output.stdout.push_str(&format!("{}\n", names.join("  ")));
+        }
+        if multiple {
+            output.stdout.push('\n');
+        }
+    }
+
+    output
+}
+
+fn long_entry(name: &str, entry: &FsEntry, path: &str) -> String {
+    let owner = path.strip_prefix("/home/").and_then(|rest| rest.split('/').next()).unwrap_or("root");
+    let executable = path.contains("bin/");
+
+    let (mode, links, size) = match entry {
+        FsEntry::Directory => ("drwxr-xr-x", 2, 4096),
+        // Binaries are empty in the snapshot but should not look like it
+        FsEntry::File(content) if executable && content.is_empty() => ("-rwxr-xr-x", 1, 18_000 + name.bytes().map(|b| b as usize * 1_337).sum::<usize>()),
+        FsEntry::File(content) if executable => ("-rwxr-xr-x", 1, content.len()),
+        FsEntry::File(content) => ("-rw-r--r--", 1, content.len()),
+    };
+
+    format!("{} {} {:<5} {:<5} {:>6} Apr 15 09:52 {}", mode, links, owner, owner, size, name)
+}
+
+fn cat(shell: &FakeShell, program: &str, args: &[String], stdin: &str) -> CommandOutput {
+    let files: Vec<&String> = args.iter().filter(|a| !a.starts_with('-') || a.as_str() == "-").collect();
+    if files.is_empty() {
+        return CommandOutput::ok(stdin);
+    }
+
+    let mut output = CommandOutput::default();
+    for file in files {
+        let path = filesystem::normalize(&shell.cwd, file);
+        if path == "/etc/shadow" && shell.user != "root" {
+            output.stderr.push_str(&format!("{}: {}: Permission denied\n", program, file));
+            output.status = 1;
+            continue;
+        }
+        match shell.fs.read(&path) {
+            Ok(content) => output.stdout.push_str(&String::from_utf8_lossy(content)),
+            Err(e) => {
+                output.stderr.push_str(&format!("{}: {}: {}\n", program, file, e.message()));
+                output.status = 1;
+            }
+        }
+    }
+    output
+}
+
+fn echo(args: &[String]) -> CommandOutput {
+    let mut newline = true;
+    let mut escapes = false;
+    let mut words = args;
+    while let Some(flag) = words.first() {
+        match flag.as_str() {
+            "-n" => newline = false,
+            "-e" => escapes = true,
+            "-ne" | "-en" => {
+                newline = false;
+                escapes = true;
+            }
+            _ => break,
+        }
+        words = &words[1..];
+    }
+
+    let mut text = words.join(" ");
+    if escapes {
+        text = unescape(&text);
+    }
+    if newline {
+        text.push('\n');
+    }
+    CommandOutput::ok(text)
+}
+
+/// `echo -e` escapes, including the `\xHH` and `\0NNN` forms droppers use to write binaries
+fn unescape(text: &str) -> String {
+    let mut output = String::new();
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            output.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n') => output.push('\n'),
+            Some('t') => output.push('\t'),
+            Some('r') => output.push('\r'),
+            Some('\\') => output.push('\\'),
+            Some(radix @ ('x' | '0')) => {
+                let (base, max) = if radix == 'x' { (16, 2) } else { (8, 3) };
+                let mut value = 0u32;
+                let mut digits = 0;
+                while digits < max && let Some(digit) = chars.peek().and_then(|d| d.to_digit(base)) {
+                    value = value * base + digit;
+                    digits += 1;
+                    chars.next();
+                }
+                output.push(char::from(value as u8));
+            }
+            Some(other) => {
+                output.push('\\');
+                output.push(other);
+            }
+            None => output.push('\\'),
+        }
+    }
+    output
+}
+
+fn uname(shell: &FakeShell, args: &[String]) -> CommandOutput {
+    let flags: String = args.iter().filter(|a| a.starts_with('-')).flat_map(|a| a.chars().skip(1)).collect();
+    let flags = if flags.contains('a') || args.iter().any(|a| a == "--all") { "snrvmpio".to_string() } else { flags };
+
+    let busybox = shell.flavor == ShellFlavor::BusyBox;
+    let mut fields = Vec::new();
+    for flag in flags.chars() {
+        match flag {
+            's' => fields.push("Linux"),
+            'n' => fields.push(shell.hostname.as_str()),
+            'r' if busybox => fields.push(BUSYBOX_KERNEL_RELEASE),
+            'r' => fields.push(KERNEL_RELEASE),
+            'v' if busybox => fields.push(BUSYBOX_KERNEL_VERSION),
+            'v' => fields.push(KERNEL_VERSION),
+            'm' if busybox => fields.push(BUSYBOX_MACHINE),
+            'p' | 'i' if busybox => {}
+            'm' | 'p' | 'i' => fields.push("x86_64"),
+            'o' => fields.push("GNU/Linux"),
+            _ => return CommandOutput::error(format!("uname: invalid option -- '{}'\nTry 'uname --help' for more information.\n", flag), 1),
+        }
+    }
+    if fields.is_empty() {
+        fields.push("Linux");
+    }
+    CommandOutput::ok(format!("{}\n", fields.join(" ")))
+}
+
+fn id(user: &str) -> String {
+    if user == "root" {
+        "uid=0(root) gid=0(root) groups=0(root)\n".to_string()
+    } else {
+        format!("uid=1000({0}) gid=1000({0}) groups=1000({0}),4(adm),24(cdrom),27(sudo),30(dip),46(plugdev)\n", user)
+    }
+}
+
+fn uptime_line() -> String {
+    let now = now();
+    let up = UPTIME_SECONDS + now % 86_400;
+    format!(
+        " {} up {} days, {:>2}:{:02},  1 user,  load average: 0.08, 0.03, 0.01",
+        format_time(now),
+        up / 86_400,
+        (up % 86_400) / 3600,
+        (up % 3600) / 60,
+    )
+}
+
+fn w(shell: &FakeShell) -> CommandOutput {
+    CommandOutput::ok(format!(
+        "{}\nUSER     TTY      FROM             LOGIN@   IDLE   JCPU   PCPU WHAT\n{:<8} pts/0    10.0.0.12        {}    0.00s  0.02s  0.00s w\n",
+        uptime_line(),
+        shell.user,
+        &format_time(now())[..5],
+    ))
+}
+
+fn ps(shell: &FakeShell, args: &[String]) -> CommandOutput {
+    let full = args.iter().any(|a| a.contains('a') || a.contains('e') || a.contains('x'));
+    if !full {
+        return CommandOutput::ok(format!(
+            "    PID TTY          TIME CMD\n   {} pts/0    00:00:00 bash\n   {} pts/0    00:00:00 ps\n",
+            SHELL_PID,
+            SHELL_PID + 37,
+        ));
+    }
+
+    let processes = [
+        ("root", 1, "0.0", "0.2", "167744", "11588", "?", "Ss", "/sbin/init"),
+        ("root", 412, "0.0", "0.4", "48232", "16404", "?", "S<s", "/lib/systemd/systemd-journald"),
+        ("root", 455, "0.0", "0.1", "25216", "6148", "?", "Ss", "/lib/systemd/systemd-udevd"),
+        ("systemd+", 611, "0.0", "0.3", "25532", "12604", "?", "Ss", "/lib/systemd/systemd-resolved"),
+        ("root", 702, "0.0", "0.0", "6896", "2884", "?", "Ss", "/usr/sbin/cron -f"),
+        ("syslog", 709, "0.0", "0.1", "222400", "5796", "?", "Ssl", "/usr/sbin/rsyslogd -n -iNONE"),
+        ("root", 744, "0.0", "0.2", "15432", "9112", "?", "Ss", "sshd: /usr/sbin/sshd -D [listener] 0 of 10-100 startups"),
+        ("mysql", 812, "0.3", "9.8", "2339528", "396812", "?", "Ssl", "/usr/sbin/mysqld"),
+        ("root", 901, "0.0", "0.5", "203720", "21260", "?", "Ss", "/usr/sbin/apache2 -k start"),
+        ("www-data", 903, "0.0", "0.2", "1212488", "9336", "?", "Sl", "/usr/sbin/apache2 -k start"),
+    ];
+
+    let mut out = String::from("USER         PID %CPU %MEM    VSZ   RSS TTY      STAT START   TIME COMMAND\n");
+    for (user, pid, cpu, mem, vsz, rss, tty, stat, cmd) in processes {
+        out.push_str(&format!("{:<8} {:>7} {:>4} {:>4} {:>6} {:>5} {:<8} {:<4} Mar02   0:{:02} {}\n", user, pid, cpu, mem, vsz, rss, tty, stat, pid % 60, cmd));
+    }
+    out.push_str(&format!("{:<8} {:>7}  0.0  0.1   8972  5444 pts/0    Ss   {}   0:00 -bash\n", shell.user, SHELL_PID, &format_time(now())[..5]));
+    out.push_str(&format!("{:<8} {:>7}  0.0  0.0  10072  3328 pts/0    R+   {}   0:00 ps {}\n", shell.user, SHELL_PID + 37, &format_time(now())[..5], args.join(" ")));
+    CommandOutput::ok(out)
+}
+
+fn free(args: &[String]) -> CommandOutput {
+    let human = args.iter().any(|a| a == "-h");
+    let mega = args.iter().any(|a| a == "-m");
+    let (mem, swap) = if human {
+        ("Mem:           3.8Gi       1.0Gi       305Mi       2.0Mi       2.5Gi       2.7Gi", "Swap:          2.0Gi          0B       2.0Gi")
+    } else if mega {
+        ("Mem:            3921        1047         305           2        2568        2751", "Swap:           2047           0        2047")
+    } else {
+        ("Mem:         4015876     1072604      312448        2348     2630824     2817340", "Swap:        2097148           0     2097148")
+    };
+    CommandOutput::ok(format!("               total        used        free      shared  buff/cache   available\n{}\n{}\n", mem, swap))
+}
+
+fn df(args: &[String]) -> CommandOutput {
+    if args.iter().any(|a| a.contains('h')) {
+        return CommandOutput::ok(
+            "Filesystem      Size  Used Avail Use% Mounted on\n\
+             tmpfs           393M  1.1M  392M   1% /run\n\
+             /dev/sda2        78G   19G   55G  26% /\n\
+             tmpfs           2.0G     0  2.0G   0% /dev/shm\n\
+             tmpfs           5.0M     0  5.0M   0% /run/lock\n\
+             /dev/sda1       511M  6.1M  505M   2% /boot/efi\n",
+        );
+    }
+    CommandOutput::ok(
+        "Filesystem     1K-blocks     Used Available Use% Mounted on\n\
+         tmpfs             401588     1120    400468   1% /run\n\
+         /dev/sda2       81000912 19456204  57389444  26% /\n\
+         tmpfs            2007936        0   2007936   0% /dev/shm\n\
+         tmpfs               5120        0      5120   0% /run/lock\n\
+         /dev/sda1         523248     6220    517028   2% /boot/efi\n",
+    )
+}
+
+fn history(shell: &FakeShell) -> CommandOutput {
+    let lines: String = shell.history.iter().enumerate().map(|(i, line)| format!("{:>5}  {}\n", i + 1, line)).collect();
+    CommandOutput::ok(lines)
+}
+
+fn export(shell: &mut FakeShell, args: &[String]) -> CommandOutput {
+    for arg in args {
+        if let Some((name, value)) = arg.split_once('=') {
+            shell.env.insert(name.to_string(), value.to_string());
+        }
+    }
+    CommandOutput::default()
+}
+
+fn which(shell: &FakeShell, args: &[String]) -> CommandOutput {
+    let mut output = CommandOutput::default();
+    for name in args.iter().filter(|a| !a.starts_with('-')) {
+        match find_in_path(shell, name) {
+            Some(path) => output.stdout.push_str(&format!("{}\n", path)),
+            None => output.status = 1,
+        }
+    }
+    output
+}
+
+fn mkdir(shell: &mut FakeShell, args: &[String]) -> CommandOutput {
+    let parents = args.iter().any(|a| a == "-p");
+    let mut output = CommandOutput::default();
+    for dir in args.iter().filter(|a| !a.starts_with('-')) {
+        let path = filesystem::normalize(&shell.cwd, dir);
+        if shell.fs.get(&path).is_some() {
+            if !parents {
+                output.stderr.push_str(&format!("mkdir: cannot create directory ‘{}’: File exists\n", dir));
+                output.status = 1;
+            }
+            continue;
+        }
+
+        let result = if parents {
+            let mut current = String::new();
+            path.split('/').filter(|c| !c.is_empty()).try_for_each(|component| {
+                current.push('/');
+                current.push_str(component);
+                if shell.fs.is_dir(&current) { Ok(()) } else { shell.fs.mkdir(&current) }
+            })
+        } else {
+            shell.fs.mkdir(&path)
+        };
+        if let Err(e) = result {
+            output.stderr.push_str(&format!("mkdir: cannot create directory ‘{}’: {}\n", dir, e.message()));
+            output.status = 1;
+        }
+    }
+    output
+}
+
+fn touch(shell: &mut FakeShell, args: &[String]) -> CommandOutput {
+    let mut output = CommandOutput::default();
+    for file in args.iter().filter(|a| !a.starts_with('-')) {
+        let path = filesystem::normalize(&shell.cwd, file);
+        if shell.fs.get(&path).is_none()
+            && let Err(e) = shell.fs.write(&path, b"", true)
+        {
+            output.stderr.push_str(&format!("touch: cannot touch '{}': {}\n", file, e.message()));
+            output.status = 1;
+        }
+    }
+    output
+}
+
+fn rm(shell: &mut FakeShell, args: &[String]) -> CommandOutput {
+    let flags: String = args.iter().filter(|a| a.starts_with('-')).flat_map(|a| a.chars().skip(1)).collect();
+    let recursive = flags.contains('r') || flags.contains('R');
+    let force = flags.contains('f');
+
+    let mut output = CommandOutput::default();
+    for file in args.iter().filter(|a| !a.starts_with('-')) {
+        let path = filesystem::normalize(&shell.cwd, file);
+        match shell.fs.remove(&path, recursive) {
+            Ok(()) => {}
+            Err(filesystem::FsError::NotFound) if force => {}
+            Err(e) => {
+                output.stderr.push_str(&format!("rm: cannot remove '{}': {}\n", file, e.message()));
+                output.status = 1;
+            }
+        }
+    }
+    output
+}
+
+fn copy(shell: &mut FakeShell, program: &str, args: &[String]) -> CommandOutput {
+    let operands: Vec<&String> = args.iter().filter(|a| !a.starts_with('-')).collect();
+    let [source, destination] = operands[..] else {
+        return CommandOutput::error(format!("{}: missing file operand\nTry '{} --help' for more information.\n", program, program), 1);
+    };
+
+    let source_path = filesystem::normalize(&shell.cwd, source);
+    let content = match shell.fs.read(&source_path) {
+        Ok(content) => content.to_vec(),
+        Err(e) => return CommandOutput::error(format!("{}: cannot stat '{}': {}\n", program, source, e.message()), 1),
+    };
+
+    let mut destination_path = filesystem::normalize(&shell.cwd, destination);
+    if shell.fs.is_dir(&destination_path) {
+        let name = source_path.rsplit('/').next().unwrap_or_default();
+        destination_path = filesystem::normalize(&destination_path, name);
+    }
+    if let Err(e) = shell.fs.write(&destination_path, &content, false) {
+        return CommandOutput::error(format!("{}: cannot create regular file '{}': {}\n", program, destination, e.message()), 1);
+    }
+    if program == "mv" {
+        let _ = shell.fs.remove(&source_path, false);
+    }
+    CommandOutput::default()
+}
+
+/// Text of the file operands, or stdin when there are none
+fn input_text(shell: &FakeShell, program: &str, files: &[&String], stdin: &str) -> Result<String, CommandOutput> {
+    if files.is_empty() {
+        return Ok(stdin.to_string());
+    }
+    let mut text = String::new();
+    for file in files {
+        match shell.fs.read(&filesystem::normalize(&shell.cwd, file)) {
+            Ok(content) => text.push_str(&String::from_utf8_lossy(content)),
+            Err(e) => return Err(CommandOutput::error(format!("{}: {}: {}\n", program, file, e.message()), 2)),
+        }
+    }
+    Ok(text)
+}
+
+fn grep(shell: &FakeShell, args: &[String], stdin: &str) -> CommandOutput {
+    let ignore_case = args.iter().any(|a| a.starts_with('-') && a.contains('i'));
+    let invert = args.iter().any(|a| a.starts_with('-') && a.contains('v'));
+    let operands: Vec<&String> = args.iter().filter(|a| !a.starts_with('-')).collect();
+    let Some((pattern, files)) = operands.split_first() else {
+        return CommandOutput::error("Usage: grep [OPTION]... PATTERNS [FILE]...\n", 2);
+    };
+
+    let text = match input_text(shell, "grep", files, stdin) {
+        Ok(text) => text,
+        Err(output) => return output,
+    };
+    let pattern = if ignore_case { pattern.to_lowercase() } else { pattern.to_string() };
+    let matches: String = text
+        .lines()
+        .filter(|line| (if ignore_case { line.to_lowercase().contains(&pattern) } else { line.contains(&pattern) }) != invert)
+        .map(|line| format!("{}\n", line))
+        .collect();
+
+    let status = if matches.is_empty() { 1 } else { 0 };
+    CommandOutput { stdout: matches, stderr: String::new(), status }
+}
+
+fn head_tail(shell: &FakeShell, program: &str, args: &[String], stdin: &str) -> CommandOutput {
+    let mut count = 10;
+    let mut files = Vec::new();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        if arg == "-n" {
+            count = args.next().and_then(|n| n.parse().ok()).unwrap_or(10);
+        } else if let Some(n) = arg.strip_prefix("-n").or_else(|| arg.strip_prefix('-')) {
+            count = n.parse().unwrap_or(count);
+        } else {
+            files.push(arg);
+        }
+    }
+
+    let text = match input_text(shell, program, &files, stdin) {
+        Ok(text) => text,
+        Err(output) => return output,
+    };
+    let lines: Vec<&str> = text.lines().collect();
+    let selected = if program == "head" { &lines[..count.min(lines.len())] } else { &lines[lines.len().saturating_sub(count)..] };
+    CommandOutput::ok(selected.iter().map(|line| format!("{}\n", line)).collect::<String>())
+}
+
+fn wc(shell: &FakeShell, args: &[String], stdin: &str) -> CommandOutput {
+    let files: Vec<&String> = args.iter().filter(|a| !a.starts_with('-')).collect();
+    let text = match input_text(shell, "wc", &files, stdin) {
+        Ok(text) => text,
+        Err(output) => return output,
+    };
+
+    let lines = text.matches('\n').count();
+    if args.iter().any(|a| a == "-l") {
+        return CommandOutput::ok(format!("{}\n", lines));
+    }
+    CommandOutput::ok(format!("{:>7} {:>7} {:>7}\n", lines, text.split_whitespace().count(), text.len()))
+}
+
+/// URLs are reported but never fetched; name resolution "fails" instead
+fn wget(shell: &mut FakeShell, args: &[String]) -> CommandOutput {
+    let urls = download_urls(args, &["-O", "-o", "-P", "--output-document", "-U", "--user-agent"]);
+    if urls.is_empty() {
+        return CommandOutput::error("wget: missing URL\nUsage: wget [OPTION]... [URL]...\n\nTry `wget --help' for more options.\n", 1);
+    }
+
+    let mut output = CommandOutput::error("", 4);
+    for url in urls {
+        shell.events.record(HoneypotEvent::Download { url: url.clone() });
+        let host = url_host(&url);
+        if shell.flavor == ShellFlavor::BusyBox {
+            let port = if url.starts_with("https://") { 443 } else { 80 };
+            output.stderr.push_str(&format!("Connecting to {} ({}:{})\nwget: can't connect to remote host ({}): Connection timed out\n", host, host, port, host));
+            output.status = 1;
+            continue;
+        }
+        output.stderr.push_str(&format!("--{}--  {}\n", format_timestamp(now()), url));
+        if host.parse::<std::net::IpAddr>().is_ok() {
+            let port = if url.starts_with("https://") { 443 } else { 80 };
+            output.stderr.push_str(&format!("Connecting to {}:{}... failed: Connection timed out.\nRetrying.\n\n", host, port));
+        } else {
+            output.stderr.push_str(&format!(
+                "Resolving {} ({})... failed: Temporary failure in name resolution.\nwget: unable to resolve host address ‘{}’\n",
+                host, host, host,
+            ));
+        }
+    }
+    output
+}
+
+fn curl(shell: &mut FakeShell, args: &[String]) -> CommandOutput {
+    let urls = download_urls(args, &["-o", "-A", "-H", "-d", "-X", "--output", "--user-agent", "--header", "--data"]);
+    let Some(url) = urls.first() else {
+        return CommandOutput::error("curl: try 'curl --help' or 'curl --manual' for more information\n", 2);
+    };
+
+    for url in &urls {
+        shell.events.record(HoneypotEvent::Download { url: url.clone() });
+    }
+    if args.iter().any(|a| a == "-s" || a == "-fsSL" || a == "-sSL" || a == "--silent") {
+        return CommandOutput::error("", 6);
+    }
+    CommandOutput::error(format!("curl: (6) Could not resolve host: {}\n", url_host(url)), 6)
+}
+
+fn download_urls(args: &[String], options_with_value: &[&str]) -> Vec<String> {
+    let mut urls = Vec::new();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        if options_with_value.contains(&arg.as_str()) {
+            args.next();
+        } else if !arg.starts_with('-') {
+            urls.push(arg.clone());
+        }
+    }
+    urls
+}
+
+fn url_host(url: &str) -> &str {
+    let rest = url.split_once("://").map(|(_, rest)| rest).unwrap_or(url);
+    let authority = rest.split('/').next().unwrap_or(rest);
+    let host = authority.rsplit('@').next().unwrap_or(authority);
+    host.split(':').next().unwrap_or(host)
+}
+
+/// Anything else: programs present in the fake PATH exit silently, the rest are not found
+fn external(shell: &FakeShell, program: &str) -> CommandOutput {
+    if program.contains('/') {
+        let path = filesystem::normalize(&shell.cwd, program);
+        return match shell.fs.get(&path) {
+            Some(FsEntry::File(content)) if !content.is_empty() && !path.contains("bin/") => {
+                CommandOutput::error(format!("{}: {}: cannot execute binary file: Exec format error\n", shell.name(), program), 126)
+            }
+            Some(FsEntry::File(_)) => CommandOutput::default(),
+            Some(FsEntry::Directory) => CommandOutput::error(format!("{}: {}: Is a directory\n", shell.name(), program), 126),
+            None => CommandOutput::error(format!("{}: {}: No such file or directory\n", shell.name(), program), 127),
+        };
+    }
+
+    match (find_in_path(shell, program), shell.flavor) {
+        (Some(_), _) => CommandOutput::default(),
+        (None, ShellFlavor::Bash) => CommandOutput::error(format!("-bash: {}: command not found\n", program), 127),
+        (None, ShellFlavor::BusyBox) => CommandOutput::error(format!("-sh: {}: not found\n", program), 127),
+    }
+}
+
+
+/// `busybox APPLET ...`: bots call unknown applets on purpose and expect "applet not found"
+fn busybox(shell: &mut FakeShell, args: &[String], stdin: &str) -> CommandOutput {
+    let Some(applet) = args.first() else {
+        return CommandOutput::ok(format!(
+            "{} multi-call binary.\nCopyright (C) 1998-2011 Erik Andersen, Rob Landley, Denys Vlasenko\nand others. Licensed under GPLv2.\n\n\
+             Usage: busybox [function] [arguments]...\n   or: busybox --list\n   or: function [arguments]...\n\n\
+             Currently defined functions:\n\t{}\n",
+            BUSYBOX_VERSION,
+            BUSYBOX_APPLETS.join(", "),
+        ));
+    };
+
+    match applet.as_str() {
+        "--list" => CommandOutput::ok(BUSYBOX_APPLETS.iter().map(|a| format!("{}\n", a)).collect::<String>()),
+        "sh" => shell.run_script(args, stdin, 1),
+        applet if BUSYBOX_APPLETS.contains(&applet) => run(shell, args, stdin),
+        applet => CommandOutput::error(format!("{}: applet not found\n", applet), 127),
+    }
+}
+
+fn busybox_ps(shell: &FakeShell) -> CommandOutput {
+    let processes = [
+        (1, "root", 1524, "S", "init"),
+        (2, "root", 0, "SW", "[kthreadd]"),
+        (3, "root", 0, "SW", "[ksoftirqd/0]"),
+        (412, "root", 1516, "S", "/sbin/syslogd -n"),
+        (468, "root", 2944, "S", "/usr/sbin/telnetd -l /bin/login"),
+        (502, "root", 18744, "S", "/usr/bin/dvr_main"),
+        (611, "root", 1520, "S", "/sbin/watchdog -t 5 /dev/watchdog"),
+    ];
+
+    let mut out = String::from("  PID USER       VSZ STAT COMMAND\n");
+    for (pid, user, vsz, stat, cmd) in processes {
+        out.push_str(&format!("{:>5} {:<8} {:>6} {:<4} {}\n", pid, user, vsz, stat, cmd));
+    }
+    out.push_str(&format!("{:>5} {:<8} {:>6} {:<4} -sh\n", SHELL_PID, shell.user, 1528, "S"));
+    out.push_str(&format!("{:>5} {:<8} {:>6} {:<4} ps\n", SHELL_PID + 37, shell.user, 1524, "R"));
+    CommandOutput::ok(out)
+}
+
+/// `tftp -g -r FILE HOST`: reported as a download, the transfer always times out
+fn tftp(shell: &mut FakeShell, args: &[String]) -> CommandOutput {
+    let mut remote = None;
+    let mut host = None;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-r" => remote = args.next(),
+            "-l" => {
+                args.next();
+            }
+            arg if arg.starts_with('-') => {}
+            _ if host.is_none() => host = Some(arg),
+            _ => {}
+        }
+    }
+
+    let (Some(remote), Some(host)) = (remote, host) else {
+        return CommandOutput::error(format!("{} multi-call binary.\n\nUsage: tftp [OPTIONS] HOST [PORT]\n", BUSYBOX_VERSION), 1);
+    };
+    shell.events.record(HoneypotEvent::Download { url: format!("tftp://{}/{}", host, remote) });
+    CommandOutput::error("tftp: timeout\n", 1)
+}
+
+fn find_in_path(shell: &FakeShell, name: &str) -> Option<String> {
+    let path = shell.env.get("PATH").map(String::as_str).unwrap_or("/usr/bin:/bin");
+    path.split(':')
+        .map(|dir| format!("{}/{}", dir, name))
+        .find(|candidate| matches!(shell.fs.get(candidate), Some(FsEntry::File(_))))
+}
@@ -0,0 +1,238 @@
+use std::{collections::{BTreeMap, HashMap}, fs::File, io::BufReader, sync::Arc};
+
+use lazy_static::lazy_static;
+use serde::Deserialize;
+use tracing::error;
+
+const FILESYSTEM_SNAPSHOT: &str = "src/honeypot/shell/filesystem.json";
+const BUSYBOX_SNAPSHOT: &str = "src/honeypot/shell/busybox.json";
+/// Bytes a session may add to its copy of the filesystem
+pub const SESSION_QUOTA: usize = 16 * 1024 * 1024;
+
+
+lazy_static! {
+    /// Snapshot every shell session starts from; sessions only ever modify their own copy
+    pub static ref DEFAULT_FILESYSTEM: Arc<VirtualFs> = Arc::new(VirtualFs::load(FILESYSTEM_SNAPSHOT));
+    /// Snapshot for BusyBox shells of emulated embedded devices
+    pub static ref BUSYBOX_FILESYSTEM: Arc<VirtualFs> = Arc::new(VirtualFs::load(BUSYBOX_SNAPSHOT));
+}
+
+/// On-disk description of the fake filesystem
+#[derive(Debug, Deserialize)]
+struct FilesystemSnapshot {
+    directories: Vec<String>,
+    files: HashMap<String, String>,
+}
+
+#[derive(Debug, Clone)]
+pub enum FsEntry {
+    Directory,
+    File(Vec<u8>),
+}
+
+#[derive(Debug)]
+pub enum FsError {
+    NotFound,
+    NotADirectory,
+    IsADirectory,
+    NoSpace,
+}
+
+impl FsError {
+    pub fn message(&self) -> &'static str {
+        match self {
+            FsError::NotFound => "No such file or directory",
+            FsError::NotADirectory => "Not a directory",
+            FsError::IsADirectory => "Is a directory",
+            FsError::NoSpace => "No space left on device",
+        }
+    }
+}
+
+/// In-memory filesystem keyed by absolute, normalized path
+#[derive(Debug, Clone, Default)]
+pub struct VirtualFs {
+    entries: BTreeMap<String, FsEntry>,
+    /// Bytes of every path and file content
+    used: usize,
+    /// Most `used` may grow to, unlimited when `None`
+    limit: Option<usize>,
+}
+
+impl VirtualFs {
+    pub fn load(path: &str) -> Self {
+        let snapshot = File::open(path)
+            .map_err(|e| e.to_string())
+            .and_then(|file| serde_json::from_reader::<_, FilesystemSnapshot>(BufReader::new(file)).map_err(|e| e.to_string()));
+
+        let mut fs = VirtualFs::default();
+        fs.entries.insert("/".to_string(), FsEntry::Directory);
+
+        match snapshot {
+            Ok(snapshot) => {
+                for dir in snapshot.directories {
+                    fs.create_dir_all(&normalize("/", &dir));
+                }
+                for (path, content) in snapshot.files {
+                    let path = normalize("/", &path);
+                    fs.create_dir_all(&parent(&path));
+                    let _ = fs.write(&path, content.as_bytes(), false);
+                }
+            }
+            Err(e) => {
+                error!("❌ Impossibile caricare il filesystem del honeypot {}: {}", path, e);
+                for dir in ["/bin", "/etc", "/home", "/root", "/tmp", "/usr", "/var"] {
+                    fs.create_dir_all(dir);
+                }
+            }
+        }
+
+        fs
+    }
+
+    /// Copy that may only grow by `bytes` over its current size
+    pub fn with_quota(mut self, bytes: usize) -> Self {
+        self.limit = Some(self.used + bytes);
+        self
+    }
+
+    /// Accounts for `added` new bytes replacing `freed` ones, unless that goes over the quota
+    fn charge(&mut self, added: usize, freed: usize) -> Result<(), FsError> {
+        let used = self.used.saturating_sub(freed) + added;
+        if self.limit.is_some_and(|limit| used > limit) {
+            return Err(FsError::NoSpace);
+        }
+        self.used = used;
+        Ok(())
+    }
+
+    pub fn get(&self, path: &str) -> Option<&FsEntry> {
+        self.entries.get(path)
+    }
+
+    pub fn is_dir(&self, path: &str) -> bool {
+        matches!(self.entries.get(path), Some(FsEntry::Directory))
+    }
+
+    pub fn read(&self, path: &str) -> Result<&[u8], FsError> {
+        match self.entries.get(path) {
+            Some(FsEntry::File(content)) => Ok(content),
+            Some(FsEntry::Directory) => Err(FsError::IsADirectory),
+            None => Err(FsError::NotFound),
+        }
+    }
+
+    /// Direct children of a directory, sorted by name
+    pub fn list(&self, dir: &str) -> Result<Vec<(&str, &FsEntry)>, FsError> {
+        match self.entries.get(dir) {
+            Some(FsEntry::Directory) => {}
+            Some(FsEntry::File(_)) => return Err(FsError::NotADirectory),
+            None => return Err(FsError::NotFound),
+        }
+
+        let prefix = if dir == "/" { "/".to_string() } else { format!("{}/", dir) };
+        Ok(self.entries
+            .range(prefix.clone()..)
+            .take_while(|(path, _)| path.starts_with(&prefix))
+            .filter(|(path, _)| path.len() > prefix.len() && !path[prefix.len()..].contains('/'))
+            .map(|(path, entry)| (&path[prefix.len()..], entry))
+            .collect())
+    }
+
+    pub fn write(&mut self, path: &str, data: &[u8], append: bool) -> Result<(), FsError> {
+        if !self.is_dir(&parent(path)) {
+            return Err(FsError::NotFound);
+        }
+
+        match self.entries.get(path) {
+            Some(FsEntry::Directory) => Err(FsError::IsADirectory),
+            Some(FsEntry::File(_)) if append => {
+                self.charge(data.len(), 0)?;
+                if let Some(FsEntry::File(content)) = self.entries.get_mut(path) {
+                    content.extend_from_slice(data);
+                }
+                Ok(())
+            }
+            Some(FsEntry::File(content)) => {
+                self.charge(data.len(), content.len())?;
+                self.entries.insert(path.to_string(), FsEntry::File(data.to_vec()));
+                Ok(())
+            }
+            None => {
+                self.charge(path.len() + data.len(), 0)?;
+                self.entries.insert(path.to_string(), FsEntry::File(data.to_vec()));
+                Ok(())
+            }
+        }
+    }
+
+    pub fn mkdir(&mut self, path: &str) -> Result<(), FsError> {
+        if !self.is_dir(&parent(path)) {
+            return Err(FsError::NotFound);
+        }
+        if !self.entries.contains_key(path) {
+            self.charge(path.len(), 0)?;
+            self.entries.insert(path.to_string(), FsEntry::Directory);
+        }
+        Ok(())
+    }
+
+    pub fn remove(&mut self, path: &str, recursive: bool) -> Result<(), FsError> {
+        match self.entries.get(path) {
+            None => Err(FsError::NotFound),
+            Some(FsEntry::Directory) if !recursive => Err(FsError::IsADirectory),
+            Some(_) => {
+                let prefix = format!("{}/", path);
+                let mut freed = 0;
+                self.entries.retain(|entry, content| {
+                    let keep = entry != path && !entry.starts_with(&prefix);
+                    if !keep {
+                        freed += entry.len() + match content {
+                            FsEntry::File(content) => content.len(),
+                            FsEntry::Directory => 0,
+                        };
+                    }
+                    keep
+                });
+                self.used = self.used.saturating_sub(freed);
+                Ok(())
+            }
+        }
+    }
+
+    // Only used while loading a snapshot, before any quota
+    fn create_dir_all(&mut self, path: &str) {
+        let mut current = String::new();
+        for component in path.split('/').filter(|c| !c.is_empty()) {
+            current.push('/');
+            current.push_str(component);
+            let _ = self.mkdir(&current);
+        }
+    }
+}
+
+
+/// Resolves `path` against `cwd` into an absolute path without `.`/`..` components
+pub fn normalize(cwd: &str, path: &str) -> String {
+    let joined = if path.starts_with('/') { path.to_string() } else { format!("{}/{}", cwd, path) };
+
+    let mut components: Vec<&str> = Vec::new();
+    for component in joined.split('/') {
+        match component {
+            "" | "." => {}
+            ".." => {
+                components.pop();
+            }
+            component => components.push(component),
+        }
+    }
+
+    format!("/{}", components.join("/"))
+}
+
+pub fn parent(path: &str) -> String {
+    match path.rfind('/') {
+        Some(0) | None => "/".to_string(),
+        Some(index) => path[..index].to_string(),
+    }
+}
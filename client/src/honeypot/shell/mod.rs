@@ -0,0 +1,696 @@
+pub mod commands;
+pub mod filesystem;
+
+use std::collections::BTreeMap;
+
+use common::types::HoneypotEvent;
+
+use commands::CommandOutput;
+use filesystem::{VirtualFs, BUSYBOX_FILESYSTEM, DEFAULT_FILESYSTEM, SESSION_QUOTA};
+use crate::honeypot::services::{session::EventSink, time};
+
+// Output kept per command in the reported transcript
+const MAX_REPORTED_OUTPUT: usize = 4096;
+// Nesting allowed for `sh -c` / `sh script`
+const MAX_DEPTH: usize = 4;
+// Commands a session may run, nested scripts included, and output it may produce: past these
+// the shell refuses to run anything, so scripts calling themselves can't keep a worker busy
+const MAX_COMMANDS: usize = 10_000;
+const MAX_OUTPUT: usize = 16 * 1024 * 1024;
+// Distribution the bash login banner names unless the service sets one
+const DEFAULT_RELEASE: &str = "Ubuntu 22.04.4 LTS";
+// Longest line the terminal buffers; keystrokes past it are dropped until the line ends
+const MAX_LINE: usize = 4096;
+
+
+/// Result of running one command line
+#[derive(Debug, Default)]
+pub struct ShellOutput {
+    pub output: String,
+    pub exit_status: u32,
+    pub exit: bool,
+}
+
+/// Which system the shell pretends to be
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ShellFlavor {
+    /// bash on an Ubuntu server
+    Bash,
+    /// BusyBox ash on an embedded Linux device
+    BusyBox,
+}
+
+/// Emulated bash session attached to a logged-in honeypot service. Each session works on its
+/// own copy of the fake filesystem and reports every command line with its output.
+pub struct FakeShell {
+    flavor: ShellFlavor,
+    user: String,
+    hostname: String,
+    release: Option<String>,
+    home: String,
+    cwd: String,
+    fs: VirtualFs,
+    env: BTreeMap<String, String>,
+    history: Vec<String>,
+    last_status: u32,
+    events: EventSink,
+    commands_run: usize,
+    output_produced: usize,
+}
+
+impl FakeShell {
+    pub fn new(user: &str, hostname: &str, events: EventSink) -> Self {
+        FakeShell::with_flavor(ShellFlavor::Bash, user, hostname, events)
+    }
+
+    /// Shell of an embedded device: BusyBox applets, ash error messages and a device filesystem
+    pub fn busybox(user: &str, hostname: &str, events: EventSink) -> Self {
+        FakeShell::with_flavor(ShellFlavor::BusyBox, user, hostname, events)
+    }
+
+    fn with_flavor(flavor: ShellFlavor, user: &str, hostname: &str, events: EventSink) -> Self {
+        let user = if user.is_empty() { "root" } else { user };
+        let home = match (flavor, user) {
+            (_, "root") => "/root".to_string(),
+            (ShellFlavor::Bash, user) => format!("/home/{}", user),
+            (ShellFlavor::BusyBox, _) => "/".to_string(),
+        };
+
+        let mut fs = match flavor {
+            ShellFlavor::Bash => VirtualFs::clone(&DEFAULT_FILESYSTEM),
+            ShellFlavor::BusyBox => VirtualFs::clone(&BUSYBOX_FILESYSTEM),
+        }.with_quota(SESSION_QUOTA);
+        if !fs.is_dir(&home) {
+            let _ = fs.mkdir(&home);
+        }
+
+        let env = match flavor {
+            ShellFlavor::Bash => BTreeMap::from([
+                ("HOME".to_string(), home.clone()),
+                ("USER".to_string(), user.to_string()),
+                ("LOGNAME".to_string(), user.to_string()),
+                ("SHELL".to_string(), "/bin/bash".to_string()),
+                ("PATH".to_string(), "/usr/local/sbin:/usr/local/bin:/usr/sbin:/usr/bin:/sbin:/bin".to_string()),
+                ("LANG".to_string(), "en_US.UTF-8".to_string()),
+                ("TERM".to_string(), "xterm-256color".to_string()),
+                ("HOSTNAME".to_string(), hostname.to_string()),
+            ]),
+            ShellFlavor::BusyBox => BTreeMap::from([
+                ("HOME".to_string(), home.clone()),
+                ("USER".to_string(), user.to_string()),
+                ("LOGNAME".to_string(), user.to_string()),
+                ("SHELL".to_string(), "/bin/sh".to_string()),
+                ("PATH".to_string(), "/bin:/sbin:/usr/bin:/usr/sbin".to_string()),
+                ("TERM".to_string(), "vt102".to_string()),
+            ]),
+        };
+
+        FakeShell {
+            flavor,
+            user: user.to_string(),
+            hostname: hostname.to_string(),
+            release: Some(DEFAULT_RELEASE.to_string()),
+            cwd: home.clone(),
+            home,
+            fs,
+            env,
+            history: Vec::new(),
+            last_status: 0,
+            events,
+            commands_run: 0,
+            output_produced: 0,
+        }
+    }
+
+    /// Distribution named by the login banner; none leaves only the last login line
+    pub fn with_release(mut self, release: Option<&str>) -> Self {
+        self.release = release.map(str::to_string);
+        self
+    }
+
+    pub fn motd(&self) -> String {
+        if self.flavor == ShellFlavor::BusyBox {
+            return format!("\n\n{} built-in shell (ash)\nEnter 'help' for a list of built-in commands.\n\n", commands::BUSYBOX_VERSION);
+        }
+        let mut motd = String::new();
+        if let Some(release) = &self.release {
+            motd.push_str(&format!("Welcome to {} (GNU/Linux {} x86_64)\n\n", release, commands::KERNEL_RELEASE));
+            if release.starts_with("Ubuntu") {
+                motd.push_str(
+                    " * Documentation:  https://help.ubuntu.com\n \
+                     * Management:     https://landscape.canonical.com\n \
+                     * Support:        https://ubuntu.com/pro\n\n",
+                );
+            }
+        }
+        motd.push_str(&format!("Last login: {} from 10.0.0.12\n", time::format_date(time::now() - 86_400 * 3 - 4_213)));
+        motd
+    }
+
+    pub fn prompt(&self) -> String {
+        let cwd = if self.cwd == self.home {
+            "~".to_string()
+        } else if let Some(rest) = self.cwd.strip_prefix(&format!("{}/", self.home)) {
+            format!("~/{}", rest)
+        } else {
+            self.cwd.clone()
+        };
+        let sigil = if self.user == "root" { '#' } else { '$' };
+        if self.flavor == ShellFlavor::BusyBox {
+            return format!("{} {} ", cwd, sigil);
+        }
+        format!("{}@{}:{}{} ", self.user, self.hostname, cwd, sigil)
+    }
+
+    /// Runs a line typed by the attacker and reports it
+    pub fn execute(&mut self, line: &str) -> ShellOutput {
+        let line = line.trim();
+        if line.is_empty() {
+            return ShellOutput::default();
+        }
+
+        let cwd = self.cwd.clone();
+        self.history.push(line.to_string());
+        let result = self.run_line(line, 0);
+
+        let mut output = result.output.clone();
+        if output.len() > MAX_REPORTED_OUTPUT {
+            let mut end = MAX_REPORTED_OUTPUT;
+            while !output.is_char_boundary(end) {
+                end -= 1;
+            }
+            output.truncate(end);
+        }
+        self.events.record(HoneypotEvent::Command { line: line.to_string(), cwd, output, exit_status: result.exit_status });
+
+        result
+    }
+
+    fn run_line(&mut self, line: &str, depth: usize) -> ShellOutput {
+        let mut result = ShellOutput::default();
+        if depth > MAX_DEPTH {
+            return result;
+        }
+
+        let tokens = tokenize(line, &self.env, self.last_status);
+        for (pipeline, connector) in split_lists(&tokens) {
+            let run = match connector {
+                Connector::And => self.last_status == 0,
+                Connector::Or => self.last_status != 0,
+                Connector::Always => true,
+            };
+            if !run {
+                continue;
+            }
+
+            let mut stdin = String::new();
+            let mut status = 0;
+            for (index, command) in pipeline.iter().enumerate() {
+                let Some(program) = command.argv.first() else {
+                    continue;
+                };
+
+                if program == "exit" || program == "logout" {
+                    result.exit = true;
+                    result.exit_status = command.argv.get(1).and_then(|s| s.parse().ok()).unwrap_or(self.last_status);
+                    if program == "logout" || (depth == 0 && self.flavor == ShellFlavor::Bash) {
+                        result.output.push_str("logout\n");
+                    }
+                    return result;
+                }
+
+                if !self.within_budget() {
+                    result.output.push_str(&format!("{}: fork: retry: Resource temporarily unavailable\n", self.name()));
+                    self.last_status = 254;
+                    result.exit_status = 254;
+                    return result;
+                }
+                self.commands_run += 1;
+
+                let mut output = if program == "sh" || program == "bash" {
+                    self.run_script(&command.argv, &stdin, depth)
+                } else {
+                    commands::run(self, &command.argv, &stdin)
+                };
+                self.output_produced += output.stdout.len() + output.stderr.len();
+                if self.output_produced > MAX_OUTPUT {
+                    output = CommandOutput::error(format!("{}: write error: No space left on device\n", program), 1);
+                }
+
+                if let Some(redirect) = &command.redirect
+                    && redirect.path != "/dev/null"
+                {
+                    let path = filesystem::normalize(&self.cwd, &redirect.path);
+                    if let Err(e) = self.fs.write(&path, output.stdout.as_bytes(), redirect.append) {
+                        output.stderr.push_str(&format!("{}: {}: {}\n", self.name(), redirect.path, e.message()));
+                        output.status = 1;
+                    }
+                }
+                if command.redirect.is_some() {
+                    output.stdout.clear();
+                }
+                if command.discard_stderr {
+                    output.stderr.clear();
+                }
+
+                // stderr always reaches the terminal, stdout feeds the next command of the pipeline
+                result.output.push_str(&output.stderr);
+                status = output.status;
+                if index + 1 == pipeline.len() {
+                    result.output.push_str(&output.stdout);
+                } else {
+                    stdin = output.stdout;
+                }
+            }
+            self.last_status = status;
+        }
+
+        result.exit_status = self.last_status;
+        result
+    }
+
+    fn within_budget(&self) -> bool {
+        self.commands_run < MAX_COMMANDS && self.output_produced <= MAX_OUTPUT
+    }
+
+    /// Name used as prefix of the shell's own error messages
+    fn name(&self) -> &'static str {
+        match self.flavor {
+            ShellFlavor::Bash => "-bash",
+            ShellFlavor::BusyBox => "-sh",
+        }
+    }
+
+    /// `sh -c "..."`, `sh script.sh` and `... | sh` run through this same shell
+    fn run_script(&mut self, argv: &[String], stdin: &str, depth: usize) -> CommandOutput {
+        let script = match argv.get(1).map(String::as_str) {
+            Some("-c") => argv.get(2).cloned().unwrap_or_default(),
+            Some(path) => {
+                let absolute = filesystem::normalize(&self.cwd, path);
+                match self.fs.read(&absolute) {
+                    Ok(content) => String::from_utf8_lossy(content).to_string(),
+                    Err(e) => return CommandOutput::error(format!("{}: {}: {}\n", argv[0], path, e.message()), 127),
+                }
+            }
+            None => stdin.to_string(),
+        };
+
+        let mut output = String::new();
+        for line in script.lines() {
+            let result = self.run_line(line, depth + 1);
+            output.push_str(&result.output);
+            if result.exit || !self.within_budget() {
+                break;
+            }
+        }
+        CommandOutput { stdout: output, stderr: String::new(), status: self.last_status }
+    }
+}
+
+
+/// Turns shell output into what a terminal expects
+pub fn to_terminal(output: &str) -> Vec<u8> {
+    output.replace('\n', "\r\n").into_bytes()
+}
+
+
+/// Result of feeding keystrokes to a `LineEditor`
+#[derive(Debug, Default)]
+pub struct LineInput {
+    pub lines: Vec<EnteredLine>,
+    /// Echo of the keystrokes on the line still being typed
+    pub echo: Vec<u8>,
+    pub eof: bool,
+}
+
+/// A finished line and the echo of the keystrokes that made it, so each line can be
+/// echoed or not depending on what it answers
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct EnteredLine {
+    pub text: String,
+    pub echo: Vec<u8>,
+}
+
+/// Minimal terminal line discipline: echo, backspace, Ctrl-C and Ctrl-D, with lines capped at `MAX_LINE`
+#[derive(Debug, Default)]
+pub struct LineEditor {
+    line: Vec<u8>,
+    last_cr: bool,
+}
+
+impl LineEditor {
+    pub fn feed(&mut self, data: &[u8]) -> LineInput {
+        let mut input = LineInput::default();
+
+        for &byte in data {
+            let last_cr = std::mem::replace(&mut self.last_cr, byte == b'\r');
+            match byte {
+                // CR LF counts as a single line end
+                b'\n' if last_cr => {}
+                b'\r' | b'\n' => {
+                    input.echo.extend_from_slice(b"\r\n");
+                    let text = String::from_utf8_lossy(&std::mem::take(&mut self.line)).to_string();
+                    input.lines.push(EnteredLine { text, echo: std::mem::take(&mut input.echo) });
+                }
+                // Backspace / DEL
+                0x08 | 0x7f if self.line.pop().is_some() => input.echo.extend_from_slice(b"\x08 \x08"),
+                0x08 | 0x7f => {}
+                // Ctrl-C drops the current line
+                0x03 => {
+                    self.line.clear();
+                    input.echo.extend_from_slice(b"^C\r\n");
+                    input.lines.push(EnteredLine { text: String::new(), echo: std::mem::take(&mut input.echo) });
+                }
+                // Ctrl-D on an empty line
+                0x04 if self.line.is_empty() => {
+                    input.eof = true;
+                    break;
+                }
+                byte if byte >= 0x20 && self.line.len() < MAX_LINE => {
+                    self.line.push(byte);
+                    input.echo.push(byte);
+                }
+                _ => {}
+            }
+        }
+
+        input
+    }
+}
+
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Word(String),
+    Operator(&'static str),
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Connector {
+    Always,
+    And,
+    Or,
+}
+
+#[derive(Debug, Default)]
+struct SimpleCommand {
+    argv: Vec<String>,
+    redirect: Option<Redirect>,
+    discard_stderr: bool,
+}
+
+#[derive(Debug)]
+struct Redirect {
+    path: String,
+    append: bool,
+}
+
+/// Splits a command line into words and operators, handling quotes, escapes, comments and `$VAR`
+fn tokenize(line: &str, env: &BTreeMap<String, String>, last_status: u32) -> Vec<Token> {
+    let mut tokens = Vec::new();
+    let mut word = String::new();
+    let mut in_word = false;
+    let mut chars = line.chars().peekable();
+
+    let expand = |chars: &mut std::iter::Peekable<std::str::Chars>, word: &mut String| {
+        if chars.peek() == Some(&'?') {
+            chars.next();
+            word.push_str(&last_status.to_string());
+            return;
+        }
+        let braced = chars.peek() == Some(&'{');
+        if braced {
+            chars.next();
+        }
+        let mut name = String::new();
+        while let Some(&c) = chars.peek() {
+            if c.is_ascii_alphanumeric() || c == '_' {
+                name.push(c);
+                chars.next();
+            } else {
+                break;
+            }
+        }
+        if braced && chars.peek() == Some(&'}') {
+            chars.next();
+        }
+        if name.is_empty() {
+            word.push('$');
+        } else if let Some(value) = env.get(&name) {
+            word.push_str(value);
+        }
+    };
+
+    while let Some(c) = chars.next() {
+        match c {
+            '\'' => {
+                in_word = true;
+                for c in chars.by_ref() {
+                    if c == '\'' {
+                        break;
+                    }
+                    word.push(c);
+                }
+            }
+            '"' => {
+                in_word = true;
+                while let Some(c) = chars.next() {
+                    match c {
+                        '"' => break,
+                        '\\' => {
+                            if let Some(next) = chars.next() {
+                                word.push(next);
+                            }
+                        }
+                        '$' => expand(&mut chars, &mut word),
+                        c => word.push(c),
+                    }
+                }
+            }
+            '\\' => {
+                in_word = true;
+                if let Some(next) = chars.next() {
+                    word.push(next);
+                }
+            }
+            '$' => {
+                // An unquoted variable that expands to nothing doesn't make a word
+                let before = word.len();
+                expand(&mut chars, &mut word);
+                in_word |= word.len() > before;
+            }
+            '#' if !in_word => break,
+            '>' => {
+                // `2>file` and `2>&1`: stderr is never separated from stdout here
+                let fd = if in_word && (word == "1" || word == "2") { Some(std::mem::take(&mut word)) } else { None };
+                if fd.is_none() && in_word {
+                    tokens.push(Token::Word(std::mem::take(&mut word)));
+                }
+                in_word = false;
+
+                let append = chars.peek() == Some(&'>');
+                if append {
+                    chars.next();
+                }
+                if chars.peek() == Some(&'&') {
+                    chars.next();
+                    while chars.peek().is_some_and(|c| c.is_ascii_digit()) {
+                        chars.next();
+                    }
+                    continue;
+                }
+                tokens.push(Token::Operator(match (fd.as_deref(), append) {
+                    (Some("2"), _) => "2>",
+                    (_, true) => ">>",
+                    (_, false) => ">",
+                }));
+            }
+            c if c.is_whitespace() || matches!(c, ';' | '&' | '|') => {
+                if in_word {
+                    tokens.push(Token::Word(std::mem::take(&mut word)));
+                    in_word = false;
+                }
+                let operator = match c {
+                    ';' => Some(";"),
+                    '&' if chars.peek() == Some(&'&') => {
+                        chars.next();
+                        Some("&&")
+                    }
+                    // Background jobs just run in the foreground
+                    '&' => Some(";"),
+                    '|' if chars.peek() == Some(&'|') => {
+                        chars.next();
+                        Some("||")
+                    }
+                    '|' => Some("|"),
+                    _ => None,
+                };
+                if let Some(operator) = operator {
+                    tokens.push(Token::Operator(operator));
+                }
+            }
+            c => {
+                in_word = true;
+                word.push(c);
+            }
+        }
+    }
+    if in_word {
+        tokens.push(Token::Word(word));
+    }
+
+    tokens
+}
+
+/// Groups tokens into pipelines, each with the operator that decides whether it runs
+fn split_lists(tokens: &[Token]) -> Vec<(Vec<SimpleCommand>, Connector)> {
+    let mut lists = Vec::new();
+    let mut pipeline = vec![SimpleCommand::default()];
+    let mut connector = Connector::Always;
+    let mut tokens = tokens.iter();
+
+    while let Some(token) = tokens.next() {
+        match token {
+            Token::Word(word) => pipeline.last_mut().unwrap().argv.push(word.clone()),
+            Token::Operator("2>") => {
+                if let Some(Token::Word(_)) = tokens.next() {
+                    pipeline.last_mut().unwrap().discard_stderr = true;
+                }
+            }
+            Token::Operator(">") | Token::Operator(">>") => {
+                if let Some(Token::Word(path)) = tokens.next() {
+                    let append = *token == Token::Operator(">>");
+                    pipeline.last_mut().unwrap().redirect = Some(Redirect { path: path.clone(), append });
+                }
+            }
+            Token::Operator("|") => pipeline.push(SimpleCommand::default()),
+            Token::Operator(operator) => {
+                lists.push((std::mem::replace(&mut pipeline, vec![SimpleCommand::default()]), connector));
+                connector = match *operator {
+                    "&&" => Connector::And,
+                    "||" => Connector::Or,
+                    _ => Connector::Always,
+                };
+            }
+        }
+    }
+    lists.push((pipeline, connector));
+
+    lists.retain(|(pipeline, _)| pipeline.iter().any(|command| !command.argv.is_empty()));
+    lists
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::honeypot::services::session::test_support;
+
+    fn shell() -> FakeShell {
+        FakeShell::new("root", "ubuntu", test_support::events("ssh", "10.0.0.2:22", "10.0.0.2:22"))
+    }
+
+    /// Expected tokens, operators written as themselves
+    fn words(words: &[&str]) -> Vec<Token> {
+        const OPERATORS: &[&str] = &[";", "&&", "||", "|", ">", ">>", "2>"];
+        words.iter().map(|word| match OPERATORS.iter().find(|operator| *operator == word) {
+            Some(operator) => Token::Operator(operator),
+            None => Token::Word(word.to_string()),
+        }).collect()
+    }
+
+    #[test]
+    fn tokenizer_handles_quotes_escapes_and_variables() {
+        let env = BTreeMap::from([("HOME".to_string(), "/root".to_string())]);
+        let tokenize = |line| tokenize(line, &env, 127);
+
+        assert_eq!(tokenize("echo 'a  b' \"$HOME/x\" c\\ d"), words(&["echo", "a  b", "/root/x", "c d"]));
+        assert_eq!(tokenize("echo ${HOME}x $UNSET '$HOME' $? $"), words(&["echo", "/rootx", "$HOME", "127", "$"]));
+        assert_eq!(tokenize("echo \"a\\\"b\" '' # comment"), words(&["echo", "a\"b", ""]));
+        assert_eq!(tokenize("echo a#b"), words(&["echo", "a#b"]));
+        assert_eq!(tokenize("cd /tmp&&wget x||curl x;sh&ls|sh"), words(&["cd", "/tmp", "&&", "wget", "x", "||", "curl", "x", ";", "sh", ";", "ls", "|", "sh"]));
+    }
+
+    #[test]
+    fn tokenizer_recognises_redirections() {
+        let env = BTreeMap::new();
+        assert_eq!(tokenize("echo a>f", &env, 0), words(&["echo", "a", ">", "f"]));
+        assert_eq!(tokenize("echo 1>f 2>/dev/null", &env, 0), words(&["echo", ">", "f", "2>", "/dev/null"]));
+        assert_eq!(tokenize("echo a >> f 2>&1", &env, 0), words(&["echo", "a", ">>", "f"]));
+        assert_eq!(tokenize("echo a 12>f", &env, 0), words(&["echo", "a", "12", ">", "f"]));
+    }
+
+    #[test]
+    fn unterminated_input_does_not_panic() {
+        let env = BTreeMap::new();
+        assert_eq!(tokenize("echo 'abc", &env, 0), words(&["echo", "abc"]));
+        assert_eq!(tokenize("echo \"abc\\", &env, 0), words(&["echo", "abc"]));
+        assert_eq!(tokenize("echo abc\\", &env, 0), words(&["echo", "abc"]));
+        assert_eq!(tokenize("echo ${HOME", &env, 0), words(&["echo"]));
+        assert_eq!(tokenize("echo >", &env, 0), words(&["echo", ">"]));
+        assert!(split_lists(&tokenize(";;&& ||", &env, 0)).is_empty());
+        assert!(split_lists(&tokenize("echo >", &env, 0))[0].0[0].redirect.is_none());
+    }
+
+    #[test]
+    fn overlong_lines_are_cut_without_echo() {
+        let mut editor = LineEditor::default();
+        let input = editor.feed(&vec![b'A'; MAX_LINE + 100]);
+        assert_eq!(input.echo.len(), MAX_LINE);
+        assert!(input.lines.is_empty());
+
+        let input = editor.feed(b"BBB\r\nid\r\nwho");
+        assert_eq!(input.lines, [
+            EnteredLine { text: "A".repeat(MAX_LINE), echo: b"\r\n".to_vec() },
+            EnteredLine { text: "id".to_string(), echo: b"id\r\n".to_vec() },
+        ]);
+        assert_eq!(input.echo, b"who");
+    }
+
+    #[tokio::test]
+    async fn redirections_write_and_append_to_the_filesystem() {
+        let mut shell = shell();
+        assert_eq!(shell.execute("echo one > /tmp/f").output, "");
+        shell.execute("echo two >> /tmp/f");
+        assert_eq!(shell.execute("cat /tmp/f").output, "one\ntwo\n");
+
+        shell.execute("echo three > /tmp/f");
+        assert_eq!(shell.fs.read("/tmp/f").unwrap(), b"three\n");
+
+        // Output sent to /dev/null is neither shown nor stored
+        assert_eq!(shell.execute("echo hidden > /dev/null").output, "");
+        assert!(shell.fs.read("/dev/null").unwrap_or_default().is_empty());
+
+        assert!(shell.execute("cat /missing").output.contains("No such file or directory"));
+        assert_eq!(shell.execute("cat /missing 2>/dev/null").output, "");
+        assert_eq!(shell.execute("cat /missing || echo fallback").output.lines().last(), Some("fallback"));
+        assert_eq!(shell.execute("false && echo skipped").output, "");
+        assert_eq!(shell.execute("echo abc | cat > /tmp/p; cat /tmp/p").output, "abc\n");
+    }
+
+    #[tokio::test]
+    async fn doubling_a_file_runs_out_of_space() {
+        let mut shell = shell();
+        shell.execute("echo 0123456789abcdef > a");
+        let mut last = ShellOutput::default();
+        for _ in 0..40 {
+            last = shell.execute("cat a a > b; cat b > a");
+            if last.exit_status != 0 {
+                break;
+            }
+        }
+        assert!(last.output.contains("No space left on device"), "{:?}", last.output);
+        assert!(shell.fs.read("/root/a").unwrap().len() <= SESSION_QUOTA);
+    }
+
+    #[tokio::test]
+    async fn self_calling_scripts_exhaust_the_command_budget() {
+        let mut shell = shell();
+        // 20 calls per line would run 20^4 times within the depth limit
+        shell.execute(&format!("echo '{}' > f.sh", vec!["sh f.sh"; 20].join("; ")));
+        shell.execute("sh f.sh");
+        assert!(shell.commands_run <= MAX_COMMANDS);
+        let after = shell.execute("id");
+        assert!(after.output.contains("Resource temporarily unavailable"), "{:?}", after.output);
+    }
+}
@@ -0,0 +1,844 @@
+use std::{collections::VecDeque, net::IpAddr, sync::{atomic::{AtomicBool, Ordering}, Arc}, time::Duration};
+
+use pnet::{datalink::DataLinkSender, packet::{tcp::{TcpFlags, TcpOptionNumbers, TcpPacket}, Packet}, util::MacAddr};
+use tokio::{io::{AsyncReadExt, AsyncWriteExt, DuplexStream}, sync::{mpsc, oneshot, Mutex}, time::{sleep_until, Instant}};
+use tracing::{info, warn};
+
+use crate::{honeypot::persona::{OsPersona, TcpFeatures}, interfaces::sender::{send_tcp_stream, SegmentHeader, TcpEndpoint}};
+
+// MSS assumed when the peer doesn't advertise one (RFC 1122)
+const DEFAULT_PEER_MSS: u16 = 536;
+// NOP, NOP and the timestamps option carried by every segment once negotiated
+const TIMESTAMP_OPTION_LEN: u16 = 12;
+const RECEIVE_BUFFER: usize = 65535;
+
+const INITIAL_RTO: Duration = Duration::from_secs(1);
+const MIN_RTO: Duration = Duration::from_millis(200);
+const MAX_RTO: Duration = Duration::from_secs(30);
+const MAX_RETRANSMISSIONS: u32 = 6;
+
+const IDLE_TIMEOUT: Duration = Duration::from_secs(300);
+const FIN_WAIT_2_TIMEOUT: Duration = Duration::from_secs(60);
+const TIME_WAIT_TIMEOUT: Duration = Duration::from_secs(30);
+
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TcpState {
+    SynSent,
+    SynReceived,
+    Established,
+    CloseWait,
+    LastAck,
+    FinWait1,
+    FinWait2,
+    Closing,
+    TimeWait,
+    Closed,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ConnectionKey {
+    pub virtual_ip: IpAddr,
+    pub virtual_port: u16,
+    pub remote_ip: IpAddr,
+    pub remote_port: u16,
+}
+
+/// Owned copy of a received segment, handed from the capture loop to the connection task.
+#[derive(Debug, Clone)]
+pub struct TcpSegment {
+    pub seq: u32,
+    pub ack: u32,
+    pub flags: u8,
+    pub window: u16,
+    pub mss: Option<u16>,
+    pub wscale: Option<u8>,
+    pub sack_permitted: bool,
+    /// Sender's TSval, if the segment carries timestamps
+    pub timestamp: Option<u32>,
+    pub payload: Vec<u8>,
+}
+
+impl TcpSegment {
+    pub fn from_packet(packet: &TcpPacket) -> Self {
+        let mut mss = None;
+        let mut wscale = None;
+        let mut sack_permitted = false;
+        let mut timestamp = None;
+
+        for option in packet.get_options_iter() {
+            let data = option.payload();
+            match option.get_number() {
+                TcpOptionNumbers::MSS if data.len() == 2 => mss = Some(u16::from_be_bytes([data[0], data[1]])),
+                TcpOptionNumbers::WSCALE if data.len() == 1 => wscale = Some(data[0].min(14)),
+                TcpOptionNumbers::SACK_PERMITTED => sack_permitted = true,
+                TcpOptionNumbers::TIMESTAMPS if data.len() == 8 => timestamp = Some(u32::from_be_bytes([data[0], data[1], data[2], data[3]])),
+                _ => {}
+            }
+        }
+
+        TcpSegment {
+            seq: packet.get_sequence(),
+            ack: packet.get_acknowledgement(),
+            flags: packet.get_flags(),
+            window: packet.get_window(),
+            mss,
+            wscale,
+            sack_permitted,
+            timestamp,
+            payload: packet.payload().to_vec(),
+        }
+    }
+
+    pub fn has(&self, flag: u8) -> bool {
+        self.flags & flag != 0
+    }
+
+    /// Extensions offered by a SYN
+    pub fn features(&self) -> TcpFeatures {
+        TcpFeatures { wscale: self.wscale.is_some(), sack: self.sack_permitted, timestamps: self.timestamp.is_some() }
+    }
+
+    /// Sequence space consumed by the segment: payload plus one for each of SYN and FIN
+    pub fn seq_len(&self) -> u32 {
+        self.payload.len() as u32 + self.has(TcpFlags::SYN) as u32 + self.has(TcpFlags::FIN) as u32
+    }
+}
+
+fn seq_lt(a: u32, b: u32) -> bool {
+    (a.wrapping_sub(b) as i32) < 0
+}
+
+fn seq_le(a: u32, b: u32) -> bool {
+    a == b || seq_lt(a, b)
+}
+
+
+struct Unacked {
+    seq: u32,
+    flags: u8,
+    payload: Vec<u8>,
+    sent_at: Instant,
+    retries: u32,
+}
+
+impl Unacked {
+    fn end(&self) -> u32 {
+        let syn_fin = (self.flags & TcpFlags::SYN != 0) as u32 + (self.flags & TcpFlags::FIN != 0) as u32;
+        self.seq.wrapping_add(self.payload.len() as u32 + syn_fin)
+    }
+}
+
+
+/// Per-connection TCP endpoint answering on behalf of a virtual node.
+/// Received in-order data is pushed into the application side of a duplex pipe, and
+/// whatever the application writes is segmented, sent and retransmitted until acknowledged.
+pub struct TcpConnection {
+    key: ConnectionKey,
+    virtual_mac: MacAddr,
+    remote_mac: MacAddr,
+    tx: Arc<Mutex<Box<dyn DataLinkSender + Send>>>,
+    os: OsPersona,
+
+    state: TcpState,
+
+    snd_una: u32,
+    snd_nxt: u32,
+    snd_wnd: u32,
+    snd_wscale: u8,
+    peer_mss: u16,
+    features: TcpFeatures,
+    fin_seq: Option<u32>,
+
+    rcv_nxt: u32,
+    rcv_wscale: u8,
+    peer_fin: bool,
+    // Peer's TSval to echo (RFC 7323)
+    ts_recent: u32,
+
+    unacked: VecDeque<Unacked>,
+    rto: Duration,
+    srtt: Option<Duration>,
+    rttvar: Duration,
+
+    to_app: VecDeque<u8>,
+    deadline: Instant,
+    // Signalled once an actively opened connection is established
+    established: Option<oneshot::Sender<()>>,
+    // Set once the handshake completes, so the SYN backlog stops counting the connection
+    synchronized: Arc<AtomicBool>,
+}
+
+impl TcpConnection {
+    pub fn new(
+        key: ConnectionKey,
+        virtual_mac: MacAddr,
+        remote_mac: MacAddr,
+        syn: &TcpSegment,
+        tx: Arc<Mutex<Box<dyn DataLinkSender + Send>>>,
+        os: OsPersona,
+        synchronized: Arc<AtomicBool>,
+    ) -> Self {
+        let iss = rand::random::<u32>();
+        let features = os.negotiate(syn.features());
+
+        TcpConnection {
+            key,
+            virtual_mac,
+            remote_mac,
+            tx,
+            state: TcpState::SynReceived,
+            snd_una: iss,
+            snd_nxt: iss,
+            // The window carried by a SYN is never scaled
+            snd_wnd: syn.window as u32,
+            snd_wscale: if features.wscale { syn.wscale.unwrap_or(0) } else { 0 },
+            peer_mss: syn.mss.unwrap_or(DEFAULT_PEER_MSS).min(os.mss(key.virtual_ip)),
+            features,
+            fin_seq: None,
+            rcv_nxt: syn.seq.wrapping_add(1),
+            rcv_wscale: os.window_shift(features),
+            peer_fin: false,
+            ts_recent: syn.timestamp.unwrap_or(0),
+            os,
+            unacked: VecDeque::new(),
+            rto: INITIAL_RTO,
+            srtt: None,
+            rttvar: Duration::ZERO,
+            to_app: VecDeque::new(),
+            deadline: Instant::now() + IDLE_TIMEOUT,
+            established: None,
+            synchronized,
+        }
+    }
+
+    /// Active open from the virtual node: `established` fires when the peer's SYN-ACK arrives,
+    /// and is dropped if the connection fails
+    pub fn connect(
+        key: ConnectionKey,
+        virtual_mac: MacAddr,
+        remote_mac: MacAddr,
+        tx: Arc<Mutex<Box<dyn DataLinkSender + Send>>>,
+        established: oneshot::Sender<()>,
+        os: OsPersona,
+        synchronized: Arc<AtomicBool>,
+    ) -> Self {
+        let iss = rand::random::<u32>();
+
+        TcpConnection {
+            key,
+            virtual_mac,
+            remote_mac,
+            tx,
+            state: TcpState::SynSent,
+            snd_una: iss,
+            snd_nxt: iss,
+            snd_wnd: 0,
+            snd_wscale: 0,
+            peer_mss: DEFAULT_PEER_MSS,
+            // Offered in our SYN, each kept only if the SYN-ACK offers it too
+            features: os.offered(),
+            fin_seq: None,
+            rcv_nxt: 0,
+            rcv_wscale: 0,
+            peer_fin: false,
+            ts_recent: 0,
+            os,
+            unacked: VecDeque::new(),
+            rto: INITIAL_RTO,
+            srtt: None,
+            rttvar: Duration::ZERO,
+            to_app: VecDeque::new(),
+            deadline: Instant::now() + IDLE_TIMEOUT,
+            established: Some(established),
+            synchronized,
+        }
+    }
+
+    /// Drives the connection until it reaches CLOSED: inbound segments arrive on `segments`,
+    /// the application talks to the other end of `stack_side`.
+    pub async fn run(mut self, mut segments: mpsc::Receiver<TcpSegment>, stack_side: DuplexStream) {
+        let (mut app_reader, mut app_writer) = tokio::io::split(stack_side);
+        let mut read_buffer = vec![0u8; RECEIVE_BUFFER];
+        let mut app_eof = false;
+        let mut app_write_closed = false;
+
+        if self.state == TcpState::SynSent {
+            self.transmit(TcpFlags::SYN, Vec::new()).await;
+        } else {
+            self.send_syn_ack().await;
+        }
+
+        while self.state != TcpState::Closed {
+            let can_send = matches!(self.state, TcpState::Established | TcpState::CloseWait) && !app_eof;
+            let send_room = if can_send { self.send_room().min(read_buffer.len()) } else { 0 };
+            let pending: Vec<u8> = self.to_app.iter().take(16 * 1024).copied().collect();
+            let retransmit_at = self.unacked.front().map(|segment| segment.sent_at + self.rto);
+            let deadline = self.deadline;
+
+            tokio::select! {
+                segment = segments.recv() => match segment {
+                    Some(segment) => self.on_segment(segment).await,
+                    None => break,
+                },
+                read = app_reader.read(&mut read_buffer[..send_room]), if send_room > 0 => match read {
+                    Ok(0) | Err(_) => {
+                        app_eof = true;
+                        self.close().await;
+                    }
+                    Ok(n) => self.send_data(&read_buffer[..n]).await,
+                },
+                written = app_writer.write(&pending), if !pending.is_empty() && !app_write_closed => match written {
+                    Ok(n) => {
+                        let was_full = self.receive_window() == 0;
+                        self.to_app.drain(..n);
+                        if was_full {
+                            self.send_ack().await;
+                        }
+                    }
+                    Err(_) => {
+                        self.to_app.clear();
+                        app_write_closed = true;
+                    }
+                },
+                _ = sleep_until(retransmit_at.unwrap_or(deadline)), if retransmit_at.is_some() => self.retransmit().await,
+                _ = sleep_until(deadline) => self.on_timeout().await,
+            }
+
+            if self.peer_fin && self.to_app.is_empty() && !app_write_closed {
+                let _ = app_writer.shutdown().await;
+                app_write_closed = true;
+            }
+        }
+
+        info!("🔌 TCP connection closed {}:{} <- {}:{}", self.key.virtual_ip, self.key.virtual_port, self.key.remote_ip, self.key.remote_port);
+    }
+
+    async fn on_segment(&mut self, segment: TcpSegment) {
+        if self.state != TcpState::TimeWait {
+            self.deadline = Instant::now() + if self.state == TcpState::FinWait2 { FIN_WAIT_2_TIMEOUT } else { IDLE_TIMEOUT };
+        }
+
+        if self.state == TcpState::SynSent {
+            self.on_syn_sent(segment).await;
+            return;
+        }
+
+        if segment.has(TcpFlags::RST) {
+            if self.in_receive_window(segment.seq) {
+                self.state = TcpState::Closed;
+            }
+            return;
+        }
+
+        if segment.has(TcpFlags::SYN) {
+            // A retransmitted SYN means our SYN-ACK got lost; anything else gets a challenge ACK (RFC 5961)
+            if self.state == TcpState::SynReceived && segment.seq.wrapping_add(1) == self.rcv_nxt {
+                self.retransmit().await;
+            } else {
+                self.send_ack().await;
+            }
+            return;
+        }
+
+        if !segment.has(TcpFlags::ACK) {
+            return;
+        }
+
+        // Only segments that don't skip ahead of what we expect update the clock we echo (RFC 7323 §4.3)
+        if let Some(tsval) = segment.timestamp && seq_le(segment.seq, self.rcv_nxt) {
+            self.ts_recent = tsval;
+        }
+
+        let acceptable = seq_lt(self.snd_una, segment.ack) && seq_le(segment.ack, self.snd_nxt);
+        if self.state == TcpState::SynReceived {
+            if !acceptable {
+                self.send_segment(segment.ack, 0, TcpFlags::RST, &[]).await;
+                return;
+            }
+            self.state = TcpState::Established;
+            self.synchronized.store(true, Ordering::Relaxed);
+            info!("🤝 TCP connection established {}:{} <- {}:{}", self.key.virtual_ip, self.key.virtual_port, self.key.remote_ip, self.key.remote_port);
+        }
+
+        if acceptable {
+            self.acknowledge(segment.ack);
+        } else if seq_lt(self.snd_nxt, segment.ack) {
+            self.send_ack().await;
+            return;
+        }
+        self.snd_wnd = (segment.window as u32) << self.snd_wscale;
+
+        if self.fin_seq.is_some_and(|fin| seq_lt(fin, self.snd_una)) {
+            match self.state {
+                TcpState::FinWait1 => {
+                    self.state = TcpState::FinWait2;
+                    self.deadline = Instant::now() + FIN_WAIT_2_TIMEOUT;
+                }
+                TcpState::Closing => self.enter_time_wait(),
+                TcpState::LastAck => {
+                    self.state = TcpState::Closed;
+                    return;
+                }
+                _ => {}
+            }
+        }
+
+        if segment.payload.is_empty() && !segment.has(TcpFlags::FIN) {
+            return;
+        }
+        if !matches!(self.state, TcpState::Established | TcpState::FinWait1 | TcpState::FinWait2) {
+            self.send_ack().await;
+            return;
+        }
+
+        self.receive(segment).await;
+    }
+
+    /// Reply to our SYN (RFC 793 §3.9, SYN-SENT state)
+    async fn on_syn_sent(&mut self, segment: TcpSegment) {
+        let ack_ok = segment.has(TcpFlags::ACK) && segment.ack == self.snd_nxt;
+        if segment.has(TcpFlags::ACK) && !ack_ok {
+            if !segment.has(TcpFlags::RST) {
+                self.send_segment(segment.ack, 0, TcpFlags::RST, &[]).await;
+            }
+            return;
+        }
+
+        if segment.has(TcpFlags::RST) {
+            if ack_ok {
+                info!("🔌 TCP connection refused {}:{} -> {}:{}", self.key.virtual_ip, self.key.virtual_port, self.key.remote_ip, self.key.remote_port);
+                self.state = TcpState::Closed;
+            }
+            return;
+        }
+
+        // Simultaneous open is not supported, only a proper SYN-ACK completes the handshake
+        if !segment.has(TcpFlags::SYN) || !ack_ok {
+            return;
+        }
+
+        self.rcv_nxt = segment.seq.wrapping_add(1);
+        self.acknowledge(segment.ack);
+        self.features = self.os.negotiate(segment.features());
+        self.snd_wscale = if self.features.wscale { segment.wscale.unwrap_or(0) } else { 0 };
+        self.rcv_wscale = self.os.window_shift(self.features);
+        self.snd_wnd = segment.window as u32;
+        self.peer_mss = segment.mss.unwrap_or(DEFAULT_PEER_MSS).min(self.os.mss(self.key.virtual_ip));
+        self.ts_recent = segment.timestamp.unwrap_or(0);
+        self.state = TcpState::Established;
+        info!("🤝 TCP connection established {}:{} -> {}:{}", self.key.virtual_ip, self.key.virtual_port, self.key.remote_ip, self.key.remote_port);
+
+        self.send_ack().await;
+        if let Some(established) = self.established.take() {
+            let _ = established.send(());
+        }
+    }
+
+    async fn receive(&mut self, segment: TcpSegment) {
+        let mut payload = segment.payload.as_slice();
+        let mut fin = segment.has(TcpFlags::FIN);
+
+        if segment.seq != self.rcv_nxt {
+            if seq_lt(self.rcv_nxt, segment.seq) {
+                // Out of order: drop it and let the duplicate ACK trigger a retransmission
+                self.send_ack().await;
+                return;
+            }
+            let already_received = self.rcv_nxt.wrapping_sub(segment.seq) as usize;
+            if already_received >= payload.len() + fin as usize {
+                self.send_ack().await;
+                return;
+            }
+            payload = &payload[already_received.min(payload.len())..];
+        }
+
+        let room = self.receive_window() as usize;
+        if payload.len() > room {
+            payload = &payload[..room];
+            fin = false;
+        }
+
+        self.to_app.extend(payload);
+        self.rcv_nxt = self.rcv_nxt.wrapping_add(payload.len() as u32);
+
+        if fin {
+            self.rcv_nxt = self.rcv_nxt.wrapping_add(1);
+            self.peer_fin = true;
+            match self.state {
+                TcpState::Established => self.state = TcpState::CloseWait,
+                TcpState::FinWait1 => self.state = TcpState::Closing,
+                TcpState::FinWait2 => self.enter_time_wait(),
+                _ => {}
+            }
+        }
+
+        self.send_ack().await;
+    }
+
+    fn acknowledge(&mut self, ack: u32) {
+        let now = Instant::now();
+
+        while let Some(segment) = self.unacked.front() {
+            if !seq_le(segment.end(), ack) {
+                break;
+            }
+            // Karn's algorithm: only segments sent once give a usable RTT sample
+            if segment.retries == 0 {
+                self.update_rto(now.duration_since(segment.sent_at));
+            }
+            self.unacked.pop_front();
+        }
+
+        self.snd_una = ack;
+    }
+
+    fn update_rto(&mut self, sample: Duration) {
+        // RFC 6298
+        let srtt = match self.srtt {
+            None => {
+                self.rttvar = sample / 2;
+                sample
+            }
+            Some(srtt) => {
+                let delta = srtt.abs_diff(sample);
+                self.rttvar = (self.rttvar * 3 + delta) / 4;
+                (srtt * 7 + sample) / 8
+            }
+        };
+        self.srtt = Some(srtt);
+        self.rto = (srtt + self.rttvar * 4).clamp(MIN_RTO, MAX_RTO);
+    }
+
+    async fn retransmit(&mut self) {
+        let Some(segment) = self.unacked.front_mut() else {
+            return;
+        };
+
+        if segment.retries >= MAX_RETRANSMISSIONS {
+            warn!("⏱️ TCP retransmissions exhausted for {}:{}, resetting", self.key.remote_ip, self.key.remote_port);
+            let seq = self.snd_nxt;
+            self.send_segment(seq, 0, TcpFlags::RST, &[]).await;
+            self.state = TcpState::Closed;
+            return;
+        }
+
+        segment.retries += 1;
+        segment.sent_at = Instant::now();
+        let (seq, flags, payload) = (segment.seq, segment.flags, segment.payload.clone());
+
+        self.rto = (self.rto * 2).min(MAX_RTO);
+
+        let ack = self.rcv_nxt;
+        self.send_segment(seq, ack, flags, &payload).await;
+    }
+
+    async fn on_timeout(&mut self) {
+        match self.state {
+            TcpState::TimeWait | TcpState::FinWait2 => self.state = TcpState::Closed,
+            _ => {
+                warn!("⏱️ TCP connection idle timeout for {}:{}", self.key.remote_ip, self.key.remote_port);
+                let seq = self.snd_nxt;
+                self.send_segment(seq, 0, TcpFlags::RST, &[]).await;
+                self.state = TcpState::Closed;
+            }
+        }
+    }
+
+    /// Application closed its write side: queue our FIN
+    async fn close(&mut self) {
+        let next_state = match self.state {
+            TcpState::Established => TcpState::FinWait1,
+            TcpState::CloseWait => TcpState::LastAck,
+            _ => return,
+        };
+
+        self.fin_seq = Some(self.snd_nxt);
+        self.transmit(TcpFlags::FIN | TcpFlags::ACK, Vec::new()).await;
+        self.state = next_state;
+    }
+
+    fn enter_time_wait(&mut self) {
+        self.state = TcpState::TimeWait;
+        self.deadline = Instant::now() + TIME_WAIT_TIMEOUT;
+    }
+
+    async fn send_syn_ack(&mut self) {
+        self.transmit(TcpFlags::SYN | TcpFlags::ACK, Vec::new()).await;
+    }
+
+    async fn send_data(&mut self, data: &[u8]) {
+        let options_len = if self.features.timestamps { TIMESTAMP_OPTION_LEN } else { 0 };
+        for chunk in data.chunks(self.peer_mss.saturating_sub(options_len).max(1) as usize) {
+            self.transmit(TcpFlags::PSH | TcpFlags::ACK, chunk.to_vec()).await;
+        }
+    }
+
+    async fn send_ack(&mut self) {
+        let (seq, ack) = (self.snd_nxt, self.rcv_nxt);
+        self.send_segment(seq, ack, TcpFlags::ACK, &[]).await;
+    }
+
+    /// Sends a segment that consumes sequence space and keeps it for retransmission
+    async fn transmit(&mut self, flags: u8, payload: Vec<u8>) {
+        let segment = Unacked {
+            seq: self.snd_nxt,
+            flags,
+            payload,
+            sent_at: Instant::now(),
+            retries: 0,
+        };
+
+        let ack = self.rcv_nxt;
+        self.send_segment(segment.seq, ack, flags, &segment.payload).await;
+
+        self.snd_nxt = segment.end();
+        self.unacked.push_back(segment);
+    }
+
+    /// Sends one segment with the window, options and IP header the node's OS would use
+    async fn send_segment(&self, seq: u32, ack: u32, flags: u8, payload: &[u8]) {
+        let (window, options) = if flags & TcpFlags::SYN != 0 {
+            // The window carried by a SYN is never scaled
+            (self.os.tcp.window, self.os.syn_options(self.features, self.os.mss(self.key.virtual_ip), self.ts_recent))
+        } else if flags & TcpFlags::RST != 0 {
+            (0, Vec::new())
+        } else {
+            (self.receive_window() >> self.rcv_wscale, self.os.segment_options(self.features, self.ts_recent))
+        };
+
+        let local = TcpEndpoint::new(self.virtual_mac, self.key.virtual_ip, self.key.virtual_port);
+        let remote = TcpEndpoint::new(self.remote_mac, self.key.remote_ip, self.key.remote_port);
+        let header = SegmentHeader { seq, ack, flags, window, options: &options };
+        send_tcp_stream(self.tx.clone(), local, remote, header, self.os.tcp_fields(), payload).await;
+    }
+
+    fn receive_window(&self) -> u16 {
+        RECEIVE_BUFFER.saturating_sub(self.to_app.len()) as u16
+    }
+
+    fn send_room(&self) -> usize {
+        let in_flight = self.snd_nxt.wrapping_sub(self.snd_una);
+        self.snd_wnd.saturating_sub(in_flight) as usize
+    }
+
+    fn in_receive_window(&self, seq: u32) -> bool {
+        let window = (self.receive_window() as u32).max(1);
+        seq_le(self.rcv_nxt, seq) && seq_lt(seq, self.rcv_nxt.wrapping_add(window))
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::{io, net::Ipv4Addr, sync::Mutex as StdMutex};
+
+    use pnet::{datalink::NetworkInterface, packet::{ethernet::EthernetPacket, ipv4::Ipv4Packet, tcp::{MutableTcpPacket, TcpOption}}};
+
+    use super::*;
+
+    /// Keeps every frame the connection sends
+    struct Capture(Arc<StdMutex<Vec<Vec<u8>>>>);
+
+    impl DataLinkSender for Capture {
+        fn build_and_send(&mut self, _: usize, _: usize, _: &mut dyn FnMut(&mut [u8])) -> Option<io::Result<()>> {
+            None
+        }
+
+        fn send_to(&mut self, packet: &[u8], _: Option<NetworkInterface>) -> Option<io::Result<()>> {
+            self.0.lock().unwrap().push(packet.to_vec());
+            Some(Ok(()))
+        }
+    }
+
+    /// (seq, ack, flags, payload) of each TCP segment sent so far, emptying the capture
+    fn sent(frames: &StdMutex<Vec<Vec<u8>>>) -> Vec<(u32, u32, u8, Vec<u8>)> {
+        frames.lock().unwrap().drain(..).map(|frame| {
+            let ethernet = EthernetPacket::new(&frame).unwrap();
+            let ip = Ipv4Packet::new(ethernet.payload()).unwrap();
+            let tcp = TcpPacket::new(ip.payload()).unwrap();
+            (tcp.get_sequence(), tcp.get_acknowledgement(), tcp.get_flags(), tcp.payload().to_vec())
+        }).collect()
+    }
+
+    fn segment(seq: u32, ack: u32, flags: u8, payload: &[u8]) -> TcpSegment {
+        TcpSegment { seq, ack, flags, window: 64240, mss: None, wscale: None, sack_permitted: false, timestamp: None, payload: payload.to_vec() }
+    }
+
+    const PEER_ISS: u32 = 1000;
+
+    /// Passive connection that has answered a SYN, with the frames it sends
+    async fn syn_received() -> (TcpConnection, Arc<StdMutex<Vec<Vec<u8>>>>) {
+        let frames = Arc::new(StdMutex::new(Vec::new()));
+        let tx: Box<dyn DataLinkSender + Send> = Box::new(Capture(frames.clone()));
+        let key = ConnectionKey {
+            virtual_ip: IpAddr::V4(Ipv4Addr::new(192, 168, 1, 50)),
+            virtual_port: 22,
+            remote_ip: IpAddr::V4(Ipv4Addr::new(192, 168, 1, 7)),
+            remote_port: 40000,
+        };
+        let mac = MacAddr::new(0x00, 0x11, 0x22, 0x33, 0x44, 0x55);
+        let syn = segment(PEER_ISS, 0, TcpFlags::SYN, &[]);
+        let mut connection = TcpConnection::new(key, mac, MacAddr::zero(), &syn, Arc::new(Mutex::new(tx)), OsPersona::for_mac(mac), Arc::new(AtomicBool::new(false)));
+        connection.send_syn_ack().await;
+        (connection, frames)
+    }
+
+    /// Connection through the three-way handshake
+    async fn established() -> (TcpConnection, Arc<StdMutex<Vec<Vec<u8>>>>) {
+        let (mut connection, frames) = syn_received().await;
+        let ack = connection.snd_nxt;
+        connection.on_segment(segment(PEER_ISS + 1, ack, TcpFlags::ACK, &[])).await;
+        frames.lock().unwrap().clear();
+        (connection, frames)
+    }
+
+    #[test]
+    fn sequence_comparison_wraps_around() {
+        assert!(seq_lt(1, 2));
+        assert!(seq_lt(u32::MAX - 10, 5));
+        assert!(!seq_lt(5, u32::MAX - 10));
+        assert!(!seq_lt(7, 7));
+        assert!(seq_le(7, 7));
+        assert!(seq_le(u32::MAX, 0));
+    }
+
+    #[test]
+    fn syn_options_are_parsed() {
+        let mut buffer = vec![0u8; 40];
+        let mut packet = MutableTcpPacket::new(&mut buffer).unwrap();
+        packet.set_sequence(PEER_ISS);
+        packet.set_flags(TcpFlags::SYN);
+        packet.set_window(64240);
+        packet.set_data_offset(10);
+        packet.set_options(&[TcpOption::mss(1460), TcpOption::sack_perm(), TcpOption::timestamp(12345, 0), TcpOption::nop(), TcpOption::wscale(20)]);
+
+        let syn = TcpSegment::from_packet(&packet.to_immutable());
+        assert_eq!(syn.seq, PEER_ISS);
+        assert_eq!(syn.mss, Some(1460));
+        // Shift counts above 14 are treated as 14 (RFC 7323 §2.3)
+        assert_eq!(syn.wscale, Some(14));
+        assert!(syn.sack_permitted);
+        assert_eq!(syn.timestamp, Some(12345));
+        assert_eq!(syn.features(), TcpFeatures { wscale: true, sack: true, timestamps: true });
+        assert_eq!(syn.seq_len(), 1);
+    }
+
+    #[test]
+    fn truncated_headers_do_not_panic() {
+        // Data offset claiming 60 bytes of header in a 24-byte segment, and an MSS option of the wrong length
+        let mut buffer = vec![0u8; 24];
+        buffer[12] = 15 << 4;
+        buffer[20..24].copy_from_slice(&[2, 4, 5, 0]);
+        let segment = TcpSegment::from_packet(&TcpPacket::new(&buffer).unwrap());
+        assert!(segment.payload.is_empty());
+
+        buffer[12] = 6 << 4;
+        buffer[20..24].copy_from_slice(&[2, 3, 5, 0]);
+        let segment = TcpSegment::from_packet(&TcpPacket::new(&buffer).unwrap());
+        assert_eq!(segment.mss, None);
+
+        assert!(TcpPacket::new(&buffer[..19]).is_none());
+    }
+
+    #[tokio::test]
+    async fn handshake_completes_on_the_final_ack() {
+        let (mut connection, frames) = syn_received().await;
+        let syn_ack = sent(&frames);
+        assert_eq!(syn_ack.len(), 1);
+        let (iss, ack, flags, _) = syn_ack[0].clone();
+        assert_eq!(flags, TcpFlags::SYN | TcpFlags::ACK);
+        assert_eq!(ack, PEER_ISS + 1);
+
+        // An ACK for something we never sent is answered with a reset
+        connection.on_segment(segment(PEER_ISS + 1, iss.wrapping_add(5), TcpFlags::ACK, &[])).await;
+        assert_eq!(connection.state, TcpState::SynReceived);
+        assert_eq!(sent(&frames)[0].2, TcpFlags::RST);
+
+        connection.on_segment(segment(PEER_ISS + 1, iss.wrapping_add(1), TcpFlags::ACK, &[])).await;
+        assert_eq!(connection.state, TcpState::Established);
+        assert!(connection.synchronized.load(Ordering::Relaxed));
+        assert!(connection.unacked.is_empty());
+    }
+
+    #[tokio::test]
+    async fn data_is_delivered_in_order() {
+        let (mut connection, frames) = established().await;
+        let snd_nxt = connection.snd_nxt;
+
+        // A segment past a gap is dropped with a duplicate ACK
+        connection.on_segment(segment(PEER_ISS + 6, snd_nxt, TcpFlags::ACK, b"world")).await;
+        assert!(connection.to_app.is_empty());
+        assert_eq!(sent(&frames), vec![(snd_nxt, PEER_ISS + 1, TcpFlags::ACK, Vec::new())]);
+
+        connection.on_segment(segment(PEER_ISS + 1, snd_nxt, TcpFlags::ACK | TcpFlags::PSH, b"hello")).await;
+        // A retransmission overlapping what was received only adds the new bytes
+        connection.on_segment(segment(PEER_ISS + 3, snd_nxt, TcpFlags::ACK | TcpFlags::PSH, b"llo world")).await;
+        assert_eq!(connection.to_app.iter().copied().collect::<Vec<u8>>(), b"hello world");
+        assert_eq!(connection.rcv_nxt, PEER_ISS + 12);
+        assert_eq!(sent(&frames).last().unwrap().1, PEER_ISS + 12);
+    }
+
+    #[tokio::test]
+    async fn passive_close_goes_through_last_ack() {
+        let (mut connection, frames) = established().await;
+        let snd_nxt = connection.snd_nxt;
+
+        connection.on_segment(segment(PEER_ISS + 1, snd_nxt, TcpFlags::FIN | TcpFlags::ACK, &[])).await;
+        assert_eq!(connection.state, TcpState::CloseWait);
+        assert!(connection.peer_fin);
+        assert_eq!(sent(&frames)[0].1, PEER_ISS + 2);
+
+        connection.close().await;
+        assert_eq!(connection.state, TcpState::LastAck);
+        assert_eq!(sent(&frames)[0].2, TcpFlags::FIN | TcpFlags::ACK);
+
+        connection.on_segment(segment(PEER_ISS + 2, snd_nxt.wrapping_add(1), TcpFlags::ACK, &[])).await;
+        assert_eq!(connection.state, TcpState::Closed);
+    }
+
+    #[tokio::test]
+    async fn active_close_ends_in_time_wait() {
+        let (mut connection, _frames) = established().await;
+        let snd_nxt = connection.snd_nxt;
+
+        connection.close().await;
+        assert_eq!(connection.state, TcpState::FinWait1);
+        connection.on_segment(segment(PEER_ISS + 1, snd_nxt.wrapping_add(1), TcpFlags::ACK, &[])).await;
+        assert_eq!(connection.state, TcpState::FinWait2);
+        connection.on_segment(segment(PEER_ISS + 1, snd_nxt.wrapping_add(1), TcpFlags::FIN | TcpFlags::ACK, &[])).await;
+        assert_eq!(connection.state, TcpState::TimeWait);
+        assert_eq!(connection.rcv_nxt, PEER_ISS + 2);
+    }
+
+    #[tokio::test]
+    async fn only_resets_inside_the_window_are_accepted() {
+        let (mut connection, _frames) = established().await;
+
+        connection.on_segment(segment(PEER_ISS + 1 + 70000, 0, TcpFlags::RST, &[])).await;
+        assert_eq!(connection.state, TcpState::Established);
+        connection.on_segment(segment(PEER_ISS + 1, 0, TcpFlags::RST, &[])).await;
+        assert_eq!(connection.state, TcpState::Closed);
+    }
+
+    #[tokio::test]
+    async fn unacknowledged_data_is_retransmitted_then_reset() {
+        let (mut connection, frames) = established().await;
+        let snd_nxt = connection.snd_nxt;
+
+        connection.send_data(b"SSH-2.0-OpenSSH\r\n").await;
+        assert_eq!(sent(&frames)[0].3, b"SSH-2.0-OpenSSH\r\n");
+
+        for _ in 0..MAX_RETRANSMISSIONS {
+            connection.retransmit().await;
+        }
+        let retransmitted = sent(&frames);
+        assert_eq!(retransmitted.len(), MAX_RETRANSMISSIONS as usize);
+        assert!(retransmitted.iter().all(|(seq, _, _, payload)| *seq == snd_nxt && payload == b"SSH-2.0-OpenSSH\r\n"));
+        assert!(connection.rto <= MAX_RTO);
+
+        connection.retransmit().await;
+        assert_eq!(connection.state, TcpState::Closed);
+        assert_eq!(sent(&frames)[0].2, TcpFlags::RST);
+    }
+}
@@ -0,0 +1,250 @@
+pub mod connection;
+pub mod stream;
+
+use std::{collections::HashMap, io, net::{IpAddr, SocketAddr}, sync::{atomic::{AtomicBool, Ordering}, Arc}, time::{Duration, Instant}};
+
+use lazy_static::lazy_static;
+use pnet::{datalink::DataLinkSender, packet::tcp::{TcpFlags, TcpPacket}, util::MacAddr};
+use tokio::{sync::{mpsc, oneshot, Mutex}, time::timeout};
+use tracing::{info, warn};
+use crate::{honeypot::{persona::OsPersona, profile::HoneypotProfile, services::run_service}, interfaces::{sender::{send_tcp_stream, SegmentHeader, TcpEndpoint}, ws::WsReporter}};
+use connection::{ConnectionKey, TcpConnection, TcpSegment};
+use stream::VirtualTcpStream;
+
+const STREAM_BUFFER: usize = 64 * 1024;
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(15);
+// Segments queued for a connection task; past this the capture loop drops them and the peer retransmits
+const SEGMENT_QUEUE: usize = 64;
+// Connections still in SYN_RECEIVED, over all decoys and per decoy: past these the oldest are
+// dropped, so a SYN flood can't hold more than this many tasks and buffers
+const MAX_HALF_OPEN: usize = 512;
+const MAX_HALF_OPEN_PER_NODE: usize = 64;
+// Established connections too, SYNs beyond it are dropped
+const MAX_CONNECTIONS: usize = 2048;
+
+
+lazy_static! {
+    static ref TCP_CONNECTIONS: Mutex<HashMap<ConnectionKey, Connection>> = Mutex::new(HashMap::new());
+    static ref TCP_LISTENERS: Mutex<HashMap<(IpAddr, u16), PendingAccept>> = Mutex::new(HashMap::new());
+}
+
+/// Connection task as the capture loop sees it
+struct Connection {
+    segments: mpsc::Sender<TcpSegment>,
+    opened: Instant,
+    /// Set by the task once the three-way handshake completes
+    established: Arc<AtomicBool>,
+}
+
+impl Connection {
+    fn half_open(&self) -> bool {
+        !self.established.load(Ordering::Relaxed)
+    }
+}
+
+/// Port of a virtual node waiting for one connection from a given peer
+struct PendingAccept {
+    remote_ip: IpAddr,
+    stream: oneshot::Sender<VirtualTcpStream>,
+}
+
+/// Segment from `remote` to the virtual node at `local`
+pub async fn handle_tcp_packet<'a>(
+    tx: Arc<Mutex<Box<dyn DataLinkSender + Send>>>,
+    tcp_received_packet: TcpPacket<'a>,
+    local: TcpEndpoint,
+    remote: TcpEndpoint,
+    profile: &HoneypotProfile,
+    reporter: &WsReporter,
+){
+    let key = ConnectionKey {
+        virtual_ip: local.addr.ip(),
+        virtual_port: local.addr.port(),
+        remote_ip: remote.addr.ip(),
+        remote_port: remote.addr.port(),
+    };
+    let (virtual_mac, source_mac) = (local.mac, remote.mac);
+    let mut segment = TcpSegment::from_packet(&tcp_received_packet);
+
+    {
+        let mut connections = TCP_CONNECTIONS.lock().await;
+
+        if let Some(connection) = connections.get(&key) {
+            match connection.segments.try_send(segment) {
+                Ok(()) | Err(mpsc::error::TrySendError::Full(_)) => return,
+                Err(mpsc::error::TrySendError::Closed(returned)) => {
+                    connections.remove(&key);
+                    segment = returned;
+                }
+            }
+        }
+
+        let is_syn = segment.has(TcpFlags::SYN) && !segment.has(TcpFlags::ACK | TcpFlags::FIN | TcpFlags::RST);
+        if is_syn && !make_room(&mut connections, key.virtual_ip) {
+            return;
+        }
+        if is_syn && let Some(emulator) = profile.services.create(key.virtual_port) {
+            let stream = open_connection(&mut connections, key, virtual_mac, source_mac, &segment, tx, profile.os.clone());
+            tokio::spawn(run_service(emulator, stream, reporter.clone()));
+            return;
+        }
+
+        if is_syn && let Some(pending) = take_listener(key).await {
+            let stream = open_connection(&mut connections, key, virtual_mac, source_mac, &segment, tx, profile.os.clone());
+            let _ = pending.send(stream);
+            return;
+        }
+    }
+
+    if segment.has(TcpFlags::RST) {
+        return;
+    }
+    // Segments without ACK to an open port are dropped (RFC 793), which is what FIN, NULL and Xmas
+    // scans detect; some stacks reset them anyway
+    if !segment.has(TcpFlags::ACK) && profile.services.contains(key.virtual_port) && !profile.os.tcp.reset_stray {
+        return;
+    }
+
+    // No listener or no connection: answer with a reset as a real closed port would (RFC 793)
+    let (seq, ack, flags) = if segment.has(TcpFlags::ACK) {
+        (segment.ack, 0, TcpFlags::RST)
+    } else {
+        (0, segment.seq.wrapping_add(segment.seq_len()), TcpFlags::RST | TcpFlags::ACK)
+    };
+
+    let header = SegmentHeader { seq, ack, flags, window: 0, options: &[] };
+    send_tcp_stream(tx.clone(), local, remote, header, profile.os.tcp_fields(), &[]).await;
+}
+
+
+/// Lets `remote_ip` open a single connection to `port` of the virtual node, for protocols
+/// that negotiate a second channel (FTP passive mode). Returns `None` if the port is taken.
+pub async fn accept_once(virtual_ip: IpAddr, port: u16, remote_ip: IpAddr) -> Option<oneshot::Receiver<VirtualTcpStream>> {
+    let mut listeners = TCP_LISTENERS.lock().await;
+    listeners.retain(|_, pending| !pending.stream.is_closed());
+    if listeners.contains_key(&(virtual_ip, port)) {
+        return None;
+    }
+
+    let (stream, accepted) = oneshot::channel();
+    listeners.insert((virtual_ip, port), PendingAccept { remote_ip, stream });
+    Some(accepted)
+}
+
+async fn take_listener(key: ConnectionKey) -> Option<oneshot::Sender<VirtualTcpStream>> {
+    let mut listeners = TCP_LISTENERS.lock().await;
+    match listeners.get(&(key.virtual_ip, key.virtual_port)) {
+        Some(pending) if pending.remote_ip == key.remote_ip && !pending.stream.is_closed() => {
+            listeners.remove(&(key.virtual_ip, key.virtual_port)).map(|pending| pending.stream)
+        }
+        _ => None,
+    }
+}
+
+/// Opens a connection from a virtual node to a remote host (FTP active mode data channel)
+pub async fn connect(
+    tx: Arc<Mutex<Box<dyn DataLinkSender + Send>>>,
+    virtual_mac: MacAddr,
+    local: SocketAddr,
+    remote_mac: MacAddr,
+    remote: SocketAddr,
+    os: OsPersona,
+) -> io::Result<VirtualTcpStream> {
+    let key = ConnectionKey { virtual_ip: local.ip(), virtual_port: local.port(), remote_ip: remote.ip(), remote_port: remote.port() };
+    let (established_tx, established) = oneshot::channel();
+
+    let stream = {
+        let mut connections = TCP_CONNECTIONS.lock().await;
+        if connections.get(&key).is_some_and(|connection| !connection.segments.is_closed()) {
+            return Err(io::ErrorKind::AddrInUse.into());
+        }
+        if connections.len() >= MAX_CONNECTIONS {
+            return Err(io::ErrorKind::OutOfMemory.into());
+        }
+        info!("🔌 New TCP connection {}:{} -> {}:{}", key.virtual_ip, key.virtual_port, key.remote_ip, key.remote_port);
+
+        let (app_side, stack_side) = tokio::io::duplex(STREAM_BUFFER);
+        let (segments, segments_rx) = mpsc::channel(SEGMENT_QUEUE);
+        // Opened by us, so never part of the SYN backlog
+        let established = Arc::new(AtomicBool::new(true));
+        let connection = TcpConnection::connect(key, virtual_mac, remote_mac, tx.clone(), established_tx, os.clone(), established.clone());
+
+        connections.insert(key, Connection { segments, opened: Instant::now(), established });
+        spawn_connection(connection, key, segments_rx, stack_side);
+
+        VirtualTcpStream::new(app_side, key, virtual_mac, remote_mac, tx, os)
+    };
+
+    match timeout(CONNECT_TIMEOUT, established).await {
+        Ok(Ok(())) => Ok(stream),
+        Ok(Err(_)) => Err(io::ErrorKind::ConnectionRefused.into()),
+        Err(_) => Err(io::ErrorKind::TimedOut.into()),
+    }
+}
+
+
+/// Whether a SYN to `virtual_ip` may open a connection, dropping the oldest half-open ones
+/// once the node's or the global backlog is full
+fn make_room(connections: &mut HashMap<ConnectionKey, Connection>, virtual_ip: IpAddr) -> bool {
+    connections.retain(|_, connection| !connection.segments.is_closed());
+
+    let node_half_open = connections.iter().filter(|(key, connection)| key.virtual_ip == virtual_ip && connection.half_open()).count();
+    if node_half_open >= MAX_HALF_OPEN_PER_NODE {
+        evict_oldest_half_open(connections, Some(virtual_ip));
+    }
+    let half_open = connections.values().filter(|connection| connection.half_open()).count();
+    if half_open >= MAX_HALF_OPEN {
+        evict_oldest_half_open(connections, None);
+    }
+    if connections.len() >= MAX_CONNECTIONS {
+        warn!("🌊 Troppe connessioni TCP ({}), SYN verso {} scartato", connections.len(), virtual_ip);
+        return false;
+    }
+    true
+}
+
+// Dropping the sender ends the connection task, which closes the emulator's stream in turn
+fn evict_oldest_half_open(connections: &mut HashMap<ConnectionKey, Connection>, virtual_ip: Option<IpAddr>) {
+    let oldest = connections.iter()
+        .filter(|(key, connection)| connection.half_open() && virtual_ip.is_none_or(|ip| key.virtual_ip == ip))
+        .min_by_key(|(_, connection)| connection.opened)
+        .map(|(key, _)| *key);
+    if let Some(key) = oldest {
+        warn!("🌊 Backlog SYN pieno, scarto la connessione semiaperta {}:{} <- {}:{}", key.virtual_ip, key.virtual_port, key.remote_ip, key.remote_port);
+        connections.remove(&key);
+    }
+}
+
+fn spawn_connection(connection: TcpConnection, key: ConnectionKey, segments: mpsc::Receiver<TcpSegment>, stack_side: tokio::io::DuplexStream) {
+    tokio::spawn(async move {
+        connection.run(segments, stack_side).await;
+        let mut connections = TCP_CONNECTIONS.lock().await;
+        // An evicted connection's key may already belong to a new one
+        if connections.get(&key).is_some_and(|connection| connection.segments.is_closed()) {
+            connections.remove(&key);
+        }
+    });
+}
+
+fn open_connection(
+    connections: &mut HashMap<ConnectionKey, Connection>,
+    key: ConnectionKey,
+    virtual_mac: MacAddr,
+    remote_mac: MacAddr,
+    syn: &TcpSegment,
+    tx: Arc<Mutex<Box<dyn DataLinkSender + Send>>>,
+    os: OsPersona,
+) -> VirtualTcpStream {
+    info!("🔌 New TCP connection {}:{} <- {}:{}", key.virtual_ip, key.virtual_port, key.remote_ip, key.remote_port);
+
+    let (app_side, stack_side) = tokio::io::duplex(STREAM_BUFFER);
+    let (segments, segments_rx) = mpsc::channel(SEGMENT_QUEUE);
+    let established = Arc::new(AtomicBool::new(false));
+    let connection = TcpConnection::new(key, virtual_mac, remote_mac, syn, tx.clone(), os.clone(), established.clone());
+
+    connections.insert(key, Connection { segments, opened: Instant::now(), established });
+    spawn_connection(connection, key, segments_rx, stack_side);
+
+    VirtualTcpStream::new(app_side, key, virtual_mac, remote_mac, tx, os)
+}
+
@@ -0,0 +1,80 @@
+use std::{io, net::SocketAddr, pin::Pin, sync::Arc, task::{Context, Poll}};
+
+use pnet::{datalink::DataLinkSender, util::MacAddr};
+use tokio::{io::{AsyncRead, AsyncWrite, DuplexStream, ReadBuf}, sync::Mutex};
+
+use super::connection::ConnectionKey;
+use crate::honeypot::persona::OsPersona;
+
+/// Application side of a virtual TCP connection, usable like a `tokio::net::TcpStream`.
+/// Reads return the peer's in-order data and EOF after its FIN; shutting down or dropping
+/// the stream makes the connection send its own FIN.
+pub struct VirtualTcpStream {
+    inner: DuplexStream,
+    local_addr: SocketAddr,
+    peer_addr: SocketAddr,
+    local_mac: MacAddr,
+    peer_mac: MacAddr,
+    tx: Arc<Mutex<Box<dyn DataLinkSender + Send>>>,
+    os: OsPersona,
+}
+
+impl VirtualTcpStream {
+    pub fn new(inner: DuplexStream, key: ConnectionKey, local_mac: MacAddr, peer_mac: MacAddr, tx: Arc<Mutex<Box<dyn DataLinkSender + Send>>>, os: OsPersona) -> Self {
+        VirtualTcpStream {
+            inner,
+            local_addr: SocketAddr::new(key.virtual_ip, key.virtual_port),
+            peer_addr: SocketAddr::new(key.remote_ip, key.remote_port),
+            local_mac,
+            peer_mac,
+            tx,
+            os,
+        }
+    }
+
+    pub fn local_addr(&self) -> SocketAddr {
+        self.local_addr
+    }
+
+    pub fn peer_addr(&self) -> SocketAddr {
+        self.peer_addr
+    }
+
+    pub fn local_mac(&self) -> MacAddr {
+        self.local_mac
+    }
+
+    pub fn peer_mac(&self) -> MacAddr {
+        self.peer_mac
+    }
+
+    /// Interface the connection sends through, for opening related connections from the same node
+    pub fn sender(&self) -> Arc<Mutex<Box<dyn DataLinkSender + Send>>> {
+        self.tx.clone()
+    }
+
+    /// OS persona of the local node, shared by related connections
+    pub fn os(&self) -> OsPersona {
+        self.os.clone()
+    }
+}
+
+impl AsyncRead for VirtualTcpStream {
+    fn poll_read(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut ReadBuf<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().inner).poll_read(cx, buf)
+    }
+}
+
+impl AsyncWrite for VirtualTcpStream {
+    fn poll_write(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8]) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.get_mut().inner).poll_write(cx, buf)
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().inner).poll_shutdown(cx)
+    }
+}
@@ -0,0 +1,74 @@
+use std::{net::SocketAddrV4, sync::Arc};
+
+use common::types::HoneypotEvent;
+use pnet::{datalink::DataLinkSender, packet::{ipv4::Ipv4Packet, udp::UdpPacket, Packet}, util::MacAddr};
+use tokio::sync::Mutex;
+use tracing::{info, warn};
+use crate::{honeypot::{budget::{charge, is_unicast, spend, Budget}, icmp::send_unreachable, profile::HoneypotProfile, services::session::EventSink}, interfaces::{sender::send_udp_datagram, ws::WsReporter}};
+
+// A reply may never be larger than this, nor this many times the request
+const MAX_REPLY_LEN: usize = 512;
+const MAX_AMPLIFICATION: usize = 4;
+// Sources that are themselves services (echo, daytime, qotd, chargen, time) would loop with us
+const LOOP_PORTS: [u16; 6] = [0, 7, 13, 17, 19, 37];
+// ICMP destination unreachable code for a closed port
+const PORT_UNREACHABLE: u8 = 3;
+
+
+pub async fn handle_udp_packet<'a>(
+    tx: Arc<Mutex<Box<dyn DataLinkSender + Send>>>,
+    ipv4_packet: &Ipv4Packet<'a>,
+    virtual_mac: MacAddr,
+    source_mac: MacAddr,
+    profile: &HoneypotProfile,
+    reporter: &WsReporter,
+) {
+    let Some(udp_packet) = UdpPacket::new(ipv4_packet.payload()) else {
+        return;
+    };
+    let virtual_ip = ipv4_packet.get_destination();
+    let source_ip = ipv4_packet.get_source();
+    let source_port = udp_packet.get_source();
+    let virtual_port = udp_packet.get_destination();
+
+    if !is_unicast(source_ip.into()) || LOOP_PORTS.contains(&source_port) {
+        return;
+    }
+
+    let emulator = profile.udp_services.get(virtual_port);
+    let local = SocketAddrV4::new(virtual_ip, virtual_port);
+    let remote = SocketAddrV4::new(source_ip, source_port);
+    let events = EventSink::new(emulator.as_ref().map(|e| e.name()).unwrap_or("udp"), virtual_mac, local.into(), source_mac, remote.into(), reporter.clone());
+
+    match charge(source_ip.into()).await {
+        Budget::Allowed => {}
+        Budget::Exceeded(first) => {
+            if first {
+                events.record(HoneypotEvent::UdpRateLimited { port: virtual_port });
+            }
+            return;
+        }
+    }
+
+    let Some(emulator) = emulator else {
+        // Closed port: answer like a real stack so scans see it closed rather than filtered
+        send_unreachable(tx, ipv4_packet, virtual_mac, source_mac, PORT_UNREACHABLE, &profile.os).await;
+        return;
+    };
+
+    let request = udp_packet.payload();
+    let Some(reply) = emulator.on_datagram(&events, request) else {
+        return;
+    };
+    if reply.len() > MAX_REPLY_LEN || reply.len() > request.len() * MAX_AMPLIFICATION {
+        warn!("🛑 Risposta {} di {} byte a {}:{} scartata: richiesta di {} byte", emulator.name(), reply.len(), source_ip, source_port, request.len());
+        return;
+    }
+    if !spend(source_ip.into(), reply.len()).await {
+        return;
+    }
+
+    info!("🍯 {} reply to {}:{} ({} bytes)", emulator.name(), source_ip, source_port, reply.len());
+    send_udp_datagram(tx, virtual_mac, local, source_mac, remote, profile.os.ipv4_fields(), &reply).await;
+}
+
@@ -1,7 +1,7 @@
 use common::packet::{build_header, build_packet};
 use common::types::{AlertPayload, PayloadType};
 use pnet::datalink::{self, Channel, Config, DataLinkSender, NetworkInterface};
-use pnet::packet::ethernet::EthernetPacket;
+use pnet::packet::ethernet::{EtherTypes, EthernetPacket};
 use pnet::packet::Packet;
 use pnet::util::MacAddr;
 use tokio_tungstenite::tungstenite::protocol::Message;
@@ -12,9 +12,19 @@ use std::sync::Arc;
 use crate::ai::anomaly::anomalies::AnomalyClassification;
 use crate::ai::features::flow::update_and_get_flow;
 use crate::ai::model::load_models;
+use crate::graph::changes::{handle_asset_changes, save_periodically};
+use crate::graph::dhcp::handle_dhcp_packet;
+use crate::graph::poisoning::{handle_poisoning_packet, query_baits_periodically};
+use crate::graph::{analytics, inventory};
 use crate::graph::utils::{get_primary_interface};
+use crate::honeypot::allocation::{IpAllocator, Subnet};
+use crate::honeypot::arp::{announce_periodically, handle_arp_packet};
+use crate::honeypot::create_honeypots::create_honeypots;
+use crate::honeypot::discovery::{advertise_periodically, handle_discovery_packet};
 use crate::honeypot::handler::handle_virtual_packet;
-use crate::graph::types::{NetworkGraph, NodeType};
+use crate::honeypot::ndp::{expire_periodically, handle_ndp_packet};
+use crate::graph::types::NetworkGraph;
+use crate::interfaces::ws::WsReporter;
 use crate::ai::anomaly::detection::detect_anomaly;
 
 pub async fn scan_datalink(
@@ -37,12 +47,27 @@ pub async fn scan_datalink(
     info!("📡 Listening to the network traffic...");
     let local_mac = interface.mac.expect("Couldn't get local mac address");
 
+    // Decoys claim their addresses while the capture loop below watches for conflicts
+    let subnet = Subnet::for_interface(&interface).expect("No IPv4 network on the interface");
+    let broadcast = subnet.broadcast();
+    let gateway = subnet.gateway;
+    let allocator = Arc::new(IpAllocator::new(subnet, tx_datalink.clone(), graph.clone()));
+    tokio::spawn(create_honeypots(graph.clone(), allocator.clone()));
+    tokio::spawn(announce_periodically(graph.clone(), tx_datalink.clone()));
+    tokio::spawn(advertise_periodically(graph.clone(), tx_datalink.clone()));
+    tokio::spawn(expire_periodically(graph.clone()));
+    tokio::spawn(query_baits_periodically(graph.clone(), tx_datalink.clone(), broadcast));
+
     let (autoencoder_model, classifier_model) = load_models();
+    let reporter = WsReporter::new(ws_tx.clone(), session_id.clone());
+    tokio::spawn(inventory::report_periodically(graph.clone(), reporter.clone(), local_mac));
+    tokio::spawn(analytics::analyze_periodically(graph.clone(), reporter.clone(), gateway));
+    tokio::spawn(save_periodically());
 
     loop {
         match rx.next() {
             Ok(packet) => {
-                scan_packet(packet, &graph, &autoencoder_model, &classifier_model, local_mac, tx_datalink.clone(), ws_tx.clone(), session_id.clone()).await;
+                scan_packet(packet, &graph, &autoencoder_model, &classifier_model, local_mac, tx_datalink.clone(), ws_tx.clone(), session_id.clone(), &reporter, &allocator).await;
             },
             Err(e) => {
                 error!("❌ Error reading packet: {}", e);
@@ -62,24 +87,41 @@ async fn scan_packet(
     tx_datalink: Arc<Mutex<Box<dyn DataLinkSender + Send>>>,
     ws_tx: futures_channel::mpsc::UnboundedSender<Message>,
     session_id: Arc<Mutex<u32>>,
+    reporter: &WsReporter,
+    allocator: &Arc<IpAllocator>,
 ) {
     if let Some(ethernet_packet) = EthernetPacket::new(packet) {                    
+        allocator.observe(&ethernet_packet).await;
+        if ethernet_packet.get_ethertype() == EtherTypes::Arp {
+            handle_arp_packet(&ethernet_packet, graph, tx_datalink.clone()).await;
+        }
+        if ethernet_packet.get_ethertype() == EtherTypes::Ipv6 {
+            handle_ndp_packet(&ethernet_packet, graph, tx_datalink.clone()).await;
+        }
+        if ethernet_packet.get_ethertype() == EtherTypes::Ipv4 {
+            handle_discovery_packet(&ethernet_packet, graph, tx_datalink.clone(), reporter).await;
+            handle_poisoning_packet(&ethernet_packet, graph, reporter).await;
+        }
         
-        let (src_mac, src_ip, dest_is_virtual) = {
+        let (src_mac, src_ip, dest_honeypot) = {
             let mut g = graph.lock().await;
             let (src_node, dest_node) = g.add_nodes_and_connections(&ethernet_packet, local_mac).await;
-
-            (src_node.mac_address, src_node.ipv4_address, dest_node.node_type == NodeType::Virtual)
+            let seen = (src_node.mac_address, src_node.ipv4_address, dest_node.honeypot.clone());
+            inventory::observe(&mut g, &ethernet_packet);
+            seen
         };
+        handle_asset_changes(&ethernet_packet, graph, reporter, local_mac).await;
+        // After the graph update, so the client's node exists to describe
+        if ethernet_packet.get_ethertype() == EtherTypes::Ipv4 {
+            handle_dhcp_packet(&ethernet_packet, graph, reporter).await;
+        }
 
-        if dest_is_virtual {
-            let tx_clone = tx_datalink.clone();
+        // Handled inline rather than spawned so segments reach the virtual TCP connections in capture order
+        if let Some(profile) = dest_honeypot {
             let packet_data = ethernet_packet.packet().to_vec();
-            tokio::spawn(async move {
-                let packet = EthernetPacket::new(&packet_data).unwrap();
-                info!("🤖 Handling virtual honeypot packet for {:?}", packet.get_destination());
-                handle_virtual_packet(packet, tx_clone).await;
-            });
+            let packet = EthernetPacket::new(&packet_data).unwrap();
+            info!("🤖 Handling virtual honeypot packet for {:?}", packet.get_destination());
+            handle_virtual_packet(packet, tx_datalink.clone(), profile, reporter.clone()).await;
         }
 
         
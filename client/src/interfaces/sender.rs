@@ -1,27 +1,26 @@
 use pnet::datalink::DataLinkSender;
 use pnet::packet::arp::{ArpOperations, MutableArpPacket};
-use pnet::packet::ethernet::{MutableEthernetPacket, EtherTypes};
-use pnet::packet::ip::IpNextHeaderProtocols;
-use pnet::packet::ipv4::{checksum, MutableIpv4Packet};
-use pnet::packet::tcp::{ipv4_checksum, MutableTcpPacket};
+use pnet::packet::ethernet::{EtherType, EtherTypes, MutableEthernetPacket};
+use pnet::packet::icmpv6::{self, MutableIcmpv6Packet};
+use pnet::packet::ip::{IpNextHeaderProtocol, IpNextHeaderProtocols};
+use pnet::packet::ipv4::{checksum, Ipv4Flags, MutableIpv4Packet};
+use pnet::packet::ipv6::MutableIpv6Packet;
+use pnet::packet::tcp::{self, MutableTcpPacket, TcpOption, TcpOptionPacket};
+use pnet::packet::udp::{self, MutableUdpPacket};
 use pnet::packet::Packet;
 use pnet::util::MacAddr;
 use tokio::sync::Mutex;
-use std::collections::HashSet;
-use std::net::Ipv4Addr;
+use tracing::error;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, SocketAddrV4};
 use std::sync::Arc;
-use lazy_static::lazy_static;
 
 
-const ETHERNET_LEN: usize = 54;
-const IPV4_LEN: usize = 40;
 const TCP_LEN: usize = 20;
+const IPV4_HEADER_LEN: usize = 20;
+const IPV6_HEADER_LEN: usize = 40;
+const UDP_HEADER_LEN: usize = 8;
 
 
-lazy_static! {
-    static ref SENT_ARP_REPLIES: Mutex<HashSet<String>> = Mutex::new(HashSet::new());
-}
-
 pub async fn send_arp_reply(
     my_mac: MacAddr,
      my_ip: Ipv4Addr, 
@@ -29,16 +28,6 @@ pub async fn send_arp_reply(
      target_ip: Ipv4Addr, 
      tx: Arc<Mutex<Box<dyn DataLinkSender + Send>>>
 ) {
-    
-    let key = format!("{}->{}", my_ip, target_ip);
-
-    let mut sent_replies = SENT_ARP_REPLIES.lock().await;
-    if sent_replies.contains(&key) {
-        return;
-    }
-
-    sent_replies.insert(key);
-
     let mut ethernet_buffer = [0u8; 42];
     let mut ethernet_packet = MutableEthernetPacket::new(&mut ethernet_buffer).unwrap();
     ethernet_packet.set_destination(target_mac);
@@ -65,62 +54,216 @@ pub async fn send_arp_reply(
 }
 
 
+/// Broadcasts an ARP request as sent by address conflict detection (RFC 5227): a probe
+/// has sender IP 0.0.0.0, an announcement has sender and target IP both set to the claimed address
+pub async fn send_arp_request(
+    tx: Arc<Mutex<Box<dyn DataLinkSender + Send>>>,
+    sender_mac: MacAddr,
+    sender_ip: Ipv4Addr,
+    target_ip: Ipv4Addr,
+) {
+    let mut ethernet_buffer = [0u8; 42];
+    let mut ethernet_packet = MutableEthernetPacket::new(&mut ethernet_buffer).unwrap();
+    ethernet_packet.set_destination(MacAddr::broadcast());
+    ethernet_packet.set_source(sender_mac);
+    ethernet_packet.set_ethertype(EtherTypes::Arp);
+
+    let mut arp_buffer = [0u8; 28];
+    let mut arp_packet = MutableArpPacket::new(&mut arp_buffer).unwrap();
+    arp_packet.set_hardware_type(pnet::packet::arp::ArpHardwareTypes::Ethernet);
+    arp_packet.set_protocol_type(EtherTypes::Ipv4);
+    arp_packet.set_hw_addr_len(6);
+    arp_packet.set_proto_addr_len(4);
+    arp_packet.set_operation(ArpOperations::Request);
+    arp_packet.set_sender_hw_addr(sender_mac);
+    arp_packet.set_sender_proto_addr(sender_ip);
+    arp_packet.set_target_hw_addr(MacAddr::zero());
+    arp_packet.set_target_proto_addr(target_ip);
+
+    ethernet_packet.set_payload(&arp_buffer);
+
+    let mut tx_sender = tx.lock().await;
+    let _ = tx_sender.send_to(ethernet_packet.packet(), None).expect("Failed sending ARP request");
+}
+
+
+/// One end of a TCP segment: the node's link address and its socket address
+#[derive(Debug, Clone, Copy)]
+pub struct TcpEndpoint {
+    pub mac: MacAddr,
+    pub addr: SocketAddr,
+}
+
+impl TcpEndpoint {
+    pub fn new(mac: MacAddr, ip: IpAddr, port: u16) -> Self {
+        TcpEndpoint { mac, addr: SocketAddr::new(ip, port) }
+    }
+}
+
+/// TCP header fields of a segment a virtual node sends, besides the ports
+#[derive(Debug, Clone, Copy)]
+pub struct SegmentHeader<'a> {
+    pub seq: u32,
+    pub ack: u32,
+    pub flags: u8,
+    pub window: u16,
+    pub options: &'a [TcpOption],
+}
+
 pub async fn send_tcp_stream(
+    tx: Arc<Mutex<Box<dyn DataLinkSender + Send>>>,
+    local: TcpEndpoint,
+    remote: TcpEndpoint,
+    header: SegmentHeader<'_>,
+    fields: Ipv4Fields,
+    payload: &[u8]
+) {
+
+    // Options are padded with EOL bytes up to the next 32-bit boundary
+    let options_len = header.options.iter().map(TcpOptionPacket::packet_size).sum::<usize>().div_ceil(4) * 4;
+
+    let mut tcp_buffer = vec![0u8; TCP_LEN + options_len + payload.len()];
+    let mut tcp_packet = MutableTcpPacket::new(&mut tcp_buffer).unwrap();
+    tcp_packet.set_source(local.addr.port());
+    tcp_packet.set_destination(remote.addr.port());
+    tcp_packet.set_sequence(header.seq);
+    tcp_packet.set_acknowledgement(header.ack);
+    tcp_packet.set_flags(header.flags);
+    tcp_packet.set_window(header.window);
+    tcp_packet.set_data_offset(((TCP_LEN + options_len) / 4) as u8);
+    tcp_packet.set_options(header.options);
+
+    tcp_packet.set_payload(payload);
+
+    match (local.addr.ip(), remote.addr.ip()) {
+        (IpAddr::V4(source), IpAddr::V4(destination)) => {
+            let tcp_checksum = tcp::ipv4_checksum(&tcp_packet.to_immutable(), &source, &destination);
+            tcp_packet.set_checksum(tcp_checksum);
+            let packet = build_ipv4(source, destination, IpNextHeaderProtocols::Tcp, fields, &tcp_buffer);
+            send_frame(tx, local.mac, remote.mac, EtherTypes::Ipv4, &packet).await;
+        }
+        (IpAddr::V6(source), IpAddr::V6(destination)) => {
+            let tcp_checksum = tcp::ipv6_checksum(&tcp_packet.to_immutable(), &source, &destination);
+            tcp_packet.set_checksum(tcp_checksum);
+            let packet = build_ipv6(source, destination, IpNextHeaderProtocols::Tcp, fields.ttl, &tcp_buffer);
+            send_frame(tx, local.mac, remote.mac, EtherTypes::Ipv6, &packet).await;
+        }
+        _ => error!("❌ Segmento TCP tra famiglie di indirizzi diverse: {} -> {}", local.addr, remote.addr),
+    }
+}
+
+
+/// IPv4 header fields that depend on the OS a virtual node impersonates;
+/// IPv6 packets only take the TTL, as hop limit
+#[derive(Debug, Clone, Copy)]
+pub struct Ipv4Fields {
+    pub ttl: u8,
+    pub identification: u16,
+    pub dont_fragment: bool,
+}
+
+
+pub async fn send_udp_datagram(
     tx: Arc<Mutex<Box<dyn DataLinkSender + Send>>>,
     virtual_mac: MacAddr,
-    virtual_ip: Ipv4Addr,
+    local: SocketAddrV4,
     destination_mac: MacAddr,
-    destination_ip: Ipv4Addr,
-    virtual_port: u16,
-    source_port: u16,
-    seq_to: u32,
-    ack_to: u32,
-    response_flag: u8,
+    destination: SocketAddrV4,
+    fields: Ipv4Fields,
     payload: &[u8]
 ) {
+    let mut udp_buffer = vec![0u8; UDP_HEADER_LEN + payload.len()];
+    let mut udp_packet = MutableUdpPacket::new(&mut udp_buffer).unwrap();
+    udp_packet.set_source(local.port());
+    udp_packet.set_destination(destination.port());
+    udp_packet.set_length((UDP_HEADER_LEN + payload.len()) as u16);
+    udp_packet.set_payload(payload);
+    let udp_checksum = udp::ipv4_checksum(&udp_packet.to_immutable(), local.ip(), destination.ip());
+    udp_packet.set_checksum(udp_checksum);
 
-    let mut ethernet_buffer = vec![0u8; ETHERNET_LEN + payload.len()];
+    let packet = build_ipv4(*local.ip(), *destination.ip(), IpNextHeaderProtocols::Udp, fields, &udp_buffer);
+    send_frame(tx, virtual_mac, destination_mac, EtherTypes::Ipv4, &packet).await;
+}
 
-    let mut ethernet_packet = MutableEthernetPacket::new(&mut ethernet_buffer).unwrap();
-    ethernet_packet.set_destination(destination_mac);
-    ethernet_packet.set_source(virtual_mac);
-    ethernet_packet.set_ethertype(EtherTypes::Ipv4);
 
-    let mut ipv4_buffer = vec![0u8; IPV4_LEN + payload.len()];
+/// Sends an ICMP message already built and checksummed by the caller
+pub async fn send_icmp(
+    tx: Arc<Mutex<Box<dyn DataLinkSender + Send>>>,
+    virtual_mac: MacAddr,
+    virtual_ip: Ipv4Addr,
+    destination_mac: MacAddr,
+    destination_ip: Ipv4Addr,
+    fields: Ipv4Fields,
+    message: &[u8]
+) {
+    let packet = build_ipv4(virtual_ip, destination_ip, IpNextHeaderProtocols::Icmp, fields, message);
+    send_frame(tx, virtual_mac, destination_mac, EtherTypes::Ipv4, &packet).await;
+}
+
+
+fn build_ipv4(source: Ipv4Addr, destination: Ipv4Addr, protocol: IpNextHeaderProtocol, fields: Ipv4Fields, payload: &[u8]) -> Vec<u8> {
+    let mut ipv4_buffer = vec![0u8; IPV4_HEADER_LEN + payload.len()];
     let mut ipv4_packet = MutableIpv4Packet::new(&mut ipv4_buffer).unwrap();
     ipv4_packet.set_version(4);
     ipv4_packet.set_header_length(5);
-    ipv4_packet.set_total_length((IPV4_LEN + payload.len()) as u16);
-    ipv4_packet.set_next_level_protocol(IpNextHeaderProtocols::Tcp);
-    ipv4_packet.set_source(virtual_ip);
-    ipv4_packet.set_destination(destination_ip);
-    ipv4_packet.set_ttl(64);
+    ipv4_packet.set_total_length((IPV4_HEADER_LEN + payload.len()) as u16);
+    ipv4_packet.set_identification(fields.identification);
+    if fields.dont_fragment {
+        ipv4_packet.set_flags(Ipv4Flags::DontFragment);
+    }
+    ipv4_packet.set_ttl(fields.ttl);
+    ipv4_packet.set_next_level_protocol(protocol);
+    ipv4_packet.set_source(source);
+    ipv4_packet.set_destination(destination);
+    ipv4_packet.set_payload(payload);
+    ipv4_packet.set_checksum(checksum(&ipv4_packet.to_immutable()));
+    ipv4_buffer
+}
 
-    let mut tcp_buffer = vec![0u8; TCP_LEN + payload.len()];
-    let mut tcp_packet = MutableTcpPacket::new(&mut tcp_buffer).unwrap();
-    tcp_packet.set_source(virtual_port); 
-    tcp_packet.set_destination(source_port);
-    tcp_packet.set_sequence(seq_to);
-    tcp_packet.set_acknowledgement(ack_to); 
-    tcp_packet.set_flags(response_flag);
-    tcp_packet.set_window(8192);
-    tcp_packet.set_data_offset((TCP_LEN / 4) as u8);
+/// Sends an ICMPv6 message from a virtual node, filling in its checksum
+pub async fn send_icmpv6(
+    tx: Arc<Mutex<Box<dyn DataLinkSender + Send>>>,
+    virtual_mac: MacAddr,
+    virtual_ip: Ipv6Addr,
+    destination_mac: MacAddr,
+    destination_ip: Ipv6Addr,
+    hop_limit: u8,
+    message: &[u8]
+) {
+    let mut message = message.to_vec();
+    let Some(mut icmpv6_packet) = MutableIcmpv6Packet::new(&mut message) else {
+        return;
+    };
+    icmpv6_packet.set_checksum(0);
+    let icmpv6_checksum = icmpv6::checksum(&icmpv6_packet.to_immutable(), &virtual_ip, &destination_ip);
+    icmpv6_packet.set_checksum(icmpv6_checksum);
 
-    tcp_packet.set_payload(payload);
+    let packet = build_ipv6(virtual_ip, destination_ip, IpNextHeaderProtocols::Icmpv6, hop_limit, &message);
+    send_frame(tx, virtual_mac, destination_mac, EtherTypes::Ipv6, &packet).await;
+}
 
-    let tcp_checksum = ipv4_checksum(
-        &tcp_packet.to_immutable(),
-        &virtual_ip,
-        &destination_ip,
-    );
-    tcp_packet.set_checksum(tcp_checksum);
 
-    let tcp_header_len = tcp_packet.get_data_offset() as usize * 4;
-    ipv4_packet.set_payload(&tcp_packet.packet()[..tcp_header_len + payload.len()]);
-    ipv4_packet.set_checksum(checksum(&ipv4_packet.to_immutable()));
+fn build_ipv6(source: Ipv6Addr, destination: Ipv6Addr, next_header: IpNextHeaderProtocol, hop_limit: u8, payload: &[u8]) -> Vec<u8> {
+    let mut ipv6_buffer = vec![0u8; IPV6_HEADER_LEN + payload.len()];
+    let mut ipv6_packet = MutableIpv6Packet::new(&mut ipv6_buffer).unwrap();
+    ipv6_packet.set_version(6);
+    ipv6_packet.set_payload_length(payload.len() as u16);
+    ipv6_packet.set_next_header(next_header);
+    ipv6_packet.set_hop_limit(hop_limit);
+    ipv6_packet.set_source(source);
+    ipv6_packet.set_destination(destination);
+    ipv6_packet.set_payload(payload);
+    ipv6_buffer
+}
 
-    ethernet_packet.set_payload(ipv4_packet.packet());
+async fn send_frame(tx: Arc<Mutex<Box<dyn DataLinkSender + Send>>>, virtual_mac: MacAddr, destination_mac: MacAddr, ethertype: EtherType, packet: &[u8]) {
+    let mut ethernet_buffer = vec![0u8; 14 + packet.len()];
+    let mut ethernet_packet = MutableEthernetPacket::new(&mut ethernet_buffer).unwrap();
+    ethernet_packet.set_destination(destination_mac);
+    ethernet_packet.set_source(virtual_mac);
+    ethernet_packet.set_ethertype(ethertype);
+    ethernet_packet.set_payload(packet);
 
     let mut tx_sender = tx.lock().await;
-    let _ = tx_sender.send_to(ethernet_packet.packet(), None).expect("Failed sending TCP stream");
+    let _ = tx_sender.send_to(ethernet_packet.packet(), None).expect("Failed sending IP packet");
 }
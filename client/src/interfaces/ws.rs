@@ -1,11 +1,42 @@
 use tokio_tungstenite::{tungstenite::protocol::Message, MaybeTlsStream};
 use futures_util::{pin_mut, future, StreamExt};
+use pnet::util::MacAddr;
 use tracing::{error, info, warn};
 use std::sync::Arc;
+use common::packet::{build_header, build_packet};
 use common::tls::generate_client_session_id;
+use common::types::{DataType, PayloadType, PriorityLevel};
 use tokio::sync::Mutex;
 
 
+/// Cloneable handle used by honeypot tasks to push packets to the server.
+/// The session id is incremented and the message queued under the same lock, so ids reach the server in order.
+#[derive(Clone)]
+pub struct WsReporter {
+    ws_tx: futures_channel::mpsc::UnboundedSender<Message>,
+    session_id: Arc<Mutex<u32>>,
+}
+
+impl WsReporter {
+    pub fn new(ws_tx: futures_channel::mpsc::UnboundedSender<Message>, session_id: Arc<Mutex<u32>>) -> Self {
+        WsReporter { ws_tx, session_id }
+    }
+
+    pub async fn send(&self, data_type: DataType, priority: PriorityLevel, mac_address: MacAddr, payload: PayloadType) {
+        let mut id = self.session_id.lock().await;
+        *id += 1;
+
+        let header = build_header(*id, data_type.to_u8(), priority.to_u8(), mac_address);
+        let ws_packet = build_packet(header, payload);
+
+        let serialized = bincode::serialize(&ws_packet).expect("serialize error");
+        if let Err(e) = self.ws_tx.unbounded_send(Message::Binary(serialized.into())) {
+            error!("❌ Failed to queue packet for the server: {}", e);
+        }
+    }
+}
+
+
 pub async fn handle_websocket(
     ws_stream: tokio_tungstenite::WebSocketStream<MaybeTlsStream<tokio::net::TcpStream>>,
 ) -> (futures_channel::mpsc::UnboundedSender<Message>, Arc<Mutex<u32>>) {
@@ -2,6 +2,7 @@ pub mod graph;
 pub mod honeypot;
 pub mod interfaces;
 pub mod ai;
+pub mod state;
 use tokio::sync::Mutex;
 use tracing::info;
 use tracing_subscriber::EnvFilter;
@@ -11,7 +12,6 @@ use std::sync::Arc;
 use std::path::PathBuf;
 use common::tls::rustls_client_config;
 use crate::graph::types::NetworkGraph;
-use crate::honeypot::create_honeypots::create_honeypots;
 use crate::interfaces::receiver::scan_datalink;
 use crate::interfaces::ws::handle_websocket;
 
@@ -23,13 +23,17 @@ async fn main() {
     .with_target(true)
     .with_line_number(true)
     .init();
+
+    // `client rotate-identities` gives the decoys new MACs, addresses and keys on the next start
+    if env::args().nth(1).as_deref() == Some("rotate-identities") {
+        honeypot::identity::rotate();
+        return;
+    }
     
     let (ws_tx, session_id) = connect_websocket().await;
 
     let graph = Arc::new(Mutex::new(NetworkGraph::default()));
 
-    create_honeypots(&graph).await;
-    
     scan_datalink(ws_tx, session_id, graph).await;
 }
 
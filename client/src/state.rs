@@ -0,0 +1,16 @@
+use std::{fs, io, path::Path};
+
+use serde::Serialize;
+
+
+/// Writes `value` as JSON next to `path` and renames it over it, so a crash never leaves
+/// the file half written
+pub fn write_json_atomic<T: Serialize>(path: impl AsRef<Path>, value: &T) -> io::Result<()> {
+    let path = path.as_ref();
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let temporary = path.with_extension("json.tmp");
+    fs::write(&temporary, serde_json::to_vec_pretty(value)?)?;
+    fs::rename(&temporary, path)
+}
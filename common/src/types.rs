@@ -23,7 +23,11 @@ pub struct Packet {
 pub enum PayloadType {
     Alert(AlertPayload),
     ArpAlert(ArpAlertPayload),
-    TcpAlert(TcpAlertPayload)
+    TcpAlert(TcpAlertPayload),
+    HoneypotSession(HoneypotSessionPayload),
+    HoneypotEvent(HoneypotEventPayload),
+    AssetInventory(AssetInventoryPayload),
+    NetworkAlert(NetworkAlertPayload),
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -48,6 +52,342 @@ pub struct TcpAlertPayload {
     pub tcp_attack_type: u8,
 }
 
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct HoneypotSessionPayload {
+    pub honeypot_mac: [u8; 6],
+    pub honeypot_ip: String,
+    pub honeypot_port: u16,
+    pub attacker_mac: [u8; 6],
+    pub attacker_ip: String,
+    pub attacker_port: u16,
+    pub service: String,
+    pub started_at: u64,
+    pub duration_ms: u64,
+    pub bytes_in: u64,
+    pub bytes_out: u64,
+    pub transcript: Vec<TranscriptChunk>,
+    pub transcript_truncated: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct TranscriptChunk {
+    pub inbound: bool,
+    pub offset_ms: u64,
+    pub data: Vec<u8>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct HoneypotEventPayload {
+    pub honeypot_mac: [u8; 6],
+    pub honeypot_ip: String,
+    pub honeypot_port: u16,
+    pub attacker_mac: [u8; 6],
+    pub attacker_ip: String,
+    pub attacker_port: u16,
+    pub service: String,
+    pub event: HoneypotEvent,
+}
+
+/// Hosts the client has seen on its network, as learned passively from their traffic
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct AssetInventoryPayload {
+    pub assets: Vec<Asset>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Asset {
+    pub mac_address: [u8; 6],
+    pub ipv4_address: Option<String>,
+    pub ipv6_addresses: Vec<String>,
+    /// Whether the host is the client's own device
+    pub device: bool,
+    pub vendor: Option<String>,
+    pub hostname: Option<String>,
+    /// Passive TCP fingerprint of the host's SYNs, p0f-style
+    pub os_fingerprint: Option<String>,
+    pub os_guess: Option<String>,
+    pub dhcp_fingerprint: Option<String>,
+    pub dhcp_vendor: Option<String>,
+    pub tcp_services: Vec<u16>,
+    pub udp_services: Vec<u16>,
+    /// Unix seconds
+    pub first_seen: u64,
+    pub last_seen: u64,
+    pub packets_sent: u64,
+    pub bytes_sent: u64,
+    pub packets_received: u64,
+    pub bytes_received: u64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub enum HoneypotEvent {
+    SshClientHello {
+        version: String,
+        kex_algorithms: Vec<String>,
+        host_key_algorithms: Vec<String>,
+        ciphers: Vec<String>,
+        macs: Vec<String>,
+        compression: Vec<String>,
+        hassh: String,
+    },
+    LoginAttempt {
+        username: String,
+        password: Option<String>,
+        public_key: Option<String>,
+        accepted: bool,
+    },
+    Command {
+        line: String,
+        cwd: String,
+        output: String,
+        exit_status: u32,
+    },
+    Download {
+        url: String,
+    },
+    Upload {
+        path: String,
+        size: u64,
+        sha256: String,
+        md5: String,
+        truncated: bool,
+    },
+    HttpRequest {
+        persona: String,
+        method: String,
+        path: String,
+        version: String,
+        headers: Vec<(String, String)>,
+        body: String,
+        user_agent: Option<String>,
+        username: Option<String>,
+        password: Option<String>,
+        status: u16,
+        detections: Vec<String>,
+    },
+    TlsClientHello {
+        sni: Option<String>,
+        alpn: Vec<String>,
+        version: String,
+        cipher_suites: Vec<u16>,
+        extensions: Vec<u16>,
+        ja3: String,
+        ja3_hash: String,
+        ja4: String,
+    },
+    DnsQuery {
+        name: String,
+        qtype: u16,
+        qclass: u16,
+    },
+    SnmpRequest {
+        version: String,
+        community: String,
+        pdu: String,
+        oids: Vec<String>,
+        accepted: bool,
+    },
+    NtpRequest {
+        version: u8,
+        mode: u8,
+    },
+    SsdpSearch {
+        target: String,
+        user_agent: Option<String>,
+    },
+    UdpRateLimited {
+        port: u16,
+    },
+    IcmpRequest {
+        icmp_type: u8,
+        code: u8,
+        identifier: u16,
+        sequence: u16,
+        size: usize,
+    },
+    ModbusRequest {
+        unit_id: u8,
+        function: u8,
+        address: Option<u16>,
+        quantity: Option<u16>,
+        values: Vec<u16>,
+        write: bool,
+        exception: Option<u8>,
+    },
+    MqttConnect {
+        client_id: String,
+        protocol_level: u8,
+        username: Option<String>,
+        password: Option<String>,
+    },
+    MqttSubscribe {
+        topics: Vec<String>,
+    },
+    MqttPublish {
+        topic: String,
+        payload: String,
+        qos: u8,
+        retain: bool,
+    },
+}
+
+impl HoneypotEvent {
+    pub fn kind(&self) -> &'static str {
+        match self {
+            HoneypotEvent::SshClientHello { .. } => "ssh_client_hello",
+            HoneypotEvent::LoginAttempt { .. } => "login_attempt",
+            HoneypotEvent::Command { .. } => "command",
+            HoneypotEvent::Download { .. } => "download",
+            HoneypotEvent::Upload { .. } => "upload",
+            HoneypotEvent::HttpRequest { .. } => "http_request",
+            HoneypotEvent::TlsClientHello { .. } => "tls_client_hello",
+            HoneypotEvent::DnsQuery { .. } => "dns_query",
+            HoneypotEvent::SnmpRequest { .. } => "snmp_request",
+            HoneypotEvent::NtpRequest { .. } => "ntp_request",
+            HoneypotEvent::SsdpSearch { .. } => "ssdp_search",
+            HoneypotEvent::UdpRateLimited { .. } => "udp_rate_limited",
+            HoneypotEvent::IcmpRequest { .. } => "icmp_request",
+            HoneypotEvent::ModbusRequest { .. } => "modbus_request",
+            HoneypotEvent::MqttConnect { .. } => "mqtt_connect",
+            HoneypotEvent::MqttSubscribe { .. } => "mqtt_subscribe",
+            HoneypotEvent::MqttPublish { .. } => "mqtt_publish",
+        }
+    }
+}
+
+/// Something the client saw on its network rather than against a decoy
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct NetworkAlertPayload {
+    /// Host the alert is about: the responder, the server, the host that changed
+    pub source_mac: [u8; 6],
+    pub source_ip: Option<String>,
+    /// Host it affects, if a single one does: the poisoned querier, the offered client, the critical host
+    pub target_mac: Option<[u8; 6]>,
+    pub target_ip: Option<String>,
+    pub alert: NetworkAlert,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub enum NetworkAlert {
+    /// Someone answered a name query on the link that nobody should answer:
+    /// a bait name queried by a decoy, or `wpad`
+    NamePoisoning {
+        protocol: NameProtocol,
+        name: String,
+        answer: Option<String>,
+        bait: bool,
+    },
+    /// A DHCP offer or acknowledgement the learned servers wouldn't have sent
+    RogueDhcp {
+        server: String,
+        offered: String,
+        routers: Vec<String>,
+        dns: Vec<String>,
+        reasons: Vec<RogueDhcpReason>,
+    },
+    /// Discovers from more clients in a short window than a real network produces
+    DhcpStarvation {
+        discovers: usize,
+        clients: usize,
+        sources: usize,
+    },
+    /// A host joined the network, came back after a long absence, or its MAC, address
+    /// or stack no longer match what was learned about it
+    AssetChange {
+        change: AssetChangeKind,
+        previous: Option<String>,
+        current: Option<String>,
+        vendor: Option<String>,
+        hostname: Option<String>,
+    },
+    /// What the traffic graph shows about a host
+    TopologyFinding {
+        finding: TopologyFindingKind,
+        score: f64,
+        related: Vec<String>,
+    },
+}
+
+impl NetworkAlert {
+    pub fn kind(&self) -> &'static str {
+        match self {
+            NetworkAlert::NamePoisoning { .. } => "name_poisoning",
+            NetworkAlert::RogueDhcp { .. } => "rogue_dhcp",
+            NetworkAlert::DhcpStarvation { .. } => "dhcp_starvation",
+            NetworkAlert::AssetChange { .. } => "asset_change",
+            NetworkAlert::TopologyFinding { .. } => "topology_finding",
+        }
+    }
+
+    pub fn priority(&self) -> PriorityLevel {
+        match self {
+            NetworkAlert::NamePoisoning { .. } | NetworkAlert::RogueDhcp { .. } => PriorityLevel::Critical,
+            NetworkAlert::DhcpStarvation { .. } => PriorityLevel::High,
+            NetworkAlert::AssetChange { change, .. } => match change {
+                AssetChangeKind::MacChanged | AssetChangeKind::StackChanged | AssetChangeKind::NewDeviceFlood => PriorityLevel::High,
+                AssetChangeKind::NewDevice | AssetChangeKind::RandomizedMac => PriorityLevel::Medium,
+                AssetChangeKind::Reappeared | AssetChangeKind::IpChanged => PriorityLevel::Low,
+            },
+            NetworkAlert::TopologyFinding { finding, .. } => match finding {
+                TopologyFindingKind::AttackPath => PriorityLevel::High,
+                TopologyFindingKind::FanOut | TopologyFindingKind::FanIn | TopologyFindingKind::Pivot => PriorityLevel::Medium,
+                TopologyFindingKind::NewEdge => PriorityLevel::Low,
+            },
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum NameProtocol {
+    Llmnr,
+    Nbns,
+    Mdns,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum RogueDhcpReason {
+    /// Not one of the servers learned
+    UnknownServer,
+    /// A known server handing out a gateway it never did
+    UnexpectedRouter,
+    /// A known server handing out a DNS server it never did
+    UnexpectedDns,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum AssetChangeKind {
+    NewDevice,
+    /// A new, locally administered MAC, as phones use per network
+    RandomizedMac,
+    Reappeared,
+    IpChanged,
+    /// The host's address moved to another MAC
+    MacChanged,
+    /// The host's SYNs come from a different initial TTL
+    StackChanged,
+    /// More new hosts at once than are reported one by one, as MAC flooding makes;
+    /// `current` says how many were left out
+    NewDeviceFlood,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum TopologyFindingKind {
+    /// Far more peers than the other hosts
+    FanOut,
+    /// Reached by far more peers than the other hosts
+    FanIn,
+    /// Peers it never talked to before
+    NewEdge,
+    /// Most paths between the other hosts cross it
+    Pivot,
+    /// A path from an anomalous host to a critical one
+    AttackPath,
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum DeviceType {
     Honeypot = 1,
@@ -58,6 +398,10 @@ pub enum DataType {
     Alert = 1,
     ArpAlert = 2,
     TcpAlert = 3,
+    HoneypotSession = 4,
+    HoneypotEvent = 5,
+    AssetInventory = 6,
+    NetworkAlert = 7,
 }
 
 impl DataType {
@@ -66,6 +410,10 @@ impl DataType {
             1 => Some(DataType::Alert),
             2 => Some(DataType::ArpAlert),
             3 => Some(DataType::TcpAlert),
+            4 => Some(DataType::HoneypotSession),
+            5 => Some(DataType::HoneypotEvent),
+            6 => Some(DataType::AssetInventory),
+            7 => Some(DataType::NetworkAlert),
             _ => None,
         }
     }
@@ -42,7 +42,7 @@ async fn ensure_buckets_exists(client: &InfluxClient) -> Result<(), String> {
         name: None,
         after: None,
         id: None,
-        limit: Some(5),
+        limit: Some(20),
         offset: None,
         org: None,
         org_id: None,
@@ -53,7 +53,7 @@ async fn ensure_buckets_exists(client: &InfluxClient) -> Result<(), String> {
     match buckets_response {
         Ok(buckets) => {
             let bucket_names: Vec<String> = buckets.buckets.iter().map(|b| b.name.clone()).collect();
-            let required_buckets = vec!["network", "process", "arp", "tcp"];
+            let required_buckets = vec!["network", "process", "arp", "tcp", "honeypot"];
 
             let missing_buckets: Vec<&str> = required_buckets
                 .into_iter()
@@ -0,0 +1,49 @@
+use influxdb2::models::DataPoint;
+use influxdb2::Client;
+use chrono::Utc;
+use futures::stream;
+use common::types::AssetInventoryPayload;
+
+use super::format_mac_address;
+
+/// Aggiunge l'inventario degli host della rete al bucket `network`, un punto per host
+pub async fn add_asset_inventory_data(
+    influx_client: &Client,
+    device_name: &str,
+    inventory_payload: &AssetInventoryPayload
+) -> Result<(), String> {
+    let bucket_name = "network";
+    let timestamp = Utc::now().timestamp_nanos_opt().unwrap_or_else(|| Utc::now().timestamp() * 1_000_000_000);
+
+    let mut points = Vec::new();
+    for asset in &inventory_payload.assets {
+        let join = |ports: &[u16]| ports.iter().map(u16::to_string).collect::<Vec<_>>().join(",");
+        let point = DataPoint::builder("assets")
+            .tag("device", device_name)
+            .tag("mac", format_mac_address(&asset.mac_address))
+            .field("ipv4_address", asset.ipv4_address.clone().unwrap_or_default())
+            .field("ipv6_addresses", asset.ipv6_addresses.join(","))
+            .field("own_device", asset.device)
+            .field("vendor", asset.vendor.clone().unwrap_or_default())
+            .field("hostname", asset.hostname.clone().unwrap_or_default())
+            .field("os_fingerprint", asset.os_fingerprint.clone().unwrap_or_default())
+            .field("os_guess", asset.os_guess.clone().unwrap_or_default())
+            .field("dhcp_fingerprint", asset.dhcp_fingerprint.clone().unwrap_or_default())
+            .field("dhcp_vendor", asset.dhcp_vendor.clone().unwrap_or_default())
+            .field("tcp_services", join(&asset.tcp_services))
+            .field("udp_services", join(&asset.udp_services))
+            .field("first_seen", asset.first_seen as i64)
+            .field("last_seen", asset.last_seen as i64)
+            .field("packets_sent", asset.packets_sent as i64)
+            .field("bytes_sent", asset.bytes_sent as i64)
+            .field("packets_received", asset.packets_received as i64)
+            .field("bytes_received", asset.bytes_received as i64)
+            .timestamp(timestamp)
+            .build()
+            .map_err(|e| format!("Error creating data point: {:?}", e))?;
+        points.push(point);
+    }
+
+    influx_client.write(bucket_name, stream::iter(points)).await
+        .map_err(|e| format!("Failed to write to InfluxDB: {:?}", e))
+}
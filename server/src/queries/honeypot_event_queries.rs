@@ -0,0 +1,37 @@
+use influxdb2::models::DataPoint;
+use influxdb2::Client;
+use chrono::Utc;
+use futures::stream;
+use common::types::HoneypotEventPayload;
+
+use super::format_mac_address;
+
+/// Aggiunge un evento honeypot al bucket `honeypot`
+pub async fn add_honeypot_event_data(
+    influx_client: &Client,
+    device_name: &str,
+    event_payload: &HoneypotEventPayload
+) -> Result<(), String> {
+    let bucket_name = "honeypot";
+
+    let details = serde_json::to_string(&event_payload.event)
+        .map_err(|e| format!("Error serializing honeypot event: {:?}", e))?;
+
+    let point = DataPoint::builder("honeypot_events")
+        .tag("device", device_name)
+        .tag("service", event_payload.service.clone())
+        .tag("event", event_payload.event.kind())
+        .field("honeypot_mac", format_mac_address(&event_payload.honeypot_mac))
+        .field("honeypot_ip", event_payload.honeypot_ip.clone())
+        .field("honeypot_port", event_payload.honeypot_port as i64)
+        .field("attacker_mac", format_mac_address(&event_payload.attacker_mac))
+        .field("attacker_ip", event_payload.attacker_ip.clone())
+        .field("attacker_port", event_payload.attacker_port as i64)
+        .field("details", details)
+        .timestamp(Utc::now().timestamp_nanos_opt().unwrap_or_else(|| Utc::now().timestamp() * 1_000_000_000))
+        .build()
+        .map_err(|e| format!("Error creating data point: {:?}", e))?;
+
+    influx_client.write(bucket_name, stream::iter(vec![point])).await
+        .map_err(|e| format!("Failed to write to InfluxDB: {:?}", e))
+}
@@ -0,0 +1,53 @@
+use influxdb2::models::DataPoint;
+use influxdb2::Client;
+use chrono::Utc;
+use futures::stream;
+use common::types::{HoneypotSessionPayload, TranscriptChunk};
+
+use super::format_mac_address;
+
+/// Aggiunge una sessione honeypot al bucket `honeypot`
+pub async fn add_honeypot_session_data(
+    influx_client: &Client,
+    device_name: &str,
+    session_payload: &HoneypotSessionPayload
+) -> Result<(), String> {
+    let bucket_name = "honeypot";
+
+    let point = DataPoint::builder("honeypot_sessions")
+        .tag("device", device_name)
+        .tag("service", session_payload.service.clone())
+        .field("honeypot_mac", format_mac_address(&session_payload.honeypot_mac))
+        .field("honeypot_ip", session_payload.honeypot_ip.clone())
+        .field("honeypot_port", session_payload.honeypot_port as i64)
+        .field("attacker_mac", format_mac_address(&session_payload.attacker_mac))
+        .field("attacker_ip", session_payload.attacker_ip.clone())
+        .field("attacker_port", session_payload.attacker_port as i64)
+        .field("started_at", session_payload.started_at as i64)
+        .field("duration_ms", session_payload.duration_ms as i64)
+        .field("bytes_in", session_payload.bytes_in as i64)
+        .field("bytes_out", session_payload.bytes_out as i64)
+        .field("transcript", format_transcript(&session_payload.transcript))
+        .field("transcript_truncated", session_payload.transcript_truncated)
+        .timestamp(Utc::now().timestamp_nanos_opt().unwrap_or_else(|| Utc::now().timestamp() * 1_000_000_000))
+        .build()
+        .map_err(|e| format!("Error creating data point: {:?}", e))?;
+
+    influx_client.write(bucket_name, stream::iter(vec![point])).await
+        .map_err(|e| format!("Failed to write to InfluxDB: {:?}", e))
+}
+
+
+/// One line per chunk: `+<offset ms> <direction> <data>`, with `>` for attacker input and `<` for honeypot output
+fn format_transcript(transcript: &[TranscriptChunk]) -> String {
+    transcript
+        .iter()
+        .map(|chunk| format!(
+            "+{} {} {}",
+            chunk.offset_ms,
+            if chunk.inbound { ">" } else { "<" },
+            String::from_utf8_lossy(&chunk.data).escape_debug()
+        ))
+        .collect::<Vec<String>>()
+        .join("\n")
+}
@@ -3,6 +3,10 @@ pub mod device_queries;
 pub mod arp_alert_queries;
 pub mod tcp_alert_queries;
 pub mod alert_queries;
+pub mod honeypot_session_queries;
+pub mod honeypot_event_queries;
+pub mod asset_queries;
+pub mod network_alert_queries;
 
 
 pub fn format_mac_address(mac: &[u8; 6]) -> String {
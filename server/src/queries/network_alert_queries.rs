@@ -0,0 +1,34 @@
+use influxdb2::models::DataPoint;
+use influxdb2::Client;
+use chrono::Utc;
+use futures::stream;
+use common::types::NetworkAlertPayload;
+
+use super::format_mac_address;
+
+/// Aggiunge un avviso sulla rete locale al bucket `network`
+pub async fn add_network_alert_data(
+    influx_client: &Client,
+    device_name: &str,
+    alert_payload: &NetworkAlertPayload
+) -> Result<(), String> {
+    let bucket_name = "network";
+
+    let details = serde_json::to_string(&alert_payload.alert)
+        .map_err(|e| format!("Error serializing network alert: {:?}", e))?;
+
+    let point = DataPoint::builder("network_alerts")
+        .tag("device", device_name)
+        .tag("alert", alert_payload.alert.kind())
+        .field("source_mac", format_mac_address(&alert_payload.source_mac))
+        .field("source_ip", alert_payload.source_ip.clone().unwrap_or_default())
+        .field("target_mac", alert_payload.target_mac.as_ref().map(format_mac_address).unwrap_or_default())
+        .field("target_ip", alert_payload.target_ip.clone().unwrap_or_default())
+        .field("details", details)
+        .timestamp(Utc::now().timestamp_nanos_opt().unwrap_or_else(|| Utc::now().timestamp() * 1_000_000_000))
+        .build()
+        .map_err(|e| format!("Error creating data point: {:?}", e))?;
+
+    influx_client.write(bucket_name, stream::iter(vec![point])).await
+        .map_err(|e| format!("Failed to write to InfluxDB: {:?}", e))
+}
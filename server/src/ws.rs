@@ -10,7 +10,7 @@ use bincode;
 use common::types::{DataType, Packet, PayloadType, PriorityLevel};
 use tracing::{info, warn, error};
 use tokio::time::{self, Duration};
-use crate::{app_state::WssAppState, queries::{arp_alert_queries::add_arp_alert_data, alert_queries::add_alert_data, tcp_alert_queries::add_tcp_alert_data}};
+use crate::{app_state::WssAppState, queries::{arp_alert_queries::add_arp_alert_data, alert_queries::add_alert_data, tcp_alert_queries::add_tcp_alert_data, honeypot_session_queries::add_honeypot_session_data, honeypot_event_queries::add_honeypot_event_data, asset_queries::add_asset_inventory_data, network_alert_queries::add_network_alert_data}};
 
 
 pub async fn ws_handler(
@@ -107,6 +107,18 @@ async fn process_packet(wss_state: &Arc<WssAppState>, device_name: &str, bin: &[
         PayloadType::TcpAlert(tcp_alert_payload) => {
             add_tcp_alert_data(&wss_state.influx_client, device_name, tcp_alert_payload).await?;
         }
+        PayloadType::HoneypotSession(session_payload) => {
+            add_honeypot_session_data(&wss_state.influx_client, device_name, session_payload).await?;
+        }
+        PayloadType::HoneypotEvent(event_payload) => {
+            add_honeypot_event_data(&wss_state.influx_client, device_name, event_payload).await?;
+        }
+        PayloadType::AssetInventory(inventory_payload) => {
+            add_asset_inventory_data(&wss_state.influx_client, device_name, inventory_payload).await?;
+        }
+        PayloadType::NetworkAlert(alert_payload) => {
+            add_network_alert_data(&wss_state.influx_client, device_name, alert_payload).await?;
+        }
     }
 
     //info!("📩 Valid message from `{}`: ID={} type={:?}", device_name, packet.header.id, packet.header.data_type);